@@ -0,0 +1,510 @@
+//! Conversion between labelme data and COCO json (instance segmentation)
+//!
+//! COCO places (0, 0) at the top-left corner of the first pixel as labelme does, so [`ORIGIN_SHIFT`] keeps coordinates as they are.
+use crate::{LabelMeData, LabelMeDataLine, OriginConvention, Shape};
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// Shift from the labelme coordinates into the COCO ones. None since both are corner-based
+pub const ORIGIN_SHIFT: Option<OriginConvention> = None;
+
+#[derive(Error, Debug)]
+pub enum CocoError {
+    #[error("{0}: label {1:?} is not in the labels")]
+    UnknownLabel(String, String),
+    #[error("Unknown image_id {0} of annotation {1}")]
+    UnknownImage(u64, u64),
+    #[error("Unknown category_id {0} of annotation {1}")]
+    UnknownCategory(u64, u64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CocoInfo {
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Default for CocoInfo {
+    fn default() -> Self {
+        Self {
+            description: "Converted from labelme by labelme-rs".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CocoImage {
+    pub id: u64,
+    pub file_name: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Polygons of `[x1, y1, x2, y2, ...]`, or RLE of crowd annotations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Segmentation {
+    Polygons(Vec<Vec<f64>>),
+    Rle(serde_json::Value),
+}
+
+impl Default for Segmentation {
+    fn default() -> Self {
+        Self::Polygons(vec![])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CocoAnnotation {
+    pub id: u64,
+    pub image_id: u64,
+    pub category_id: u64,
+    /// `[x, y, width, height]`
+    pub bbox: [f64; 4],
+    #[serde(default)]
+    pub area: f64,
+    #[serde(default)]
+    pub segmentation: Segmentation,
+    /// `[x1, y1, v1, x2, y2, v2, ...]` where `v` is the visibility (0: not labeled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypoints: Option<Vec<f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_keypoints: Option<usize>,
+    #[serde(default)]
+    pub iscrowd: u8,
+}
+
+impl CocoAnnotation {
+    /// Crowd annotation, which is typically RLE
+    pub fn is_crowd(&self) -> bool {
+        self.iscrowd != 0 || matches!(self.segmentation, Segmentation::Rle(_))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CocoCategory {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CocoDataset {
+    #[serde(default)]
+    pub info: CocoInfo,
+    pub images: Vec<CocoImage>,
+    pub annotations: Vec<CocoAnnotation>,
+    pub categories: Vec<CocoCategory>,
+}
+
+/// Export of point shapes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PointExport {
+    Skip,
+    /// Keypoint annotations with zero-size bboxes
+    #[default]
+    Keypoints,
+    /// Boxes of the size centered at the points. Circles are exported as their bounding boxes as well
+    Boxes(f64),
+}
+
+/// Options of [`to_coco_dataset_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct CocoOptions {
+    /// Labels whose 1-based indices are the category ids. Sorted labels of the exported shapes are used if `None`
+    pub labels: Option<Vec<String>>,
+    pub points: PointExport,
+}
+
+/// Annotation of the shape without the ids. `None` for the shapes to be skipped
+fn convert_shape(shape: &Shape, points: PointExport) -> Option<CocoAnnotation> {
+    let bbox = |((x0, y0), (x1, y1)): ((f64, f64), (f64, f64))| [x0, y0, x1 - x0, y1 - y0];
+    let annotation = |bbox: [f64; 4], area: f64, segmentation: Segmentation| CocoAnnotation {
+        id: 0,
+        image_id: 0,
+        category_id: 0,
+        bbox,
+        area,
+        segmentation,
+        keypoints: None,
+        num_keypoints: None,
+        iscrowd: 0,
+    };
+    match (shape.shape_type.as_str(), points) {
+        ("rectangle", _) if shape.points.len() >= 2 => {
+            let bbox = bbox(shape.bounding_box()?);
+            Some(annotation(bbox, bbox[2] * bbox[3], Segmentation::default()))
+        }
+        ("polygon", _) if shape.points.len() >= 3 => {
+            let segmentation = shape.points.iter().flat_map(|p| [p.0, p.1]).collect();
+            Some(annotation(
+                bbox(shape.bounding_box()?),
+                shape.area()?,
+                Segmentation::Polygons(vec![segmentation]),
+            ))
+        }
+        ("point", PointExport::Keypoints) => {
+            let (x, y) = *shape.points.first()?;
+            Some(CocoAnnotation {
+                keypoints: Some(vec![x, y, 2.0]),
+                num_keypoints: Some(1),
+                ..annotation([x, y, 0.0, 0.0], 0.0, Segmentation::default())
+            })
+        }
+        ("point", PointExport::Boxes(size)) => {
+            let (x, y) = *shape.points.first()?;
+            Some(annotation(
+                [x - size / 2.0, y - size / 2.0, size, size],
+                size * size,
+                Segmentation::default(),
+            ))
+        }
+        ("circle", PointExport::Boxes(_)) if shape.points.len() >= 2 => {
+            let bbox = bbox(shape.bounding_box()?);
+            Some(annotation(bbox, bbox[2] * bbox[3], Segmentation::default()))
+        }
+        _ => None,
+    }
+}
+
+/// Image ids are the filename stems if all of them are distinct integers, otherwise 1-based indices
+fn image_ids(filenames: &[&str]) -> Vec<u64> {
+    let stems: Option<Vec<u64>> = filenames
+        .iter()
+        .map(|f| Path::new(f).file_stem()?.to_str()?.parse().ok())
+        .collect();
+    match stems {
+        Some(stems) if stems.iter().collect::<IndexSet<_>>().len() == stems.len() => stems,
+        _ => (1..=filenames.len() as u64).collect(),
+    }
+}
+
+/// Convert labelme data paired with the filenames into a COCO dataset with the default [`CocoOptions`].
+///
+/// Rectangles become bboxes, polygons become segmentations, and points become keypoints. The other shapes are skipped.
+/// Images are sorted by filename and their ids are the filename stems if they are all distinct integers, otherwise the 1-based indices.
+/// Category ids are the 1-based indices of the sorted labels
+///
+/// ```
+/// let data = labelme_rs::LabelMeData::new(&[(1.0, 2.0)], &["L1".into()], 128, 128, "1.jpg");
+/// let dataset = labelme_rs::coco::to_coco_dataset(&[(data, "1.json")]);
+/// assert_eq!(dataset.images[0].id, 1);
+/// assert_eq!(dataset.annotations[0].keypoints, Some(vec![1.0, 2.0, 2.0]));
+/// assert_eq!(dataset.categories[0].name, "L1");
+/// ```
+pub fn to_coco_dataset(entries: &[(LabelMeData, &str)]) -> CocoDataset {
+    to_coco_dataset_with_options(entries, &CocoOptions::default())
+        .expect("Labels are taken from the entries")
+}
+
+/// Same as [`to_coco_dataset`] with [`CocoOptions`]. Fails if a label is not in [`CocoOptions::labels`]
+pub fn to_coco_dataset_with_options(
+    entries: &[(LabelMeData, &str)],
+    options: &CocoOptions,
+) -> Result<CocoDataset, CocoError> {
+    let mut entries: Vec<_> = entries
+        .iter()
+        .map(|(data, filename)| (data.with_origin_shift(ORIGIN_SHIFT), *filename))
+        .collect();
+    entries.sort_by(|a, b| a.1.cmp(b.1));
+    let shapes: Vec<Vec<_>> = entries
+        .iter()
+        .map(|(data, _)| {
+            data.shapes
+                .iter()
+                .filter_map(|shape| Some((shape, convert_shape(shape, options.points)?)))
+                .collect()
+        })
+        .collect();
+    let labels: Vec<&str> = match &options.labels {
+        Some(labels) => labels.iter().map(String::as_str).collect(),
+        None => {
+            let mut labels: Vec<_> = shapes
+                .iter()
+                .flatten()
+                .map(|(shape, _)| shape.label.as_str())
+                .collect::<IndexSet<_>>()
+                .into_iter()
+                .collect();
+            labels.sort();
+            labels
+        }
+    };
+    let category_ids: IndexMap<&str, u64> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (*label, i as u64 + 1))
+        .collect();
+    let filenames: Vec<_> = entries.iter().map(|(_, f)| *f).collect();
+    let ids = image_ids(&filenames);
+    let mut images = vec![];
+    let mut annotations = vec![];
+    for (((data, filename), shapes), image_id) in entries.iter().zip(shapes).zip(ids) {
+        images.push(CocoImage {
+            id: image_id,
+            file_name: data.imagePath.clone(),
+            width: data.imageWidth,
+            height: data.imageHeight,
+        });
+        for (shape, annotation) in shapes {
+            let category_id = *category_ids.get(shape.label.as_str()).ok_or_else(|| {
+                CocoError::UnknownLabel(filename.to_string(), shape.label.clone())
+            })?;
+            annotations.push(CocoAnnotation {
+                id: annotations.len() as u64 + 1,
+                image_id,
+                category_id,
+                ..annotation
+            });
+        }
+    }
+    let categories = category_ids
+        .into_iter()
+        .map(|(name, id)| CocoCategory {
+            id,
+            name: name.to_string(),
+        })
+        .collect();
+    Ok(CocoDataset {
+        info: CocoInfo::default(),
+        images,
+        annotations,
+        categories,
+    })
+}
+
+/// Convert a COCO dataset into labelme data of each image in the order of `images`.
+/// Filenames are `file_name` of the images with the extension replaced with `json`.
+///
+/// Keypoints become points, segmentation polygons become polygons, and the other annotations become rectangles of the bboxes.
+/// Crowd annotations (see [`CocoAnnotation::is_crowd`]) are skipped
+pub fn from_coco_dataset(dataset: &CocoDataset) -> Result<Vec<LabelMeDataLine>, CocoError> {
+    let labels: IndexMap<u64, &str> = dataset
+        .categories
+        .iter()
+        .map(|c| (c.id, c.name.as_str()))
+        .collect();
+    let mut lines: IndexMap<u64, LabelMeDataLine> = dataset
+        .images
+        .iter()
+        .map(|image| {
+            let filename = Path::new(&image.file_name)
+                .with_extension("json")
+                .to_string_lossy()
+                .to_string();
+            let content = LabelMeData::new(&[], &[], image.width, image.height, &image.file_name);
+            (image.id, LabelMeDataLine { filename, content })
+        })
+        .collect();
+    for annotation in dataset.annotations.iter().filter(|a| !a.is_crowd()) {
+        let line = lines
+            .get_mut(&annotation.image_id)
+            .ok_or(CocoError::UnknownImage(annotation.image_id, annotation.id))?;
+        let label = labels
+            .get(&annotation.category_id)
+            .ok_or(CocoError::UnknownCategory(
+                annotation.category_id,
+                annotation.id,
+            ))?;
+        let shapes = &mut line.content.shapes;
+        match (&annotation.keypoints, &annotation.segmentation) {
+            (Some(keypoints), _) => {
+                for keypoint in keypoints.chunks_exact(3).filter(|k| k[2] > 0.0) {
+                    shapes.push(Shape {
+                        label: label.to_string(),
+                        points: vec![(keypoint[0], keypoint[1])],
+                        shape_type: "point".into(),
+                        ..Default::default()
+                    });
+                }
+            }
+            (None, Segmentation::Polygons(polygons)) if !polygons.is_empty() => {
+                for polygon in polygons {
+                    let points = polygon.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+                    shapes.push(Shape::polygon(points, label));
+                }
+            }
+            _ => {
+                let [x, y, w, h] = annotation.bbox;
+                shapes.push(Shape::rectangle((x, y), (x + w, y + h), label));
+            }
+        }
+    }
+    let mut lines: Vec<_> = lines.into_values().collect();
+    if let Some(by) = ORIGIN_SHIFT {
+        for line in &mut lines {
+            line.content.shift_origin(by.inverse());
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    /// Label, shape type, and points
+    type ShapeSpec<'a> = (&'a str, &'a str, &'a [Point]);
+
+    fn entry<'a>(filename: &'a str, shapes: &[ShapeSpec]) -> (LabelMeData, &'a str) {
+        let data = LabelMeData {
+            shapes: shapes
+                .iter()
+                .map(|(label, shape_type, points)| crate::tests::shape(label, shape_type, *points))
+                .collect(),
+            imagePath: filename.replace(".json", ".jpg"),
+            imageWidth: 64,
+            imageHeight: 32,
+            ..Default::default()
+        };
+        (data, filename)
+    }
+
+    #[test]
+    fn test_to_coco_dataset() -> Result<(), CocoError> {
+        let entries = vec![
+            entry(
+                "b.json",
+                &[("iris", "circle", &[(10.0, 10.0), (13.0, 14.0)])],
+            ),
+            entry(
+                "a.json",
+                &[
+                    ("tumor", "rectangle", &[(10.0, 20.0), (4.0, 2.0)]),
+                    ("cell", "polygon", &[(0.0, 0.0), (4.0, 0.0), (0.0, 3.0)]),
+                    ("dot", "point", &[(5.0, 5.0)]),
+                    ("edge", "line", &[(0.0, 0.0), (1.0, 1.0)]),
+                ],
+            ),
+        ];
+        let options = |labels: Option<&[&str]>, points| CocoOptions {
+            labels: labels.map(|labels| labels.iter().map(|l| l.to_string()).collect()),
+            points,
+        };
+        let dataset = to_coco_dataset_with_options(&entries, &options(None, PointExport::Skip))?;
+        assert_eq!(dataset.images.len(), 2);
+        assert_eq!(dataset.images[1].id, 2);
+        assert_eq!(dataset.images[1].file_name, "b.jpg");
+        assert_eq!(
+            (dataset.images[0].width, dataset.images[0].height),
+            (64, 32)
+        );
+        let names: Vec<_> = dataset.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["cell", "tumor"]);
+        assert_eq!(dataset.annotations.len(), 2);
+        let rect = &dataset.annotations[0];
+        assert_eq!((rect.id, rect.image_id, rect.category_id), (1, 1, 2));
+        assert_eq!(rect.bbox, [4.0, 2.0, 6.0, 18.0]);
+        assert_eq!(rect.area, 108.0);
+        let polygon = &dataset.annotations[1];
+        assert_eq!(polygon.bbox, [0.0, 0.0, 4.0, 3.0]);
+        assert_eq!(polygon.area, 6.0);
+        assert_eq!(
+            polygon.segmentation,
+            Segmentation::Polygons(vec![vec![0.0, 0.0, 4.0, 0.0, 0.0, 3.0]])
+        );
+
+        let dataset =
+            to_coco_dataset_with_options(&entries, &options(None, PointExport::Boxes(2.0)))?;
+        let names: Vec<_> = dataset.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["cell", "dot", "iris", "tumor"]);
+        assert_eq!(dataset.annotations[2].bbox, [4.0, 4.0, 2.0, 2.0]);
+        assert_eq!(dataset.annotations[3].bbox, [5.0, 5.0, 10.0, 10.0]);
+        assert_eq!(dataset.annotations[3].image_id, 2);
+
+        let dataset = to_coco_dataset(&entries);
+        assert_eq!(dataset.annotations.len(), 3);
+        assert_eq!(dataset.annotations[2].keypoints, Some(vec![5.0, 5.0, 2.0]));
+        assert_eq!(dataset.annotations[2].bbox, [5.0, 5.0, 0.0, 0.0]);
+        let json = serde_json::to_value(&dataset).unwrap();
+        assert!(json["info"]["description"].is_string());
+        assert!(json["annotations"][0].get("keypoints").is_none());
+
+        let labels = options(Some(&["tumor", "cell"]), PointExport::Skip);
+        let dataset = to_coco_dataset_with_options(&entries, &labels)?;
+        assert_eq!(dataset.annotations[0].category_id, 1);
+        assert_eq!(dataset.annotations[1].category_id, 2);
+        assert!(matches!(
+            to_coco_dataset_with_options(&entries, &options(Some(&["tumor"]), PointExport::Skip)),
+            Err(CocoError::UnknownLabel(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_ids() {
+        assert_eq!(image_ids(&["10.json", "2.json"]), vec![10, 2]);
+        assert_eq!(image_ids(&["10.json", "a.json"]), vec![1, 2]);
+        assert_eq!(image_ids(&["1.json", "sub/1.json"]), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), CocoError> {
+        let entries = vec![
+            entry(
+                "1.json",
+                &[
+                    ("tumor", "rectangle", &[(4.0, 2.5), (10.0, 20.0)]),
+                    ("cell", "polygon", &[(0.5, 0.25), (4.0, 0.0), (0.0, 3.75)]),
+                    ("edge", "line", &[(0.0, 0.0), (1.0, 1.0)]),
+                    ("dot", "point", &[(5.5, 5.0)]),
+                ],
+            ),
+            entry("2.json", &[]),
+            entry(
+                "3.json",
+                &[("tumor", "rectangle", &[(1.0, 2.0), (3.0, 4.0)])],
+            ),
+        ];
+        let json = serde_json::to_string(&to_coco_dataset(&entries)).unwrap();
+        let lines = from_coco_dataset(&serde_json::from_str(&json).unwrap())?;
+        assert_eq!(lines.len(), 3);
+        for (line, (data, filename)) in lines.iter().zip(&entries) {
+            assert_eq!(&line.filename, filename);
+            assert_eq!(line.content.imagePath, data.imagePath);
+            assert_eq!(
+                (line.content.imageWidth, line.content.imageHeight),
+                (data.imageWidth, data.imageHeight)
+            );
+            let expected: Vec<_> = data
+                .shapes
+                .iter()
+                .filter(|s| s.shape_type != "line")
+                .collect();
+            assert_eq!(line.content.shapes.len(), expected.len());
+            for (shape, expected) in line.content.shapes.iter().zip(expected) {
+                assert_eq!(shape.label, expected.label);
+                assert_eq!(shape.shape_type, expected.shape_type);
+                assert_eq!(shape.points, expected.points);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_coco_dataset_crowd() -> Result<(), CocoError> {
+        let dataset: CocoDataset = serde_json::from_str(
+            r#"{
+                "images": [{"id": 7, "file_name": "img/a.png", "width": 8, "height": 4}],
+                "annotations": [
+                    {"id": 1, "image_id": 7, "category_id": 3, "bbox": [1, 1, 2, 2], "iscrowd": 1, "segmentation": {"counts": [0, 4], "size": [4, 8]}},
+                    {"id": 2, "image_id": 7, "category_id": 3, "bbox": [1, 1, 2, 2], "segmentation": [[1, 1, 3, 1, 3, 3], [0, 0, 1, 0, 0, 1]]}
+                ],
+                "categories": [{"id": 3, "name": "cell", "supercategory": "bio"}]
+            }"#,
+        )
+        .unwrap();
+        assert!(dataset.annotations[0].is_crowd());
+        let lines = from_coco_dataset(&dataset)?;
+        assert_eq!(lines[0].filename, "img/a.json");
+        assert_eq!(lines[0].content.shapes.len(), 2);
+        assert!(lines[0]
+            .content
+            .shapes
+            .iter()
+            .all(|s| s.shape_type == "polygon" && s.label == "cell"));
+        Ok(())
+    }
+}
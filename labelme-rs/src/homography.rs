@@ -0,0 +1,262 @@
+//! Homography estimated from point correspondences, e.g. to align annotations between modalities
+//!
+//! The homography is solved by the direct linear transformation (DLT) in the least-squares sense with `h33 = 1`.
+//! Points are normalized beforehand so that their centroid is at the origin and their mean distance from it is √2.
+use crate::{LabelMeData, Point};
+use indexmap::IndexMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HomographyError {
+    #[error("at least 4 correspondences are required, but {0} found")]
+    TooFewCorrespondences(usize),
+    #[error("correspondences are degenerate (e.g. 3 of the points are collinear)")]
+    Degenerate,
+    #[error("label {0:?} of point shapes is not unique")]
+    DuplicateLabel(String),
+}
+
+/// 3x3 projective transformation in row-major order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography(pub [[f64; 3]; 3]);
+
+impl Homography {
+    /// Estimate the homography mapping `src` points to `dst` points
+    ///
+    /// ```
+    /// use labelme_rs::homography::Homography;
+    /// let src = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+    /// let dst = [(10.0, 20.0), (12.0, 20.0), (12.0, 22.0), (10.0, 22.0)];
+    /// let h = Homography::from_correspondences(&src, &dst).unwrap();
+    /// let (x, y) = h.apply((0.5, 0.5));
+    /// assert!((x - 11.0).abs() < 1e-9 && (y - 21.0).abs() < 1e-9);
+    /// ```
+    pub fn from_correspondences(src: &[Point], dst: &[Point]) -> Result<Self, HomographyError> {
+        let n = src.len().min(dst.len());
+        if n < 4 {
+            return Err(HomographyError::TooFewCorrespondences(n));
+        }
+        let (src, dst) = (&src[..n], &dst[..n]);
+        let (t_src, t_dst) = (normalization(src)?, normalization(dst)?);
+        // normal equations of the 2n x 8 system
+        let mut ata = [[0.0; 8]; 8];
+        let mut atb = [0.0; 8];
+        for (s, d) in src.iter().zip(dst) {
+            let (x, y) = t_src.apply(*s);
+            let (u, v) = t_dst.apply(*d);
+            for (row, b) in [
+                ([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u),
+                ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v),
+            ] {
+                for i in 0..8 {
+                    for j in 0..8 {
+                        ata[i][j] += row[i] * row[j];
+                    }
+                    atb[i] += row[i] * b;
+                }
+            }
+        }
+        let h = solve(ata, atb).ok_or(HomographyError::Degenerate)?;
+        let normalized = Homography([[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]]);
+        let inverse_t_dst = t_dst.inverse().ok_or(HomographyError::Degenerate)?;
+        Ok(inverse_t_dst
+            .compose(&normalized)
+            .compose(&t_src)
+            .normalized())
+    }
+
+    /// Estimate the homography from the point shapes of `src` to those of `dst` with the same labels.
+    /// Labels of the point shapes must be unique in each data
+    pub fn from_point_labels(
+        src: &LabelMeData,
+        dst: &LabelMeData,
+    ) -> Result<Self, HomographyError> {
+        let (src, dst) = (labeled_points(src)?, labeled_points(dst)?);
+        let (src, dst): (Vec<_>, Vec<_>) = src
+            .iter()
+            .filter_map(|(label, s)| dst.get(label).map(|d| (*s, *d)))
+            .unzip();
+        Self::from_correspondences(&src, &dst)
+    }
+
+    /// Transform the point
+    pub fn apply(&self, (x, y): Point) -> Point {
+        let m = &self.0;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        (
+            (m[0][0] * x + m[0][1] * y + m[0][2]) / w,
+            (m[1][0] * x + m[1][1] * y + m[1][2]) / w,
+        )
+    }
+
+    /// `self * other`, i.e. `other` is applied first
+    fn compose(&self, other: &Homography) -> Homography {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Homography(m)
+    }
+
+    /// Inverse of the similarity made by [`normalization`]
+    fn inverse(&self) -> Option<Homography> {
+        let (s, tx, ty) = (self.0[0][0], self.0[0][2], self.0[1][2]);
+        (s != 0.0).then(|| {
+            Homography([
+                [1.0 / s, 0.0, -tx / s],
+                [0.0, 1.0 / s, -ty / s],
+                [0.0, 0.0, 1.0],
+            ])
+        })
+    }
+
+    /// Scale so that `h33 = 1`
+    fn normalized(&self) -> Homography {
+        let s = self.0[2][2];
+        Homography(self.0.map(|row| row.map(|v| v / s)))
+    }
+}
+
+/// Similarity moving the centroid of `points` to the origin with the mean distance of √2
+fn normalization(points: &[Point]) -> Result<Homography, HomographyError> {
+    let n = points.len() as f64;
+    let (cx, cy) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+    let mean_distance = points
+        .iter()
+        .map(|(x, y)| (x - cx).hypot(y - cy))
+        .sum::<f64>()
+        / n;
+    if mean_distance == 0.0 {
+        return Err(HomographyError::Degenerate);
+    }
+    let s = std::f64::consts::SQRT_2 / mean_distance;
+    Ok(Homography([
+        [s, 0.0, -s * cx],
+        [0.0, s, -s * cy],
+        [0.0, 0.0, 1.0],
+    ]))
+}
+
+/// Solve `a x = b` by Gaussian elimination with partial pivoting. `None` if `a` is singular
+fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    let scale = a.iter().flatten().fold(0.0f64, |m, v| m.max(v.abs()));
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() <= scale * 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (pivot_row, pivot_b) = (a[col], b[col]);
+        for (row, b) in a.iter_mut().zip(b.iter_mut()).skip(col + 1) {
+            let f = row[col] / pivot_row[col];
+            for (v, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= f * p;
+            }
+            *b -= f * pivot_b;
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+fn labeled_points(data: &LabelMeData) -> Result<IndexMap<&str, Point>, HomographyError> {
+    let mut points = IndexMap::new();
+    for shape in data.shapes.iter().filter(|s| s.shape_type == "point") {
+        let Some(p) = shape.points.first() else {
+            continue;
+        };
+        if points.insert(shape.label.as_str(), *p).is_some() {
+            return Err(HomographyError::DuplicateLabel(shape.label.clone()));
+        }
+    }
+    Ok(points)
+}
+
+/// Transform all points of `data` by `h`.
+/// Rectangles and circles are transformed by their two points and may not represent the projected shapes exactly.
+/// Does not change imageWidth and imageHeight
+pub fn reproject(data: &mut LabelMeData, h: &Homography) {
+    for shape in &mut data.shapes {
+        for p in &mut shape.points {
+            *p = h.apply(*p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_correspondences() {
+        let truth = Homography([[1.2, 0.1, 30.0], [-0.05, 0.9, 12.0], [0.0004, -0.0002, 1.0]]);
+        let src: Vec<Point> = [
+            (0.0, 0.0),
+            (640.0, 0.0),
+            (640.0, 480.0),
+            (0.0, 480.0),
+            (320.0, 100.0),
+            (50.0, 300.0),
+        ]
+        .into();
+        let dst: Vec<Point> = src.iter().map(|p| truth.apply(*p)).collect();
+        let h = Homography::from_correspondences(&src, &dst).unwrap();
+        for (a, b) in h.0.iter().flatten().zip(truth.0.iter().flatten()) {
+            assert!((a - b).abs() < 1e-9, "{:?} vs. {:?}", h, truth);
+        }
+        let (x, y) = h.apply((100.0, 200.0));
+        let (tx, ty) = truth.apply((100.0, 200.0));
+        assert!((x - tx).abs() < 1e-6 && (y - ty).abs() < 1e-6);
+
+        assert_eq!(
+            Homography::from_correspondences(&src[..3], &dst[..3]),
+            Err(HomographyError::TooFewCorrespondences(3))
+        );
+        let collinear = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        assert_eq!(
+            Homography::from_correspondences(&collinear, &collinear),
+            Err(HomographyError::Degenerate)
+        );
+    }
+
+    #[test]
+    fn test_from_point_labels() {
+        let labels: Vec<String> = ["A", "B", "C", "D", "E"].map(String::from).into();
+        let src = LabelMeData::new(
+            &[
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 10.0),
+                (0.0, 10.0),
+                (5.0, 5.0),
+            ],
+            &labels,
+            10,
+            10,
+            "src.jpg",
+        );
+        let mut dst = src.clone();
+        dst.shift(3.0, 4.0);
+        dst.shapes.remove(4);
+        let h = Homography::from_point_labels(&src, &dst).unwrap();
+        let mut data = src.clone();
+        reproject(&mut data, &h);
+        let (x, y) = data.shapes[4].points[0];
+        assert!((x - 8.0).abs() < 1e-9 && (y - 9.0).abs() < 1e-9);
+
+        dst.shapes.push(dst.shapes[0].clone());
+        assert_eq!(
+            Homography::from_point_labels(&src, &dst),
+            Err(HomographyError::DuplicateLabel("A".into()))
+        );
+    }
+}
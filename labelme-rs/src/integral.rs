@@ -0,0 +1,181 @@
+//! JSON output writing integral coordinates as integers
+//!
+//! Labelme writes whole-number coordinates as integers (e.g. `[100, 200]`), while [`crate::Point`] is written as `[100.0, 200.0]` by serde_json.
+//! Commands rewriting files without transforming coordinates use these functions to keep such files unchanged byte for byte.
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use std::io;
+
+/// Floats within this distance from an integer are written as the integer
+pub const EPSILON: f64 = 1e-9;
+
+/// Largest magnitude exactly representable as an integer by f64
+const MAX_EXACT: f64 = (1u64 << 53) as f64;
+
+/// Round `value` to the integer if it is integral within [`EPSILON`]
+pub fn as_integer(value: f64) -> Option<i64> {
+    let rounded = value.round();
+    ((value - rounded).abs() <= EPSILON && rounded.abs() <= MAX_EXACT).then_some(rounded as i64)
+}
+
+/// Formatter writing integral floats as integers and delegating everything else to `F`
+#[derive(Debug, Clone, Default)]
+pub struct IntegralFormatter<F>(pub F);
+
+impl<F: Formatter> Formatter for IntegralFormatter<F> {
+    fn write_f64<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        match as_integer(value) {
+            Some(i) => self.0.write_i64(writer, i),
+            None => self.0.write_f64(writer, value),
+        }
+    }
+
+    fn write_f32<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f32) -> io::Result<()> {
+        match as_integer(value as f64) {
+            Some(i) => self.0.write_i64(writer, i),
+            None => self.0.write_f32(writer, value),
+        }
+    }
+
+    fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.0.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.0.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_object_value(writer)
+    }
+}
+
+/// [`serde_json::to_writer`] writing integral floats as integers
+pub fn to_writer<W: io::Write, T: ?Sized + Serialize>(
+    writer: W,
+    value: &T,
+) -> serde_json::Result<()> {
+    let mut ser =
+        serde_json::Serializer::with_formatter(writer, IntegralFormatter(CompactFormatter));
+    value.serialize(&mut ser)
+}
+
+/// [`serde_json::to_writer_pretty`] writing integral floats as integers
+pub fn to_writer_pretty<W: io::Write, T: ?Sized + Serialize>(
+    writer: W,
+    value: &T,
+) -> serde_json::Result<()> {
+    let mut ser =
+        serde_json::Serializer::with_formatter(writer, IntegralFormatter(PrettyFormatter::new()));
+    value.serialize(&mut ser)
+}
+
+/// [`serde_json::to_string`] writing integral floats as integers
+///
+/// ```
+/// let data = labelme_rs::LabelMeData::new(&[(100.0, 20.5)], &["L".into()], 512, 512, "img.jpg");
+/// let s = labelme_rs::integral::to_string(&data.shapes[0].points).unwrap();
+/// assert_eq!(s, "[[100,20.5]]");
+/// ```
+pub fn to_string<T: ?Sized + Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut buf = vec![];
+    to_writer(&mut buf, value)?;
+    Ok(String::from_utf8(buf).expect("serde_json writes valid UTF-8"))
+}
+
+/// [`serde_json::to_string_pretty`] writing integral floats as integers
+pub fn to_string_pretty<T: ?Sized + Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut buf = vec![];
+    to_writer_pretty(&mut buf, value)?;
+    Ok(String::from_utf8(buf).expect("serde_json writes valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LabelMeData;
+
+    #[test]
+    fn test_as_integer() {
+        assert_eq!(as_integer(100.0), Some(100));
+        assert_eq!(as_integer(-3.0), Some(-3));
+        assert_eq!(as_integer(99.9999999999), Some(100));
+        assert_eq!(as_integer(0.5), None);
+        assert_eq!(as_integer(f64::NAN), None);
+        assert_eq!(as_integer(f64::INFINITY), None);
+        assert_eq!(as_integer(1e20), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let s = r#"{
+  "version": "5.0.1",
+  "flags": {},
+  "shapes": [
+    {
+      "label": "L",
+      "points": [
+        [
+          100,
+          200
+        ],
+        [
+          10.5,
+          0
+        ]
+      ],
+      "group_id": null,
+      "shape_type": "line",
+      "flags": {}
+    }
+  ],
+  "imagePath": "img.jpg",
+  "imageData": null,
+  "imageHeight": 512,
+  "imageWidth": 512
+}"#;
+        let data = LabelMeData::try_from(s).unwrap();
+        assert_eq!(to_string_pretty(&data).unwrap(), s);
+        assert_ne!(serde_json::to_string_pretty(&data).unwrap(), s);
+        assert_eq!(
+            to_string(&data.shapes[0].points).unwrap(),
+            "[[100,200],[10.5,0]]"
+        );
+    }
+}
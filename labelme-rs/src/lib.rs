@@ -1,4 +1,5 @@
 use base64::Engine;
+pub use encoding_rs;
 pub use image;
 use image::{DynamicImage, GenericImageView};
 pub use indexmap;
@@ -7,11 +8,13 @@ use regex::Regex;
 pub use serde;
 use serde::{Deserialize, Serialize};
 pub use serde_json;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::Cursor;
 use std::path::Path;
 pub use svg;
 use svg::node::element;
+use svg::Node;
 use thiserror::Error;
 
 #[cfg(feature = "dicom")]
@@ -20,6 +23,14 @@ use dicom_pixeldata::{ConvertOptions, PixelDecoder, VoiLutOption};
 #[macro_use]
 extern crate lazy_static;
 
+pub mod coco;
+pub mod homography;
+pub mod integral;
+pub mod repair;
+pub mod tiles;
+pub mod voc;
+pub mod yolo;
+
 pub type Flags = IndexMap<String, bool>;
 pub type FlagSet = IndexSet<String>;
 pub type Point = (f64, f64);
@@ -31,13 +42,367 @@ pub struct Shape {
     pub group_id: Option<String>,
     pub shape_type: String,
     pub flags: Flags,
+    /// Free text of the shape (labelme 5). Omitted in the output if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Union of two bounding boxes
+fn union_box(a: (Point, Point), b: (Point, Point)) -> (Point, Point) {
+    (
+        (a.0 .0.min(b.0 .0), a.0 .1.min(b.0 .1)),
+        (a.1 .0.max(b.1 .0), a.1 .1.max(b.1 .1)),
+    )
+}
+
+impl Shape {
+    /// Rectangle with the standardized corners, i.e. the top-left and bottom-right corners in this order
+    ///
+    /// ```
+    /// let shape = labelme_rs::Shape::rectangle((10.0, 2.0), (4.0, 8.0), "L1");
+    /// assert_eq!(shape.points, vec![(4.0, 2.0), (10.0, 8.0)]);
+    /// assert_eq!(shape.shape_type, "rectangle");
+    /// ```
+    pub fn rectangle(p1: Point, p2: Point, label: &str) -> Self {
+        Self {
+            label: label.into(),
+            points: vec![
+                (p1.0.min(p2.0), p1.1.min(p2.1)),
+                (p1.0.max(p2.0), p1.1.max(p2.1)),
+            ],
+            shape_type: "rectangle".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Polygon of the points
+    pub fn polygon(points: Vec<Point>, label: &str) -> Self {
+        Self {
+            label: label.into(),
+            points,
+            shape_type: "polygon".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Content of `text` shapes, which is the description or the label if the description is absent or empty
+    ///
+    /// ```
+    /// let mut shape = labelme_rs::Shape::polygon(vec![], "sign");
+    /// assert_eq!(shape.text_content(), "sign");
+    /// shape.description = Some("STOP".into());
+    /// assert_eq!(shape.text_content(), "STOP");
+    /// ```
+    pub fn text_content(&self) -> &str {
+        match self.description.as_deref() {
+            Some(description) if !description.is_empty() => description,
+            _ => &self.label,
+        }
+    }
+
+    /// Axis-aligned bounding box as `((min_x, min_y), (max_x, max_y))`. `None` if `points` is empty.
+    ///
+    /// Circles (center and a point on the circumference) are bounded by their radii
+    ///
+    /// ```
+    /// let shape = labelme_rs::Shape {
+    ///     points: vec![(10.0, 10.0), (13.0, 14.0)],
+    ///     shape_type: "circle".into(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(shape.bounding_box(), Some(((5.0, 5.0), (15.0, 15.0))));
+    /// ```
+    pub fn bounding_box(&self) -> Option<(Point, Point)> {
+        if self.shape_type == "circle" && self.points.len() == 2 {
+            let (c, p) = (self.points[0], self.points[1]);
+            let r = (c.0 - p.0).hypot(c.1 - p.1);
+            return Some(((c.0 - r, c.1 - r), (c.0 + r, c.1 + r)));
+        }
+        self.points.iter().map(|p| (*p, *p)).reduce(union_box)
+    }
+
+    /// Geometric centroid. `None` if `points` is empty
+    ///
+    /// - polygon: centroid by the signed area, or the mean of the points if the area is zero
+    /// - rectangle and mask: center of the bounding box
+    /// - circle and line: midpoint of the two stored points
+    /// - point: the point
+    /// - others (e.g. linestrip): mean of the points
+    ///
+    /// ```
+    /// let shape = labelme_rs::Shape {
+    ///     points: vec![(0.0, 0.0), (6.0, 0.0), (0.0, 3.0)],
+    ///     shape_type: "polygon".into(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(shape.centroid(), Some((2.0, 1.0)));
+    /// ```
+    pub fn centroid(&self) -> Option<Point> {
+        let first = *self.points.first()?;
+        match self.shape_type.as_str() {
+            "polygon" => polygon_centroid(&self.points),
+            "rectangle" | "mask" => {
+                let ((x0, y0), (x1, y1)) = self.bounding_box()?;
+                Some(((x0 + x1) / 2.0, (y0 + y1) / 2.0))
+            }
+            "point" => Some(first),
+            _ => {
+                let n = self.points.len() as f64;
+                let (sx, sy) = self
+                    .points
+                    .iter()
+                    .fold((0.0, 0.0), |(sx, sy), p| (sx + p.0, sy + p.1));
+                Some((sx / n, sy / n))
+            }
+        }
+    }
+
+    /// Area of polygons, rectangles and circles. `None` for the other shape types and shapes with too few points
+    ///
+    /// Self-intersecting polygons yield the absolute value of the shoelace formula
+    ///
+    /// ```
+    /// let shape = labelme_rs::Shape {
+    ///     points: vec![(0.0, 0.0), (4.0, 0.0), (0.0, 3.0)],
+    ///     shape_type: "polygon".into(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(shape.area(), Some(6.0));
+    /// ```
+    pub fn area(&self) -> Option<f64> {
+        let points = &self.points;
+        match self.shape_type.as_str() {
+            "polygon" if points.len() >= 3 => Some(signed_area(points).abs()),
+            "rectangle" if points.len() >= 2 => {
+                let (p, q) = (points[0], points[1]);
+                Some((q.0 - p.0).abs() * (q.1 - p.1).abs())
+            }
+            "circle" if points.len() >= 2 => {
+                let (c, p) = (points[0], points[1]);
+                Some(std::f64::consts::PI * ((c.0 - p.0).powi(2) + (c.1 - p.1).powi(2)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Vertices of polygons and rectangles (the four corners). `None` for the other shape types and shapes with too few points
+    pub fn vertices(&self) -> Option<Vec<Point>> {
+        let points = &self.points;
+        match self.shape_type.as_str() {
+            "polygon" if points.len() >= 3 => Some(points.clone()),
+            "rectangle" if points.len() >= 2 => {
+                let (p, q) = (points[0], points[1]);
+                Some(vec![p, (q.0, p.1), q, (p.0, q.1)])
+            }
+            _ => None,
+        }
+    }
+
+    /// Intersection over union of polygons and rectangles. `0.0` for the other shape types and shapes without overlap
+    ///
+    /// Pairs of rectangles are computed from the bounding boxes.
+    /// The others are clipped by Sutherland-Hodgman algorithm, which is exact if either of the polygons is convex
+    ///
+    /// ```
+    /// let rect = |points| labelme_rs::Shape {
+    ///     points,
+    ///     shape_type: "rectangle".into(),
+    ///     ..Default::default()
+    /// };
+    /// let a = rect(vec![(0.0, 0.0), (2.0, 2.0)]);
+    /// let b = rect(vec![(1.0, 0.0), (3.0, 2.0)]);
+    /// assert!((a.iou(&b) - 1.0 / 3.0).abs() < 1e-9);
+    /// ```
+    pub fn iou(&self, other: &Shape) -> f64 {
+        let (Some(a), Some(b)) = (self.vertices(), other.vertices()) else {
+            return 0.0;
+        };
+        let (area_a, area_b) = (signed_area(&a).abs(), signed_area(&b).abs());
+        let intersection = if self.shape_type == "rectangle" && other.shape_type == "rectangle" {
+            let (Some(((ax0, ay0), (ax1, ay1))), Some(((bx0, by0), (bx1, by1)))) =
+                (self.bounding_box(), other.bounding_box())
+            else {
+                return 0.0;
+            };
+            (ax1.min(bx1) - ax0.max(bx0)).max(0.0) * (ay1.min(by1) - ay0.max(by0)).max(0.0)
+        } else if is_convex(&b) {
+            signed_area(&clip_polygon(&a, &b)).abs()
+        } else {
+            signed_area(&clip_polygon(&b, &a)).abs()
+        };
+        let union = area_a + area_b - intersection;
+        if union > 0.0 {
+            intersection / union
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Signed area of the polygon. Positive if the vertices are counterclockwise in the y-up coordinates
+fn signed_area(points: &[Point]) -> f64 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(p, q)| p.0 * q.1 - q.0 * p.1)
+        .sum::<f64>()
+        / 2.0
+}
+
+/// z component of the cross product of `b - a` and `c - a`
+fn cross(a: Point, b: Point, c: Point) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn is_convex(points: &[Point]) -> bool {
+    let n = points.len();
+    let crosses: Vec<f64> = (0..n)
+        .map(|i| cross(points[i], points[(i + 1) % n], points[(i + 2) % n]))
+        .filter(|c| c.abs() > f64::EPSILON)
+        .collect();
+    crosses.iter().all(|c| *c > 0.0) || crosses.iter().all(|c| *c < 0.0)
+}
+
+/// Clip `subject` by the polygon `clip` (Sutherland-Hodgman algorithm)
+fn clip_polygon(subject: &[Point], clip: &[Point]) -> Vec<Point> {
+    let orientation = signed_area(clip).signum();
+    let mut output = subject.to_vec();
+    for (&e0, &e1) in clip.iter().zip(clip.iter().cycle().skip(1)) {
+        if output.is_empty() {
+            break;
+        }
+        let inside = |p: Point| cross(e0, e1, p) * orientation >= 0.0;
+        let intersect = |p: Point, q: Point| {
+            let (cp, cq) = (cross(e0, e1, p), cross(e0, e1, q));
+            let t = cp / (cp - cq);
+            (p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t)
+        };
+        let input = std::mem::take(&mut output);
+        for (&p, &q) in input.iter().zip(input.iter().cycle().skip(1)) {
+            match (inside(p), inside(q)) {
+                (true, true) => output.push(q),
+                (true, false) => output.push(intersect(p, q)),
+                (false, true) => {
+                    output.push(intersect(p, q));
+                    output.push(q);
+                }
+                (false, false) => {}
+            }
+        }
+    }
+    output
+}
+
+/// Fill pixels of the row whose centers are in `[x0, x1)`
+fn fill_span(mask: &mut image::GrayImage, row: u32, x0: f64, x1: f64, value: u8) {
+    let width = mask.width() as f64;
+    let start = (x0 - 0.5).ceil().clamp(0.0, width) as u32;
+    let end = (x1 - 0.5).ceil().clamp(0.0, width) as u32;
+    for x in start..end {
+        mask.put_pixel(x, row, image::Luma([value]));
+    }
+}
+
+/// Rows of the mask whose pixel centers may be in `[y0, y1]`
+fn mask_rows(mask: &image::GrayImage, y0: f64, y1: f64) -> std::ops::Range<u32> {
+    let height = mask.height() as f64;
+    (y0 - 0.5).floor().clamp(0.0, height) as u32..(y1 + 0.5).ceil().clamp(0.0, height) as u32
+}
+
+/// Fill pixels whose centers are inside the polygon (scanline with the even-odd rule)
+fn fill_polygon(mask: &mut image::GrayImage, vertices: &[Point], value: u8) {
+    let (y0, y1) = vertices
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(y0, y1), p| {
+            (y0.min(p.1), y1.max(p.1))
+        });
+    let mut xs = vec![];
+    for row in mask_rows(mask, y0, y1) {
+        let yc = row as f64 + 0.5;
+        xs.clear();
+        for (p, q) in vertices.iter().zip(vertices.iter().cycle().skip(1)) {
+            if (p.1 <= yc) != (q.1 <= yc) {
+                xs.push(p.0 + (yc - p.1) * (q.0 - p.0) / (q.1 - p.1));
+            }
+        }
+        xs.sort_by(f64::total_cmp);
+        for span in xs.chunks_exact(2) {
+            fill_span(mask, row, span[0], span[1], value);
+        }
+    }
+}
+
+/// Fill pixels whose centers are inside the circle
+fn fill_circle(mask: &mut image::GrayImage, center: Point, radius: f64, value: u8) {
+    for row in mask_rows(mask, center.1 - radius, center.1 + radius) {
+        let dy = row as f64 + 0.5 - center.1;
+        if dy.abs() < radius {
+            let half = (radius * radius - dy * dy).sqrt();
+            fill_span(mask, row, center.0 - half, center.0 + half, value);
+        }
+    }
+}
+
+/// Label of labelme for the background, filled with 0 by [`LabelMeData::to_mask`] (e.g. holes in polygons)
+pub const BACKGROUND_LABEL: &str = "_background_";
+
+/// Conversion between conventions of the pixel coordinate origin.
+///
+/// In the corner-based convention (e.g. labelme), (0, 0) is the top-left corner of the first pixel and its center is (0.5, 0.5).
+/// In the center-based convention, (0, 0) is the center of the first pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginConvention {
+    /// Corner-based to center-based. i.e. shift by -0.5
+    CornerToCenter,
+    /// Center-based to corner-based. i.e. shift by +0.5
+    CenterToCorner,
+}
+
+impl OriginConvention {
+    /// Offset added to coordinates
+    pub fn offset(&self) -> f64 {
+        match self {
+            OriginConvention::CornerToCenter => -0.5,
+            OriginConvention::CenterToCorner => 0.5,
+        }
+    }
+
+    /// Conversion in the opposite direction
+    pub fn inverse(&self) -> Self {
+        match self {
+            OriginConvention::CornerToCenter => OriginConvention::CenterToCorner,
+            OriginConvention::CenterToCorner => OriginConvention::CornerToCenter,
+        }
+    }
+}
+
+/// Mirroring of the image. See [`LabelMeData::flip`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+    /// Left and right, i.e. `x' = imageWidth - x`
+    Horizontal,
+    /// Upside down, i.e. `y' = imageHeight - y`
+    Vertical,
+}
+
+/// Deserialize `null` as the default value
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[allow(non_snake_case)]
 pub struct LabelMeData {
     pub version: String,
+    /// Missing or `null` flags are read as empty
+    #[serde(default, deserialize_with = "null_as_default")]
     pub flags: Flags,
+    /// Missing or `null` shapes are read as empty
+    #[serde(default, deserialize_with = "null_as_default")]
     pub shapes: Vec<Shape>,
     pub imagePath: String,
     pub imageData: Option<String>,
@@ -53,6 +418,12 @@ pub enum LabelMeDataError {
     SerdeError(#[from] serde_json::Error),
     #[error("Image Error")]
     ImageError(#[from] ImageError),
+    #[error("Base64 Error")]
+    Base64Error(#[from] base64::DecodeError),
+    #[error("Duplicate flag key: {0}")]
+    DuplicateFlag(String),
+    #[error("Invalid UTF-8 at byte {0}. Specify the encoding (e.g. shift_jis)")]
+    NonUtf8(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -89,10 +460,10 @@ impl TryFrom<&Path> for LabelMeDataWImage {
 }
 
 impl TryFrom<LabelMeData> for LabelMeDataWImage {
-    type Error = ImageError;
+    type Error = LabelMeDataError;
 
     fn try_from(data: LabelMeData) -> Result<Self, Self::Error> {
-        let image = load_image(Path::new(&data.imagePath))?;
+        let image = data.load_image()?;
         Ok(Self { data, image })
     }
 }
@@ -102,6 +473,25 @@ impl LabelMeDataWImage {
         Self { data, image }
     }
 
+    /// Crop the image and the data to the region. See [`LabelMeData::crop`].
+    /// The region is clipped by the image
+    pub fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.image = self.image.crop_imm(x, y, width, height);
+        let (width, height) = self.image.dimensions();
+        self.data.crop(x, y, width, height);
+    }
+
+    /// Rotate the image and data clockwise by `quarter_turns` x 90 degrees. See [`LabelMeData::rotate`]
+    pub fn rotate90(&mut self, quarter_turns: u32) {
+        self.image = match quarter_turns % 4 {
+            1 => self.image.rotate90(),
+            2 => self.image.rotate180(),
+            3 => self.image.rotate270(),
+            _ => return,
+        };
+        self.data.rotate(quarter_turns as f64 * 90.0);
+    }
+
     /// Resize image and data
     pub fn resize(&mut self, param: &ResizeParam) {
         let scale = param.scale(self.image.width(), self.image.height());
@@ -110,6 +500,35 @@ impl LabelMeDataWImage {
             self.data.scale(scale)
         }
     }
+
+    /// Downscale the image and data until the image encoded in `format` fits in `max_bytes` as base64.
+    /// Returns the total scaling factor
+    pub fn fit_encoded_size(
+        &mut self,
+        max_bytes: usize,
+        format: image::ImageFormat,
+    ) -> Result<f64, LabelMeDataError> {
+        let mut total_scale = 1.0;
+        loop {
+            let encoded_len = base64::encoded_len(encode_image(&self.image, format)?.len(), true)
+                .unwrap_or(usize::MAX);
+            let (width, height) = self.image.dimensions();
+            if encoded_len <= max_bytes || (width <= 1 && height <= 1) {
+                return Ok(total_scale);
+            }
+            // The encoded size is roughly proportional to the number of pixels
+            let ratio = ((max_bytes as f64 / encoded_len as f64).sqrt() * 0.9).min(0.9);
+            let (new_width, new_height) = ResizeParam::Percentage(ratio).size(width, height);
+            // Shrink by at least 1px since the rounding may keep small sides as they are
+            let shrink = |new: u32, old: u32| new.min(old.saturating_sub(1)).max(1);
+            let param = ResizeParam::Size(shrink(new_width, width), shrink(new_height, height));
+            total_scale *= param.scale(width, height);
+            self.resize(&param);
+            if self.image.dimensions() == (width, height) {
+                return Ok(total_scale);
+            }
+        }
+    }
 }
 
 /// LabeleMeData with additional `filename` field for ndjsons
@@ -224,11 +643,74 @@ impl ResizeParam {
     }
 }
 
+lazy_static! {
+    static ref RE_GEOMETRY: Regex = Regex::new(r"^(\d+)x(\d+)\+(\d+)\+(\d+)$").unwrap();
+}
+
+/// Cropping region. Use imagemagick's `-crop`-like geometry `WxH+X+Y` to construct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropParam {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TryFrom<&str> for CropParam {
+    type Error = ResizeParamError;
+
+    /// Parse cropping region
+    /// ```
+    /// use labelme_rs::CropParam;
+    /// assert_eq!(CropParam::try_from("300x400+10+20").unwrap(), CropParam { x: 10, y: 20, width: 300, height: 400 });
+    /// assert!(CropParam::try_from("300x400").is_err());
+    /// ```
+    fn try_from(param: &str) -> Result<Self, Self::Error> {
+        let cap = RE_GEOMETRY
+            .captures(param)
+            .ok_or_else(|| ResizeParamError::FormatError(param.into()))?;
+        let parse = |i: usize| cap.get(i).unwrap().as_str().parse::<u32>();
+        Ok(CropParam {
+            width: parse(1)?,
+            height: parse(2)?,
+            x: parse(3)?,
+            y: parse(4)?,
+        })
+    }
+}
+
+impl CropParam {
+    /// Clip the region by the image of `width` x `height`. `None` if the region is outside the image
+    /// ```
+    /// use labelme_rs::CropParam;
+    /// let param = CropParam::try_from("300x400+10+20").unwrap();
+    /// assert_eq!(param.clip(100, 100), Some(CropParam { x: 10, y: 20, width: 90, height: 80 }));
+    /// assert_eq!(param.clip(10, 100), None);
+    /// ```
+    pub fn clip(&self, width: u32, height: u32) -> Option<Self> {
+        (self.x < width && self.y < height).then(|| Self {
+            width: self.width.min(width - self.x),
+            height: self.height.min(height - self.y),
+            ..*self
+        })
+    }
+}
+
+/// Encode image in the given format
+pub fn encode_image(
+    img: &DynamicImage,
+    format: image::ImageFormat,
+) -> Result<Vec<u8>, LabelMeDataError> {
+    encode_image_with_quality(img, format, None)
+}
+
+/// Encode image in the given format with the JPEG `quality` (1 to 100). `None` uses the default quality of the encoder
 #[cfg(feature = "mozjpeg")]
-pub fn img2base64(
+pub fn encode_image_with_quality(
     img: &DynamicImage,
     format: image::ImageFormat,
-) -> Result<String, LabelMeDataError> {
+    quality: Option<u8>,
+) -> Result<Vec<u8>, LabelMeDataError> {
     if format == image::ImageFormat::Jpeg {
         let result = std::panic::catch_unwind(|| -> std::io::Result<Vec<u8>> {
             let img = std::borrow::Cow::Borrowed(img);
@@ -259,6 +741,9 @@ pub fn img2base64(
                 _ => panic!("Unsupported color type"),
             };
 
+            if let Some(quality) = quality {
+                comp.set_quality(quality as f32);
+            }
             comp.set_size(img.width() as usize, img.height() as usize);
             let mut comp = comp.start_compress(Vec::new())?;
 
@@ -269,30 +754,478 @@ pub fn img2base64(
             Ok(writer)
         });
         match result {
-            Ok(Ok(writer)) => return Ok(base64::engine::general_purpose::STANDARD.encode(writer)),
+            Ok(Ok(writer)) => return Ok(writer),
             Ok(Err(e)) => return Err(e.into()),
             Err(e) => {
-                return Err(LabelMeDataError::IoError(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("{:?}", e),
-                )))
+                return Err(LabelMeDataError::IoError(std::io::Error::other(format!(
+                    "{:?}",
+                    e
+                ))))
             }
         };
     }
     let mut cursor = Cursor::new(Vec::new());
     img.write_to(&mut cursor, format)
         .map_err(|e| LabelMeDataError::from(ImageError::from(e)))?;
-    Ok(base64::engine::general_purpose::STANDARD.encode(cursor.into_inner()))
+    Ok(cursor.into_inner())
 }
 
+/// Encode image in the given format with the JPEG `quality` (1 to 100). `None` uses the default quality of the encoder
 #[cfg(not(feature = "mozjpeg"))]
+pub fn encode_image_with_quality(
+    img: &DynamicImage,
+    format: image::ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, LabelMeDataError> {
+    let mut cursor = Cursor::new(Vec::new());
+    match (format, quality) {
+        (image::ImageFormat::Jpeg, Some(quality)) => {
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut cursor, quality);
+            let img = if img.color().has_alpha() {
+                std::borrow::Cow::Owned(DynamicImage::ImageRgb8(img.to_rgb8()))
+            } else {
+                std::borrow::Cow::Borrowed(img)
+            };
+            img.write_with_encoder(encoder)
+        }
+        _ => img.write_to(&mut cursor, format),
+    }
+    .map_err(|e| LabelMeDataError::from(ImageError::from(e)))?;
+    Ok(cursor.into_inner())
+}
+
 pub fn img2base64(
     img: &DynamicImage,
     format: image::ImageFormat,
 ) -> Result<String, LabelMeDataError> {
-    let mut cursor = Cursor::new(Vec::new());
-    img.write_to(&mut cursor, format)?;
-    Ok(base64::engine::general_purpose::STANDARD.encode(cursor.into_inner()))
+    let bytes = encode_image(img, format)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Decode base64 encoded `imageData` of labelme into the bytes of the image file
+pub fn decode_image_bytes(image_data: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    base64::engine::general_purpose::STANDARD.decode(image_data)
+}
+
+/// Decode base64 encoded `imageData` of labelme
+pub fn decode_image_data(image_data: &str) -> Result<DynamicImage, LabelMeDataError> {
+    let bytes = decode_image_bytes(image_data)?;
+    image::load_from_memory(&bytes).map_err(|e| LabelMeDataError::from(ImageError::from(e)))
+}
+
+/// Encode image as a `data:` URL.
+/// The base64 string is encoded into a pre-sized buffer following the prefix to avoid copying
+pub fn img2data_url(
+    img: &DynamicImage,
+    format: image::ImageFormat,
+) -> Result<String, LabelMeDataError> {
+    img2data_url_with_quality(img, format, None)
+}
+
+/// Same as [`img2data_url`] with the JPEG `quality`. See [`encode_image_with_quality`]
+pub fn img2data_url_with_quality(
+    img: &DynamicImage,
+    format: image::ImageFormat,
+    quality: Option<u8>,
+) -> Result<String, LabelMeDataError> {
+    let bytes = encode_image_with_quality(img, format, quality)?;
+    let prefix = format!("data:{};base64,", format.to_mime_type());
+    let len = base64::encoded_len(bytes.len(), true).unwrap_or_default();
+    let mut url = String::with_capacity(prefix.len() + len);
+    url.push_str(&prefix);
+    base64::engine::general_purpose::STANDARD.encode_string(bytes, &mut url);
+    Ok(url)
+}
+
+/// `data:` URL of base64 encoded image without re-encoding.
+/// `None` if the image is not decodable or not of `dimensions`
+fn image_data_url(image_data: &str, dimensions: (u32, u32)) -> Option<String> {
+    let bytes = decode_image_bytes(image_data).ok()?;
+    let reader = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format()?;
+    (reader.into_dimensions().ok()? == dimensions)
+        .then(|| format!("data:{};base64,{}", format.to_mime_type(), image_data))
+}
+
+/// Shape flag to draw the shape with a dashed line regardless of its label (e.g. to mark uncertain shapes)
+pub const DASHED_FLAG: &str = "dashed";
+
+/// Shape flag to skip the shape in SVG
+pub const HIDDEN_FLAG: &str = "hidden";
+
+/// Add `nodes` to the nested `<g data-group-id="{group_id}" class="group-{group_id}">` of `group`,
+/// which is created at the first shape of the group so that whole groups can be toggled by CSS or JS
+fn add_to_shape_group(
+    mut group: element::Group,
+    nodes: Vec<element::Element>,
+    group_id: &str,
+) -> element::Group {
+    let children = element::Element::get_children_mut(&mut group);
+    let nested = children.iter_mut().find(|child| {
+        child.get_name() == "g"
+            && child
+                .get_attributes()
+                .and_then(|attributes| attributes.get("data-group-id"))
+                .is_some_and(|id| **id == *group_id)
+    });
+    match nested.and_then(|nested| nested.get_children_mut()) {
+        Some(nested_children) => nested_children.extend(
+            nodes
+                .into_iter()
+                .map(|node| Box::new(node) as Box<dyn Node>),
+        ),
+        None => {
+            let nested = nodes.into_iter().fold(
+                element::Group::new()
+                    .set("class", format!("group-{group_id}"))
+                    .set("data-group-id", group_id),
+                |nested, node| nested.add(node),
+            );
+            children.push(Box::new(nested));
+        }
+    }
+    group
+}
+
+/// Ratio of the default font size of label texts (and text shapes) to the point radius of the label
+pub const LABEL_FONT_SCALE: usize = 6;
+
+/// Class of the groups of label texts drawn by [`SvgOptions::show_labels`].
+/// Distinct from the `text` class of text shapes so that they can be toggled separately
+pub const LABEL_TEXT_CLASS: &str = "label-text";
+
+/// Marker glyph of point shapes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PointMarker {
+    /// Filled circle of the point radius
+    #[default]
+    Circle,
+    /// Two lines crossing at the point
+    Cross,
+    /// Hollow square with the side of twice the point radius
+    Square,
+}
+
+/// Options for [`LabelMeData::to_svg_with_options`]
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// Set `group_id` of shapes to `data-group` attribute of the elements
+    pub group_ids: bool,
+    /// Draw a black or white halo (`class="halo"`) behind each shape, whichever contrasts with the image under the shape
+    pub auto_contrast: bool,
+    /// Draw the label of each shape (except text shapes) as text in the color of the shape with a white halo, in groups with `class="label-text <label>"`
+    pub show_labels: bool,
+    /// Font size of the labels and text shapes. `None` uses the point radius of each label times [`LABEL_FONT_SCALE`]
+    pub font_size: Option<usize>,
+    /// Marker of point shapes
+    pub point_marker: PointMarker,
+    /// Format to encode the embedded image in (e.g. [`image::ImageFormat::Png`] for lossless backgrounds)
+    pub image_format: image::ImageFormat,
+    /// Quality (1 to 100) of the embedded JPEG image. `None` uses the default quality of the encoder
+    pub jpeg_quality: Option<u8>,
+    /// Embed the image as base64. Otherwise, `xlink:href` refers to `imagePath` as is to keep the SVG small
+    pub embed_image: bool,
+    /// Per-label point radius and line width overriding the global ones
+    pub label_styles: LabelStyles,
+    /// Fill polygons with their label colors at the opacity. `None` or `0.0` draws only the outlines
+    pub polygon_fill_opacity: Option<f64>,
+    /// Draw only the shapes of the shape types. All shapes are drawn if empty
+    pub shape_types: Vec<String>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            group_ids: false,
+            auto_contrast: false,
+            show_labels: false,
+            font_size: None,
+            point_marker: PointMarker::default(),
+            image_format: image::ImageFormat::Jpeg,
+            jpeg_quality: None,
+            embed_image: true,
+            label_styles: LabelStyles::new(),
+            polygon_fill_opacity: None,
+            shape_types: vec![],
+        }
+    }
+}
+
+/// Centroid of the polygon by the signed area, or the mean of the points if the area is zero
+fn polygon_centroid(points: &[Point]) -> Option<Point> {
+    if points.is_empty() {
+        return None;
+    }
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for (p, q) in points.iter().zip(points.iter().cycle().skip(1)) {
+        let cross = p.0 * q.1 - q.0 * p.1;
+        area += cross;
+        cx += (p.0 + q.0) * cross;
+        cy += (p.1 + q.1) * cross;
+    }
+    if area.abs() < f64::EPSILON {
+        let n = points.len() as f64;
+        let (sx, sy) = points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), p| (sx + p.0, sy + p.1));
+        return Some((sx / n, sy / n));
+    }
+    Some((cx / (3.0 * area), cy / (3.0 * area)))
+}
+
+/// Style of the shapes of a label in SVG
+struct SvgShapeStyle<'a> {
+    color: &'a str,
+    point_radius: usize,
+    line_width: usize,
+    point_marker: PointMarker,
+    /// `url(#...)` of the vertex marker of polygons
+    vertex_marker: &'a str,
+}
+
+/// Function creating the SVG elements of a shape. Malformed shapes have no element
+type SvgRenderFn = fn(&Shape, &SvgShapeStyle) -> Vec<element::Element>;
+
+/// Shape types drawn in SVG in the drawing order and the functions creating their elements.
+/// Shapes of the other types are not drawn
+const SVG_RENDERERS: [(&str, SvgRenderFn); 8] = [
+    ("point", point_elements),
+    ("rectangle", box_elements),
+    // mask data is not drawn. Only the bounding box of the mask is drawn with a dashed line
+    ("mask", box_elements),
+    ("line", line_elements),
+    ("linestrip", linestrip_elements),
+    ("polygon", polygon_elements),
+    ("circle", circle_elements),
+    ("text", text_elements),
+];
+
+/// `<g>` of the shapes of the shape type and the label
+fn svg_shape_group(
+    shape_type: &str,
+    label: &str,
+    style: &SvgShapeStyle,
+    options: &SvgOptions,
+) -> element::Group {
+    let (color, line_width) = (style.color, style.line_width);
+    let group = element::Group::new().set("class", format!("{} {}", shape_type, label));
+    match shape_type {
+        "point" => group.set("fill", color).set("stroke", "none"),
+        "text" => group.set("fill", color).set("stroke", "none").set(
+            "font-size",
+            options
+                .font_size
+                .unwrap_or(style.point_radius * LABEL_FONT_SCALE),
+        ),
+        "circle" => group.set("stroke-width", line_width),
+        "polygon" => match options.polygon_fill_opacity.filter(|o| *o > 0.0) {
+            Some(opacity) => group.set("fill", color).set("fill-opacity", opacity),
+            None => group.set("fill", "none"),
+        }
+        .set("stroke", color)
+        .set("stroke-width", line_width),
+        "mask" => group
+            .set("fill", "none")
+            .set("stroke", color)
+            .set("stroke-width", line_width)
+            .set(
+                "stroke-dasharray",
+                format!("{} {}", line_width, line_width * 2),
+            ),
+        _ => group
+            .set("fill", "none")
+            .set("stroke", color)
+            .set("stroke-width", line_width),
+    }
+}
+
+fn point_elements(shape: &Shape, style: &SvgShapeStyle) -> Vec<element::Element> {
+    let (x, y) = shape.points[0];
+    let r = style.point_radius as f64;
+    match style.point_marker {
+        PointMarker::Circle => vec![element::Circle::new()
+            .set("cx", x)
+            .set("cy", y)
+            .set("r", style.point_radius)
+            .into()],
+        PointMarker::Cross => [(r, 0.0), (0.0, r)]
+            .into_iter()
+            .map(|(dx, dy)| {
+                element::Line::new()
+                    .set("x1", x - dx)
+                    .set("y1", y - dy)
+                    .set("x2", x + dx)
+                    .set("y2", y + dy)
+                    .set("stroke", style.color)
+                    .set("stroke-width", style.line_width)
+                    .into()
+            })
+            .collect(),
+        PointMarker::Square => vec![element::Rectangle::new()
+            .set("x", x - r)
+            .set("y", y - r)
+            .set("width", 2 * style.point_radius)
+            .set("height", 2 * style.point_radius)
+            .set("fill", "none")
+            .set("stroke", style.color)
+            .set("stroke-width", style.line_width)
+            .into()],
+    }
+}
+
+/// Content of the text shape at its point. See [`Shape::text_content`]
+fn text_elements(shape: &Shape, _style: &SvgShapeStyle) -> Vec<element::Element> {
+    let Some((x, y)) = shape.points.first() else {
+        return vec![];
+    };
+    vec![element::Text::new(shape.text_content())
+        .set("x", *x)
+        .set("y", *y)
+        .into()]
+}
+
+/// Rectangle spanned by the two points of rectangles and masks
+fn box_elements(shape: &Shape, _style: &SvgShapeStyle) -> Vec<element::Element> {
+    let points = &shape.points;
+    if points.len() != 2 {
+        return vec![];
+    }
+    vec![element::Rectangle::new()
+        .set("x", points[0].0.min(points[1].0))
+        .set("y", points[0].1.min(points[1].1))
+        .set("width", (points[1].0 - points[0].0).abs())
+        .set("height", (points[1].1 - points[0].1).abs())
+        .into()]
+}
+
+fn line_elements(shape: &Shape, _style: &SvgShapeStyle) -> Vec<element::Element> {
+    let line = &shape.points;
+    vec![element::Line::new()
+        .set("x1", line[0].0)
+        .set("y1", line[0].1)
+        .set("x2", line[1].0)
+        .set("y2", line[1].1)
+        .into()]
+}
+
+fn linestrip_elements(shape: &Shape, _style: &SvgShapeStyle) -> Vec<element::Element> {
+    let points = shape
+        .points
+        .iter()
+        .map(|p| format!("{} {}", p.0, p.1))
+        .collect::<Vec<_>>()
+        .join(" ");
+    vec![element::Polyline::new().set("points", points).into()]
+}
+
+fn polygon_elements(shape: &Shape, style: &SvgShapeStyle) -> Vec<element::Element> {
+    let points = shape
+        .points
+        .iter()
+        .map(|(a, b)| format!("{} {}", a, b))
+        .collect::<Vec<String>>()
+        .join(" ");
+    vec![element::Polygon::new()
+        .set("points", points)
+        .set("marker-start", style.vertex_marker)
+        .set("marker-mid", style.vertex_marker)
+        .into()]
+}
+
+/// Center of the point radius and the outline through the second point
+fn circle_elements(shape: &Shape, style: &SvgShapeStyle) -> Vec<element::Element> {
+    let circle = &shape.points;
+    if circle.len() != 2 {
+        return vec![];
+    }
+    let (p1, p2) = (circle[0], circle[1]);
+    let radius = ((p1.0 - p2.0).powi(2) + (p1.1 - p2.1).powi(2)).sqrt();
+    vec![
+        element::Circle::new()
+            .set("cx", p1.0)
+            .set("cy", p1.1)
+            .set("r", style.point_radius)
+            .set("fill", style.color)
+            .set("stroke", "none")
+            .into(),
+        element::Circle::new()
+            .set("cx", p1.0)
+            .set("cy", p1.1)
+            .set("r", radius)
+            .set("fill", "none")
+            .set("stroke", style.color)
+            .into(),
+    ]
+}
+
+/// Position of the label text of the shape
+///
+/// Points are offset by `point_radius + 2`, rectangles and masks are anchored at the top-left corner,
+/// and the others at the first vertex
+fn label_anchor(shape: &Shape, point_radius: usize) -> Option<Point> {
+    let first = *shape.points.first()?;
+    match shape.shape_type.as_str() {
+        "point" => {
+            let offset = (point_radius + 2) as f64;
+            Some((first.0 + offset, first.1 - offset))
+        }
+        "rectangle" | "mask" => shape.bounding_box().map(|(top_left, _)| top_left),
+        _ => Some(first),
+    }
+}
+
+/// The number of samples along each axis in [`region_mean_luma`]
+const REGION_SAMPLES: u32 = 16;
+
+/// Relative luminance of sRGB color (0: black, 1: white)
+fn relative_luminance(rgb: [u8; 3]) -> f64 {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+}
+
+/// Mean relative luminance (0: black, 1: white) of the image in `bbox` as (x0, y0, x1, y1).
+///
+/// The bbox is clipped by the image and at most 16x16 pixels on a grid are sampled
+pub fn region_mean_luma(img: &DynamicImage, bbox: (f64, f64, f64, f64)) -> f64 {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let range = |lo: f64, hi: f64, size: u32| {
+        let lo = (lo.floor().max(0.0) as u32).min(size - 1);
+        let hi = (hi.ceil().max(0.0) as u32).clamp(lo + 1, size);
+        let n = (hi - lo).min(REGION_SAMPLES);
+        (0..n).map(move |i| lo + i * (hi - lo) / n)
+    };
+    let mut sum = 0.0;
+    let mut count = 0;
+    for y in range(bbox.1, bbox.3, height) {
+        for x in range(bbox.0, bbox.2, width) {
+            let p = img.get_pixel(x, y);
+            sum += relative_luminance([p[0], p[1], p[2]]);
+            count += 1;
+        }
+    }
+    sum / count as f64
+}
+
+/// Black for light backgrounds and white for dark ones.
+/// The threshold is where the contrast ratios against black and white are equal
+pub fn contrasting_color(luma: f64) -> &'static str {
+    if luma > 0.179 {
+        "#000000"
+    } else {
+        "#FFFFFF"
+    }
 }
 
 impl LabelMeData {
@@ -312,6 +1245,7 @@ impl LabelMeData {
                 group_id: None,
                 shape_type: "point".into(),
                 flags: Flags::new(),
+                description: None,
             })
             .collect();
         Self {
@@ -325,6 +1259,26 @@ impl LabelMeData {
         }
     }
 
+    /// Check if there is no shape
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// Points outside `[0, imageWidth]` x `[0, imageHeight]` with the labels of the shapes
+    ///
+    /// ```
+    /// let data = labelme_rs::LabelMeData::new(&[(1.0, 1.0), (-0.5, 2.0)], &["L1".into(), "L2".into()], 128, 128, "image.jpg");
+    /// assert_eq!(data.points_out_of_bounds(), vec![("L2", (-0.5, 2.0))]);
+    /// ```
+    pub fn points_out_of_bounds(&self) -> Vec<(&str, Point)> {
+        let (w, h) = (self.imageWidth as f64, self.imageHeight as f64);
+        self.shapes
+            .iter()
+            .flat_map(|shape| shape.points.iter().map(move |p| (shape.label.as_str(), *p)))
+            .filter(|(_, p)| !(0.0..=w).contains(&p.0) || !(0.0..=h).contains(&p.1))
+            .collect()
+    }
+
     /// Convert to a shape_type-centered map with a structure map\[`shape_type`\]\[label\] -> points
     pub fn to_shape_map(&self) -> IndexMap<&str, IndexMap<&str, Vec<&Vec<Point>>>> {
         let mut map = IndexMap::new();
@@ -338,25 +1292,288 @@ impl LabelMeData {
         map
     }
 
-    /// Scale points, imageWidth and imageHeight
-    pub fn scale(&mut self, scale: f64) {
+    /// Shapes of the shape type as (label, shape) in the order of the shapes
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["TL".into()], 8, 8, "");
+    /// data.shapes.push(labelme_rs::Shape::rectangle((0.0, 0.0), (2.0, 2.0), "car"));
+    /// let labels: Vec<_> = data.iter_shapes_by_type("rectangle").map(|(label, _)| label).collect();
+    /// assert_eq!(labels, vec!["car"]);
+    /// ```
+    pub fn iter_shapes_by_type<'a>(
+        &'a self,
+        shape_type: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a Shape)> {
+        self.shapes
+            .iter()
+            .filter(move |shape| shape.shape_type == shape_type)
+            .map(|shape| (shape.label.as_str(), shape))
+    }
+
+    /// Map all points of the shapes by `transform`
+    fn map_points(&mut self, transform: impl Fn(Point) -> Point) {
         for shape in &mut self.shapes {
             for p in &mut shape.points {
-                p.0 *= scale;
-                p.1 *= scale;
+                *p = transform(*p);
             }
         }
-        self.imageWidth = (self.imageWidth as f64 * scale) as _;
+    }
+
+    /// Scale points, imageWidth and imageHeight
+    pub fn scale(&mut self, scale: f64) {
+        self.map_points(|(x, y)| (x * scale, y * scale));
+        self.imageWidth = (self.imageWidth as f64 * scale) as _;
         self.imageHeight = (self.imageHeight as f64 * scale) as _;
     }
 
     /// Shift points.
     /// Does not change imageWidth and imageHeight
     pub fn shift(&mut self, tx: f64, ty: f64) {
+        self.map_points(|(x, y)| (x + tx, y + ty));
+    }
+
+    /// Rotate points clockwise by `degrees`.
+    ///
+    /// Multiples of 90 degrees rotate the whole image, i.e. points are offset to stay within the rotated image
+    /// and imageWidth and imageHeight are swapped for 90 and 270 degrees.
+    /// Other angles rotate points about the center of the image without changing imageWidth and imageHeight
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(&[(10.0, 20.0)], &["L1".into()], 100, 50, "image.jpg");
+    /// data.rotate(90.0);
+    /// assert_eq!(data.shapes[0].points[0], (30.0, 10.0));
+    /// assert_eq!((data.imageWidth, data.imageHeight), (50, 100));
+    /// ```
+    pub fn rotate(&mut self, degrees: f64) {
+        let (w, h) = (self.imageWidth as f64, self.imageHeight as f64);
+        let degrees = degrees.rem_euclid(360.0);
+        let transform: Box<dyn Fn(Point) -> Point> = match degrees {
+            0.0 => return,
+            90.0 => Box::new(|(x, y)| (h - y, x)),
+            180.0 => Box::new(|(x, y)| (w - x, h - y)),
+            270.0 => Box::new(|(x, y)| (y, w - x)),
+            _ => {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let (cx, cy) = (w / 2.0, h / 2.0);
+                Box::new(move |(x, y)| {
+                    let (dx, dy) = (x - cx, y - cy);
+                    (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+                })
+            }
+        };
+        self.map_points(transform);
+        if degrees == 90.0 || degrees == 270.0 {
+            std::mem::swap(&mut self.imageWidth, &mut self.imageHeight);
+        }
+    }
+
+    /// Mirror points within the image. imageWidth and imageHeight are kept
+    ///
+    /// ```
+    /// use labelme_rs::Flip;
+    /// let mut data = labelme_rs::LabelMeData::new(&[(10.0, 20.0)], &["L1".into()], 100, 50, "image.jpg");
+    /// data.flip(Flip::Horizontal);
+    /// assert_eq!(data.shapes[0].points[0], (90.0, 20.0));
+    /// data.flip(Flip::Vertical);
+    /// assert_eq!(data.shapes[0].points[0], (90.0, 30.0));
+    /// ```
+    pub fn flip(&mut self, flip: Flip) {
+        let (w, h) = (self.imageWidth as f64, self.imageHeight as f64);
+        match flip {
+            Flip::Horizontal => self.map_points(|(x, y)| (w - x, y)),
+            Flip::Vertical => self.map_points(|(x, y)| (x, h - y)),
+        }
+    }
+
+    /// Crop to the region at (`x`, `y`) of `width` x `height`.
+    ///
+    /// Points are shifted by (-x, -y) and imageWidth and imageHeight are set to the size of the region.
+    /// Points of rectangles, polygons, lines, and linestrips are clamped to the region,
+    /// while points and circles with the centers outside the region and the other shapes with the bounding boxes outside the region are removed.
+    /// Shapes degenerated by the clamping (e.g. zero area rectangles and polygons with less than 3 distinct points) are removed
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(&[(10.0, 10.0), (50.0, 50.0)], &["L1".into(), "L2".into()], 64, 64, "image.jpg");
+    /// data.crop(5, 5, 20, 20);
+    /// assert_eq!(data.shapes.len(), 1);
+    /// assert_eq!(data.shapes[0].points[0], (5.0, 5.0));
+    /// assert_eq!((data.imageWidth, data.imageHeight), (20, 20));
+    /// ```
+    pub fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.shift(-(x as f64), -(y as f64));
+        let (w, h) = (width as f64, height as f64);
+        let inside = |p: &Point| (0.0..=w).contains(&p.0) && (0.0..=h).contains(&p.1);
+        let distinct = |points: &[Point]| {
+            let mut distinct: Vec<Point> = vec![];
+            for p in points {
+                if !distinct.contains(p) {
+                    distinct.push(*p);
+                }
+            }
+            distinct.len()
+        };
+        let overlaps = |shape: &Shape| {
+            shape
+                .bounding_box()
+                .is_some_and(|((x0, y0), (x1, y1))| x1 >= 0.0 && y1 >= 0.0 && x0 <= w && y0 <= h)
+        };
+        self.shapes
+            .retain_mut(|shape| match shape.shape_type.as_str() {
+                "point" | "circle" | "text" => shape.points.first().is_some_and(inside),
+                _ if !overlaps(shape) => false,
+                shape_type => {
+                    for p in &mut shape.points {
+                        *p = (p.0.clamp(0.0, w), p.1.clamp(0.0, h));
+                    }
+                    match shape_type {
+                        "rectangle" => shape.area().is_some_and(|area| area > 0.0),
+                        "polygon" => {
+                            distinct(&shape.points) >= 3
+                                && shape.area().is_some_and(|area| area > 0.0)
+                        }
+                        "line" | "linestrip" => distinct(&shape.points) >= 2,
+                        _ => true,
+                    }
+                }
+            });
+        self.imageWidth = width as usize;
+        self.imageHeight = height as usize;
+    }
+
+    /// Remove consecutive vertices of polygons, linestrips, and lines within `tolerance` of each other (e.g. from double-clicks).
+    /// Explicitly closed shapes stay closed. See [`repair::dedupe_near_vertices`].
+    /// Returns the number of removed vertices
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData {
+    ///     shapes: vec![labelme_rs::Shape::polygon(
+    ///         vec![(0.0, 0.0), (4.0, 0.0), (4.0, 0.0), (4.0, 3.0)],
+    ///         "L",
+    ///     )],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(data.dedupe_consecutive_points(0.0), 1);
+    /// assert_eq!(data.shapes[0].points, vec![(0.0, 0.0), (4.0, 0.0), (4.0, 3.0)]);
+    /// ```
+    pub fn dedupe_consecutive_points(&mut self, tolerance: f64) -> usize {
+        self.shapes
+            .iter_mut()
+            .map(|shape| repair::dedupe_near_vertices(shape, tolerance))
+            .sum()
+    }
+
+    /// Remove shapes whose `is_duplicate` holds with an earlier surviving shape of the same label.
+    /// Surviving shapes keep their order. Returns the removed shapes
+    fn dedup_shapes_by(&mut self, is_duplicate: impl Fn(&Shape, &Shape) -> bool) -> Vec<Shape> {
+        let mut kept: Vec<Shape> = vec![];
+        let mut removed = vec![];
+        for shape in std::mem::take(&mut self.shapes) {
+            if kept
+                .iter()
+                .any(|k| k.label == shape.label && is_duplicate(k, &shape))
+            {
+                removed.push(shape);
+            } else {
+                kept.push(shape);
+            }
+        }
+        self.shapes = kept;
+        removed
+    }
+
+    /// Remove shapes with the same label, shape type, and number of points as an earlier shape
+    /// and every coordinate within `tolerance` of it (e.g. after merging annotations of two annotators).
+    /// The first occurrences are kept in place. Returns the removed shapes
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(
+    ///     &[(1.0, 1.0), (5.0, 5.0), (1.2, 0.9), (1.2, 0.9)],
+    ///     &["L1".into(), "L1".into(), "L1".into(), "L2".into()],
+    ///     8,
+    ///     8,
+    ///     "",
+    /// );
+    /// let removed = data.dedup_shapes(0.5);
+    /// assert_eq!(removed[0].points, vec![(1.2, 0.9)]);
+    /// let labels: Vec<_> = data.shapes.iter().map(|s| s.label.as_str()).collect();
+    /// assert_eq!(labels, vec!["L1", "L1", "L2"]);
+    /// ```
+    pub fn dedup_shapes(&mut self, tolerance: f64) -> Vec<Shape> {
+        self.dedup_shapes_by(|a, b| {
+            a.shape_type == b.shape_type
+                && a.points.len() == b.points.len()
+                && a.points
+                    .iter()
+                    .zip(&b.points)
+                    .all(|(p, q)| (p.0 - q.0).abs() <= tolerance && (p.1 - q.1).abs() <= tolerance)
+        })
+    }
+
+    /// Remove rectangles and polygons whose IoU with an earlier surviving rectangle or polygon of the same label exceeds `threshold`.
+    /// See [`Shape::iou`]. Returns the removed shapes
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData {
+    ///     shapes: vec![
+    ///         labelme_rs::Shape::rectangle((0.0, 0.0), (10.0, 10.0), "car"),
+    ///         labelme_rs::Shape::rectangle((1.0, 0.0), (11.0, 10.0), "car"),
+    ///         labelme_rs::Shape::rectangle((5.0, 0.0), (15.0, 10.0), "car"),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(data.dedup_overlapping_shapes(0.5).len(), 1);
+    /// assert_eq!(data.shapes[1].points, vec![(5.0, 0.0), (15.0, 10.0)]);
+    /// ```
+    pub fn dedup_overlapping_shapes(&mut self, threshold: f64) -> Vec<Shape> {
+        self.dedup_shapes_by(|a, b| a.iou(b) > threshold)
+    }
+
+    /// Shift points between the corner-based and center-based pixel coordinate conventions
+    pub fn shift_origin(&mut self, by: OriginConvention) {
+        let offset = by.offset();
+        self.shift(offset, offset);
+    }
+
+    /// Data with the points shifted by `by`, or the data as is if `None`.
+    /// Converters use it with the origin conventions fixed by their formats (e.g. [`voc::ORIGIN_SHIFT`])
+    ///
+    /// ```
+    /// use labelme_rs::{LabelMeData, OriginConvention};
+    /// let data = LabelMeData::new(&[(1.0, 2.0)], &["L1".into()], 8, 8, "");
+    /// assert_eq!(data.with_origin_shift(None).shapes[0].points[0], (1.0, 2.0));
+    /// let shifted = data.with_origin_shift(Some(OriginConvention::CornerToCenter));
+    /// assert_eq!(shifted.shapes[0].points[0], (0.5, 1.5));
+    /// ```
+    pub fn with_origin_shift(&self, by: Option<OriginConvention>) -> Cow<'_, Self> {
+        match by {
+            Some(by) => {
+                let mut data = self.clone();
+                data.shift_origin(by);
+                Cow::Owned(data)
+            }
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Divide points by imageWidth and imageHeight so that the image spans 0..1.
+    /// Does not change imageWidth and imageHeight, which are required by [`LabelMeData::denormalize`]
+    pub fn normalize(&mut self) {
+        let (width, height) = (self.imageWidth as f64, self.imageHeight as f64);
         for shape in &mut self.shapes {
             for p in &mut shape.points {
-                p.0 += tx;
-                p.1 += ty;
+                p.0 /= width;
+                p.1 /= height;
+            }
+        }
+    }
+
+    /// Inverse of [`LabelMeData::normalize`]
+    pub fn denormalize(&mut self) {
+        let (width, height) = (self.imageWidth as f64, self.imageHeight as f64);
+        for shape in &mut self.shapes {
+            for p in &mut shape.points {
+                p.0 *= width;
+                p.1 *= height;
             }
         }
     }
@@ -375,6 +1592,20 @@ impl LabelMeData {
         Ok(data)
     }
 
+    /// Load the image of `imagePath`, or decode `imageData` if `imagePath` is empty or not a file
+    pub fn load_image(&self) -> Result<DynamicImage, LabelMeDataError> {
+        let path = Path::new(&self.imagePath);
+        match &self.imageData {
+            Some(image_data) if !path.is_file() => decode_image_data(image_data),
+            _ => Ok(load_image(path)?),
+        }
+    }
+
+    /// Check if the image is available as the file of `imagePath` or `imageData`
+    pub fn has_image(&self) -> bool {
+        Path::new(&self.imagePath).is_file() || self.imageData.is_some()
+    }
+
     /// Update `imagePath` to absolute path if it is relative
     pub fn to_absolute_path(mut self, canonical_json_dir: &Path) -> Self {
         self.imagePath = self.imagePath.replace('\\', "/");
@@ -389,6 +1620,122 @@ impl LabelMeData {
         self
     }
 
+    /// Bounding box of all shapes as (min x, min y, max x, max y). Circles include their radii.
+    /// `None` if there is no point
+    ///
+    /// ```
+    /// let data = labelme_rs::LabelMeData::new(&[(1.0, 5.0), (3.0, 2.0)], &["L1".into(), "L2".into()], 128, 128, "image.jpg");
+    /// assert_eq!(data.bounding_box(), Some((1.0, 2.0, 3.0, 5.0)));
+    /// ```
+    pub fn bounding_box(&self) -> Option<(f64, f64, f64, f64)> {
+        self.shapes
+            .iter()
+            .filter_map(Shape::bounding_box)
+            .reduce(union_box)
+            .map(|((x0, y0), (x1, y1))| (x0, y0, x1, y1))
+    }
+
+    /// Rasterize polygons, rectangles, and circles into a mask of `imageWidth` x `imageHeight` with the values of their labels.
+    ///
+    /// Pixels whose centers are inside the shapes are filled (scanline with the even-odd rule), and later shapes overwrite earlier ones.
+    /// Shapes of [`BACKGROUND_LABEL`] are filled with 0 unless the label has a value, e.g. to make holes.
+    /// Shapes of the other labels without values are skipped
+    ///
+    /// ```
+    /// let data = labelme_rs::LabelMeData {
+    ///     shapes: vec![labelme_rs::Shape::rectangle((1.0, 1.0), (5.0, 4.0), "car")],
+    ///     imageWidth: 8,
+    ///     imageHeight: 8,
+    ///     ..Default::default()
+    /// };
+    /// let values = labelme_rs::indexmap::IndexMap::from([("car".to_string(), 2)]);
+    /// let mask = data.to_mask(&values);
+    /// assert_eq!(mask.pixels().filter(|p| p.0[0] == 2).count(), 12);
+    /// ```
+    pub fn to_mask(&self, label_values: &IndexMap<String, u8>) -> image::GrayImage {
+        let mut mask = image::GrayImage::new(self.imageWidth as u32, self.imageHeight as u32);
+        for shape in &self.shapes {
+            let value = match label_values.get(&shape.label) {
+                Some(value) => *value,
+                None if shape.label == BACKGROUND_LABEL => 0,
+                None => continue,
+            };
+            if shape.shape_type == "circle" && shape.points.len() == 2 {
+                let (c, p) = (shape.points[0], shape.points[1]);
+                fill_circle(&mut mask, c, (c.0 - p.0).hypot(c.1 - p.1), value);
+            } else if let Some(vertices) = shape.vertices() {
+                fill_polygon(&mut mask, &vertices, value);
+            }
+        }
+        mask
+    }
+
+    /// Areas of shapes grouped by label in the order of appearance. Shapes without area are skipped. See [`Shape::area`]
+    pub fn shape_areas(&self) -> IndexMap<&str, Vec<f64>> {
+        let mut map: IndexMap<&str, Vec<f64>> = IndexMap::new();
+        for shape in &self.shapes {
+            if let Some(area) = shape.area() {
+                map.entry(shape.label.as_str()).or_default().push(area);
+            }
+        }
+        map
+    }
+
+    /// Centroids of the shapes with the label. See [`Shape::centroid`]
+    pub fn centroids_for_label(&self, label: &str) -> Vec<Point> {
+        self.shapes
+            .iter()
+            .filter(|s| s.label == label)
+            .filter_map(Shape::centroid)
+            .collect()
+    }
+
+    /// Total area of the shapes with the label. Shapes without area (e.g. points and lines) count as zero
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(&[(0.0, 0.0)], &["L1".into()], 128, 128, "image.jpg");
+    /// data.shapes.push(labelme_rs::Shape {
+    ///     label: "L1".into(),
+    ///     points: vec![(0.0, 0.0), (4.0, 2.0)],
+    ///     shape_type: "rectangle".into(),
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(data.total_area_for_label("L1"), 8.0);
+    /// assert_eq!(data.total_area_for_label("L2"), 0.0);
+    /// ```
+    pub fn total_area_for_label(&self, label: &str) -> f64 {
+        self.shapes
+            .iter()
+            .filter(|s| s.label == label)
+            .filter_map(Shape::area)
+            .sum()
+    }
+
+    /// Union of the bounding boxes of the shapes with the label. See [`Shape::bounding_box`]
+    ///
+    /// ```
+    /// let data = labelme_rs::LabelMeData::new(&[(1.0, 5.0), (3.0, 2.0), (9.0, 9.0)], &["L1".into(), "L1".into(), "L2".into()], 128, 128, "image.jpg");
+    /// assert_eq!(data.bounding_box_for_label("L1"), Some(((1.0, 2.0), (3.0, 5.0))));
+    /// assert_eq!(data.bounding_box_for_label("L3"), None);
+    /// ```
+    pub fn bounding_box_for_label(&self, label: &str) -> Option<(Point, Point)> {
+        self.shapes
+            .iter()
+            .filter(|s| s.label == label)
+            .filter_map(Shape::bounding_box)
+            .reduce(union_box)
+    }
+
+    /// Pairs of shapes whose IoU is above `threshold` in the order of appearance. See [`Shape::iou`]
+    pub fn shapes_with_iou_above(&self, threshold: f64) -> Vec<(&Shape, &Shape)> {
+        self.shapes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| self.shapes[i + 1..].iter().map(move |b| (a, b)))
+            .filter(|(a, b)| a.iou(b) > threshold)
+            .collect()
+    }
+
     /// Count the number of labels
     ///
     /// ```
@@ -399,15 +1746,29 @@ impl LabelMeData {
     /// assert_eq!(counts.get("L0").cloned().unwrap_or(0usize), 0usize);
     /// ```
     pub fn count_labels(&self) -> IndexMap<&str, usize> {
-        let mut counts: IndexMap<&str, usize> = IndexMap::new();
-        let mut shape_map = self.to_shape_map();
-        if let Some(point_data) = shape_map.swap_remove("point") {
-            for (label, points) in point_data {
-                counts
-                    .entry(label)
-                    .and_modify(|count| *count += points.len())
-                    .or_insert_with(|| points.len());
-            }
+        self.count_by_shape_type()
+            .swap_remove("point")
+            .unwrap_or_default()
+    }
+
+    /// Count the number of shapes with a structure map\[`shape_type`\]\[label\] -> count
+    ///
+    /// ```
+    /// let mut data = labelme_rs::LabelMeData::new(&[(1.0, 1.0), (2.0, 2.0)], &["L1".into(), "L1".into()], 128, 128, "image.jpg");
+    /// data.shapes[1].shape_type = "rectangle".into();
+    /// let counts = data.count_by_shape_type();
+    /// assert_eq!(counts["point"]["L1"], 1);
+    /// assert_eq!(counts["rectangle"]["L1"], 1);
+    /// assert!(!counts.contains_key("polygon"));
+    /// ```
+    pub fn count_by_shape_type(&self) -> IndexMap<&str, IndexMap<&str, usize>> {
+        let mut counts: IndexMap<&str, IndexMap<&str, usize>> = IndexMap::new();
+        for shape in &self.shapes {
+            *counts
+                .entry(shape.shape_type.as_str())
+                .or_default()
+                .entry(shape.label.as_str())
+                .or_default() += 1;
         }
         counts
     }
@@ -419,194 +1780,350 @@ impl LabelMeData {
         line_width: usize,
         img: &DynamicImage,
     ) -> svg::Document {
+        self.to_svg_with_options(
+            label_colors,
+            point_radius,
+            line_width,
+            img,
+            &SvgOptions::default(),
+        )
+    }
+
+    /// Same as [`LabelMeData::to_svg`] with additional [`SvgOptions`]
+    pub fn to_svg_with_options(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        img: &DynamicImage,
+        options: &SvgOptions,
+    ) -> svg::Document {
+        self.to_svg_with_image_data(label_colors, point_radius, line_width, img, None, options)
+    }
+
+    /// Same as [`LabelMeData::to_svg_with_options`] embedding `image_data` (base64 encoded image such as `imageData`) as is
+    /// instead of re-encoding `img` as JPEG, if the size of `image_data` is the same as `img` (e.g. not resized).
+    /// `img` is used only for the size (and [`SvgOptions::auto_contrast`]) without [`SvgOptions::embed_image`]
+    pub fn to_svg_with_image_data(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        img: &DynamicImage,
+        image_data: Option<&str>,
+        options: &SvgOptions,
+    ) -> svg::Document {
+        let href = if options.embed_image {
+            image_data
+                .and_then(|image_data| image_data_url(image_data, img.dimensions()))
+                .unwrap_or_else(|| {
+                    img2data_url_with_quality(img, options.image_format, options.jpeg_quality)
+                        .unwrap()
+                })
+        } else {
+            self.imagePath.clone()
+        };
         let (image_width, image_height) = img.dimensions();
+        let view_box = (0.0, 0.0, image_width as f64, image_height as f64);
+        self.to_svg_document(
+            label_colors,
+            point_radius,
+            line_width,
+            view_box,
+            Some(href),
+            Some(img),
+            options,
+        )
+    }
+
+    /// Create SVG for deep zoom viewers.
+    ///
+    /// Low resolution `base` image is stretched over the full resolution `size` as the background
+    /// and `dzi` (url of the DZI descriptor of the tiles. See [`tiles`]) is set to `data-dzi` attribute.
+    pub fn to_deep_zoom_svg(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        size: (u32, u32),
+        base: &DynamicImage,
+        dzi: &str,
+    ) -> svg::Document {
+        let b64 = img2data_url(base, image::ImageFormat::Jpeg).unwrap();
+        self.to_svg_with_background(label_colors, point_radius, line_width, size, b64)
+            .set("data-dzi", dzi)
+    }
+
+    /// Create SVG of the same shape groups as [`LabelMeData::to_svg`] without the background image,
+    /// e.g. to overlay the shapes on other viewers. The view box is `(0, 0, width, height)`
+    ///
+    /// ```
+    /// let data = labelme_rs::LabelMeData::new(&[(1.0, 5.0)], &["L1".into()], 128, 64, "image.jpg");
+    /// let svg = data.to_svg_overlay(&Default::default(), 2, 2, 128, 64).to_string();
+    /// assert!(svg.contains(r#"viewBox="0 0 128 64""#));
+    /// assert!(!svg.contains("<image"));
+    /// ```
+    pub fn to_svg_overlay(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        width: u32,
+        height: u32,
+    ) -> svg::Document {
+        self.to_svg_document(
+            label_colors,
+            point_radius,
+            line_width,
+            (0.0, 0.0, width as f64, height as f64),
+            None,
+            None,
+            &SvgOptions::default(),
+        )
+    }
+
+    /// Create SVG of the shapes without the image.
+    /// The view box fits to the [`LabelMeData::bounding_box`] of the shapes plus `margin`, or to the image size if there is no shape.
+    pub fn to_svg_without_image(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        margin: f64,
+    ) -> svg::Document {
+        let view_box = match self.bounding_box() {
+            Some((x0, y0, x1, y1)) => (
+                x0 - margin,
+                y0 - margin,
+                x1 - x0 + 2.0 * margin,
+                y1 - y0 + 2.0 * margin,
+            ),
+            None => (0.0, 0.0, self.imageWidth as f64, self.imageHeight as f64),
+        };
+        self.to_svg_document(
+            label_colors,
+            point_radius,
+            line_width,
+            view_box,
+            None,
+            None,
+            &SvgOptions::default(),
+        )
+    }
+
+    fn to_svg_with_background(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        (image_width, image_height): (u32, u32),
+        b64: String,
+    ) -> svg::Document {
+        let view_box = (0.0, 0.0, image_width as f64, image_height as f64);
+        self.to_svg_document(
+            label_colors,
+            point_radius,
+            line_width,
+            view_box,
+            Some(b64),
+            None,
+            &SvgOptions::default(),
+        )
+    }
+
+    /// Create SVG with `view_box` as (x, y, width, height) and `background` image stretched over the view box.
+    /// `image` in the coordinates of the shapes is sampled for [`SvgOptions::auto_contrast`]
+    #[allow(clippy::too_many_arguments)]
+    fn to_svg_document(
+        &self,
+        label_colors: &LabelColorsHex,
+        point_radius: usize,
+        line_width: usize,
+        view_box: (f64, f64, f64, f64),
+        background: Option<String>,
+        image: Option<&DynamicImage>,
+        options: &SvgOptions,
+    ) -> svg::Document {
+        let (x, y, width, height) = view_box;
         let mut document = svg::Document::new()
-            .set("width", image_width)
-            .set("height", image_height)
-            .set("viewBox", (0i64, 0i64, image_width, image_height))
+            .set("width", width)
+            .set("height", height)
+            .set("viewBox", view_box)
             .set("xmlns:xlink", "http://www.w3.org/1999/xlink");
-        let b64 = format!(
-            "data:image/jpeg;base64,{}",
-            img2base64(img, image::ImageFormat::Jpeg).unwrap()
-        );
-        let bg = element::Image::new()
-            .set("x", 0i64)
-            .set("y", 0i64)
-            .set("width", image_width)
-            .set("height", image_height)
-            .set("xlink:href", b64);
-        document = document.add(bg);
+        if let Some(b64) = background {
+            let bg = element::Image::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", width)
+                .set("height", height)
+                .set("xlink:href", b64);
+            document = document.add(bg);
+        }
+        // vertex markers of polygons with the label-specific radii are distinguished by the radii
+        let marker_id = |color: &str, radius: usize| {
+            if radius == point_radius {
+                format!("dot{}", color)
+            } else {
+                format!("dot{}-{}", color, radius)
+            }
+        };
         let mut color_cycler = ColorCycler::default();
-        let shape_map = self.to_shape_map();
-        if let Some(point_data) = shape_map.get("point") {
-            for (label, points) in point_data {
-                let color = label_colors
-                    .get(*label)
-                    .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
-                let mut group = element::Group::new()
-                    .set("class", format!("point {}", label))
-                    .set("fill", color)
-                    .set("stroke", "none");
-                for point in points {
-                    let point_xy = point[0];
-                    let circle = element::Circle::new()
-                        .set("cx", point_xy.0)
-                        .set("cy", point_xy.1)
-                        .set("r", point_radius);
-                    group = group.add(circle);
-                }
-                document = document.add(group);
+        let drawn = |shape_type: &str| {
+            options.shape_types.is_empty() || options.shape_types.iter().any(|t| t == shape_type)
+        };
+        // point radius and line width of the label
+        let style = |label: &str| {
+            let style = options.label_styles.get(label);
+            (
+                style.and_then(|s| s.radius).unwrap_or(point_radius),
+                style.and_then(|s| s.line_width).unwrap_or(line_width),
+            )
+        };
+        // color of the first group of each label for the label text
+        let mut text_colors: IndexMap<&str, &str> = IndexMap::new();
+        // per-shape attributes set to each element rather than the group of the label
+        let with_shape_attrs = |mut node: element::Element, shape: &Shape| {
+            let (_, line_width) = style(&shape.label);
+            if let (true, Some(group_id)) = (options.group_ids, &shape.group_id) {
+                node.assign("data-group", group_id.as_str());
             }
-        }
-        if let Some(rectangle_data) = shape_map.get("rectangle") {
-            for (label, rectangles) in rectangle_data {
-                let color = label_colors
-                    .get(*label)
-                    .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
-                let mut group = element::Group::new()
-                    .set("class", format!("rectangle {}", label))
-                    .set("fill", "none")
-                    .set("stroke", color)
-                    .set("stroke-width", line_width);
-                for rectangle in rectangles {
-                    if rectangle.len() != 2 {
-                        continue;
+            if shape.flags.get(DASHED_FLAG).copied().unwrap_or(false) {
+                node.assign(
+                    "stroke-dasharray",
+                    format!("{} {}", line_width * 4, line_width * 2),
+                );
+            }
+            node
+        };
+        // halo is a copy of the element underneath with a wider solid stroke in the contrasting color.
+        // shapes with group_id are nested in the group of the group_id
+        let add_shape = |group: element::Group, node: element::Element, shape: &Shape| {
+            if shape.flags.get(HIDDEN_FLAG).copied().unwrap_or(false) {
+                return group;
+            }
+            let node = with_shape_attrs(node, shape);
+            let (point_radius, line_width) = style(&shape.label);
+            let bbox = shape.bounding_box().filter(|_| options.auto_contrast);
+            let nodes = match (image, bbox) {
+                (Some(img), Some(((x0, y0), (x1, y1)))) => {
+                    let r = point_radius as f64;
+                    let luma = region_mean_luma(img, (x0 - r, y0 - r, x1 + r, y1 + r));
+                    let mut halo = node.clone();
+                    let attributes = halo.get_attributes_mut();
+                    for name in [
+                        "marker-start",
+                        "marker-mid",
+                        "stroke-dasharray",
+                        "data-group",
+                    ] {
+                        attributes.remove(name);
                     }
-                    let rect = element::Rectangle::new()
-                        .set("x", rectangle[0].0.min(rectangle[1].0))
-                        .set("y", rectangle[0].1.min(rectangle[1].1))
-                        .set("width", (rectangle[1].0 - rectangle[0].0).abs())
-                        .set("height", (rectangle[1].1 - rectangle[0].1).abs());
-                    group = group.add(rect);
+                    halo.assign("class", "halo");
+                    halo.assign("fill", "none");
+                    halo.assign("stroke", contrasting_color(luma));
+                    halo.assign("stroke-width", line_width + 2);
+                    vec![halo, node]
                 }
-                document = document.add(group);
+                _ => vec![node],
+            };
+            match &shape.group_id {
+                Some(group_id) => add_to_shape_group(group, nodes, group_id),
+                None => nodes.into_iter().fold(group, |group, node| group.add(node)),
             }
-        }
-        let mut line_colors: IndexSet<&str> = IndexSet::default();
-        if let Some(line_data) = shape_map.get("line") {
-            for (label, lines) in line_data {
-                let color = label_colors
-                    .get(*label)
-                    .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
-                line_colors.insert(color);
-                let mut group = element::Group::new()
-                    .set("class", format!("line {}", label))
-                    .set("fill", "none")
-                    .set("stroke", color)
-                    .set("stroke-width", line_width);
-                for line in lines {
-                    let line = element::Line::new()
-                        .set("x1", line[0].0)
-                        .set("y1", line[0].1)
-                        .set("x2", line[1].0)
-                        .set("y2", line[1].1);
-                    group = group.add(line);
-                }
-                document = document.add(group);
+        };
+        for (shape_type, render) in SVG_RENDERERS {
+            if !drawn(shape_type) {
+                continue;
             }
-        }
-        if let Some(polyline_data) = shape_map.get("linestrip") {
-            for (label, polylines) in polyline_data {
+            let mut shapes_by_label: IndexMap<&str, Vec<&Shape>> = IndexMap::new();
+            for (label, shape) in self.iter_shapes_by_type(shape_type) {
+                shapes_by_label.entry(label).or_default().push(shape);
+            }
+            if shapes_by_label.is_empty() {
+                continue;
+            }
+            let mut polygon_colors: IndexSet<(&str, usize)> = IndexSet::default();
+            for (label, shapes) in shapes_by_label {
                 let color = label_colors
-                    .get(*label)
+                    .get(label)
                     .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
-                line_colors.insert(color);
-                let mut group = element::Group::new()
-                    .set("class", format!("linestrip {}", label))
-                    .set("fill", "none")
-                    .set("stroke", color)
-                    .set("stroke-width", line_width);
-                for polyline in polylines {
-                    let points = polyline
-                        .iter()
-                        .map(|p| format!("{} {}", p.0, p.1))
-                        .collect::<Vec<_>>()
-                        .join(" ");
-                    let polyline = element::Polyline::new().set("points", points);
-                    group = group.add(polyline);
+                let (point_radius, line_width) = style(label);
+                if shape_type != "text" {
+                    text_colors.entry(label).or_insert(color);
+                }
+                if shape_type == "polygon" {
+                    polygon_colors.insert((color, point_radius));
+                }
+                let vertex_marker = format!("url(#{})", marker_id(color, point_radius));
+                let shape_style = SvgShapeStyle {
+                    color,
+                    point_radius,
+                    line_width,
+                    point_marker: options.point_marker,
+                    vertex_marker: &vertex_marker,
+                };
+                let mut group = svg_shape_group(shape_type, label, &shape_style, options);
+                for shape in shapes {
+                    let nodes = render(shape, &shape_style);
+                    if nodes.is_empty() {
+                        continue;
+                    }
+                    for node in nodes {
+                        group = add_shape(group, node, shape);
+                    }
                 }
                 document = document.add(group);
             }
-        }
-        if let Some(polygon_data) = shape_map.get("polygon") {
-            let mut polygon_colors: IndexSet<&str> = IndexSet::default();
-            for (label, polygons) in polygon_data {
-                let color = label_colors
-                    .get(*label)
-                    .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
-                polygon_colors.insert(color);
-                let mut group = element::Group::new()
-                    .set("class", format!("polygon {}", label))
-                    .set("fill", "none")
-                    .set("stroke", color)
-                    .set("stroke-width", line_width);
-                for polygon in polygons {
-                    let value: String = polygon
-                        .iter()
-                        .map(|(a, b)| format!("{} {}", a, b))
-                        .collect::<Vec<String>>()
-                        .join(" ");
-                    let marker_url = format!("url(#dot{})", color);
-                    let poly = element::Polygon::new()
-                        .set("points", value)
-                        .set("marker-start", marker_url.as_str())
-                        .set("marker-mid", marker_url.as_str());
-                    group = group.add(poly);
+            if shape_type == "polygon" {
+                let mut defs = svg::node::element::Definitions::new();
+                for (color, point_radius) in polygon_colors.into_iter() {
+                    let marker = svg::node::element::Marker::new()
+                        .set("id", marker_id(color, point_radius))
+                        .set(
+                            "viewBox",
+                            format!("0 0 {} {}", point_radius * 2, point_radius * 2),
+                        )
+                        .set("refX", point_radius)
+                        .set("refY", point_radius)
+                        .set("markerWidth", point_radius)
+                        .set("markerHeight", point_radius)
+                        .add(
+                            element::Circle::new()
+                                .set("cx", point_radius)
+                                .set("cy", point_radius)
+                                .set("r", point_radius)
+                                .set("fill", color),
+                        );
+                    defs = defs.add(marker);
                 }
-                document = document.add(group);
+                document = document.add(defs);
             }
-            let mut defs = svg::node::element::Definitions::new();
-            for color in polygon_colors.into_iter() {
-                let marker = svg::node::element::Marker::new()
-                    .set("id", format!("dot{}", color))
-                    .set(
-                        "viewBox",
-                        format!("0 0 {} {}", point_radius * 2, point_radius * 2),
-                    )
-                    .set("refX", point_radius)
-                    .set("refY", point_radius)
-                    .set("markerWidth", point_radius)
-                    .set("markerHeight", point_radius)
-                    .add(
-                        element::Circle::new()
-                            .set("cx", point_radius)
-                            .set("cy", point_radius)
-                            .set("r", point_radius)
-                            .set("fill", color),
-                    );
-                defs = defs.add(marker);
-            }
-            document = document.add(defs);
-        }
-        if let Some(circle_data) = shape_map.get("circle") {
-            for (label, circles) in circle_data {
-                let color = label_colors
-                    .get(*label)
-                    .map_or_else(|| color_cycler.cycle(), |s| s.as_str());
+        }
+        if options.show_labels {
+            for (label, color) in text_colors {
+                let font_size = options
+                    .font_size
+                    .unwrap_or(style(label).0 * LABEL_FONT_SCALE);
                 let mut group = element::Group::new()
-                    .set("class", format!("circle {}", label))
-                    .set("stroke-width", line_width);
-                for circle in circles {
-                    if circle.len() != 2 {
-                        continue;
-                    }
-                    let center = element::Circle::new()
-                        .set("cx", circle[0].0)
-                        .set("cy", circle[0].1)
-                        .set("r", point_radius)
-                        .set("fill", color)
-                        .set("stroke", "none");
-                    group = group.add(center);
-                    if circle.len() > 1 {
-                        let (p1, p2) = (circle[0], circle[1]);
-                        let radius = ((p1.0 - p2.0).powi(2) + (p1.1 - p2.1).powi(2)).sqrt();
-                        let c = element::Circle::new()
-                            .set("cx", circle[0].0)
-                            .set("cy", circle[0].1)
-                            .set("r", radius)
-                            .set("fill", "none")
-                            .set("stroke", color);
-                        group = group.add(c);
+                    .set("class", format!("{} {}", LABEL_TEXT_CLASS, label))
+                    .set("fill", color)
+                    .set("font-size", font_size)
+                    .set("stroke", "#FFFFFF")
+                    .set("stroke-width", (font_size / 6).max(1))
+                    .set("paint-order", "stroke");
+                let visible = |s: &&Shape| {
+                    !s.flags.get(HIDDEN_FLAG).copied().unwrap_or(false)
+                        && s.shape_type != "text"
+                        && drawn(&s.shape_type)
+                };
+                for shape in self
+                    .shapes
+                    .iter()
+                    .filter(|s| s.label == label)
+                    .filter(visible)
+                {
+                    if let Some((x, y)) = label_anchor(shape, style(label).0) {
+                        group = group.add(element::Text::new(label).set("x", x).set("y", y));
                     }
                 }
                 document = document.add(group);
@@ -628,13 +2145,169 @@ impl TryFrom<String> for LabelMeData {
     fn try_from(s: String) -> Result<Self, Self::Error> {
         serde_json::from_str(&s)
     }
-}
+}
+
+impl TryFrom<&Path> for LabelMeData {
+    type Error = LabelMeDataError;
+    fn try_from(filename: &Path) -> Result<Self, Self::Error> {
+        let s = std::fs::read_to_string(filename)?;
+        Ok(s.as_str().try_into()?)
+    }
+}
+
+/// Decode text in `encoding`, or UTF-8 if `None`.
+///
+/// A BOM takes precedence over `encoding`. Malformed sequences in `encoding` are replaced with U+FFFD,
+/// while invalid UTF-8 without `encoding` is an error
+///
+/// ```
+/// let encoding = labelme_rs::encoding_rs::SHIFT_JIS;
+/// let (bytes, _, _) = encoding.encode("{\"label\": \"右目\"}");
+/// assert!(labelme_rs::decode_text(bytes.to_vec(), None).is_err());
+/// assert_eq!(labelme_rs::decode_text(bytes.to_vec(), Some(encoding)).unwrap(), "{\"label\": \"右目\"}");
+/// ```
+pub fn decode_text(
+    bytes: Vec<u8>,
+    encoding: Option<&'static encoding_rs::Encoding>,
+) -> Result<String, LabelMeDataError> {
+    match encoding {
+        Some(encoding) => Ok(encoding.decode(&bytes).0.into_owned()),
+        None => String::from_utf8(bytes)
+            .map_err(|e| LabelMeDataError::NonUtf8(e.utf8_error().valid_up_to())),
+    }
+}
+
+impl LabelMeData {
+    /// Load json written in `encoding`. See [`decode_text`]
+    pub fn from_path_with_encoding(
+        filename: &Path,
+        encoding: Option<&'static encoding_rs::Encoding>,
+    ) -> Result<Self, LabelMeDataError> {
+        let s = decode_text(std::fs::read(filename)?, encoding)?;
+        Ok(s.as_str().try_into()?)
+    }
+}
+
+/// Questionable but parsable content found by [`LabelMeData::parse_with_warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// Key appearing more than once in `flags`
+    DuplicateFlag(String),
+    /// Shape identical to an earlier shape
+    DuplicateShape { index: usize, original: usize },
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::DuplicateFlag(name) => {
+                write!(f, "duplicate flag key {:?}: the last value is used", name)
+            }
+            ParseWarning::DuplicateShape { index, original } => {
+                write!(f, "shape {} is identical to shape {}", index, original)
+            }
+        }
+    }
+}
+
+/// Flags with the keys appearing more than once in the order of their second appearances
+#[derive(Debug, Default)]
+struct FlagsWithDuplicates {
+    flags: Flags,
+    duplicates: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for FlagsWithDuplicates {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FlagsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for FlagsVisitor {
+            type Value = FlagsWithDuplicates;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map of flags")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut value = FlagsWithDuplicates::default();
+                while let Some((name, state)) = map.next_entry::<String, bool>()? {
+                    if value.flags.insert(name.clone(), state).is_some()
+                        && !value.duplicates.contains(&name)
+                    {
+                        value.duplicates.push(name);
+                    }
+                }
+                Ok(value)
+            }
+        }
+
+        deserializer.deserialize_map(FlagsVisitor)
+    }
+}
+
+/// [`LabelMeData`] keeping track of duplicate flag keys
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct LabelMeDataWithDuplicates {
+    version: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    flags: FlagsWithDuplicates,
+    #[serde(default, deserialize_with = "null_as_default")]
+    shapes: Vec<Shape>,
+    imagePath: String,
+    imageData: Option<String>,
+    imageHeight: usize,
+    imageWidth: usize,
+}
+
+impl LabelMeData {
+    /// Parse json and report duplicate flag keys and duplicate shapes.
+    ///
+    /// A duplicate flag key is resolved by last-wins: the flag takes the value of its last occurrence
+    /// at the position of its first occurrence, which is also the behavior of [`TryFrom<&str>`].
+    /// Duplicate shapes are kept as they are.
+    pub fn parse_with_warnings(s: &str) -> Result<(Self, Vec<ParseWarning>), serde_json::Error> {
+        let raw: LabelMeDataWithDuplicates = serde_json::from_str(s)?;
+        let mut warnings: Vec<_> = raw
+            .flags
+            .duplicates
+            .into_iter()
+            .map(ParseWarning::DuplicateFlag)
+            .collect();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (index, shape) in raw.shapes.iter().enumerate() {
+            let key = serde_json::to_string(shape)?;
+            if let Some(&original) = seen.get(&key) {
+                warnings.push(ParseWarning::DuplicateShape { index, original });
+            } else {
+                seen.insert(key, index);
+            }
+        }
+        let data = LabelMeData {
+            version: raw.version,
+            flags: raw.flags.flags,
+            shapes: raw.shapes,
+            imagePath: raw.imagePath,
+            imageData: raw.imageData,
+            imageHeight: raw.imageHeight,
+            imageWidth: raw.imageWidth,
+        };
+        Ok((data, warnings))
+    }
 
-impl TryFrom<&Path> for LabelMeData {
-    type Error = LabelMeDataError;
-    fn try_from(filename: &Path) -> Result<Self, Self::Error> {
-        let s = std::fs::read_to_string(filename)?;
-        Ok(s.as_str().try_into()?)
+    /// Parse json rejecting duplicate flag keys. Duplicate shapes are accepted
+    pub fn parse_strict(s: &str) -> Result<Self, LabelMeDataError> {
+        let (data, warnings) = Self::parse_with_warnings(s)?;
+        match warnings.into_iter().find_map(|w| match w {
+            ParseWarning::DuplicateFlag(name) => Some(name),
+            _ => None,
+        }) {
+            Some(name) => Err(LabelMeDataError::DuplicateFlag(name)),
+            None => Ok(data),
+        }
     }
 }
 
@@ -680,13 +2353,41 @@ pub fn load_image(path: &Path) -> Result<DynamicImage, ImageError> {
     Ok(image::open(path)?)
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Read (width, height) of the image without decoding pixels when possible
+pub fn image_dimensions(path: &Path) -> Result<(u32, u32), ImageError> {
+    #[cfg(feature = "dicom")]
+    if path
+        .extension()
+        .map_or(false, |ext| ext == "dcm" || ext == "dicom")
+    {
+        let dynamic_image = load_dicom(path)?;
+        return Ok((dynamic_image.width(), dynamic_image.height()));
+    }
+    Ok(image::image_dimensions(path)?)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Color(u8, u8, u8);
 
+#[derive(Error, Debug)]
+#[error("invalid hex color: {0}")]
+pub struct InvalidHexColorError(String);
+
 impl Color {
     pub fn to_hex(&self) -> String {
         format!("#{:02X}{:02X}{:02X}", self.0, self.1, self.2)
     }
+
+    /// Parse `#RRGGBB` (case insensitive)
+    pub fn from_hex(hex: &str) -> Result<Self, InvalidHexColorError> {
+        let err = || InvalidHexColorError(hex.to_string());
+        let digits = hex.strip_prefix('#').ok_or_else(err)?;
+        if digits.len() != 6 || !digits.is_ascii() {
+            return Err(err());
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| err());
+        Ok(Self(channel(0)?, channel(2)?, channel(4)?))
+    }
 }
 
 impl From<Color> for String {
@@ -703,6 +2404,21 @@ pub struct LabelColorsInConfig {
 pub type LabelColors = HashMap<String, Color>;
 pub type LabelColorsHex = HashMap<String, String>;
 
+/// Per-label overrides of the point radius and the line width
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelStyle {
+    pub radius: Option<usize>,
+    pub line_width: Option<usize>,
+}
+
+pub type LabelStyles = HashMap<String, LabelStyle>;
+
+#[derive(Deserialize, Debug)]
+struct LabelStylesInConfig {
+    #[serde(default, deserialize_with = "null_as_default")]
+    label_styles: LabelStyles,
+}
+
 pub static TAB10: [&str; 10] = [
     "#1f77b4", "#ff7f0f", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
     "#bcbd22", "#16becf",
@@ -740,6 +2456,8 @@ pub enum LabelColorError {
     IoError(#[from] std::io::Error),
     #[error("Yaml error")]
     YamlError(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    InvalidHexColor(#[from] InvalidHexColorError),
 }
 
 /// Load colormap written in yaml
@@ -752,6 +2470,86 @@ pub fn load_label_colors(filename: &Path) -> Result<LabelColorsHex, LabelColorEr
     Ok(hex)
 }
 
+/// Load per-label styles written in yaml along with `label_colors`. Empty if `label_styles` is missing
+/// Example: `label_styles: {"L1": {radius: 4, line_width: 1}}`
+pub fn load_label_styles(filename: &Path) -> Result<LabelStyles, LabelColorError> {
+    let config: LabelStylesInConfig =
+        serde_yaml::from_reader(std::io::BufReader::new(std::fs::File::open(filename)?))?;
+    Ok(config.label_styles)
+}
+
+/// Load the values of labels for [`LabelMeData::to_mask`] written in yaml
+/// Example: `{"road": 1, "car": 2}`
+pub fn load_label_values(filename: &Path) -> Result<IndexMap<String, u8>, LabelColorError> {
+    Ok(serde_yaml::from_reader(std::io::BufReader::new(
+        std::fs::File::open(filename)?,
+    ))?)
+}
+
+/// Load a mapping of labels (e.g. old to new labels) written in yaml
+/// Example: `{"TL": "top_left", "TR": "top_right"}`
+pub fn load_label_mapping(filename: &Path) -> Result<IndexMap<String, String>, LabelColorError> {
+    Ok(serde_yaml::from_reader(std::io::BufReader::new(
+        std::fs::File::open(filename)?,
+    ))?)
+}
+
+/// Serialize `label_colors` as a labelme config yaml sorted by label
+pub fn label_colors_to_yaml(label_colors: &LabelColorsHex) -> Result<String, LabelColorError> {
+    let mut labels: Vec<_> = label_colors.keys().collect();
+    labels.sort();
+    let mut colors = serde_yaml::Mapping::new();
+    for label in labels {
+        let color = Color::from_hex(&label_colors[label])?;
+        colors.insert(label.as_str().into(), serde_yaml::to_value(color)?);
+    }
+    let mut config = serde_yaml::Mapping::new();
+    config.insert("label_colors".into(), colors.into());
+    Ok(serde_yaml::to_string(&config)?)
+}
+
+/// Save `label_colors` to a labelme config yaml.
+///
+/// If the file exists, labels already in its `label_colors` keep their colors and the other contents are preserved.
+/// New labels are appended in the order of their names.
+/// The file is replaced by renaming a temporary file so that readers never see a partially written file.
+pub fn save_label_colors(
+    filename: &Path,
+    label_colors: &LabelColorsHex,
+) -> Result<(), LabelColorError> {
+    let mut config: serde_yaml::Mapping = if filename.exists() {
+        serde_yaml::from_reader(std::io::BufReader::new(std::fs::File::open(filename)?))?
+    } else {
+        serde_yaml::Mapping::new()
+    };
+    let key = serde_yaml::Value::from("label_colors");
+    let colors = config
+        .entry(key)
+        .or_insert_with(|| serde_yaml::Mapping::new().into());
+    if colors.is_null() {
+        *colors = serde_yaml::Mapping::new().into();
+    }
+    let colors = colors.as_mapping_mut().ok_or_else(|| {
+        <serde_yaml::Error as serde::de::Error>::custom("label_colors is not a mapping")
+    })?;
+    let mut labels: Vec<_> = label_colors.keys().collect();
+    labels.sort();
+    for label in labels {
+        let key = serde_yaml::Value::from(label.as_str());
+        if !colors.contains_key(&key) {
+            let color = Color::from_hex(&label_colors[label])?;
+            colors.insert(key, serde_yaml::to_value(color)?);
+        }
+    }
+    let mut tmp_name = filename.as_os_str().to_owned();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = std::path::PathBuf::from(tmp_name);
+    let file = std::fs::File::create(&tmp_path)?;
+    serde_yaml::to_writer(std::io::BufWriter::new(file), &config)?;
+    std::fs::rename(&tmp_path, filename)?;
+    Ok(())
+}
+
 impl ColorCycler {
     /// Get next color
     pub fn cycle(&mut self) -> &'static str {
@@ -766,6 +2564,15 @@ mod tests {
     use super::*;
     use anyhow::{Context, Result};
 
+    pub(crate) fn shape(label: &str, shape_type: &str, points: impl Into<Vec<Point>>) -> Shape {
+        Shape {
+            label: label.into(),
+            points: points.into(),
+            shape_type: shape_type.into(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_lmdata_line() -> Result<()> {
         let lmd = LabelMeData::default();
@@ -780,6 +2587,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_null_shapes() -> Result<()> {
+        let base =
+            r#""version": "5.0.1", "imagePath": "img.jpg", "imageHeight": 10, "imageWidth": 10"#;
+        for fields in [
+            r#""flags": {}, "shapes": null"#,
+            r#""flags": {}"#,
+            r#""flags": null, "shapes": []"#,
+            r#""shapes": []"#,
+        ] {
+            let data = LabelMeData::try_from(format!("{{{}, {}}}", fields, base))?;
+            assert!(data.is_empty());
+            assert!(data.flags.is_empty());
+        }
+        assert!(
+            LabelMeData::try_from(format!(r#"{{"flags": {{}}, "shapes": "", {}}}"#, base)).is_err()
+        );
+        assert!(
+            LabelMeData::try_from(format!(r#"{{"flags": [], "shapes": [], {}}}"#, base)).is_err()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_image_load() -> Result<()> {
         let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
@@ -808,6 +2638,1071 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fit_encoded_size() -> anyhow::Result<()> {
+        // Noisy image which does not compress well
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(512, 256, |x, y| {
+            let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761);
+            image::Rgb([(v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+        }));
+        let data = LabelMeData::new(&[(100.0, 200.0)], &["L".into()], 512, 256, "img.png");
+        let max_bytes = 30_000;
+        let mut data_w_image = LabelMeDataWImage::new(data.clone(), image.clone());
+        assert!(img2base64(&image, image::ImageFormat::Jpeg)?.len() > max_bytes);
+        let scale = data_w_image.fit_encoded_size(max_bytes, image::ImageFormat::Jpeg)?;
+        assert!(scale < 1.0);
+        let encoded = img2base64(&data_w_image.image, image::ImageFormat::Jpeg)?;
+        assert!(encoded.len() <= max_bytes);
+        let (width, height) = data_w_image.image.dimensions();
+        assert!((width as f64 - 512.0 * scale).abs() <= 1.0);
+        assert!((height as f64 - 256.0 * scale).abs() <= 1.0);
+        assert!(data_w_image.data.imageWidth.abs_diff(width as usize) <= 1);
+        let point = data_w_image.data.shapes[0].points[0];
+        assert!((point.0 - 100.0 * scale).abs() < 1e-9);
+        assert!((point.1 - 200.0 * scale).abs() < 1e-9);
+
+        let mut data_w_image = LabelMeDataWImage::new(data.clone(), image);
+        let scale = data_w_image.fit_encoded_size(usize::MAX, image::ImageFormat::Jpeg)?;
+        assert_eq!(scale, 1.0);
+        assert_eq!(data_w_image.data, data);
+
+        // Tiny images are shrunk even if the ratio rounds to the same size
+        for size in [2, 3] {
+            let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(size, size, |x, y| {
+                image::Rgb([(x * 97) as u8, (y * 89) as u8, ((x + y) * 53) as u8])
+            }));
+            let encoded_len = img2base64(&image, image::ImageFormat::Png)?.len();
+            let data = LabelMeData::new(&[], &[], size as usize, size as usize, "img.png");
+            let mut data_w_image = LabelMeDataWImage::new(data, image);
+            let scale = data_w_image.fit_encoded_size(encoded_len - 1, image::ImageFormat::Png)?;
+            assert!(scale < 1.0);
+            assert!(data_w_image.image.width() < size);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_img2data_url() -> anyhow::Result<()> {
+        let img = DynamicImage::new_rgb8(8, 8);
+        let url = img2data_url(&img, image::ImageFormat::Jpeg)?;
+        let b64 = img2base64(&img, image::ImageFormat::Jpeg)?;
+        assert_eq!(url, format!("data:image/jpeg;base64,{b64}"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_image_data() -> anyhow::Result<()> {
+        let img = DynamicImage::new_rgb8(3, 2);
+        let decoded = decode_image_data(&img2base64(&img, image::ImageFormat::Png)?)?;
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert!(matches!(
+            decode_image_data("not base64!"),
+            Err(LabelMeDataError::Base64Error(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shift_origin() {
+        let mut data = LabelMeData::new(
+            &[(0.5, 0.5), (10.0, 3.25)],
+            &["L1".into(), "L2".into()],
+            16,
+            16,
+            "1.jpg",
+        );
+        data.shift_origin(OriginConvention::CornerToCenter);
+        assert_eq!(data.shapes[0].points[0], (0.0, 0.0));
+        assert_eq!(data.shapes[1].points[0], (9.5, 2.75));
+        data.shift_origin(OriginConvention::CornerToCenter.inverse());
+        assert_eq!(data.shapes[0].points[0], (0.5, 0.5));
+        assert_eq!(data.shapes[1].points[0], (10.0, 3.25));
+        assert_eq!((data.imageWidth, data.imageHeight), (16, 16));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut data = LabelMeData::new(
+            &[(320.0, 240.0), (0.0, 480.0)],
+            &["L1".into(), "L2".into()],
+            640,
+            480,
+            "1.jpg",
+        );
+        data.normalize();
+        assert_eq!(data.shapes[0].points[0], (0.5, 0.5));
+        assert_eq!(data.shapes[1].points[0], (0.0, 1.0));
+        data.denormalize();
+        assert_eq!(data.shapes[0].points[0], (320.0, 240.0));
+        assert_eq!(data.shapes[1].points[0], (0.0, 480.0));
+        assert_eq!((data.imageWidth, data.imageHeight), (640, 480));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let original = LabelMeData::new(
+            &[(0.0, 0.0), (10.0, 20.0), (99.5, 0.25)],
+            &["L1".into(), "L2".into(), "L3".into()],
+            100,
+            50,
+            "image.jpg",
+        );
+        let close = |a: &LabelMeData, b: &LabelMeData| {
+            a.shapes.iter().zip(&b.shapes).all(|(s, t)| {
+                s.points
+                    .iter()
+                    .zip(&t.points)
+                    .all(|(p, q)| (p.0 - q.0).abs() < 1e-9 && (p.1 - q.1).abs() < 1e-9)
+            })
+        };
+        for (degrees, times) in [(90.0, 4), (-90.0, 4), (180.0, 2), (270.0, 4), (30.0, 12)] {
+            let mut data = original.clone();
+            for _ in 0..times {
+                data.rotate(degrees);
+                if degrees == 30.0 {
+                    continue;
+                }
+                for shape in &data.shapes {
+                    let (x, y) = shape.points[0];
+                    assert!((0.0..=data.imageWidth as f64).contains(&x), "{degrees}");
+                    assert!((0.0..=data.imageHeight as f64).contains(&y), "{degrees}");
+                }
+            }
+            assert_eq!(
+                (data.imageWidth, data.imageHeight),
+                (original.imageWidth, original.imageHeight)
+            );
+            assert!(close(&data, &original), "{degrees}");
+        }
+        let mut data = original.clone();
+        data.rotate(270.0);
+        assert_eq!(data.shapes[0].points[0], (0.0, 100.0));
+        data.rotate(180.0);
+        assert_eq!(data.shapes[0].points[0], (50.0, 0.0));
+    }
+
+    #[test]
+    fn test_iou() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let rect = shape("L1", "rectangle", vec![(2.0, 2.0), (0.0, 0.0)]);
+        assert!(close(rect.iou(&rect), 1.0));
+        let square = shape(
+            "L1",
+            "polygon",
+            vec![(0.0, 0.0), (0.0, 2.0), (2.0, 2.0), (2.0, 0.0)],
+        );
+        assert!(close(rect.iou(&square), 1.0));
+        let triangle = shape("L1", "polygon", vec![(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)]);
+        assert!(close(rect.iou(&triangle), 0.5));
+        assert!(close(triangle.iou(&rect), 0.5));
+        // concave subject clipped by the convex one: 4x4 square minus 2x2 square vs. [1, 3]x[1, 3]
+        let l_shape = shape(
+            "L1",
+            "polygon",
+            vec![
+                (0.0, 0.0),
+                (4.0, 0.0),
+                (4.0, 2.0),
+                (2.0, 2.0),
+                (2.0, 4.0),
+                (0.0, 4.0),
+            ],
+        );
+        let center = shape("L1", "rectangle", vec![(1.0, 1.0), (3.0, 3.0)]);
+        assert!(close(l_shape.iou(&center), 3.0 / 13.0));
+        assert!(close(center.iou(&l_shape), 3.0 / 13.0));
+        let apart = shape("L1", "rectangle", vec![(5.0, 5.0), (6.0, 6.0)]);
+        assert_eq!(rect.iou(&apart), 0.0);
+        assert_eq!(triangle.iou(&apart), 0.0);
+        let circle = shape("L1", "circle", vec![(1.0, 1.0), (2.0, 1.0)]);
+        assert_eq!(rect.iou(&circle), 0.0);
+        assert_eq!(
+            shape("L1", "line", vec![(0.0, 0.0), (2.0, 2.0)]).iou(&rect),
+            0.0
+        );
+
+        let data = LabelMeData {
+            shapes: vec![rect, triangle, square, apart],
+            ..Default::default()
+        };
+        let pairs = data.shapes_with_iou_above(0.5);
+        assert_eq!(pairs, vec![(&data.shapes[0], &data.shapes[2])]);
+        assert_eq!(data.shapes_with_iou_above(0.4).len(), 3);
+    }
+
+    #[test]
+    fn test_area() {
+        // L-shaped hexagon: 4x4 square minus 2x2 square
+        let l_shape = vec![
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 4.0),
+            (0.0, 4.0),
+        ];
+        assert_eq!(shape("L1", "polygon", l_shape.clone()).area(), Some(12.0));
+        // clockwise order
+        let reversed: Vec<_> = l_shape.iter().rev().cloned().collect();
+        assert_eq!(shape("L1", "polygon", reversed).area(), Some(12.0));
+        // bow tie: the two triangles cancel each other
+        let bow_tie = vec![(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0)];
+        assert_eq!(shape("L1", "polygon", bow_tie).area(), Some(0.0));
+        let twisted = vec![(0.0, 0.0), (4.0, 0.0), (0.0, 4.0), (2.0, 4.0)];
+        assert_eq!(shape("L1", "polygon", twisted).area(), Some(4.0));
+        assert_eq!(
+            shape("L1", "rectangle", vec![(5.0, 1.0), (2.0, 3.0)]).area(),
+            Some(6.0)
+        );
+        let circle = shape("L1", "circle", vec![(1.0, 1.0), (4.0, 5.0)]);
+        assert!((circle.area().unwrap() - std::f64::consts::PI * 25.0).abs() < 1e-9);
+        assert_eq!(shape("L1", "point", vec![(1.0, 1.0)]).area(), None);
+        assert_eq!(
+            shape("L1", "line", vec![(1.0, 1.0), (2.0, 2.0)]).area(),
+            None
+        );
+        assert_eq!(
+            shape("L1", "polygon", vec![(1.0, 1.0), (2.0, 2.0)]).area(),
+            None
+        );
+        assert_eq!(shape("L1", "rectangle", vec![(1.0, 1.0)]).area(), None);
+        assert_eq!(shape("L1", "circle", vec![]).area(), None);
+
+        let mut data = LabelMeData {
+            shapes: vec![
+                shape("polygon", "polygon", l_shape),
+                shape("point", "point", vec![(1.0, 1.0)]),
+                shape("rectangle", "rectangle", vec![(0.0, 0.0), (2.0, 3.0)]),
+                shape("rectangle", "rectangle", vec![(0.0, 0.0), (1.0, 1.0)]),
+            ],
+            ..Default::default()
+        };
+        data.scale(2.0);
+        let areas = data.shape_areas();
+        assert_eq!(
+            areas.keys().collect::<Vec<_>>(),
+            vec![&"polygon", &"rectangle"]
+        );
+        assert_eq!(areas["polygon"], vec![48.0]);
+        assert_eq!(areas["rectangle"], vec![24.0, 4.0]);
+    }
+
+    #[test]
+    fn test_centroid() {
+        let data = LabelMeData {
+            shapes: vec![
+                shape("L1", "point", vec![(1.0, 2.0)]),
+                shape("L1", "line", vec![(0.0, 0.0), (4.0, 2.0)]),
+                shape("L1", "circle", vec![(5.0, 5.0), (5.0, 9.0)]),
+                shape("L1", "rectangle", vec![(8.0, 2.0), (2.0, 6.0)]),
+                // L-shaped hexagon
+                shape(
+                    "L2",
+                    "polygon",
+                    vec![
+                        (0.0, 0.0),
+                        (4.0, 0.0),
+                        (4.0, 2.0),
+                        (2.0, 2.0),
+                        (2.0, 4.0),
+                        (0.0, 4.0),
+                    ],
+                ),
+                shape("L2", "polygon", vec![(0.0, 0.0), (3.0, 0.0), (6.0, 0.0)]),
+                shape("L2", "linestrip", vec![(0.0, 0.0), (3.0, 0.0), (3.0, 3.0)]),
+                shape("L2", "polygon", vec![]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            data.centroids_for_label("L1"),
+            vec![(1.0, 2.0), (2.0, 1.0), (5.0, 7.0), (5.0, 4.0)]
+        );
+        let centroids = data.centroids_for_label("L2");
+        assert_eq!(centroids.len(), 3);
+        let expected = 10.0 / 6.0;
+        assert!((centroids[0].0 - expected).abs() < 1e-9);
+        assert!((centroids[0].1 - expected).abs() < 1e-9);
+        assert_eq!(centroids[1..], [(3.0, 0.0), (2.0, 1.0)]);
+        assert!(data.centroids_for_label("L3").is_empty());
+    }
+
+    #[test]
+    fn test_crop() {
+        let data = LabelMeData {
+            shapes: vec![
+                shape("in", "point", vec![(15.0, 15.0)]),
+                shape("out", "point", vec![(5.0, 15.0)]),
+                shape("in", "circle", vec![(12.0, 12.0), (2.0, 12.0)]),
+                shape("out", "circle", vec![(40.0, 12.0), (20.0, 12.0)]),
+                shape("in", "rectangle", vec![(5.0, 5.0), (20.0, 20.0)]),
+                shape("out", "rectangle", vec![(0.0, 0.0), (8.0, 20.0)]),
+                shape("in", "polygon", vec![(5.0, 5.0), (40.0, 5.0), (20.0, 20.0)]),
+                shape("out", "polygon", vec![(0.0, 0.0), (5.0, 5.0), (0.0, 8.0)]),
+                shape("in", "line", vec![(0.0, 15.0), (20.0, 15.0)]),
+                shape("out", "line", vec![(0.0, 15.0), (5.0, 15.0)]),
+                shape("in", "linestrip", vec![(15.0, 15.0), (15.0, 40.0)]),
+                shape("out", "linestrip", vec![(5.0, 11.0), (5.0, 40.0)]),
+                shape(
+                    "out",
+                    "polygon",
+                    vec![(5.0, 11.0), (2.0, 15.0), (5.0, 20.0)],
+                ),
+            ],
+            ..LabelMeData::new(&[], &[], 32, 32, "")
+        };
+        let mut data_w_image = LabelMeDataWImage::new(data, DynamicImage::new_rgb8(32, 32));
+        data_w_image.crop(10, 10, 30, 20);
+        let data = &data_w_image.data;
+        assert_eq!(data_w_image.image.dimensions(), (22, 20));
+        assert_eq!((data.imageWidth, data.imageHeight), (22, 20));
+        assert!(
+            data.shapes.iter().all(|s| s.label == "in"),
+            "{:?}",
+            data.shapes
+        );
+        let points: Vec<_> = data.shapes.iter().map(|s| s.points.clone()).collect();
+        assert_eq!(
+            points,
+            vec![
+                vec![(5.0, 5.0)],
+                vec![(2.0, 2.0), (-8.0, 2.0)],
+                vec![(0.0, 0.0), (10.0, 10.0)],
+                vec![(0.0, 0.0), (22.0, 0.0), (10.0, 10.0)],
+                vec![(0.0, 5.0), (10.0, 5.0)],
+                vec![(5.0, 5.0), (5.0, 20.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        assert_eq!(
+            shape("L1", "point", vec![(3.0, 4.0)]).bounding_box(),
+            Some(((3.0, 4.0), (3.0, 4.0)))
+        );
+        assert_eq!(
+            shape("L1", "rectangle", vec![(10.0, 2.0), (1.0, 8.0)]).bounding_box(),
+            Some(((1.0, 2.0), (10.0, 8.0)))
+        );
+        assert_eq!(
+            shape(
+                "L1",
+                "polygon",
+                vec![(5.0, 0.0), (10.0, 5.0), (5.0, 10.0), (0.0, 5.0)]
+            )
+            .bounding_box(),
+            Some(((0.0, 0.0), (10.0, 10.0)))
+        );
+        assert_eq!(
+            shape("L1", "circle", vec![(10.0, 10.0), (10.0, 12.0)]).bounding_box(),
+            Some(((8.0, 8.0), (12.0, 12.0)))
+        );
+        assert_eq!(shape("L1", "polygon", vec![]).bounding_box(), None);
+
+        let mut data = LabelMeData::new(
+            &[(1.0, 1.0), (8.0, 3.0)],
+            &["L1".into(), "L2".into()],
+            16,
+            16,
+            "1.jpg",
+        );
+        data.shapes
+            .push(shape("L1", "circle", vec![(5.0, 5.0), (6.0, 5.0)]));
+        assert_eq!(
+            data.bounding_box_for_label("L1"),
+            Some(((1.0, 1.0), (6.0, 6.0)))
+        );
+        assert_eq!(data.bounding_box(), Some((1.0, 1.0, 8.0, 6.0)));
+    }
+
+    #[test]
+    fn test_svg_group_ids() {
+        let data = LabelMeData {
+            shapes: vec![
+                Shape {
+                    group_id: Some("1".into()),
+                    ..shape("L1", "point", vec![(1.0, 1.0)])
+                },
+                Shape {
+                    group_id: Some("1".into()),
+                    ..shape("L2", "rectangle", vec![(0.0, 0.0), (2.0, 2.0)])
+                },
+                shape("L1", "polygon", vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 4, 4, "")
+        };
+        let img = DynamicImage::new_rgb8(4, 4);
+        let colors = LabelColorsHex::new();
+        let options = SvgOptions {
+            group_ids: true,
+            ..Default::default()
+        };
+        let svg = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        assert_eq!(svg.matches(r#"data-group="1""#).count(), 2);
+        assert!(svg.contains(r#"<circle cx="1" cy="1" data-group="1" r="2"/>"#));
+        let svg = data.to_svg(&colors, 2, 2, &img).to_string();
+        assert!(!svg.contains("data-group="));
+    }
+
+    #[test]
+    fn test_svg_hidden_and_shape_groups() {
+        let mut data = LabelMeData {
+            shapes: vec![
+                Shape {
+                    group_id: Some("7".into()),
+                    ..shape("L1", "point", vec![(1.0, 1.0)])
+                },
+                shape("L1", "point", vec![(2.0, 2.0)]),
+                Shape {
+                    group_id: Some("7".into()),
+                    ..shape("L1", "point", vec![(3.0, 3.0)])
+                },
+                Shape {
+                    group_id: Some("8".into()),
+                    ..shape("L1", "point", vec![(4.0, 4.0)])
+                },
+            ],
+            ..LabelMeData::new(&[], &[], 8, 8, "")
+        };
+        let img = DynamicImage::new_rgb8(8, 8);
+        let colors = LabelColorsHex::new();
+        let svg = data.to_svg(&colors, 2, 2, &img).to_string();
+        assert!(svg.contains(
+            "<g class=\"group-7\" data-group-id=\"7\">\n<circle cx=\"1\" cy=\"1\" r=\"2\"/>\n<circle cx=\"3\" cy=\"3\" r=\"2\"/>\n</g>"
+        ));
+        assert!(svg.contains(
+            "<g class=\"group-8\" data-group-id=\"8\">\n<circle cx=\"4\" cy=\"4\" r=\"2\"/>\n</g>"
+        ));
+        // nested at the first shape of the group
+        assert!(svg.find("group-7").unwrap() < svg.find(r#"cx="2""#).unwrap());
+
+        data.shapes[0].flags.insert(HIDDEN_FLAG.into(), true);
+        let options = SvgOptions {
+            show_labels: true,
+            ..Default::default()
+        };
+        let svg = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        assert!(!svg.contains(r#"cx="1""#));
+        assert!(!svg.contains(r#"x="5""#));
+        assert_eq!(svg.matches("<text").count(), 3);
+    }
+
+    #[test]
+    fn test_svg_image_format() {
+        let img = DynamicImage::new_rgb8(8, 8);
+        let data = LabelMeData::new(&[(1.0, 1.0)], &["L1".into()], 8, 8, "");
+        let colors = LabelColorsHex::new();
+        let svg = |image_format| {
+            let options = SvgOptions {
+                image_format,
+                ..Default::default()
+            };
+            data.to_svg_with_options(&colors, 2, 2, &img, &options)
+                .to_string()
+        };
+        assert!(svg(image::ImageFormat::Jpeg).contains("data:image/jpeg;base64,"));
+        let png = svg(image::ImageFormat::Png);
+        assert!(png.contains(&format!(
+            "data:image/png;base64,{}",
+            img2base64(&img, image::ImageFormat::Png).unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_svg_jpeg_quality_and_no_embed() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8])
+        }));
+        let data = LabelMeData::new(&[(1.0, 1.0)], &["L1".into()], 64, 64, "images/a&b.png");
+        let colors = LabelColorsHex::new();
+        let svg = |options: SvgOptions| {
+            data.to_svg_with_options(&colors, 2, 2, &img, &options)
+                .to_string()
+        };
+        let low = svg(SvgOptions {
+            jpeg_quality: Some(10),
+            ..Default::default()
+        });
+        let high = svg(SvgOptions {
+            jpeg_quality: Some(100),
+            ..Default::default()
+        });
+        assert!(low.contains("data:image/jpeg;base64,"));
+        assert!(low.len() < high.len());
+
+        let svg = svg(SvgOptions {
+            embed_image: false,
+            ..Default::default()
+        });
+        assert!(svg.contains(r#"xlink:href="images/a&amp;b.png""#), "{svg}");
+        assert!(!svg.contains("base64"));
+    }
+
+    #[test]
+    fn test_svg_image_data() -> Result<()> {
+        let img = DynamicImage::new_rgb8(8, 8);
+        let image_data = img2base64(&img, image::ImageFormat::Png)?;
+        let data = LabelMeData::new(&[(1.0, 1.0)], &["L1".into()], 8, 8, "");
+        let colors = LabelColorsHex::new();
+        let svg = |img: &DynamicImage| {
+            data.to_svg_with_image_data(
+                &colors,
+                2,
+                2,
+                img,
+                Some(&image_data),
+                &SvgOptions::default(),
+            )
+            .to_string()
+        };
+        assert!(svg(&img).contains(&format!("data:image/png;base64,{image_data}")));
+        // resized
+        let resized = svg(&img.thumbnail(4, 4));
+        assert!(resized.contains("data:image/jpeg;base64,"));
+        assert!(!resized.contains(&image_data));
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_point_marker() {
+        let data = LabelMeData::new(&[(5.0, 6.0)], &["L1".into()], 16, 16, "");
+        let img = DynamicImage::new_rgb8(16, 16);
+        let colors = LabelColorsHex::new();
+        let svg = |point_marker| {
+            let options = SvgOptions {
+                point_marker,
+                ..Default::default()
+            };
+            data.to_svg_with_options(&colors, 2, 1, &img, &options)
+                .to_string()
+        };
+        assert_eq!(
+            svg(PointMarker::Circle),
+            data.to_svg(&colors, 2, 1, &img).to_string()
+        );
+        let cross = svg(PointMarker::Cross);
+        assert!(cross.contains(
+            r##"<line stroke="#1f77b4" stroke-width="1" x1="3" x2="7" y1="6" y2="6"/>"##
+        ));
+        assert!(cross.contains(
+            r##"<line stroke="#1f77b4" stroke-width="1" x1="5" x2="5" y1="4" y2="8"/>"##
+        ));
+        assert!(!cross.contains("<circle"));
+        let square = svg(PointMarker::Square);
+        assert!(square.contains(
+            r##"<rect fill="none" height="4" stroke="#1f77b4" stroke-width="1" width="4" x="3" y="4"/>"##
+        ));
+        assert!(square.contains(r#"class="point L1""#));
+    }
+
+    #[test]
+    fn test_to_mask() {
+        let values = IndexMap::from([("road".to_string(), 1), ("car".to_string(), 2)]);
+        let count =
+            |mask: &image::GrayImage, value| mask.pixels().filter(|p| p.0[0] == value).count();
+
+        // overlapping shapes: later ones overwrite earlier ones
+        let mask = LabelMeData {
+            shapes: vec![
+                shape("road", "rectangle", vec![(0.0, 0.0), (10.0, 10.0)]),
+                shape("car", "rectangle", vec![(5.0, 5.0), (8.0, 8.0)]),
+                shape("unknown", "rectangle", vec![(12.0, 0.0), (14.0, 2.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 20, 10, "")
+        }
+        .to_mask(&values);
+        assert_eq!(mask.dimensions(), (20, 10));
+        assert_eq!(count(&mask, 1), 100 - 9);
+        assert_eq!(count(&mask, 2), 9);
+
+        // partially outside the image, and a triangle of the half area of the square
+        let mask = LabelMeData {
+            shapes: vec![
+                shape(
+                    "road",
+                    "polygon",
+                    vec![(15.0, -5.0), (25.0, -5.0), (25.0, 5.0), (15.0, 5.0)],
+                ),
+                shape("car", "polygon", vec![(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 20, 10, "")
+        }
+        .to_mask(&values);
+        assert_eq!(count(&mask, 1), 25);
+        assert_eq!(count(&mask, 2), 45);
+
+        // holes by the background label
+        let mask = LabelMeData {
+            shapes: vec![
+                shape("road", "rectangle", vec![(0.0, 0.0), (10.0, 10.0)]),
+                shape(
+                    BACKGROUND_LABEL,
+                    "polygon",
+                    vec![(2.0, 2.0), (6.0, 2.0), (6.0, 6.0), (2.0, 6.0)],
+                ),
+            ],
+            ..LabelMeData::new(&[], &[], 20, 10, "")
+        }
+        .to_mask(&values);
+        assert_eq!(count(&mask, 1), 100 - 16);
+
+        let circle = LabelMeData {
+            shapes: vec![shape("car", "circle", vec![(10.0, 5.0), (10.0, 1.0)])],
+            ..LabelMeData::new(&[], &[], 20, 10, "")
+        };
+        let area = count(&circle.to_mask(&values), 2) as f64;
+        assert!((area - std::f64::consts::PI * 16.0).abs() < 4.0, "{area}");
+    }
+
+    #[test]
+    fn test_svg_overlay() {
+        let mut data = LabelMeData::new(
+            &[(5.0, 6.0), (9.0, 9.0)],
+            &["L1".into(), "L2".into()],
+            16,
+            12,
+            "",
+        );
+        data.shapes.push(Shape::polygon(
+            vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)],
+            "L1",
+        ));
+        let img = DynamicImage::new_rgb8(16, 12);
+        let colors = LabelColorsHex::new();
+        let with_image = data.to_svg(&colors, 2, 2, &img).to_string();
+        let overlay = data.to_svg_overlay(&colors, 2, 2, 16, 12).to_string();
+        let without_image: Vec<_> = with_image
+            .lines()
+            .filter(|l| !l.starts_with("<image"))
+            .collect();
+        assert_eq!(overlay, without_image.join("\n"));
+    }
+
+    #[test]
+    fn test_svg_polygon_fill_opacity() {
+        let mut data = LabelMeData::new(&[], &[], 8, 8, "");
+        data.shapes.push(Shape::polygon(
+            vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)],
+            "L1",
+        ));
+        let img = DynamicImage::new_rgb8(8, 8);
+        let colors = LabelColorsHex::new();
+        let render = |polygon_fill_opacity| {
+            let options = SvgOptions {
+                polygon_fill_opacity,
+                ..Default::default()
+            };
+            data.to_svg_with_options(&colors, 2, 2, &img, &options)
+                .to_string()
+        };
+        let outline = render(None);
+        assert!(outline.contains(r##"<g class="polygon L1" fill="none" stroke="#1f77b4""##));
+        assert_eq!(render(Some(0.0)), outline);
+        let filled = render(Some(0.3));
+        assert!(filled.contains(
+            r##"<g class="polygon L1" fill="#1f77b4" fill-opacity="0.3" stroke="#1f77b4""##
+        ));
+        // vertex markers are defined as before and not affected by the opacity
+        let defs = |svg: &str| svg[svg.find("<defs>").unwrap()..].to_string();
+        assert_eq!(defs(&filled), defs(&outline));
+    }
+
+    #[test]
+    fn test_svg_label_styles() -> Result<()> {
+        let mut data = LabelMeData::new(
+            &[(5.0, 6.0), (9.0, 9.0)],
+            &["L1".into(), "L2".into()],
+            16,
+            16,
+            "",
+        );
+        data.shapes.push(Shape::polygon(
+            vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)],
+            "L1",
+        ));
+        let img = DynamicImage::new_rgb8(16, 16);
+        let colors = LabelColorsHex::new();
+        let tmp_dir = tempfile::tempdir()?;
+        let config = tmp_dir.path().join("config.yaml");
+        std::fs::write(
+            &config,
+            "label_colors: {}\nlabel_styles:\n  L1: {radius: 4, line_width: 1}\n",
+        )?;
+        let options = SvgOptions {
+            label_styles: load_label_styles(&config)?,
+            ..Default::default()
+        };
+        let styled = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        let plain = data.to_svg(&colors, 2, 2, &img).to_string();
+        assert!(
+            styled.contains(r#"<circle cx="5" cy="6" r="4"/>"#),
+            "{}",
+            styled
+        );
+        // labels without styles are unchanged
+        let l2 = r##"<g class="point L2" fill="#ff7f0f" stroke="none">
+<circle cx="9" cy="9" r="2"/>
+</g>"##;
+        assert!(plain.contains(l2), "{}", plain);
+        assert!(styled.contains(l2));
+        assert!(styled
+            .contains(r##"class="polygon L1" fill="none" stroke="#2ca02c" stroke-width="1""##));
+        assert!(styled.contains(r##"marker-start="url(#dot#2ca02c-4)""##));
+        assert!(styled.contains(r##"id="dot#2ca02c-4""##));
+
+        std::fs::write(&config, "label_colors: {}\n")?;
+        assert!(load_label_styles(&config)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_mask() {
+        let data = LabelMeData {
+            shapes: vec![
+                shape("L1", "mask", vec![(4.0, 6.0), (1.0, 2.0)]),
+                shape("L1", "mask", vec![(1.0, 2.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 8, 8, "")
+        };
+        let img = DynamicImage::new_rgb8(8, 8);
+        let svg = data.to_svg(&LabelColorsHex::new(), 2, 2, &img).to_string();
+        assert!(svg.contains(r#"class="mask L1""#));
+        assert!(svg.contains(r#"stroke-dasharray="2 4""#));
+        assert!(svg.contains(r#"<rect height="4" width="3" x="1" y="2"/>"#));
+        assert_eq!(svg.matches("<rect").count(), 1);
+    }
+
+    #[test]
+    fn test_svg_show_labels() {
+        let data = LabelMeData {
+            shapes: vec![
+                shape("L1", "point", vec![(1.0, 20.0)]),
+                shape("L2", "rectangle", vec![(8.0, 9.0), (2.0, 3.0)]),
+                shape("L1", "polygon", vec![(0.0, 0.0), (6.0, 0.0), (0.0, 6.0)]),
+                shape("<L3>", "line", vec![(5.0, 5.0), (7.0, 7.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 32, 32, "")
+        };
+        let img = DynamicImage::new_rgb8(32, 32);
+        let colors = LabelColorsHex::from([("L1".to_string(), "#ff0000".to_string())]);
+        let options = SvgOptions {
+            show_labels: true,
+            font_size: Some(10),
+            ..Default::default()
+        };
+        let svg = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        assert!(svg.contains(
+            r##"<g class="label-text L1" fill="#ff0000" font-size="10" paint-order="stroke" stroke="#FFFFFF" stroke-width="1">"##
+        ));
+        assert!(svg.contains(r#"<text x="5" y="16">"#));
+        assert!(svg.contains(r#"<text x="0" y="0">"#));
+        assert!(svg.contains(r#"<text x="2" y="3">"#));
+        assert!(svg.contains("&lt;L3&gt;"));
+        assert_eq!(svg.matches("<text ").count(), 4);
+        let svg = data.to_svg(&colors, 2, 2, &img).to_string();
+        assert!(!svg.contains("<text"));
+
+        // the default font size scales with the point radius of the label
+        let options = SvgOptions {
+            show_labels: true,
+            label_styles: LabelStyles::from([(
+                "L2".to_string(),
+                LabelStyle {
+                    radius: Some(3),
+                    line_width: None,
+                },
+            )]),
+            ..Default::default()
+        };
+        let svg = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        assert!(svg.contains(r##"<g class="label-text L1" fill="#ff0000" font-size="12" paint-order="stroke" stroke="#FFFFFF" stroke-width="2">"##));
+        assert!(svg.contains(r##"<g class="label-text L2" fill="#1f77b4" font-size="18" paint-order="stroke" stroke="#FFFFFF" stroke-width="3">"##), "{svg}");
+    }
+
+    /// SVG of the fixture with all shape types and most of the options
+    fn all_shapes_svg() -> String {
+        let data = LabelMeData::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../tests/fixtures/all_shapes.json")
+                .as_path(),
+        )
+        .unwrap();
+        let mut img = image::RgbImage::new(32, 32);
+        for (x, _, pixel) in img.enumerate_pixels_mut() {
+            *pixel = image::Rgb([x as u8 * 8; 3]);
+        }
+        let colors = LabelColorsHex::from([("road".to_string(), "#808080".to_string())]);
+        let options = SvgOptions {
+            group_ids: true,
+            auto_contrast: true,
+            show_labels: true,
+            embed_image: false,
+            label_styles: LabelStyles::from([(
+                "lake".to_string(),
+                LabelStyle {
+                    radius: Some(3),
+                    line_width: Some(1),
+                },
+            )]),
+            polygon_fill_opacity: Some(0.25),
+            ..Default::default()
+        };
+        data.to_svg_with_options(&colors, 2, 2, &DynamicImage::ImageRgb8(img), &options)
+            .to_string()
+    }
+
+    #[test]
+    fn test_svg_all_shapes_fixture() {
+        let expected = include_str!("../../tests/fixtures/all_shapes.svg");
+        assert_eq!(all_shapes_svg(), expected.trim_end());
+    }
+
+    #[test]
+    fn test_svg_text_shape() {
+        let data = LabelMeData {
+            shapes: vec![
+                Shape {
+                    description: Some("STOP & GO".into()),
+                    ..shape("sign", "text", vec![(3.0, 4.0)])
+                },
+                shape("note", "text", vec![(3.0, 4.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 32, 32, "")
+        };
+        let img = DynamicImage::new_rgb8(32, 32);
+        let colors = LabelColorsHex::from([("sign".to_string(), "#ff0000".to_string())]);
+        let svg = data.to_svg(&colors, 2, 2, &img).to_string();
+        assert!(
+            svg.contains(
+                r##"<g class="text sign" fill="#ff0000" font-size="12" stroke="none">
+<text x="3" y="4">STOP &amp; GO</text>"##
+            ),
+            "{svg}"
+        );
+        assert!(svg.contains(r#"<text x="3" y="4">note</text>"#), "{svg}");
+        assert_eq!(data.count_by_shape_type()["text"].len(), 2);
+
+        // --show-labels does not draw labels over text shapes, and --font-size applies to them
+        let options = SvgOptions {
+            show_labels: true,
+            font_size: Some(20),
+            ..Default::default()
+        };
+        let svg = data
+            .to_svg_with_options(&colors, 2, 2, &img, &options)
+            .to_string();
+        assert!(
+            svg.contains(r##"<g class="text sign" fill="#ff0000" font-size="20" stroke="none">"##),
+            "{svg}"
+        );
+        assert!(!svg.contains(LABEL_TEXT_CLASS), "{svg}");
+        assert_eq!(svg.matches("<text ").count(), 2);
+
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(json.matches(r#""description":"STOP & GO""#).count(), 1);
+        assert_eq!(json.matches("description").count(), 1);
+        assert_eq!(LabelMeData::try_from(json.as_str()).unwrap(), data);
+    }
+
+    #[test]
+    fn test_svg_dashed_flag() {
+        let mut dashed = shape("L1", "polygon", vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0)]);
+        dashed.flags.insert(DASHED_FLAG.into(), true);
+        let data = LabelMeData {
+            shapes: vec![
+                dashed,
+                shape("L1", "polygon", vec![(1.0, 1.0), (3.0, 1.0), (3.0, 3.0)]),
+            ],
+            ..LabelMeData::new(&[], &[], 4, 4, "")
+        };
+        let img = DynamicImage::new_rgb8(4, 4);
+        let svg = data.to_svg(&LabelColorsHex::new(), 2, 1, &img).to_string();
+        assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+        assert!(svg.contains(r#"<polygon marker-mid="url(#dot#1f77b4)" marker-start="url(#dot#1f77b4)" points="0 0 2 0 2 2" stroke-dasharray="4 2"/>"#), "{}", svg);
+        assert!(svg.contains(r#"marker-start="url(#dot#1f77b4)" points="1 1 3 1 3 3"/>"#));
+    }
+
+    #[test]
+    fn test_parse_duplicates() {
+        let json = r#"{
+            "version": "5.0.1",
+            "flags": {"todo": true, "reviewed": false, "todo": false, "reviewed": true},
+            "shapes": [
+                {"label": "L1", "points": [[0, 0]], "group_id": null, "shape_type": "point", "flags": {}},
+                {"label": "L2", "points": [[0, 0]], "group_id": null, "shape_type": "point", "flags": {}},
+                {"label": "L1", "points": [[0, 0]], "group_id": null, "shape_type": "point", "flags": {}}
+            ],
+            "imagePath": "img.jpg",
+            "imageData": null,
+            "imageHeight": 4,
+            "imageWidth": 4
+        }"#;
+        let (data, warnings) = LabelMeData::parse_with_warnings(json).unwrap();
+        assert_eq!(
+            data.flags.iter().collect::<Vec<_>>(),
+            vec![
+                (&"todo".to_string(), &false),
+                (&"reviewed".to_string(), &true)
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::DuplicateFlag("todo".into()),
+                ParseWarning::DuplicateFlag("reviewed".into()),
+                ParseWarning::DuplicateShape {
+                    index: 2,
+                    original: 0
+                },
+            ]
+        );
+        assert_eq!(data.shapes.len(), 3);
+        // the same resolution as the default parse
+        assert_eq!(LabelMeData::try_from(json).unwrap(), data);
+        assert!(matches!(
+            LabelMeData::parse_strict(json),
+            Err(LabelMeDataError::DuplicateFlag(name)) if name == "todo"
+        ));
+
+        let json = json.replace(r#", "todo": false, "reviewed": true"#, "");
+        let data = LabelMeData::parse_strict(&json).unwrap();
+        assert_eq!(data.flags.len(), 2);
+        let (_, warnings) = LabelMeData::parse_with_warnings(r#"{"version": "", "flags": null, "imagePath": "", "imageData": null, "imageHeight": 0, "imageWidth": 0}"#).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_region_mean_luma() {
+        let black = DynamicImage::new_rgb8(64, 64);
+        let white = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            64,
+            64,
+            image::Rgb([255, 255, 255]),
+        ));
+        assert_eq!(region_mean_luma(&black, (0.0, 0.0, 64.0, 64.0)), 0.0);
+        assert!((region_mean_luma(&white, (10.0, 10.0, 20.0, 20.0)) - 1.0).abs() < 1e-9);
+        // clipped by the image
+        assert!((region_mean_luma(&white, (-10.0, 60.0, 100.0, 100.0)) - 1.0).abs() < 1e-9);
+        assert_eq!(region_mean_luma(&black, (100.0, 100.0, 200.0, 200.0)), 0.0);
+        // left half is white
+        let mut half = image::RgbImage::new(64, 64);
+        for (x, _, p) in half.enumerate_pixels_mut() {
+            if x < 32 {
+                *p = image::Rgb([255, 255, 255]);
+            }
+        }
+        let half = DynamicImage::ImageRgb8(half);
+        assert!((region_mean_luma(&half, (0.0, 0.0, 64.0, 64.0)) - 0.5).abs() < 1e-9);
+        assert_eq!(region_mean_luma(&half, (40.0, 0.0, 60.0, 64.0)), 0.0);
+        assert_eq!(contrasting_color(0.0), "#FFFFFF");
+        assert_eq!(contrasting_color(1.0), "#000000");
+    }
+
+    #[test]
+    fn test_svg_auto_contrast() {
+        let data = LabelMeData::new(&[(2.0, 2.0)], &["L1".into()], 8, 8, "");
+        let options = SvgOptions {
+            auto_contrast: true,
+            ..Default::default()
+        };
+        let colors = LabelColorsHex::new();
+        let dark = DynamicImage::new_rgb8(8, 8);
+        let svg = data
+            .to_svg_with_options(&colors, 2, 1, &dark, &options)
+            .to_string();
+        assert!(
+            svg.contains(r##"<circle class="halo" cx="2" cy="2" fill="none" r="2" stroke="#FFFFFF" stroke-width="3"/>"##),
+            "{}",
+            svg
+        );
+        let light = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            8,
+            8,
+            image::Rgb([250, 250, 200]),
+        ));
+        let svg = data
+            .to_svg_with_options(&colors, 2, 1, &light, &options)
+            .to_string();
+        assert!(svg.contains(r##"class="halo""##));
+        assert!(svg.contains(r##"stroke="#000000""##));
+        let svg = data.to_svg(&colors, 2, 1, &light).to_string();
+        assert!(!svg.contains("halo"));
+    }
+
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!(Color::from_hex("#1f77B4").unwrap(), Color(0x1f, 0x77, 0xb4));
+        assert_eq!(Color::from_hex("#FF0000").unwrap().to_hex(), "#FF0000");
+        for hex in TAB10 {
+            assert_eq!(
+                Color::from_hex(hex).unwrap().to_hex(),
+                hex.to_ascii_uppercase()
+            );
+        }
+        for invalid in ["1f77b4", "#1f77b", "#1f77b4f", "#gggggg", "red", "#ＡＢ"] {
+            assert!(Color::from_hex(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_save_label_colors() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let filename = tmp_dir.path().join("colors.yaml");
+        std::fs::write(
+            &filename,
+            "auto_save: true\nlabel_colors:\n  L1: [255, 0, 0]\n",
+        )?;
+        let colors = LabelColorsHex::from_iter([
+            ("L2".to_string(), "#00ff00".to_string()),
+            ("L1".to_string(), "#0000FF".to_string()),
+        ]);
+        save_label_colors(&filename, &colors)?;
+        let loaded = load_label_colors(&filename)?;
+        assert_eq!(loaded["L1"], "#FF0000", "existing entry is preserved");
+        assert_eq!(loaded["L2"], "#00FF00");
+        assert!(std::fs::read_to_string(&filename)?.contains("auto_save: true"));
+        assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+
+        let new_file = tmp_dir.path().join("new.yaml");
+        save_label_colors(&new_file, &colors)?;
+        assert_eq!(load_label_colors(&new_file)?["L1"], "#0000FF");
+
+        let colors = LabelColorsHex::from_iter([("L3".to_string(), "red".to_string())]);
+        assert!(matches!(
+            save_label_colors(&filename, &colors),
+            Err(LabelColorError::InvalidHexColor(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_label_colors_to_yaml() -> Result<()> {
+        let colors = LabelColorsHex::from_iter([
+            ("L2".to_string(), "#00ff00".to_string()),
+            ("L1".to_string(), "#FF0000".to_string()),
+        ]);
+        let yaml = label_colors_to_yaml(&colors)?;
+        assert!(yaml.find("L1").unwrap() < yaml.find("L2").unwrap());
+        let tmp_dir = tempfile::tempdir()?;
+        let filename = tmp_dir.path().join("colors.yaml");
+        std::fs::write(&filename, yaml)?;
+        let loaded = load_label_colors(&filename)?;
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["L2"], "#00FF00");
+        Ok(())
+    }
+
     #[test]
     fn test_color_cycler() {
         let mut cycler = ColorCycler::default();
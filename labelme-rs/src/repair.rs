@@ -0,0 +1,299 @@
+//! Repairs for malformed shapes persisted by labelme or other tools
+use crate::{LabelMeData, Point, Shape};
+use indexmap::IndexMap;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Repair type
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Repair {
+    /// Reorder rectangle corners to (top-left, bottom-right)
+    CornerOrder,
+    /// Remove consecutive identical vertices
+    DedupeVertices,
+    /// Drop the redundant last vertex of a polygon (or a linestrip) whose first and last vertices are within epsilon
+    ClosePolygons,
+    /// Drop rectangles and polygons without area
+    DropZeroArea,
+}
+
+impl Repair {
+    /// All repairs in the order of application
+    pub const ALL: [Repair; 4] = [
+        Repair::CornerOrder,
+        Repair::DedupeVertices,
+        Repair::ClosePolygons,
+        Repair::DropZeroArea,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Repair::CornerOrder => "corner-order",
+            Repair::DedupeVertices => "dedupe-vertices",
+            Repair::ClosePolygons => "close-polygons",
+            Repair::DropZeroArea => "drop-zero-area",
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown repair: {0}")]
+pub struct UnknownRepairError(String);
+
+impl std::str::FromStr for Repair {
+    type Err = UnknownRepairError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Repair::ALL
+            .into_iter()
+            .find(|r| r.name() == s)
+            .ok_or_else(|| UnknownRepairError(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Reorder the two corners of a rectangle to (min x, min y), (max x, max y).
+/// Returns true if the corners were changed
+pub fn fix_corner_order(shape: &mut Shape) -> bool {
+    if shape.shape_type != "rectangle" || shape.points.len() != 2 {
+        return false;
+    }
+    let (p0, p1) = (shape.points[0], shape.points[1]);
+    let fixed = vec![
+        (p0.0.min(p1.0), p0.1.min(p1.1)),
+        (p0.0.max(p1.0), p0.1.max(p1.1)),
+    ];
+    if fixed == shape.points {
+        false
+    } else {
+        shape.points = fixed;
+        true
+    }
+}
+
+/// Remove consecutive identical vertices of polygons, linestrips, and lines.
+/// Returns the number of removed vertices
+pub fn dedupe_vertices(shape: &mut Shape) -> usize {
+    dedupe_near_vertices(shape, 0.0)
+}
+
+/// Remove consecutive vertices within `tolerance` of the previous kept vertex from polygons, linestrips, and lines.
+///
+/// The last vertex is kept in place of the removed one before it so that explicitly closed shapes stay closed.
+/// The first and last vertices are not compared with each other.
+/// Returns the number of removed vertices
+pub fn dedupe_near_vertices(shape: &mut Shape, tolerance: f64) -> usize {
+    if !matches!(shape.shape_type.as_str(), "polygon" | "linestrip" | "line") {
+        return 0;
+    }
+    let len = shape.points.len();
+    let Some(&last) = shape.points.last() else {
+        return 0;
+    };
+    let mut kept: Vec<Point> = Vec::with_capacity(len);
+    for p in &shape.points {
+        if !kept.last().is_some_and(|k| is_near(k, p, tolerance)) {
+            kept.push(*p);
+        }
+    }
+    if kept.len() > 1 {
+        *kept.last_mut().unwrap() = last;
+    }
+    shape.points = kept;
+    len - shape.points.len()
+}
+
+fn is_near(p0: &Point, p1: &Point, epsilon: f64) -> bool {
+    (p0.0 - p1.0).hypot(p0.1 - p1.1) <= epsilon
+}
+
+/// Close a polygon drawn with an explicit closing vertex.
+///
+/// Labelme closes polygons implicitly, so the last vertex of a polygon within `epsilon` of the first vertex is dropped.
+/// A linestrip whose endpoints are within `epsilon` is converted into a polygon the same way.
+/// Returns true if the shape was changed
+pub fn close_polygon(shape: &mut Shape, epsilon: f64) -> bool {
+    let min_len = match shape.shape_type.as_str() {
+        "polygon" | "linestrip" => 4,
+        _ => return false,
+    };
+    if shape.points.len() < min_len
+        || !is_near(
+            shape.points.first().unwrap(),
+            shape.points.last().unwrap(),
+            epsilon,
+        )
+    {
+        return false;
+    }
+    shape.points.pop();
+    shape.shape_type = "polygon".into();
+    true
+}
+
+/// Check if the shape is a rectangle or a polygon without area, including ones with too few points.
+/// See [`Shape::area`]
+pub fn is_zero_area(shape: &Shape) -> bool {
+    matches!(shape.shape_type.as_str(), "rectangle" | "polygon")
+        && !shape.area().is_some_and(|a| a > 0.0)
+}
+
+/// Apply `repairs` to `data` in the order of [`Repair::ALL`].
+/// Returns the number of changes for each repair
+pub fn repair(data: &mut LabelMeData, repairs: &[Repair], epsilon: f64) -> IndexMap<Repair, usize> {
+    let mut counts = IndexMap::new();
+    for r in Repair::ALL.into_iter().filter(|r| repairs.contains(r)) {
+        let count = match r {
+            Repair::CornerOrder => data
+                .shapes
+                .iter_mut()
+                .filter_map(|s| fix_corner_order(s).then_some(()))
+                .count(),
+            Repair::DedupeVertices => data.shapes.iter_mut().map(dedupe_vertices).sum(),
+            Repair::ClosePolygons => data
+                .shapes
+                .iter_mut()
+                .filter_map(|s| close_polygon(s, epsilon).then_some(()))
+                .count(),
+            Repair::DropZeroArea => {
+                let len = data.shapes.len();
+                data.shapes.retain(|s| !is_zero_area(s));
+                len - data.shapes.len()
+            }
+        };
+        counts.insert(r, count);
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::shape;
+
+    #[test]
+    fn test_fix_corner_order() {
+        let mut s = shape("L", "rectangle", vec![(10.0, 2.0), (1.0, 20.0)]);
+        assert!(fix_corner_order(&mut s));
+        assert_eq!(s.points, vec![(1.0, 2.0), (10.0, 20.0)]);
+        assert!(!fix_corner_order(&mut s));
+        let mut s = shape("L", "line", vec![(10.0, 2.0), (1.0, 20.0)]);
+        assert!(!fix_corner_order(&mut s));
+    }
+
+    #[test]
+    fn test_dedupe_vertices() {
+        let mut s = shape(
+            "L",
+            "polygon",
+            vec![(0.0, 0.0), (0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (1.0, 1.0)],
+        );
+        assert_eq!(dedupe_vertices(&mut s), 2);
+        assert_eq!(s.points, vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+        let mut s = shape("L", "point", vec![(0.0, 0.0), (0.0, 0.0)]);
+        assert_eq!(dedupe_vertices(&mut s), 0);
+    }
+
+    #[test]
+    fn test_dedupe_near_vertices() {
+        // explicitly closed linestrip stays closed
+        let mut s = shape(
+            "L",
+            "linestrip",
+            vec![
+                (0.0, 0.0),
+                (5.0, 0.0),
+                (5.2, 0.1),
+                (5.0, 5.0),
+                (0.1, 0.0),
+                (0.0, 0.0),
+            ],
+        );
+        assert_eq!(dedupe_near_vertices(&mut s, 0.5), 2);
+        assert_eq!(
+            s.points,
+            vec![(0.0, 0.0), (5.0, 0.0), (5.0, 5.0), (0.0, 0.0)]
+        );
+        let mut s = shape("L", "line", vec![(0.0, 0.0), (0.1, 0.0)]);
+        assert_eq!(dedupe_near_vertices(&mut s, 0.5), 1);
+        assert_eq!(s.points, vec![(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_close_polygon() {
+        let mut s = shape(
+            "L",
+            "linestrip",
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.5, 0.0)],
+        );
+        assert!(!close_polygon(&mut s, 0.1));
+        assert!(close_polygon(&mut s, 1.0));
+        assert_eq!(s.shape_type, "polygon");
+        assert_eq!(s.points.len(), 3);
+        assert!(!close_polygon(&mut s, 1.0));
+    }
+
+    #[test]
+    fn test_is_zero_area() {
+        assert!(is_zero_area(&shape(
+            "L",
+            "rectangle",
+            vec![(1.0, 1.0), (1.0, 5.0)]
+        )));
+        assert!(!is_zero_area(&shape(
+            "L",
+            "rectangle",
+            vec![(1.0, 1.0), (2.0, 5.0)]
+        )));
+        assert!(is_zero_area(&shape(
+            "L",
+            "polygon",
+            vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]
+        )));
+        assert!(!is_zero_area(&shape(
+            "L",
+            "polygon",
+            vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]
+        )));
+        assert!(is_zero_area(&shape(
+            "L",
+            "polygon",
+            vec![(0.0, 0.0), (1.0, 1.0)]
+        )));
+        assert!(!is_zero_area(&shape("L", "point", vec![(0.0, 0.0)])));
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut data = LabelMeData {
+            shapes: vec![
+                shape("L", "rectangle", vec![(10.0, 10.0), (0.0, 0.0)]),
+                shape("L", "polygon", vec![(0.0, 0.0), (0.0, 0.0), (1.0, 1.0)]),
+            ],
+            ..Default::default()
+        };
+        let counts = repair(&mut data, &[Repair::CornerOrder], 1.0);
+        assert_eq!(counts.get(&Repair::CornerOrder), Some(&1));
+        assert_eq!(counts.len(), 1);
+        let counts = repair(&mut data, &Repair::ALL, 1.0);
+        assert_eq!(counts.get(&Repair::CornerOrder), Some(&0));
+        assert_eq!(counts.get(&Repair::DedupeVertices), Some(&1));
+        assert_eq!(counts.get(&Repair::DropZeroArea), Some(&1));
+        assert_eq!(data.shapes.len(), 1);
+    }
+
+    #[test]
+    fn test_repair_from_str() {
+        assert_eq!(
+            "dedupe-vertices".parse::<Repair>().unwrap(),
+            Repair::DedupeVertices
+        );
+        assert!("unknown".parse::<Repair>().is_err());
+    }
+}
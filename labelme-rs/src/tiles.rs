@@ -0,0 +1,126 @@
+//! Image pyramid tiles in the Deep Zoom Image (DZI) layout
+//!
+//! Level `max_level(width, height)` is the full resolution and each lower level halves the size down to 1x1 at level 0.
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+/// Tile of the image pyramid
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub level: u32,
+    pub col: u32,
+    pub row: u32,
+    pub image: DynamicImage,
+}
+
+/// The highest (i.e. full resolution) level
+pub fn max_level(width: u32, height: u32) -> u32 {
+    let size = width.max(height).max(1);
+    u32::BITS - (size - 1).leading_zeros()
+}
+
+/// Image size at the level
+pub fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let scale = 1u64 << (max_level(width, height) - level.min(max_level(width, height)));
+    (
+        (width as u64).div_ceil(scale) as u32,
+        (height as u64).div_ceil(scale) as u32,
+    )
+}
+
+/// The number of (columns, rows) of tiles at the level
+pub fn tile_counts(width: u32, height: u32, level: u32, tile_size: u32) -> (u32, u32) {
+    let (w, h) = level_size(width, height, level);
+    (w.div_ceil(tile_size), h.div_ceil(tile_size))
+}
+
+/// Crop and scale a tile. `None` if the tile is out of the pyramid
+pub fn tile(img: &DynamicImage, tile_size: u32, level: u32, col: u32, row: u32) -> Option<Tile> {
+    let (width, height) = img.dimensions();
+    if level > max_level(width, height) {
+        return None;
+    }
+    let (cols, rows) = tile_counts(width, height, level, tile_size);
+    if col >= cols || row >= rows {
+        return None;
+    }
+    let (level_width, level_height) = level_size(width, height, level);
+    let tile_width = tile_size.min(level_width - col * tile_size);
+    let tile_height = tile_size.min(level_height - row * tile_size);
+    let scale = 1u64 << (max_level(width, height) - level);
+    let x = col as u64 * tile_size as u64 * scale;
+    let y = row as u64 * tile_size as u64 * scale;
+    let region = img.crop_imm(
+        x as u32,
+        y as u32,
+        (tile_width as u64 * scale).min(width as u64 - x) as u32,
+        (tile_height as u64 * scale).min(height as u64 - y) as u32,
+    );
+    let image = if region.dimensions() == (tile_width, tile_height) {
+        region
+    } else {
+        region.resize_exact(tile_width, tile_height, FilterType::Triangle)
+    };
+    Some(Tile {
+        level,
+        col,
+        row,
+        image,
+    })
+}
+
+/// Iterate over all tiles from the full resolution level to level 0
+pub fn make_tiles(img: &DynamicImage, tile_size: u32) -> impl Iterator<Item = Tile> + '_ {
+    let (width, height) = img.dimensions();
+    (0..=max_level(width, height)).rev().flat_map(move |level| {
+        let (cols, rows) = tile_counts(width, height, level, tile_size);
+        (0..rows).flat_map(move |row| {
+            (0..cols).map(move |col| tile(img, tile_size, level, col, row).unwrap())
+        })
+    })
+}
+
+/// DZI descriptor with jpeg tiles without overlap.
+/// `url` is the base url of the tiles, which is `<name>_files/` next to the descriptor if omitted
+pub fn dzi_descriptor(width: u32, height: u32, tile_size: u32, url: Option<&str>) -> String {
+    let url = url.map_or_else(String::new, |url| format!(r#" Url="{}""#, url));
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="jpg" Overlap="0" TileSize="{tile_size}"{url}>
+  <Size Width="{width}" Height="{height}"/>
+</Image>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(max_level(1, 1), 0);
+        assert_eq!(max_level(256, 256), 8);
+        assert_eq!(max_level(600, 300), 10);
+        assert_eq!(level_size(600, 300, 10), (600, 300));
+        assert_eq!(level_size(600, 300, 9), (300, 150));
+        assert_eq!(level_size(600, 300, 8), (150, 75));
+        assert_eq!(level_size(600, 300, 0), (1, 1));
+    }
+
+    #[test]
+    fn test_make_tiles() {
+        let img = DynamicImage::new_rgb8(600, 300);
+        let tiles: Vec<_> = make_tiles(&img, 256).collect();
+        // 3x2 tiles at level 10, 2x1 at level 9, and 1 for each of levels 0-8
+        assert_eq!(tiles.len(), 6 + 2 + 9);
+        assert_eq!(tiles.iter().filter(|t| t.level == 10).count(), 6);
+        assert_eq!(tiles.iter().filter(|t| t.level == 9).count(), 2);
+        let last = tiles.iter().find(|t| (t.level, t.col, t.row) == (10, 2, 1));
+        assert_eq!(last.unwrap().image.dimensions(), (88, 44));
+        let low = tiles.iter().find(|t| (t.level, t.col, t.row) == (9, 1, 0));
+        assert_eq!(low.unwrap().image.dimensions(), (44, 150));
+        assert_eq!(tiles.last().unwrap().image.dimensions(), (1, 1));
+        assert!(tile(&img, 256, 10, 3, 0).is_none());
+        assert!(tile(&img, 256, 11, 0, 0).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+//! Conversion of labelme data into Pascal VOC xml
+//!
+//! VOC coordinates are 1-based pixel indices of the pixel centers, whereas labelme places (0, 0) at the top-left corner of the first pixel.
+//! Points are converted with [`ORIGIN_SHIFT`] and then [`INDEX_BASE`], e.g. the center of the first pixel `(0.5, 0.5)` becomes `(1, 1)`.
+//! `xmin`/`ymin` and the inclusive `xmax`/`ymax` are the first and the last pixels in the box, which are half a pixel inside its edges.
+//! Therefore a box from `(x0, y0)` to `(x1, y1)` in labelme becomes `xmin = x0 + 1`, `ymin = y0 + 1`, `xmax = x1`, and `ymax = y1`.
+//!
+//! Objects are marked as `difficult` if the shape flag [`DIFFICULT_FLAG`] is set.
+use crate::{LabelMeData, OriginConvention};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Shift from the corner-based labelme coordinates into the center-based VOC ones
+pub const ORIGIN_SHIFT: Option<OriginConvention> = Some(OriginConvention::CornerToCenter);
+
+/// Index of the first pixel in VOC
+pub const INDEX_BASE: f64 = 1.0;
+
+/// Shape flag to mark the object as `difficult`
+pub const DIFFICULT_FLAG: &str = "difficult";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VocOptions {
+    /// Export polygons with their vertices in `<segm>` instead of skipping them
+    pub include_segmentation: bool,
+    /// Export polygons as their bounding boxes instead of skipping them
+    pub polygon_bboxes: bool,
+}
+
+/// Escape the text for xml
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Pascal VOC `<annotation>` of the rectangles (and polygons with [`VocOptions::include_segmentation`] or [`VocOptions::polygon_bboxes`]).
+/// `folder` and `filename` are taken from `imagePath`
+///
+/// ```
+/// let data = labelme_rs::LabelMeData {
+///     shapes: vec![labelme_rs::Shape::rectangle((10.0, 20.0), (30.0, 60.0), "car")],
+///     imageWidth: 100,
+///     imageHeight: 200,
+///     imagePath: "images/img.jpg".into(),
+///     ..Default::default()
+/// };
+/// let xml = labelme_rs::voc::to_voc_xml(&data, &Default::default());
+/// assert!(xml.contains("<filename>img.jpg</filename>"));
+/// assert!(xml.contains("<xmin>11</xmin>"));
+/// assert!(xml.contains("<xmax>30</xmax>"));
+/// ```
+pub fn to_voc_xml(data: &LabelMeData, options: &VocOptions) -> String {
+    let mut xml = String::new();
+    write_voc_xml(&mut xml, data, options).expect("Writing to String never fails");
+    xml
+}
+
+fn write_voc_xml(
+    xml: &mut impl Write,
+    data: &LabelMeData,
+    options: &VocOptions,
+) -> std::fmt::Result {
+    let image_path = Path::new(&data.imagePath);
+    let folder = image_path
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|f| f.to_string_lossy())
+        .unwrap_or_default();
+    let filename = image_path
+        .file_name()
+        .map(|f| f.to_string_lossy())
+        .unwrap_or_default();
+    writeln!(xml, "<annotation>")?;
+    writeln!(xml, "  <folder>{}</folder>", escape(&folder))?;
+    writeln!(xml, "  <filename>{}</filename>", escape(&filename))?;
+    writeln!(xml, "  <size>")?;
+    writeln!(xml, "    <width>{}</width>", data.imageWidth)?;
+    writeln!(xml, "    <height>{}</height>", data.imageHeight)?;
+    writeln!(xml, "    <depth>3</depth>")?;
+    writeln!(xml, "  </size>")?;
+    writeln!(
+        xml,
+        "  <segmented>{}</segmented>",
+        options.include_segmentation as u8
+    )?;
+    let mut shifted = data.with_origin_shift(ORIGIN_SHIFT).into_owned();
+    shifted.shift(INDEX_BASE, INDEX_BASE);
+    for shape in &shifted.shapes {
+        let segm = match shape.shape_type.as_str() {
+            "rectangle" if shape.points.len() >= 2 => None,
+            "polygon" if options.include_segmentation && !shape.points.is_empty() => {
+                Some(&shape.points)
+            }
+            "polygon" if options.polygon_bboxes && !shape.points.is_empty() => None,
+            _ => continue,
+        };
+        let Some(((x0, y0), (x1, y1))) = shape.bounding_box() else {
+            continue;
+        };
+        writeln!(xml, "  <object>")?;
+        writeln!(xml, "    <name>{}</name>", escape(&shape.label))?;
+        writeln!(xml, "    <pose>Unspecified</pose>")?;
+        writeln!(xml, "    <truncated>0</truncated>")?;
+        let difficult = shape.flags.get(DIFFICULT_FLAG).copied().unwrap_or(false);
+        writeln!(xml, "    <difficult>{}</difficult>", difficult as u8)?;
+        writeln!(xml, "    <bndbox>")?;
+        // centers of the first and the last pixels in the box
+        writeln!(xml, "      <xmin>{}</xmin>", x0 + 0.5)?;
+        writeln!(xml, "      <ymin>{}</ymin>", y0 + 0.5)?;
+        writeln!(xml, "      <xmax>{}</xmax>", x1 - 0.5)?;
+        writeln!(xml, "      <ymax>{}</ymax>", y1 - 0.5)?;
+        writeln!(xml, "    </bndbox>")?;
+        if let Some(points) = segm {
+            let points: Vec<_> = points.iter().map(|(x, y)| format!("{} {}", x, y)).collect();
+            writeln!(xml, "    <segm>{}</segm>", points.join(" "))?;
+        }
+        writeln!(xml, "  </object>")?;
+    }
+    writeln!(xml, "</annotation>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shape;
+
+    #[test]
+    fn test_to_voc_xml() {
+        let mut data = LabelMeData::new(&[(5.0, 5.0)], &["P".into()], 40, 20, "dir/img.jpg");
+        data.shapes
+            .push(Shape::rectangle((0.0, 0.0), (40.0, 20.0), "a&b"));
+        data.shapes.push(Shape::polygon(
+            vec![(0.5, 0.0), (10.0, 5.0), (3.0, 8.0)],
+            "<poly>",
+        ));
+        let xml = to_voc_xml(&data, &VocOptions::default());
+        assert!(xml
+            .starts_with("<annotation>\n  <folder>dir</folder>\n  <filename>img.jpg</filename>\n"));
+        assert!(xml.contains("<segmented>0</segmented>"));
+        assert!(xml.contains("<name>a&amp;b</name>"));
+        assert!(xml.contains(
+            "<xmin>1</xmin>\n      <ymin>1</ymin>\n      <xmax>40</xmax>\n      <ymax>20</ymax>"
+        ));
+        assert_eq!(xml.matches("<object>").count(), 1);
+        assert!(xml.ends_with("</annotation>\n"));
+
+        let options = VocOptions {
+            include_segmentation: true,
+            ..Default::default()
+        };
+        let xml = to_voc_xml(&data, &options);
+        assert_eq!(xml.matches("<object>").count(), 2);
+        assert!(xml.contains("<name>&lt;poly&gt;</name>"));
+        assert!(xml.contains("<xmin>1.5</xmin>"));
+        assert!(xml.contains("<segm>1 0.5 10.5 5.5 3.5 8.5</segm>"));
+
+        // vertices in `<segm>` are converted back exactly
+        let segm = xml
+            .split("<segm>")
+            .nth(1)
+            .unwrap()
+            .split("</segm>")
+            .next()
+            .unwrap();
+        let values: Vec<f64> = segm.split(' ').map(|v| v.parse().unwrap()).collect();
+        let mut restored = LabelMeData {
+            shapes: vec![Shape::polygon(
+                values.chunks_exact(2).map(|p| (p[0], p[1])).collect(),
+                "<poly>",
+            )],
+            ..Default::default()
+        };
+        restored.shift(-INDEX_BASE, -INDEX_BASE);
+        restored.shift_origin(ORIGIN_SHIFT.unwrap().inverse());
+        assert_eq!(restored.shapes[0].points, data.shapes[2].points);
+
+        let options = VocOptions {
+            polygon_bboxes: true,
+            ..Default::default()
+        };
+        data.shapes[2].flags.insert(DIFFICULT_FLAG.into(), true);
+        let xml = to_voc_xml(&data, &options);
+        assert_eq!(xml.matches("<object>").count(), 2);
+        assert_eq!(xml.matches("<difficult>1</difficult>").count(), 1);
+        assert!(xml.contains("<xmin>1.5</xmin>"));
+        assert!(!xml.contains("<segm>"));
+    }
+}
@@ -0,0 +1,254 @@
+//! Conversion of labelme data into YOLO txt (bounding boxes)
+//!
+//! Each line is `class cx cy w h` normalized by the image size.
+//! YOLO places (0, 0) at the top-left corner of the first pixel as labelme does, so [`ORIGIN_SHIFT`] keeps coordinates as they are and they are only normalized.
+//! Boxes are clamped to the image beforehand, and boxes without area after clamping (e.g. outside the image) are dropped.
+use crate::{LabelMeData, OriginConvention};
+use std::fmt;
+use thiserror::Error;
+
+/// Shift from the labelme coordinates into the YOLO ones. None since both are corner-based
+pub const ORIGIN_SHIFT: Option<OriginConvention> = None;
+
+#[derive(Error, Debug)]
+pub enum YoloError {
+    #[error("label {0:?} is not in the class names")]
+    UnknownLabel(String),
+}
+
+/// Bounding box normalized by the image size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YoloBox {
+    pub class: usize,
+    pub cx: f64,
+    pub cy: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+/// Fixed 6 decimals so that outputs are diffable. Negative zero is written as zero
+fn fmt_coord(v: f64) -> String {
+    format!("{:.6}", v + 0.0)
+}
+
+impl fmt::Display for YoloBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.class,
+            fmt_coord(self.cx),
+            fmt_coord(self.cy),
+            fmt_coord(self.w),
+            fmt_coord(self.h)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct YoloOptions {
+    /// Skip shapes whose labels are not in the class names instead of failing
+    pub skip_unknown: bool,
+    /// Convert polygons into their bounding boxes instead of skipping them
+    pub poly_as_bbox: bool,
+    /// Convert every shape into its bounding box. See [`crate::Shape::bounding_box`]
+    pub all_shapes: bool,
+}
+
+impl YoloOptions {
+    fn converts(&self, shape: &crate::Shape) -> bool {
+        match shape.shape_type.as_str() {
+            _ if self.all_shapes => !shape.points.is_empty(),
+            "rectangle" => shape.points.len() >= 2,
+            "polygon" => self.poly_as_bbox && !shape.points.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Sorted labels of the shapes to convert
+///
+/// ```
+/// let data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["point".into()], 8, 8, "");
+/// let mut options = labelme_rs::yolo::YoloOptions::default();
+/// assert!(labelme_rs::yolo::class_names([&data], &options).is_empty());
+/// options.all_shapes = true;
+/// assert_eq!(labelme_rs::yolo::class_names([&data], &options), vec!["point"]);
+/// ```
+pub fn class_names<'a>(
+    entries: impl IntoIterator<Item = &'a LabelMeData>,
+    options: &YoloOptions,
+) -> Vec<String> {
+    let labels: std::collections::BTreeSet<_> = entries
+        .into_iter()
+        .flat_map(|data| &data.shapes)
+        .filter(|shape| options.converts(shape))
+        .map(|shape| shape.label.clone())
+        .collect();
+    labels.into_iter().collect()
+}
+
+/// Boxes of rectangles (and polygons with [`YoloOptions::poly_as_bbox`], or all shapes with [`YoloOptions::all_shapes`]). Class indices are the positions of the labels in `names`
+///
+/// ```
+/// let data = labelme_rs::LabelMeData {
+///     shapes: vec![labelme_rs::Shape::rectangle((10.0, 20.0), (30.0, 60.0), "car")],
+///     imageWidth: 100,
+///     imageHeight: 200,
+///     ..Default::default()
+/// };
+/// let names = vec!["person".to_string(), "car".to_string()];
+/// let boxes = labelme_rs::yolo::to_yolo_boxes(&data, &names, &Default::default()).unwrap();
+/// assert_eq!(boxes[0].to_string(), "1 0.200000 0.200000 0.200000 0.200000");
+/// ```
+pub fn to_yolo_boxes(
+    data: &LabelMeData,
+    names: &[String],
+    options: &YoloOptions,
+) -> Result<Vec<YoloBox>, YoloError> {
+    let data = data.with_origin_shift(ORIGIN_SHIFT);
+    let (width, height) = (data.imageWidth as f64, data.imageHeight as f64);
+    let mut boxes = vec![];
+    for shape in &data.shapes {
+        if !options.converts(shape) {
+            continue;
+        }
+        let Some(class) = names.iter().position(|name| *name == shape.label) else {
+            if options.skip_unknown {
+                continue;
+            }
+            return Err(YoloError::UnknownLabel(shape.label.clone()));
+        };
+        let Some(((x0, y0), (x1, y1))) = shape.bounding_box() else {
+            continue;
+        };
+        let (x0, x1) = (x0.clamp(0.0, width), x1.clamp(0.0, width));
+        let (y0, y1) = (y0.clamp(0.0, height), y1.clamp(0.0, height));
+        if x0 >= x1 || y0 >= y1 {
+            continue;
+        }
+        boxes.push(YoloBox {
+            class,
+            cx: (x0 + x1) / 2.0 / width,
+            cy: (y0 + y1) / 2.0 / height,
+            w: (x1 - x0) / width,
+            h: (y1 - y0) / height,
+        });
+    }
+    Ok(boxes)
+}
+
+/// Contents of the YOLO txt, one line per box. See [`to_yolo_boxes`]
+pub fn to_yolo_txt(
+    data: &LabelMeData,
+    names: &[String],
+    options: &YoloOptions,
+) -> Result<String, YoloError> {
+    Ok(to_yolo_boxes(data, names, options)?
+        .iter()
+        .map(|b| format!("{b}\n"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shape;
+
+    #[test]
+    fn test_to_yolo_txt() {
+        let mut data = LabelMeData::new(&[(5.0, 5.0)], &["car".into()], 40, 20, "img.jpg");
+        data.shapes
+            .push(Shape::rectangle((30.0, 10.0), (10.0, 0.0), "car"));
+        data.shapes.push(Shape::polygon(
+            vec![(0.0, 0.0), (40.0, 5.0), (20.0, 20.0)],
+            "road",
+        ));
+        data.shapes
+            .push(Shape::rectangle((0.0, 0.0), (1.0, 1.0), "tree"));
+        let names = vec!["road".to_string(), "car".to_string()];
+
+        assert!(matches!(
+            to_yolo_txt(&data, &names, &YoloOptions::default()),
+            Err(YoloError::UnknownLabel(label)) if label == "tree"
+        ));
+        let options = YoloOptions {
+            skip_unknown: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            to_yolo_txt(&data, &names, &options).unwrap(),
+            "1 0.500000 0.250000 0.500000 0.500000\n"
+        );
+        let options = YoloOptions {
+            skip_unknown: true,
+            poly_as_bbox: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            to_yolo_txt(&data, &names, &options).unwrap(),
+            "1 0.500000 0.250000 0.500000 0.500000\n0 0.500000 0.500000 1.000000 1.000000\n"
+        );
+        assert_eq!(class_names([&data], &options), vec!["car", "road", "tree"]);
+
+        // clamped to the image, and the point and the box outside the image are dropped
+        let options = YoloOptions {
+            all_shapes: true,
+            ..Default::default()
+        };
+        let data = LabelMeData {
+            shapes: vec![
+                Shape::rectangle((-10.0, 10.0), (20.0, 30.0), "car"),
+                Shape::rectangle((50.0, 0.0), (60.0, 10.0), "car"),
+                Shape {
+                    label: "car".into(),
+                    points: vec![(5.0, 5.0)],
+                    shape_type: "point".into(),
+                    ..Default::default()
+                },
+            ],
+            imageWidth: 40,
+            imageHeight: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            to_yolo_txt(&data, &["car".into()], &options).unwrap(),
+            "0 0.250000 0.750000 0.500000 0.500000\n"
+        );
+
+        let third = YoloBox {
+            class: 0,
+            cx: 1.0 / 3.0,
+            cy: -0.0,
+            w: 0.0,
+            h: 1.0,
+        };
+        assert_eq!(third.to_string(), "0 0.333333 0.000000 0.000000 1.000000");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let rectangle = Shape::rectangle((2.5, 4.0), (30.0, 12.25), "car");
+        let data = LabelMeData {
+            shapes: vec![rectangle.clone()],
+            imageWidth: 40,
+            imageHeight: 20,
+            ..Default::default()
+        };
+        let boxes = to_yolo_boxes(&data, &["car".into()], &YoloOptions::default()).unwrap();
+        let b = boxes[0];
+        let (width, height) = (data.imageWidth as f64, data.imageHeight as f64);
+        let mut restored = LabelMeData {
+            shapes: vec![Shape::rectangle(
+                ((b.cx - b.w / 2.0) * width, (b.cy - b.h / 2.0) * height),
+                ((b.cx + b.w / 2.0) * width, (b.cy + b.h / 2.0) * height),
+                "car",
+            )],
+            ..Default::default()
+        };
+        if let Some(by) = ORIGIN_SHIFT {
+            restored.shift_origin(by.inverse());
+        }
+        assert_eq!(restored.shapes[0].points, rectangle.points);
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{ensure, Context, Result};
+use labelme_rs::image::GrayImage;
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::rasterize::rasterize;
+use lmrs::cli::AgreementCmdArgs as CmdArgs;
+
+/// Intersection over union of the non-zero pixels. `1.0` if both masks are empty
+fn mask_iou(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (mut intersection, mut union) = (0usize, 0usize);
+    for (p, q) in a.pixels().zip(b.pixels()) {
+        let (p, q) = (p.0[0] > 0, q.0[0] > 0);
+        intersection += (p && q) as usize;
+        union += (p || q) as usize;
+    }
+    if union == 0 {
+        1.0
+    } else {
+        intersection as f64 / union as f64
+    }
+}
+
+/// IoU of the shapes of the label between the annotations of the same image
+fn iou(a: &LabelMeData, b: &LabelMeData, label: &str) -> f64 {
+    let labels = [label.to_string()];
+    mask_iou(&rasterize(a, &labels, false), &rasterize(b, &labels, false))
+}
+
+fn load_ndjson(input: &Path) -> Result<IndexMap<String, LabelMeData>> {
+    let reader = BufReader::new(File::open(input).with_context(|| format!("Opening {:?}", input))?);
+    let mut records = IndexMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let lm_line = LabelMeDataLine::try_from(line.as_str())?;
+        records.insert(lm_line.filename, lm_line.content);
+    }
+    Ok(records)
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let a = load_ndjson(&args.a)?;
+    let mut b = load_ndjson(&args.b)?;
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    let mut ious = vec![];
+    for (filename, data_a) in a {
+        let Some(data_b) = b.shift_remove(&filename) else {
+            warn!("{}: not found in {:?}", filename, args.b);
+            continue;
+        };
+        ensure!(
+            (data_a.imageWidth, data_a.imageHeight) == (data_b.imageWidth, data_b.imageHeight),
+            "{}: image sizes differ; {}x{} vs. {}x{}",
+            filename,
+            data_a.imageWidth,
+            data_a.imageHeight,
+            data_b.imageWidth,
+            data_b.imageHeight
+        );
+        let iou = iou(&data_a, &data_b, &args.label);
+        serde_json::to_writer(
+            &mut writer,
+            &serde_json::json!({"filename": filename, "iou": iou}),
+        )?;
+        writeln!(writer)?;
+        ious.push(iou);
+    }
+    for filename in b.keys() {
+        warn!("{}: not found in {:?}", filename, args.a);
+    }
+    if !ious.is_empty() {
+        info!(
+            "Mean IoU of {} file(s): {}",
+            ious.len(),
+            ious.iter().sum::<f64>() / ious.len() as f64
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::shape;
+
+    #[test]
+    fn test_iou() {
+        let a = LabelMeData {
+            shapes: vec![shape(
+                "road",
+                "polygon",
+                [(2.0, 4.0), (10.0, 4.0), (10.0, 12.0), (2.0, 12.0)],
+            )],
+            ..LabelMeData::new(&[], &[], 32, 32, "")
+        };
+        assert!((iou(&a, &a, "road") - 1.0).abs() < 1e-9);
+        let mut b = a.clone();
+        b.shapes = vec![shape(
+            "road",
+            "polygon",
+            [(20.0, 4.0), (28.0, 4.0), (28.0, 12.0), (20.0, 12.0)],
+        )];
+        assert!(iou(&a, &b, "road").abs() < 1e-9);
+        b.shapes = vec![shape(
+            "road",
+            "polygon",
+            [(6.5, 4.0), (14.5, 4.0), (14.5, 12.0), (6.5, 12.0)],
+        )];
+        let half = iou(&a, &b, "road");
+        assert!(half > 0.2 && half < 0.6, "{half}");
+        // other labels are ignored
+        let mut other = a.clone();
+        other
+            .shapes
+            .push(shape("car", "rectangle", [(0.0, 0.0), (31.0, 31.0)]));
+        assert!((iou(&a, &other, "road") - 1.0).abs() < 1e-9);
+        let empty = LabelMeData::new(&[], &[], 32, 32, "");
+        assert_eq!(iou(&empty, &empty, "road"), 1.0);
+    }
+}
@@ -1,7 +1,14 @@
 use std::{
+    collections::BTreeMap,
+    ffi::OsString,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{sync_channel, Receiver},
+        Mutex,
+    },
 };
 
 use anyhow::{Context, Result};
@@ -10,85 +17,236 @@ use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
 use lmrs::cli::ArchiveCmdArgs as CmdArgs;
 use tar::{Builder, Header};
 
-fn add_image<W: std::io::Write>(data: &LabelMeData, ar: &mut Builder<W>) -> Result<()> {
+/// Source of an archive entry
+enum Job {
+    /// Line of ndjson
+    Line(String),
+    /// Json file
+    File(PathBuf),
+}
+
+/// Json and image ready to be appended. The image is streamed when appended
+struct Prepared {
+    json_name: OsString,
+    json: Vec<u8>,
+    image_name: OsString,
+    image: File,
+    image_header: Header,
+}
+
+fn open_image(data: &LabelMeData) -> Result<(OsString, File, Header)> {
     let image_path: PathBuf = data.imagePath.clone().into();
-    let mut image_file = File::open(&image_path)
+    let image_file = File::open(&image_path)
         .with_context(|| format!("Failed to open image file: {:?}", image_path))?;
-    let image_name = image_path.file_name().unwrap().to_str().unwrap();
-    ar.append_file(image_name, &mut image_file)?;
-    Ok(())
+    let mut header = Header::new_gnu();
+    header.set_metadata(&image_file.metadata()?);
+    let image_name = image_path
+        .file_name()
+        .with_context(|| format!("Failed to get file_name: {:?}", image_path))?
+        .to_os_string();
+    Ok((image_name, image_file, header))
 }
 
-fn add_data<W: std::io::Write, P: AsRef<Path>>(
-    path: P,
-    data: &mut LabelMeData,
-    ar: &mut Builder<W>,
-) -> Result<()> {
+fn serialize_data(data: &mut LabelMeData) -> Result<Vec<u8>> {
     data.imagePath = Path::new(&data.imagePath)
         .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
-    let json = serde_json::to_string(data)?;
+        .with_context(|| format!("Failed to get file_name: {}", data.imagePath))?
+        .to_string_lossy()
+        .into();
+    Ok(serde_json::to_vec(data)?)
+}
+
+fn prepare(job: Result<Job>, json_dir: &Path) -> Result<Prepared> {
+    let (json_name, data) = match job? {
+        Job::Line(line) => {
+            let data_line: LabelMeDataLine = serde_json::from_str(&line)?;
+            let name = Path::new(&data_line.filename)
+                .file_name()
+                .with_context(|| format!("Failed to get file_name: {}", data_line.filename))?
+                .to_os_string();
+            (name, data_line.content)
+        }
+        Job::File(input) => (
+            input
+                .file_name()
+                .with_context(|| format!("Failed to get file_name: {:?}", input))?
+                .to_os_string(),
+            LabelMeData::try_from(input.as_path())?,
+        ),
+    };
+    let mut data = data.to_absolute_path(json_dir);
+    let (image_name, image, image_header) = open_image(&data)?;
+    let json = serialize_data(&mut data)?;
+    Ok(Prepared {
+        json_name,
+        json,
+        image_name,
+        image,
+        image_header,
+    })
+}
+
+fn add_image<W: std::io::Write>(prepared: &mut Prepared, ar: &mut Builder<W>) -> Result<()> {
+    let size = prepared.image_header.size()?;
+    // `take` keeps the entry consistent with the header even if the file grows meanwhile
+    ar.append_data(
+        &mut prepared.image_header,
+        &prepared.image_name,
+        (&prepared.image).take(size),
+    )?;
+    Ok(())
+}
+
+fn add_data<W: std::io::Write>(prepared: &Prepared, ar: &mut Builder<W>) -> Result<()> {
     let mut header = Header::new_gnu();
-    header.set_size(json.len() as u64);
+    header.set_size(prepared.json.len() as u64);
     header.set_mode(0o644);
-    ar.append_data(&mut header, path, json.as_bytes())?;
+    ar.append_data(&mut header, &prepared.json_name, prepared.json.as_slice())?;
     Ok(())
 }
 
-fn archive<W: std::io::Write>(args: CmdArgs, ar: Builder<W>) -> Result<()> {
-    let mut ar = ar;
-    if args.input.is_file() || args.input.as_os_str() == "-" {
+/// Append prepared entries in the order of the sequence numbers.
+/// A slot is released for each appended entry so that the number of entries in flight stays bounded
+fn write_entries<W: std::io::Write>(
+    ar: &mut Builder<W>,
+    prepared: Receiver<(usize, Result<Prepared>)>,
+    slots: Receiver<()>,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let cancelled = |next| lmrs::cancel::Cancelled {
+        completed: next,
+        total: None,
+    };
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    for (i, entry) in prepared {
+        pending.insert(i, entry);
+        while let Some(entry) = pending.remove(&next) {
+            if lmrs::cancel::is_cancelled(cancel) {
+                return Err(cancelled(next).into());
+            }
+            let mut entry = entry?;
+            add_image(&mut entry, ar)?;
+            add_data(&entry, ar)?;
+            next += 1;
+            let _ = slots.recv();
+        }
+    }
+    if lmrs::cancel::is_cancelled(cancel) {
+        return Err(cancelled(next).into());
+    }
+    Ok(())
+}
+
+type Jobs = Box<dyn Iterator<Item = Result<Job>> + Send>;
+
+/// List jobs and the base directory of relative image paths
+fn list_jobs(input: &Path) -> Result<(Jobs, PathBuf)> {
+    if input.is_file() || input.as_os_str() == "-" {
         // process ndjson file
-        let (reader, json_dir): (Box<dyn BufRead>, _) = if args.input.as_os_str() == "-" {
+        let (reader, json_dir): (Box<dyn BufRead + Send>, _) = if input.as_os_str() == "-" {
             let reader = Box::new(BufReader::new(std::io::stdin()));
             let json_dir = std::env::current_dir()?.canonicalize()?;
             (reader, json_dir)
         } else {
-            let reader = Box::new(BufReader::new(File::open(&args.input)?));
-            let json_dir = args.input.parent().unwrap().canonicalize()?;
+            let reader = Box::new(BufReader::new(File::open(input)?));
+            let json_dir = input.parent().unwrap().canonicalize()?;
             (reader, json_dir)
         };
-
-        for line in reader.lines() {
-            let line = line?;
-            let data_line: LabelMeDataLine = serde_json::from_str(&line)?;
-
-            let mut data = data_line.content.to_absolute_path(&json_dir);
-            let path = Path::new(&data_line.filename).file_name().unwrap();
-            add_image(&data, &mut ar)?;
-            add_data(path, &mut data, &mut ar)?;
-        }
+        let jobs = reader.lines().map(|line| Ok(Job::Line(line?)));
+        Ok((Box::new(jobs), json_dir))
     } else {
         let entries = glob::glob(
-            args.input
+            input
                 .join("*.json")
                 .to_str()
                 .context("Failed to obtain glob string")?,
         )
         .expect("Failed to read glob pattern");
-        let json_dir = args.input.canonicalize()?;
+        let json_dir = input.canonicalize()?;
+        let jobs = entries.map(|entry| Ok(Job::File(entry?)));
+        Ok((Box::new(jobs), json_dir))
+    }
+}
 
-        for entry in entries {
-            let input = entry?;
-            let mut data = LabelMeData::try_from(input.as_path())?.to_absolute_path(&json_dir);
-            add_image(&data, &mut ar)?;
-            add_data(input.file_name().unwrap(), &mut data, &mut ar)?;
+fn archive<W: std::io::Write>(
+    jobs: Jobs,
+    json_dir: &Path,
+    n_jobs: usize,
+    ar: Builder<W>,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let mut ar = ar;
+    let capacity = 2 * n_jobs;
+    let (job_tx, job_rx) = sync_channel::<(usize, Result<Job>)>(capacity);
+    let job_rx = Mutex::new(job_rx);
+    let (prepared_tx, prepared_rx) = sync_channel(capacity);
+    let (slot_tx, slot_rx) = sync_channel(capacity);
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            for job in jobs.enumerate() {
+                if lmrs::cancel::is_cancelled(cancel)
+                    || slot_tx.send(()).is_err()
+                    || job_tx.send(job).is_err()
+                {
+                    break;
+                }
+            }
+        });
+        for _ in 0..n_jobs {
+            let prepared_tx = prepared_tx.clone();
+            let job_rx = &job_rx;
+            s.spawn(move || loop {
+                let Ok((i, job)) = job_rx.lock().unwrap().recv() else {
+                    break;
+                };
+                if prepared_tx.send((i, prepare(job, json_dir))).is_err() {
+                    break;
+                }
+            });
         }
-    }
+        drop(prepared_tx);
+        // Dropping the receivers on error unblocks and stops the other threads
+        write_entries(&mut ar, prepared_rx, slot_rx, cancel)
+    })?;
     ar.finish()?;
     Ok(())
 }
 
+/// Archive into `output`. The incomplete archive is removed on cancellation
+fn write_archive(
+    output: &Path,
+    jobs: Jobs,
+    json_dir: &Path,
+    n_jobs: usize,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let output_file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create file: {:?}", output))?;
+    let result = archive(jobs, json_dir, n_jobs, Builder::new(output_file), cancel);
+    if result
+        .as_ref()
+        .is_err_and(|e| e.is::<lmrs::cancel::Cancelled>())
+    {
+        std::fs::remove_file(output)?;
+        warn!("Removed incomplete archive {:?}", output);
+    }
+    result
+}
+
 pub fn cmd(args: CmdArgs) -> Result<()> {
+    let (jobs, json_dir) = list_jobs(&args.input)?;
+    let n_jobs = if args.jobs == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        args.jobs
+    };
+    let cancel = lmrs::cancel::flag();
     if args.output.as_os_str() == "-" {
-        archive(args, Builder::new(std::io::stdout()))
+        let ar = Builder::new(std::io::stdout());
+        archive(jobs, &json_dir, n_jobs, ar, cancel)
     } else {
-        let output_file = std::fs::File::create(&args.output)
-            .with_context(|| format!("Failed to create file: {:?}", args.output))?;
-        archive(args, Builder::new(output_file))
+        write_archive(&args.output, jobs, &json_dir, n_jobs, cancel)
     }
 }
 
@@ -105,6 +263,7 @@ mod tests {
         let args = CmdArgs {
             input: data_dir.clone(),
             output: output.path().into(),
+            jobs: 1,
         };
         cmd(args)?;
         let file = File::open(output.path())?;
@@ -136,4 +295,82 @@ mod tests {
         remove_file(output.path())?;
         Ok(())
     }
+
+    fn entries(path: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let mut a = tar::Archive::new(File::open(path)?);
+        let mut entries = vec![];
+        for file in a.entries()? {
+            let mut file = file?;
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            entries.push((file.path()?.into_owned(), content));
+        }
+        Ok(entries)
+    }
+
+    /// Create 8 jsons with dummy images
+    fn make_input() -> Result<tempfile::TempDir> {
+        let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+        let data = LabelMeData::try_from(data_dir.join("Mandrill.json").as_path())?;
+        let input_dir = tempfile::tempdir()?;
+        for i in 0..8 {
+            let mut data = data.clone();
+            data.imagePath = format!("img{}.bin", i);
+            std::fs::write(
+                input_dir.path().join(&data.imagePath),
+                vec![i as u8; 1000 * i],
+            )?;
+            std::fs::write(
+                input_dir.path().join(format!("{}.json", i)),
+                serde_json::to_string(&data)?,
+            )?;
+        }
+        Ok(input_dir)
+    }
+
+    #[test]
+    fn test_archive_jobs() -> Result<()> {
+        let input_dir = make_input()?;
+        let serial = tempfile::NamedTempFile::with_prefix(".tar")?;
+        let parallel = tempfile::NamedTempFile::with_prefix(".tar")?;
+        for (output, jobs) in [(&serial, 1), (&parallel, 4)] {
+            cmd(CmdArgs {
+                input: input_dir.path().into(),
+                output: output.path().into(),
+                jobs,
+            })?;
+        }
+        let serial = entries(serial.path())?;
+        let names: Vec<_> = serial
+            .iter()
+            .map(|(name, _)| name.to_str().unwrap().to_string())
+            .collect();
+        let expected: Vec<_> = (0..8)
+            .flat_map(|i| [format!("img{}.bin", i), format!("{}.json", i)])
+            .collect();
+        assert_eq!(names, expected);
+        assert_eq!(serial[2].1, vec![1u8; 1000]);
+        assert_eq!(entries(parallel.path())?, serial);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_cancel() -> Result<()> {
+        static CANCEL: AtomicBool = AtomicBool::new(false);
+        let input_dir = make_input()?;
+        let (jobs, json_dir) = list_jobs(input_dir.path())?;
+        // Cancel while the 4th entry is being scheduled
+        let jobs: Jobs = Box::new(jobs.enumerate().map(|(i, job)| {
+            if i == 3 {
+                CANCEL.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+            job
+        }));
+        let output = input_dir.path().join("output.tar");
+        let err = write_archive(&output, jobs, &json_dir, 2, &CANCEL).unwrap_err();
+        let cancelled = err.downcast_ref::<lmrs::cancel::Cancelled>().unwrap();
+        assert!(cancelled.completed <= 3);
+        assert!(!output.exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use labelme_rs::{serde_json, Flip, LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use lmrs::cli::{AugmentCmdArgs as CmdArgs, QuarterTurn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Augmentation {
+    FlipH,
+    FlipV,
+    Rotate(QuarterTurn),
+}
+
+impl Augmentation {
+    /// Suffix appended to the file stem of the augmented data
+    fn suffix(&self) -> &'static str {
+        match self {
+            Augmentation::FlipH => "_fliph",
+            Augmentation::FlipV => "_flipv",
+            Augmentation::Rotate(QuarterTurn::Cw) => "_rotcw",
+            Augmentation::Rotate(QuarterTurn::Ccw) => "_rotccw",
+            Augmentation::Rotate(QuarterTurn::Half) => "_rot180",
+        }
+    }
+
+    fn apply(&self, data: &mut LabelMeData) {
+        match self {
+            Augmentation::FlipH => data.flip(Flip::Horizontal),
+            Augmentation::FlipV => data.flip(Flip::Vertical),
+            Augmentation::Rotate(QuarterTurn::Cw) => data.rotate(90.0),
+            Augmentation::Rotate(QuarterTurn::Ccw) => data.rotate(270.0),
+            Augmentation::Rotate(QuarterTurn::Half) => data.rotate(180.0),
+        }
+    }
+}
+
+/// Augmentations of the args in the order of flips and rotations
+fn augmentations(args: &CmdArgs) -> Vec<Augmentation> {
+    let rotations = if args.all {
+        vec![QuarterTurn::Cw, QuarterTurn::Ccw, QuarterTurn::Half]
+    } else {
+        args.rotate_90.clone()
+    };
+    [
+        (args.flip_h || args.all).then_some(Augmentation::FlipH),
+        (args.flip_v || args.all).then_some(Augmentation::FlipV),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(rotations.into_iter().map(Augmentation::Rotate))
+    .collect()
+}
+
+/// Append `suffix` to the file stem of `filename`, e.g. `dir/img.json` to `dir/img_fliph.json`
+fn with_suffix(filename: &str, suffix: &str) -> String {
+    let path = Path::new(filename);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}{}.{}",
+                stem.to_string_lossy(),
+                suffix,
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .to_string(),
+        _ => format!("{filename}{suffix}"),
+    }
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let augmentations = augmentations(&args);
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(
+            File::open(&args.input).with_context(|| format!("Opening {:?}", args.input))?,
+        ))
+    };
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    for line in reader.lines() {
+        let line = line?;
+        let lm_line: LabelMeDataLine =
+            serde_json::from_str(&line).with_context(|| format!("Processing line:{line}"))?;
+        for augmentation in &augmentations {
+            let mut content = lm_line.content.clone();
+            augmentation.apply(&mut content);
+            let augmented = LabelMeDataLine {
+                filename: with_suffix(&lm_line.filename, augmentation.suffix()),
+                content,
+            };
+            serde_json::to_writer(&mut writer, &augmented)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_suffix() {
+        assert_eq!(with_suffix("dir/img.json", "_fliph"), "dir/img_fliph.json");
+        assert_eq!(with_suffix("img", "_rotcw"), "img_rotcw");
+    }
+
+    #[test]
+    fn test_augmentations() {
+        let data = LabelMeData::new(&[(10.0, 20.0)], &["L1".into()], 100, 50, "img.jpg");
+        let augmented: Vec<_> = [
+            Augmentation::FlipH,
+            Augmentation::FlipV,
+            Augmentation::Rotate(QuarterTurn::Cw),
+            Augmentation::Rotate(QuarterTurn::Ccw),
+            Augmentation::Rotate(QuarterTurn::Half),
+        ]
+        .iter()
+        .map(|augmentation| {
+            let mut data = data.clone();
+            augmentation.apply(&mut data);
+            (
+                data.shapes[0].points[0],
+                (data.imageWidth, data.imageHeight),
+            )
+        })
+        .collect();
+        assert_eq!(
+            augmented,
+            vec![
+                ((90.0, 20.0), (100, 50)),
+                ((10.0, 30.0), (100, 50)),
+                ((30.0, 10.0), (50, 100)),
+                ((20.0, 90.0), (50, 100)),
+                ((90.0, 30.0), (100, 50)),
+            ]
+        );
+    }
+}
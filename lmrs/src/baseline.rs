@@ -0,0 +1,106 @@
+//! Baseline of known validation failures to tolerate legacy ones while rejecting new ones
+use crate::CheckError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Key of the dataset rule failures in the baseline
+pub const DATASET_KEY: &str = "@dataset";
+
+#[derive(Error, Debug)]
+pub enum BaselineError {
+    #[error("Failed to read the baseline {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Failed to parse the baseline {0:?}: {1}")]
+    Json(PathBuf, labelme_rs::serde_json::Error),
+}
+
+/// Reasons of a failure compared with the baseline: unsatisfied rules, or the error message for other errors
+pub fn reasons(err: &CheckError) -> Vec<String> {
+    match err {
+        CheckError::EvaluatedFalse(rule, _) => vec![rule.clone()],
+        CheckError::EvaluatedMultipleFalses(errors) => {
+            errors.iter().map(|(rule, _)| rule.clone()).collect()
+        }
+        _ => vec![err.to_string()],
+    }
+}
+
+/// Failure reasons by filename, saved as a json object sorted by filename
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Baseline {
+    failures: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self, BaselineError> {
+        let s =
+            std::fs::read_to_string(path).map_err(|e| BaselineError::Io(path.to_path_buf(), e))?;
+        labelme_rs::serde_json::from_str(&s).map_err(|e| BaselineError::Json(path.to_path_buf(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        crate::lock::write_atomic(path, |writer| {
+            labelme_rs::serde_json::to_writer_pretty(&mut *writer, self)?;
+            Ok(writeln!(writer)?)
+        })
+    }
+
+    pub fn insert(&mut self, filename: &str, reasons: impl IntoIterator<Item = String>) {
+        self.failures
+            .entry(filename.to_string())
+            .or_default()
+            .extend(reasons);
+    }
+
+    /// Check if all the reasons of the failure are recorded for the file
+    pub fn tolerates(&self, filename: &str, reasons: &[String]) -> bool {
+        self.failures
+            .get(filename)
+            .is_some_and(|recorded| reasons.iter().all(|r| recorded.contains(r)))
+    }
+
+    /// The number of files with failures
+    pub fn len(&self) -> usize {
+        self.failures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Filename used as the key of the baseline. Separators are normalized to `/`
+pub fn key(filename: &Path) -> String {
+    filename.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerates() -> anyhow::Result<()> {
+        let mut baseline = Baseline::default();
+        let err = CheckError::EvaluatedMultipleFalses(vec![
+            ("TL > 0".into(), (0, 0)),
+            ("TR > 0".into(), (0, 0)),
+        ]);
+        baseline.insert("a.json", reasons(&err));
+        assert!(baseline.tolerates("a.json", &reasons(&err)));
+        assert!(baseline.tolerates("a.json", &["TL > 0".into()]));
+        assert!(!baseline.tolerates("a.json", &["BL > 0".into()]));
+        assert!(!baseline.tolerates("b.json", &reasons(&err)));
+        assert!(!baseline.tolerates("a.json", &reasons(&CheckError::InvalidJson("EOF".into()))));
+
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("baseline.json");
+        baseline.save(&path)?;
+        assert_eq!(Baseline::load(&path)?, baseline);
+        assert!(std::fs::read_to_string(&path)?.starts_with("{\n  \"a.json\": [\n"));
+        Ok(())
+    }
+}
@@ -1,21 +1,31 @@
 use std::{
     path::{Path, PathBuf},
-    sync::{LazyLock, OnceLock},
+    sync::{Arc, LazyLock, Mutex, OnceLock},
 };
 
 use actix_web::{get, http::StatusCode, web, App, HttpResponse, HttpServer};
 use anyhow::{Context, Result};
-use clap::{CommandFactory, FromArgMatches};
-use labelme_rs::{load_label_colors, LabelColorsHex, LabelMeDataWImage};
+use clap::{ArgMatches, FromArgMatches};
+use labelme_rs::image::{DynamicImage, GenericImageView};
+use labelme_rs::{
+    load_label_colors, load_label_styles, LabelColorsHex, LabelMeData, LabelMeDataWImage,
+};
 use lmrs::cli::{BrowseCmdArgs as CmdArgs, BrowseServerConfig, SvgConfig};
+use lmrs::config::SvgDefaults;
 use serde::{Deserialize, Serialize};
 
+/// Last loaded full resolution image for serving tiles
+type ImageCache = Arc<Mutex<Option<(String, Arc<DynamicImage>)>>>;
+
 #[derive(Debug, Clone)]
 struct AppState {
     svg: SvgConfig,
     dir: PathBuf,
     label_colors: LabelColorsHex,
     templates: tera::Tera,
+    /// Tile size if deep zoom is enabled
+    tile_size: Option<u32>,
+    image_cache: ImageCache,
 }
 
 static PARENT_DIR: OnceLock<PathBuf> = OnceLock::new();
@@ -38,19 +48,58 @@ static ID_LIST: LazyLock<Vec<String>> = LazyLock::new(|| {
     v_id_list
 });
 
+/// Load the full resolution image of `id` using the cache
+fn load_image(app_state: &AppState, id: &str) -> Result<(LabelMeData, Arc<DynamicImage>)> {
+    let path = app_state.dir.join(id).with_extension("json");
+    let data = LabelMeData::try_from(path.as_path())?.reset_image_path(&path)?;
+    let mut cache = app_state.image_cache.lock().unwrap();
+    if let Some((cached_id, image)) = cache.as_ref() {
+        if cached_id == id {
+            return Ok((data, image.clone()));
+        }
+    }
+    let image = Arc::new(data.load_image()?);
+    *cache = Some((id.to_string(), image.clone()));
+    Ok((data, image))
+}
+
 fn _get_svg(app_state: &web::Data<AppState>, id: &String) -> Result<String> {
+    if let Some(tile_size) = app_state.tile_size {
+        let (data, image) = load_image(app_state, id)?;
+        let base = image.thumbnail(tile_size, tile_size);
+        let svg = data.to_deep_zoom_svg(
+            &app_state.label_colors,
+            app_state.svg.radius,
+            app_state.svg.line_width,
+            image.dimensions(),
+            &base,
+            &format!("/tiles/{}.dzi", id),
+        );
+        return Ok(svg.to_string());
+    }
     let path = app_state.dir.join(id).with_extension("json");
     let mut data_image = LabelMeDataWImage::try_from(path.as_path())?;
     if let Some(resize) = app_state.svg.resize.as_ref() {
         let resize_param = labelme_rs::ResizeParam::try_from(resize.as_str())?;
         data_image.resize(&resize_param);
     }
+    if let Some(max_bytes) = app_state.svg.max_image_bytes {
+        data_image.fit_encoded_size(max_bytes, app_state.svg.image_format.into())?;
+    }
     let data = data_image.data;
-    let svg = data.to_svg(
+    let image_data = app_state
+        .svg
+        .max_image_bytes
+        .is_none()
+        .then_some(data.imageData.as_deref())
+        .flatten();
+    let svg = data.to_svg_with_image_data(
         &app_state.label_colors,
         app_state.svg.radius,
         app_state.svg.line_width,
         &data_image.image,
+        image_data,
+        &app_state.svg.svg_options(),
     );
     Ok(svg.to_string())
 }
@@ -63,15 +112,74 @@ async fn get_svg(app_state: web::Data<AppState>, path: web::Path<String>) -> Htt
         Ok(svg) => HttpResponse::build(StatusCode::OK)
             .content_type("image/svg+xml")
             .body(svg),
-        Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
-            .content_type("text/plain")
-            .body(
-                e.chain()
-                    .map(|e| e.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-                    .to_string(),
-            ),
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(e: anyhow::Error) -> HttpResponse {
+    HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+        .content_type("text/plain")
+        .body(
+            e.chain()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .to_string(),
+        )
+}
+
+#[get("/tiles/{id}.dzi")]
+async fn get_dzi(app_state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let Some(tile_size) = app_state.tile_size else {
+        return HttpResponse::NotFound().body("Deep zoom is not enabled");
+    };
+    match load_image(&app_state, &id).with_context(|| format!("Failed to load image for {}", id)) {
+        Ok((_, image)) => {
+            let (width, height) = image.dimensions();
+            let url = format!("/tiles/{}/", id);
+            HttpResponse::build(StatusCode::OK)
+                .content_type("application/xml")
+                .body(labelme_rs::tiles::dzi_descriptor(
+                    width,
+                    height,
+                    tile_size,
+                    Some(&url),
+                ))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+fn _get_tile(
+    app_state: &AppState,
+    id: &str,
+    level: u32,
+    col: u32,
+    row: u32,
+) -> Result<Option<Vec<u8>>> {
+    let tile_size = app_state.tile_size.context("Deep zoom is not enabled")?;
+    let (_, image) = load_image(app_state, id)?;
+    labelme_rs::tiles::tile(&image, tile_size, level, col, row)
+        .map(|tile| labelme_rs::encode_image(&tile.image, labelme_rs::image::ImageFormat::Jpeg))
+        .transpose()
+        .context("Failed to encode tile")
+}
+
+#[get("/tiles/{id}/{level}/{col:\\d+}_{row:\\d+}.jpg")]
+async fn get_tile(
+    app_state: web::Data<AppState>,
+    path: web::Path<(String, u32, u32, u32)>,
+) -> HttpResponse {
+    let (id, level, col, row) = path.into_inner();
+    match _get_tile(&app_state, &id, level, col, row)
+        .with_context(|| format!("Failed to get tile for {}", id))
+    {
+        Ok(Some(bytes)) => HttpResponse::build(StatusCode::OK)
+            .content_type("image/jpeg")
+            .body(bytes),
+        Ok(None) => HttpResponse::NotFound().body("Tile is out of range"),
+        Err(e) => error_response(e),
     }
 }
 
@@ -118,15 +226,7 @@ async fn browse_id(
         Ok(html) => HttpResponse::build(StatusCode::OK)
             .content_type("text/html")
             .body(html),
-        Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
-            .content_type("text/plain")
-            .body(
-                e.chain()
-                    .map(|e| e.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-                    .to_string(),
-            ),
+        Err(e) => error_response(e),
     }
 }
 
@@ -152,7 +252,7 @@ async fn index(_app_state: web::Data<AppState>) -> HttpResponse {
 
 #[actix_web::main]
 async fn actix_main(
-    config: Config,
+    server: BrowseServerConfig,
     default_url_path: String,
     args: CmdArgs,
     app_state: AppState,
@@ -163,9 +263,11 @@ async fn actix_main(
             .service(index)
             .service(browse_id)
             .service(get_svg)
+            .service(get_dzi)
+            .service(get_tile)
     })
     .workers(1)
-    .bind((config.server.address, config.server.port))?;
+    .bind((server.address, server.port))?;
     let addr = *http_server.addrs().first().unwrap();
     let server = http_server.run();
 
@@ -188,14 +290,25 @@ async fn actix_main(
 pub struct Config {
     /// Server address
     pub server: BrowseServerConfig,
-    /// SVG
-    pub svg: SvgConfig,
+    /// SVG. Unspecified fields fall back to the user defaults and the builtin defaults
+    pub svg: SvgDefaults,
+}
+
+/// Size to resize images to by default
+const DEFAULT_RESIZE: &str = "512x512";
+
+/// Builtin defaults of the svg config of browse
+fn builtin_svg_config() -> SvgConfig {
+    SvgConfig {
+        resize: Some(DEFAULT_RESIZE.to_string()),
+        ..Default::default()
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let svg = SvgConfig {
-            resize: Some("512x512".to_string()),
+        let svg = SvgDefaults {
+            resize: Some(DEFAULT_RESIZE.to_string()),
             ..Default::default()
         };
         Self {
@@ -217,8 +330,8 @@ fn load_config(config_dir: &Path) -> Result<Option<Config>> {
 }
 
 fn _load_config_from_config_dir() -> Result<Option<Config>> {
-    if let Some(config_dir) = dirs::config_dir() {
-        return load_config(&config_dir.join("lmrs"));
+    if let Some(config_dir) = lmrs::config::config_dir() {
+        return load_config(&config_dir);
     }
     Ok(None)
 }
@@ -249,7 +362,7 @@ fn load_config_next_to_executable() -> Option<Config> {
     }
 }
 
-pub fn cmd(mut args: CmdArgs) -> Result<()> {
+pub fn cmd(mut args: CmdArgs, matches: &ArgMatches) -> Result<()> {
     if args.default {
         let config = Config::default();
         let toml = toml::to_string(&config).unwrap();
@@ -258,31 +371,21 @@ pub fn cmd(mut args: CmdArgs) -> Result<()> {
     }
 
     // Initialize config from file
-    let config: Config = if let Some(path) = args.base_config.as_ref() {
-        toml::from_str(&std::fs::read_to_string(path)?)?
+    let base_config: Option<Config> = if let Some(path) = args.base_config.as_ref() {
+        Some(toml::from_str(&std::fs::read_to_string(path)?)?)
     } else {
-        load_config_from_config_dir()
-            .or_else(load_config_next_to_executable)
-            .unwrap_or_default()
+        load_config_from_config_dir().or_else(load_config_next_to_executable)
     };
+    let mut svg = args.svg.resolve(
+        matches,
+        builtin_svg_config(),
+        base_config.as_ref().map(|config| &config.svg),
+    )?;
+    let config = base_config.unwrap_or_default();
 
     // Update config from arguments
     args.server = config.server.clone();
-    args.svg = config.svg.clone();
-
-    // Cut-off arguments before `browse`
-    let command_args = std::env::args()
-        .skip_while(|arg| arg != "browse")
-        .collect::<Vec<_>>();
-
-    let matches = <CmdArgs as CommandFactory>::command().get_matches_from(command_args);
-
-    args.update_from_arg_matches(&matches)?;
-
-    let config = Config {
-        server: args.server.clone(),
-        svg: args.svg.clone(),
-    };
+    args.update_from_arg_matches(matches)?;
 
     if !args.input.exists() {
         panic!("Input file does not exist: {:?}", args.input);
@@ -300,8 +403,11 @@ pub fn cmd(mut args: CmdArgs) -> Result<()> {
     } else {
         args.input.clone()
     };
-    let label_colors = match &config.svg.config {
-        Some(config) => load_label_colors(config)?,
+    let label_colors = match &svg.config {
+        Some(path) => {
+            svg.label_styles = load_label_styles(path)?;
+            load_label_colors(path)?
+        }
         None => LabelColorsHex::new(),
     };
 
@@ -321,13 +427,16 @@ pub fn cmd(mut args: CmdArgs) -> Result<()> {
     PARENT_DIR.get_or_init(|| dir.clone());
 
     let app_state = AppState {
-        svg: config.svg.clone(),
+        svg,
         dir,
         label_colors,
         templates,
+        tile_size: args.deep_zoom.deep_zoom.then_some(args.deep_zoom.tile_size),
+        image_cache: ImageCache::default(),
     };
 
-    actix_main(config, default_url, args, app_state).context("Failed to start actix server")?;
+    actix_main(args.server.clone(), default_url, args, app_state)
+        .context("Failed to start actix server")?;
     Ok(())
 }
 
@@ -350,16 +459,17 @@ mod tests {
     use super::*;
 
     fn init_app_state() -> AppState {
-        let config = Config::default();
         let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
         PARENT_DIR.get_or_init(|| dir.clone());
         let templates = get_templates();
 
         AppState {
-            svg: config.svg.clone(),
+            svg: builtin_svg_config(),
             dir,
             label_colors: LabelColorsHex::new(),
             templates,
+            tile_size: None,
+            image_cache: ImageCache::default(),
         }
     }
 
@@ -399,4 +509,50 @@ mod tests {
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
     }
+
+    #[actix_web::test]
+    async fn test_deep_zoom_gets() {
+        let app_state = AppState {
+            tile_size: Some(128),
+            ..init_app_state()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state.clone()))
+                .service(get_svg)
+                .service(get_dzi)
+                .service(get_tile),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/svg/Mandrill").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let svg = std::str::from_utf8(&body).unwrap();
+        assert!(svg.contains(r#"data-dzi="/tiles/Mandrill.dzi""#));
+        assert!(svg.contains(r#"width="256""#));
+
+        let req = test::TestRequest::get()
+            .uri("/tiles/Mandrill.dzi")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let dzi = std::str::from_utf8(&body).unwrap();
+        assert!(dzi.contains(r#"TileSize="128""#));
+        assert!(dzi.contains(r#"<Size Width="256" Height="256"/>"#));
+
+        let req = test::TestRequest::get()
+            .uri("/tiles/Mandrill/8/1_1.jpg")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let tile = labelme_rs::image::load_from_memory(&body).unwrap();
+        assert_eq!(tile.dimensions(), (128, 128));
+
+        let req = test::TestRequest::get()
+            .uri("/tiles/Mandrill/8/2_0.jpg")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,62 @@
+//! Graceful cancellation of long running commands by SIGINT (Ctrl-C)
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+/// Exit code of cancelled commands. i.e. 128 + SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Cancellation flag set by the handler
+pub fn flag() -> &'static AtomicBool {
+    &CANCELLED
+}
+
+/// Install a SIGINT handler setting [`flag`]. The second signal exits immediately
+pub fn install_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_CODE);
+        }
+        log::warn!("Cancelling. Press Ctrl-C again to exit immediately");
+    })
+}
+
+/// Check if `flag` is set
+pub fn is_cancelled(flag: &AtomicBool) -> bool {
+    flag.load(Ordering::SeqCst)
+}
+
+/// Command was cancelled after completing `completed` of `total` (if known) items
+#[derive(Error, Debug, PartialEq)]
+pub struct Cancelled {
+    pub completed: usize,
+    pub total: Option<usize>,
+}
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.total {
+            Some(total) => write!(f, "Cancelled after {} of {} items", self.completed, total),
+            None => write!(f, "Cancelled after {} items", self.completed),
+        }
+    }
+}
+
+#[test]
+fn test_cancelled() {
+    let flag = AtomicBool::new(false);
+    assert!(!is_cancelled(&flag));
+    flag.store(true, Ordering::SeqCst);
+    assert!(is_cancelled(&flag));
+    let cancelled = Cancelled {
+        completed: 3,
+        total: Some(10),
+    };
+    assert_eq!(cancelled.to_string(), "Cancelled after 3 of 10 items");
+    let cancelled = Cancelled {
+        completed: 3,
+        total: None,
+    };
+    assert_eq!(cancelled.to_string(), "Cancelled after 3 items");
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use lmrs::cli::CheckDimsCmdArgs as CmdArgs;
+
+/// Annotation whose image size disagrees with the image file
+#[derive(Serialize, Debug)]
+struct Mismatch {
+    filename: String,
+    #[serde(rename = "imagePath")]
+    image_path: String,
+    /// (width, height) in the annotation
+    annotation: (usize, usize),
+    /// (width, height) of the image file
+    image: (u32, u32),
+}
+
+/// Read annotations in the directory or the ndjson file (or stdin)
+/// paired with the directory which `imagePath` is relative to
+fn load_entries(input: &Path) -> Result<(Vec<(String, LabelMeData)>, PathBuf)> {
+    if input.is_dir() {
+        let mut entries = vec![];
+        for entry in glob::glob(
+            input
+                .join("*.json")
+                .to_str()
+                .context("Failed to get glob")?,
+        )
+        .expect("Failed to read glob pattern")
+        {
+            let entry = entry?;
+            let reader = BufReader::new(
+                File::open(&entry).with_context(|| format!("opening {}", entry.display()))?,
+            );
+            let data: LabelMeData = serde_json::from_reader(reader)
+                .with_context(|| format!("parsing {}", entry.display()))?;
+            entries.push((entry.to_string_lossy().to_string(), data));
+        }
+        Ok((entries, input.to_path_buf()))
+    } else {
+        let (reader, json_dir): (Box<dyn BufRead>, PathBuf) = if input.as_os_str() == "-" {
+            (
+                Box::new(BufReader::new(std::io::stdin())),
+                PathBuf::from("."),
+            )
+        } else {
+            (
+                Box::new(BufReader::new(
+                    File::open(input).with_context(|| format!("opening {}", input.display()))?,
+                )),
+                input.parent().unwrap().to_path_buf(),
+            )
+        };
+        let mut entries = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            let lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
+            entries.push((lm_data_line.filename, lm_data_line.content));
+        }
+        Ok((entries, json_dir))
+    }
+}
+
+/// Compare the size in the annotation with the size of the image file
+fn check(filename: String, data: LabelMeData, json_dir: &Path) -> Result<Option<Mismatch>> {
+    let image_path = data.imagePath.replace('\\', "/");
+    let abs_path = json_dir.join(&image_path);
+    let (width, height) = labelme_rs::image_dimensions(&abs_path)
+        .with_context(|| format!("reading {}", abs_path.display()))?;
+    if (width as usize, height as usize) == (data.imageWidth, data.imageHeight) {
+        Ok(None)
+    } else {
+        Ok(Some(Mismatch {
+            filename,
+            image_path,
+            annotation: (data.imageWidth, data.imageHeight),
+            image: (width, height),
+        }))
+    }
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let (entries, json_dir) = load_entries(&args.input)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build()?;
+    let results: Vec<_> = pool.install(|| {
+        entries
+            .into_par_iter()
+            .map(|(filename, data)| check(filename, data, &json_dir))
+            .collect()
+    });
+    let mut writer = std::io::stdout().lock();
+    for result in results {
+        match result {
+            Ok(Some(mismatch)) => writeln!(writer, "{}", serde_json::to_string(&mismatch)?)?,
+            Ok(None) => {}
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[clap(name=env!("CARGO_CRATE_NAME"), author, version, about, long_about = None)]
@@ -39,16 +39,67 @@ pub enum Command {
     Resize(ResizeCmdArgs),
     /// Create empty labelme json for the image
     Init(InitCmdArgs),
-    /// Check if `imagePath` exists. `imagePath` is resolved relative to the input ndjson file or the current working directory if the input is stdin
+    /// Check if `imagePath` exists or `imageData` is embedded. `imagePath` is resolved relative to the input ndjson file or the current working directory if the input is stdin
     Exist(ExistCmdArgs),
     /// Archive json and associated images as a tarball
     Archive(ArchiveCmdArgs),
-    /// Count flags
+    /// Count flags, or labels and shape types with `--distinct-labels`
     Count(CountCmdArgs),
     /// Sort shapes by point coordinates
     Sort(SortCmdArgs),
     /// Browse labelme annotations
     Browse(BrowseCmdArgs),
+    /// Check if `imageWidth` and `imageHeight` agree with the image files
+    CheckDims(CheckDimsCmdArgs),
+    /// Repair malformed shapes and report the changes to stderr
+    Fix(FixCmdArgs),
+    /// Count files containing each pair of labels
+    Cooccur(CooccurCmdArgs),
+    /// Transform point coordinates
+    Mat(MatCmdArgs),
+    /// Transform point coordinates by the homography estimated from point correspondences
+    Reproject(ReprojectCmdArgs),
+    /// Augment annotations by flips and rotations by 90 degrees
+    Augment(AugmentCmdArgs),
+    /// Compare the number of shapes of each label between two inputs
+    CompareStats(CompareStatsCmdArgs),
+    /// Merge `label_colors` of labelme config yamls
+    MergeConfigs(MergeConfigsCmdArgs),
+    /// List the number of shapes, distinct labels, and true flags of each file as TSV
+    Ls(LsCmdArgs),
+    /// Set or clear flags
+    Flags(FlagsCmdArgs),
+    /// Rename labels of shapes
+    #[clap(visible_alias = "relabel")]
+    Rename(RenameCmdArgs),
+    /// Split ndjson into train/val/test subsets deterministically by the hash of `filename`
+    DatasetSplit(DatasetSplitCmdArgs),
+    /// Randomly subsample ndjson
+    Sample(SampleCmdArgs),
+    /// Normalize point coordinates by `imageWidth` and `imageHeight` into 0..1, or the inverse with `--denormalize`
+    Normalize(NormalizeCmdArgs),
+    /// Crop shapes (and images) to the region
+    Crop(CropCmdArgs),
+    /// Remove consecutive near-duplicate vertices within each shape
+    DedupePoints(DedupePointsCmdArgs),
+    /// Remove duplicate shapes within each json, keeping the first occurrences
+    Dedup(DedupCmdArgs),
+    /// Export rectangles and polygons as COCO json
+    Coco(CocoCmdArgs),
+    /// Rasterize polygons and rectangles into mask images
+    Rasterize(RasterizeCmdArgs),
+    /// Render polygons, rectangles, and circles into label mask PNGs with the values of the labels
+    Mask(MaskCmdArgs),
+    /// Export rectangles as YOLO txt files
+    Yolo(YoloCmdArgs),
+    /// Convert annotations into another format, one file per json
+    Convert(ConvertCmdArgs),
+    /// Export rectangles and polygons as Pascal VOC xml files
+    Voc(VocCmdArgs),
+    /// Build ndjson from csv of points (`filename,label,x,y`)
+    ToLabelme(ToLabelmeCmdArgs),
+    /// Compute IoU of the rasterized shapes of a label between two annotation sets per file
+    Agreement(AgreementCmdArgs),
 }
 
 #[derive(Debug, Args)]
@@ -58,6 +109,10 @@ pub struct DropCmdArgs {
     /// Key for duplicate checking
     #[clap(long, default_value = "filename")]
     pub key: String,
+    /// Check duplicates by the exact content (SHA-256) of the image instead of the key.
+    /// `imageData` is used if present, otherwise `imagePath` is read relative to the input ndjson file (or the working directory for stdin)
+    #[clap(long, conflicts_with = "key")]
+    pub by_image: bool,
 }
 
 #[derive(Args, Debug)]
@@ -70,6 +125,16 @@ pub struct FilterCmdArgs {
     /// Invert filtering. i.e. output invalid lines
     #[clap(short = 'v', long)]
     pub invert: bool,
+    /// Variable extracted from each line in `NAME=PATH[*SCALE]` format (e.g. `blur_pct=metrics.blur*100`).
+    /// The scaled value is truncated to an integer
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub var: Vec<crate::LineVar>,
+    /// Fail on variables missing in a line instead of evaluating them as 0
+    #[clap(long)]
+    pub strict_vars: bool,
+    /// How each shape counts toward its label in rules. Count shapes (default) or their points
+    #[clap(long, default_value = "shapes")]
+    pub count: crate::CountMode,
 }
 
 #[derive(Args, Debug)]
@@ -120,6 +185,10 @@ pub struct ValidateCmdArgs {
     /// Ignore json files containing given flag(s). Multiple flags are concatenated by OR.
     #[clap(short, long, value_hint = ValueHint::Other)]
     pub ignore: Vec<String>,
+    /// Check only json files whose flags satisfy the expression (e.g. `(reviewed && !rejected) || urgent`).
+    /// Applied in addition to `--flag` and `--ignore`
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub flag_expr: Option<String>,
     /// Additional rules
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub additional: Vec<PathBuf>,
@@ -132,6 +201,38 @@ pub struct ValidateCmdArgs {
     /// Set the number of threads
     #[clap(short, long, default_value_t = 0)]
     pub threads: usize,
+    /// How each shape counts toward its label in rules. Count shapes (default) or their points
+    #[clap(long, default_value = "shapes")]
+    pub count: crate::CountMode,
+    #[clap(flatten)]
+    pub retry: IoRetryArgs,
+    /// Baseline json of known failures. Failures recorded in the baseline are tolerated,
+    /// and the command fails only if a file not in the baseline fails or a file fails with a rule not recorded for it
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub baseline: Option<PathBuf>,
+    /// Record the current failures to the baseline instead of comparing with it
+    #[clap(long, requires = "baseline")]
+    pub update_baseline: bool,
+}
+
+/// Retry args for commands reading many files
+#[derive(Debug, Clone, Args)]
+pub struct IoRetryArgs {
+    /// The number of retries on transient IO errors (e.g. on network-mounted inputs)
+    #[clap(long, default_value_t = 0)]
+    pub io_retries: usize,
+    /// Wait in milliseconds before the first retry. Doubled for each retry up to 60 s
+    #[clap(long, default_value_t = 500, value_name = "MS")]
+    pub io_backoff: u64,
+}
+
+impl From<&IoRetryArgs> for crate::retry::RetryPolicy {
+    fn from(args: &IoRetryArgs) -> Self {
+        Self::new(
+            args.io_retries,
+            std::time::Duration::from_millis(args.io_backoff),
+        )
+    }
 }
 
 #[derive(Debug, Args)]
@@ -142,6 +243,9 @@ pub struct HtmlCmdArgs {
     /// Output html filename
     #[clap(value_hint = ValueHint::FilePath)]
     pub output: PathBuf,
+    /// Write a PDF with the rasterized figure and the caption of each annotation per page instead of html
+    #[clap(long, conflicts_with_all = ["css", "external_assets"])]
+    pub pdf: bool,
     /// Flags filename. Used to sort flags
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub flags: Option<PathBuf>,
@@ -153,18 +257,30 @@ pub struct HtmlCmdArgs {
     /// CSS filename
     #[clap(long, value_hint = ValueHint::FilePath)]
     pub css: Option<PathBuf>,
+    /// Save the label colors including automatically assigned ones to the yaml (labelme config format).
+    /// Existing colors in the file are kept. Pass the same file to `--config` to reuse the colors
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub save_colors: Option<PathBuf>,
+    /// Write CSS and JavaScript to `catalog.css` and `catalog.js` in the directory and link them instead of inlining
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub external_assets: Option<PathBuf>,
     /// Override imagePath's directory
     #[clap(long, value_hint = ValueHint::DirPath)]
     pub image_dir: Option<PathBuf>,
     /// The number of jobs. Use all available cores by default.
     #[clap(short, long)]
     pub jobs: Option<usize>,
+    #[clap(flatten)]
+    pub retry: IoRetryArgs,
 }
 
 /// SVG args shared by svg related commands
+///
+/// Defaults can be set in `defaults.toml` of the lmrs config directory (e.g. `~/.config/lmrs/defaults.toml`)
+/// or in the file specified by `LMRS_CONFIG`. See [`SvgConfig::resolve`] for the precedence.
 #[derive(Debug, Clone, Args, Serialize, Deserialize)]
 pub struct SvgConfig {
-    /// Config yaml file of Labelme. Only `label_colors` is used
+    /// Config yaml file of Labelme. `label_colors` and `label_styles` are used
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     pub config: Option<PathBuf>,
     /// Circle radius
@@ -176,6 +292,54 @@ pub struct SvgConfig {
     /// Resize image. Specify in imagemagick's `-resize`-like format
     #[clap(long, value_hint = ValueHint::Other)]
     pub resize: Option<String>,
+    /// Downscale images further until each embedded (base64 encoded) image fits in the number of bytes
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub max_image_bytes: Option<usize>,
+    /// Draw a black or white halo behind each shape, whichever contrasts better with the image region under the shape
+    #[clap(long)]
+    #[serde(default)]
+    pub auto_contrast: bool,
+    /// Draw the label next to each shape in the color of the shape with a white halo
+    #[clap(long, visible_alias = "draw-labels")]
+    #[serde(default, alias = "draw_labels")]
+    pub show_labels: bool,
+    /// Font size of the labels drawn by `--show-labels` and of text shapes. Defaults to 6 times the radius of each label
+    #[clap(long, value_hint = ValueHint::Other)]
+    #[serde(default)]
+    pub font_size: Option<usize>,
+    /// Marker of point shapes
+    #[clap(long, value_enum, default_value_t = Marker::Circle)]
+    #[serde(default)]
+    pub marker: Marker,
+    /// Format of the embedded image. `png` is lossless but larger
+    #[clap(long, value_enum, default_value_t = EmbedFormat::Jpeg)]
+    #[serde(default)]
+    pub image_format: EmbedFormat,
+    /// Quality (1 to 100) of the embedded JPEG image. The default quality of the encoder is used if not given
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    #[serde(default)]
+    pub jpeg_quality: Option<u8>,
+    /// Refer to the image file by `imagePath` (resolved from the json) instead of embedding the image to keep the outputs small
+    #[clap(long)]
+    #[serde(default)]
+    pub no_embed: bool,
+    /// Fill polygons with their label colors at the opacity (0.0 to 1.0). Polygons are drawn as outlines if not given or 0
+    #[clap(long, value_name = "OPACITY")]
+    #[serde(default)]
+    pub polygon_fill_opacity: Option<f64>,
+    /// Draw only the shapes of the shape types. Comma separated list (e.g. `polygon,point`)
+    #[clap(long, value_delimiter = ',', value_hint = ValueHint::Other)]
+    #[serde(default)]
+    pub only_shapes: Vec<String>,
+    /// Ignore user defaults.
+    /// Svg options are resolved in the order of builtin defaults < user defaults (`defaults.toml` in the lmrs config directory or `$LMRS_CONFIG`) < per-command config < command line options
+    #[clap(long)]
+    #[serde(skip)]
+    pub no_user_config: bool,
+    /// Per-label styles loaded from `label_styles` of the config yaml
+    #[clap(skip)]
+    #[serde(skip)]
+    pub label_styles: labelme_rs::LabelStyles,
 }
 
 impl Default for SvgConfig {
@@ -185,20 +349,96 @@ impl Default for SvgConfig {
             radius: 2,
             line_width: 2,
             resize: None,
+            max_image_bytes: None,
+            auto_contrast: false,
+            show_labels: false,
+            font_size: None,
+            marker: Marker::Circle,
+            image_format: EmbedFormat::Jpeg,
+            jpeg_quality: None,
+            no_embed: false,
+            polygon_fill_opacity: None,
+            only_shapes: vec![],
+            no_user_config: false,
+            label_styles: labelme_rs::LabelStyles::new(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Marker {
+    /// Filled circle
+    #[default]
+    Circle,
+    /// Cross of two lines showing the exact pixel
+    Cross,
+    /// Hollow square
+    Square,
+}
+
+impl From<Marker> for labelme_rs::PointMarker {
+    fn from(value: Marker) -> Self {
+        match value {
+            Marker::Circle => labelme_rs::PointMarker::Circle,
+            Marker::Cross => labelme_rs::PointMarker::Cross,
+            Marker::Square => labelme_rs::PointMarker::Square,
+        }
+    }
+}
+
+/// Format of the image embedded in svg
+#[derive(ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmbedFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl From<EmbedFormat> for labelme_rs::image::ImageFormat {
+    fn from(value: EmbedFormat) -> Self {
+        match value {
+            EmbedFormat::Jpeg => labelme_rs::image::ImageFormat::Jpeg,
+            EmbedFormat::Png => labelme_rs::image::ImageFormat::Png,
         }
     }
 }
 
 #[derive(Debug, Args)]
 pub struct SvgCmdArgs {
-    /// Input json filename
+    /// Input json filename. With `--output-template`, json containing directory or ndjson filename (`-` for stdin)
     #[clap(value_hint = ValueHint::FilePath)]
     pub input: PathBuf,
     /// Output svg filename
-    #[clap(value_hint = ValueHint::FilePath)]
-    pub output: PathBuf,
+    #[clap(value_hint = ValueHint::FilePath, required_unless_present = "output_template")]
+    pub output: Option<PathBuf>,
+    /// Render each json of the input in parallel into the filename made by the pattern.
+    /// Placeholders: `{stem}`, `{parent}`, and `{index}` of the json filename. e.g. `svgs/{stem}.svg`
+    #[clap(long, conflicts_with_all = ["output", "deep_zoom"], value_hint = ValueHint::Other)]
+    pub output_template: Option<crate::FilenamePattern>,
     #[clap(flatten)]
     pub svg: SvgConfig,
+    #[clap(flatten)]
+    pub deep_zoom: DeepZoomArgs,
+    /// Draw only shapes without the image. The view box fits to the shapes plus `--margin`
+    #[clap(long, conflicts_with = "deep_zoom")]
+    pub no_image: bool,
+    /// Margin around the shapes for `--no-image`
+    #[clap(long, default_value_t = 10.0, requires = "no_image")]
+    pub margin: f64,
+}
+
+/// Deep zoom args shared by svg and browse
+#[derive(Debug, Clone, Args)]
+pub struct DeepZoomArgs {
+    /// [Experimental] Embed only a low resolution base layer and generate DZI tiles of the full resolution image.
+    /// `--resize` is ignored
+    #[clap(long)]
+    pub deep_zoom: bool,
+    /// Tile size for `--deep-zoom`
+    #[clap(long, default_value_t = 256, requires = "deep_zoom")]
+    pub tile_size: u32,
 }
 
 #[derive(Args, Debug)]
@@ -214,6 +454,11 @@ pub struct SwapCmdArgs {
     /// Swap suffix (e.g. ".jpg") with the given suffix instead of swapping the prefix
     #[clap(long)]
     pub suffix: bool,
+    /// Input format. `auto` infers it from the input
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub lock: LockArgs,
 }
 
 #[derive(Args, Debug)]
@@ -226,6 +471,188 @@ pub struct ResizeCmdArgs {
     /// Output directory for resized images
     #[clap(long, value_hint = ValueHint::DirPath)]
     pub image: Option<PathBuf>,
+    /// Fail if any point is outside the image after resizing
+    #[clap(long)]
+    pub assert_in_bounds: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CropCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Cropping region. Specify in imagemagick's `-crop`-like geometry `WxH+X+Y`
+    #[clap(value_hint = ValueHint::Other, required_unless_present = "output_dir")]
+    pub region: Option<String>,
+    /// Output directory for cropped images
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub image: Option<PathBuf>,
+    /// Crop around each shape instead of the region and write `{stem}_{shape index}` images and jsons to the directory
+    #[clap(long, value_hint = ValueHint::DirPath, conflicts_with_all = ["region", "image"])]
+    pub output_dir: Option<PathBuf>,
+    /// Labels of the shapes to crop around. All shapes if not specified
+    #[clap(short, long, requires = "output_dir")]
+    pub label: Vec<String>,
+    /// Padding around the bounding boxes of the shapes in pixels
+    #[clap(long, default_value_t = 0, requires = "output_dir")]
+    pub padding: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct DedupePointsCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Distance within which consecutive vertices are regarded as duplicates
+    #[clap(long, default_value_t = 0.5)]
+    pub tolerance: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct DedupCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Shapes with the same label, shape type, and coordinates within the tolerance are regarded as duplicates
+    #[clap(long, default_value_t = 0.5)]
+    pub tolerance: f64,
+    /// Also remove rectangles and polygons whose IoU with an earlier shape of the same label exceeds the threshold
+    #[clap(long, value_parser = crate::parse_fraction, value_hint = ValueHint::Other)]
+    pub iou: Option<f64>,
+    /// Report the numbers of removed shapes per label to stderr at the end
+    #[clap(long)]
+    pub stats: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct NormalizeCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Scale normalized coordinates back to pixels
+    #[clap(long)]
+    pub denormalize: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CocoCmdArgs {
+    /// Input directory or jsonl/ndjson. Specify `-` to use stdin. COCO json with `--import`
+    pub input: PathBuf,
+    /// Text file listing labels one per line. Category ids follow the order instead of the sorted labels
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub labels: Option<PathBuf>,
+    /// Export points as boxes of the size centered at the points and circles as their bounding boxes instead of skipping them
+    #[clap(long, value_name = "SIZE")]
+    pub points_as_boxes: Option<f64>,
+    /// Export points as keypoints instead of skipping them
+    #[clap(long, conflicts_with = "points_as_boxes")]
+    pub points_as_keypoints: bool,
+    /// Convert COCO json into ndjson instead. Segmentation polygons become polygons and the other annotations become rectangles of the bboxes
+    #[clap(long, conflicts_with_all = ["labels", "points_as_boxes", "points_as_keypoints"])]
+    pub import: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RasterizeCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Labels to rasterize. All labels if not specified
+    #[clap(short, long, value_hint = ValueHint::Other)]
+    pub label: Vec<String>,
+    /// Output directory for `{image stem}_mask.png`
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output_dir: PathBuf,
+    /// Write the 1-based index of the label in `--label` as the pixel value instead of 255.
+    /// Later shapes overwrite earlier ones
+    #[clap(long, requires = "label")]
+    pub multi_class: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MaskCmdArgs {
+    /// Input directory or jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Yaml mapping labels to pixel values (e.g. `{road: 1, car: 2}`). Shapes of the other labels are skipped
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub labels: PathBuf,
+    /// Output directory for `{json stem}.png`
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct YoloCmdArgs {
+    /// Input directory or jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Text file listing class names one per line. Class indices follow the order
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub names: PathBuf,
+    /// Output directory for `{json stem}.txt`
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+    /// Skip shapes whose labels are not in the class names instead of failing
+    #[clap(long)]
+    pub skip_unknown: bool,
+    /// Convert polygons into their bounding boxes instead of skipping them
+    #[clap(long)]
+    pub poly_as_bbox: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AgreementCmdArgs {
+    /// Input jsonl/ndjson of an annotator
+    pub a: PathBuf,
+    /// Input jsonl/ndjson of the other annotator. Files are paired by the filenames
+    pub b: PathBuf,
+    /// Label to compare
+    #[clap(short, long, value_hint = ValueHint::Other)]
+    pub label: String,
+}
+
+#[derive(Args, Debug)]
+pub struct VocCmdArgs {
+    /// Input directory or jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Output directory for `{json stem}.xml`. Created if it does not exist
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ToLabelmeCmdArgs {
+    /// Input csv with the header row. Specify `-` to use stdin.
+    /// Columns are `filename`, `label`, `x`, `y`, and optionally `shape_type`, `group_id`, `width`, and `height`
+    pub input: PathBuf,
+    /// Image directory to obtain the image sizes from the images (`filename` relative to the directory)
+    /// instead of the `width` and `height` columns
+    #[clap(long, value_hint = ValueHint::DirPath)]
+    pub image_dir: Option<PathBuf>,
+}
+
+/// Output format of `convert`
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// Pascal VOC xml
+    Voc,
+    /// YOLO txt of rectangles with `classes.txt`
+    Yolo,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertCmdArgs {
+    /// Input directory or jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Output format
+    #[clap(short, long, value_enum)]
+    pub format: ConvertFormat,
+    /// Output directory for `{json stem}.xml` (voc) or `{json stem}.txt` and `classes.txt` (yolo)
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+    /// Export polygons with their vertices (`<segm>` of voc) instead of skipping them
+    #[clap(long)]
+    pub include_segmentation: bool,
+    /// Text file listing class names one per line for yolo. Class indices follow the order instead of the sorted labels
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub classes: Option<PathBuf>,
+    /// Export the bounding boxes of all shapes for yolo instead of rectangles only
+    #[clap(long)]
+    pub all_shapes: bool,
 }
 
 #[derive(ValueEnum, Debug, Copy, Clone)]
@@ -255,6 +682,16 @@ pub struct NdjsonCmdArgs {
     /// Do not ignore entries starting with `.`
     #[clap(short, long)]
     pub all: bool,
+    /// Output ndjson filename. Stdout is used if omitted
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Append to the output instead of truncating it
+    #[clap(long, requires = "output")]
+    pub append: bool,
+    /// Encoding of the json files (e.g. `shift_jis`). UTF-8 is assumed by default and other encodings are converted to UTF-8.
+    /// Malformed sequences are replaced with U+FFFD
+    #[clap(long, value_parser = crate::parse_encoding, value_hint = ValueHint::Other)]
+    pub encoding: Option<&'static labelme_rs::encoding_rs::Encoding>,
 }
 
 #[derive(Debug, Args)]
@@ -278,6 +715,9 @@ pub struct ArchiveCmdArgs {
     /// Output archive (.tar) or "-" for stdout
     #[clap(value_hint = ValueHint::FilePath)]
     pub output: PathBuf,
+    /// Set the number of threads reading files. Use all available cores by default
+    #[clap(short, long, default_value_t = 0)]
+    pub jobs: usize,
 }
 
 #[derive(ValueEnum, Debug, Copy, Clone)]
@@ -288,6 +728,18 @@ pub enum SplitParentHandling {
     Ignore,
 }
 
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SplitIfExists {
+    /// Fail on the first existing file
+    Error,
+    /// Keep existing files as they are
+    Skip,
+    /// Rewrite existing files
+    Overwrite,
+    /// Rewrite existing files only if the content differs. Formatting differences are ignored
+    Update,
+}
+
 #[derive(Debug, Args)]
 pub struct SplitCmdArgs {
     /// Input ndjson filename. Stdin is used if omitted
@@ -302,12 +754,28 @@ pub struct SplitCmdArgs {
     /// Key for content
     #[clap(long, default_value = "content", value_hint = ValueHint::Other)]
     pub content: String,
-    /// Overwrite json files if exist
-    #[clap(long, action)]
+    /// Overwrite json files if exist. Same as `--if-exists overwrite`
+    #[clap(long, action, conflicts_with = "if_exists")]
     pub overwrite: bool,
+    /// What to do when an output file already exists.
+    /// The numbers of written, skipped, and updated files are reported to stderr with `skip` and `update`
+    #[clap(long, default_value = "error")]
+    pub if_exists: SplitIfExists,
     /// How to handle the parent directory in the filename field
     #[clap(short, long, default_value = "keep")]
     pub parent: SplitParentHandling,
+    /// Output filename pattern relative to the output directory instead of the filename field.
+    /// Placeholders: `{stem}`, `{parent}`, and `{index}` (0-based line number). e.g. `{index}_{stem}.json`
+    #[clap(long, conflicts_with = "parent", value_hint = ValueHint::Other)]
+    pub pattern: Option<crate::FilenamePattern>,
+    /// Route records as they are into `<FLAG>.ndjson` and `not_<FLAG>.ndjson` in the output directory by whether the flag of the content is true,
+    /// instead of splitting into json files
+    #[clap(long, conflicts_with_all = ["by_label", "pattern"], value_hint = ValueHint::Other)]
+    pub by_flag: Option<String>,
+    /// Route records as they are into `<LABEL>.ndjson` and `not_<LABEL>.ndjson` in the output directory by whether any shape of the content has the label,
+    /// instead of splitting into json files
+    #[clap(long, conflicts_with = "pattern", value_hint = ValueHint::Other)]
+    pub by_label: Option<String>,
 }
 
 #[derive(Debug, Args)]
@@ -324,6 +792,12 @@ pub struct JoinCmdArgs {
     /// Missing key handling
     #[clap(long, default_value = "exit")]
     pub missing: MissingHandling,
+    /// Reassign `group_id` of the right shapes to those of the nearest left groups so that shapes of the same instances share groups
+    #[clap(long)]
+    pub by_group: bool,
+    /// Groups farther than this distance in pixels are not matched by `--by-group`
+    #[clap(long, default_value_t = f64::INFINITY, requires = "by_group")]
+    pub max_distance: f64,
 }
 
 #[derive(ValueEnum, Debug, Copy, Clone)]
@@ -342,6 +816,8 @@ pub enum MissingHandling {
     Exit,
     /// Continue on missing key
     Continue,
+    /// Warn and continue on missing key
+    Warn,
 }
 
 #[derive(Debug, Args)]
@@ -351,12 +827,334 @@ pub struct ExistCmdArgs {
     /// Invert output. i.e. output non-existing files
     #[clap(short = 'v', long)]
     pub invert: bool,
+    /// Check only the image files. By default, records with `imageData` are present regardless of `imagePath`
+    #[clap(long)]
+    pub ignore_image_data: bool,
 }
 
 #[derive(Debug, Args)]
 pub struct CountCmdArgs {
+    /// Input json or jsonl/ndjson filename, json containing directory, or tar archive (`.tar`, `.tar.gz`, or `.tgz`). Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Count distinct labels and shape types instead of flags
+    #[clap(long)]
+    pub distinct_labels: bool,
+    /// Count files by image size (`WxH`) of `imageWidth` and `imageHeight` instead of flags
+    #[clap(long, conflicts_with = "distinct_labels")]
+    pub image_sizes: bool,
+    /// Read the image sizes from the image files instead of the json. `imagePath` is resolved relative to the input directory, the input file, or the current working directory for stdin
+    #[clap(long, requires = "image_sizes")]
+    pub open_images: bool,
+    /// Input format. `auto` infers it from the input
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+    /// Count only the shapes of the shape types with `--distinct-labels`. Comma separated list (e.g. `polygon,point`)
+    #[clap(long, value_delimiter = ',', requires = "distinct_labels", value_hint = ValueHint::Other)]
+    pub only_shapes: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct LsCmdArgs {
+    /// Input json or jsonl/ndjson filename, json containing directory, or tar archive (`.tar`, `.tar.gz`, or `.tgz`). Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Print the header row
+    #[clap(long)]
+    pub header: bool,
+    /// Field delimiter
+    #[clap(short, long, default_value_t = '\t')]
+    pub delimiter: char,
+    /// Print one row per pair of filename and label with the number of shapes instead
+    #[clap(long)]
+    pub labels_only: bool,
+    /// Input format. `auto` infers it from the input
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckDimsCmdArgs {
+    /// Input json containing directory or jsonl/ndjson filename. Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Set the number of threads. Use all available cores by default
+    #[clap(short, long, default_value_t = 0)]
+    pub threads: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct FixCmdArgs {
+    /// Input json or jsonl/ndjson filename or json containing directory. Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Output json filename or output directory. Defaults: <INPUT> for directory or single file input, stdout for jsonl/ndjson input.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Apply only the given repairs. Comma separated list of
+    /// corner-order, dedupe-vertices, close-polygons, and drop-zero-area.
+    /// All repairs are applied by default
+    #[clap(long, value_delimiter = ',', value_hint = ValueHint::Other)]
+    pub only: Option<Vec<labelme_rs::repair::Repair>>,
+    /// Distance between the first and last vertices to regard a polygon as explicitly closed
+    #[clap(long, default_value_t = 1.0)]
+    pub epsilon: f64,
+    #[clap(flatten)]
+    pub preview: PreviewArgs,
+    #[clap(flatten)]
+    pub lock: LockArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct FlagsCmdArgs {
+    /// Input json or jsonl/ndjson filename or json containing directory. Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Output json filename or output directory. Defaults: <INPUT> for directory or single file input, stdout for jsonl/ndjson input.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Flags to set as `NAME[=true|false]` (true if the value is omitted). Comma separated list. Missing flags are appended
+    #[clap(long, value_delimiter = ',', value_parser = crate::parse_flag_assignment, required_unless_present = "clear", value_hint = ValueHint::Other)]
+    pub set: Vec<(String, bool)>,
+    /// Flags to remove. Comma separated list
+    #[clap(long, alias = "unset", value_delimiter = ',', value_hint = ValueHint::Other)]
+    pub clear: Vec<String>,
+    /// Update only the files satisfying the rule (e.g. `TL != TR`). The others are written unchanged
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub only_if: Option<String>,
+    #[clap(flatten)]
+    pub lock: LockArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RenameCmdArgs {
     /// Input json or jsonl/ndjson filename or json containing directory. Specify `-` for ndjson input with stdin (for piping).
     pub input: PathBuf,
+    /// Output json filename or output directory. Defaults: <INPUT> for directory or single file input, stdout for jsonl/ndjson input.
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Mapping of labels `old=new`. Overrides `--map-file`
+    #[clap(long, value_parser = crate::parse_label_mapping, required_unless_present = "map_file", value_hint = ValueHint::Other)]
+    pub map: Vec<(String, String)>,
+    /// Yaml (`{old: new}`) or csv (`old,new` without header) file of the mapping
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub map_file: Option<PathBuf>,
+    /// Behavior when a label in the mapping is not found in any of the inputs. Nothing is written with `exit`
+    #[clap(long, default_value = "warn")]
+    pub missing: MissingHandling,
+    /// Fail without writing outputs if any mapping renamed no shapes. Same as `--missing exit`
+    #[clap(long, conflicts_with = "missing")]
+    pub strict: bool,
+    /// Treat old labels as regular expressions matching whole labels. New labels may refer to the capture groups (e.g. `${1}`)
+    #[clap(long)]
+    pub regex: bool,
+    #[clap(flatten)]
+    pub lock: LockArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DatasetSplitCmdArgs {
+    /// Input ndjson filename. Stdin is used if omitted
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub input: Option<PathBuf>,
+    /// Output directory. `<NAME>.ndjson` is written for each split. Working directory is used by default
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    pub output: Option<PathBuf>,
+    /// Ratios of the splits. Comma separated list normalized to sum up to 1
+    #[clap(long, value_delimiter = ',', default_value = "0.8,0.1,0.1", value_hint = ValueHint::Other)]
+    pub ratios: Vec<f64>,
+    /// Names of the splits. Comma separated list of the same length as `--ratios`
+    #[clap(long, value_delimiter = ',', default_value = "train,val,test", value_hint = ValueHint::Other)]
+    pub names: Vec<String>,
+    /// Seed mixed into the hash. The same seed yields the same assignment
+    #[clap(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Split records with and without the label separately so that each split has the label in the same proportion.
+    /// The assignment of a record then depends on the other records with(out) the label
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub stratify: Option<String>,
+    /// Overwrite ndjson files if exist
+    #[clap(long)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Args)]
+#[clap(group(clap::ArgGroup::new("size").required(true).args(&["count", "fraction"])))]
+pub struct SampleCmdArgs {
+    /// Input ndjson filename. Stdin is used if omitted
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub input: Option<PathBuf>,
+    /// The number of records to sample
+    #[clap(short = 'n', long, value_hint = ValueHint::Other)]
+    pub count: Option<usize>,
+    /// Fraction (0.0 to 1.0) of the records to sample
+    #[clap(long, value_parser = crate::parse_fraction, value_hint = ValueHint::Other)]
+    pub fraction: Option<f64>,
+    /// Seed of the random number generator for reproducible samples. Random if omitted
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub seed: Option<u64>,
+    /// Sample records with and without the flag set to true separately to preserve their proportions
+    #[clap(long, value_hint = ValueHint::Other)]
+    pub stratify: Option<String>,
+}
+
+/// Lock args for commands writing files into a directory.
+/// The output directory is locked by `.lmrs.lock` while the command runs
+#[derive(Debug, Clone, Args)]
+pub struct LockArgs {
+    /// Take over the lock of the output directory even if another run seems to hold it
+    #[clap(long)]
+    pub force_lock: bool,
+}
+
+/// Preview args shared by commands modifying annotations
+#[derive(Debug, Clone, Args)]
+pub struct PreviewArgs {
+    /// Print changes as ndjson of `{"filename": ..., "patch": [JSON Patch operations]}` instead of writing outputs
+    #[clap(long)]
+    pub preview: bool,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum CooccurFormat {
+    /// Matrix with labels as the header row and column
+    Csv,
+    /// `{"labels": [...], "matrix": [[...]]}`
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct CooccurCmdArgs {
+    /// Input jsonl/ndjson filename or json containing directory. Specify `-` for ndjson input with stdin (for piping).
+    pub input: PathBuf,
+    /// Output format
+    #[clap(long, default_value = "csv")]
+    pub format: CooccurFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareStatsCmdArgs {
+    /// Input before the change. jsonl/ndjson filename or json containing directory. Specify `-` for ndjson input with stdin (for piping).
+    pub before: PathBuf,
+    /// Input after the change. jsonl/ndjson filename or json containing directory.
+    pub after: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeConfigsCmdArgs {
+    /// Input config yamls. Labels are merged in the given order
+    #[clap(required = true, value_hint = ValueHint::FilePath)]
+    pub input: Vec<PathBuf>,
+    /// Output yaml. Stdout is used if omitted
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+    /// Use the color in the latter config for conflicting labels instead of failing
+    #[clap(long)]
+    pub last_wins: bool,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum OriginShift {
+    /// From (0, 0) at the corner of the first pixel (labelme) to (0, 0) at its center. i.e. shift by -0.5
+    CornerToCenter,
+    /// From (0, 0) at the center of the first pixel to (0, 0) at its corner (labelme). i.e. shift by +0.5
+    CenterToCorner,
+}
+
+impl From<OriginShift> for labelme_rs::OriginConvention {
+    fn from(value: OriginShift) -> Self {
+        match value {
+            OriginShift::CornerToCenter => labelme_rs::OriginConvention::CornerToCenter,
+            OriginShift::CenterToCorner => labelme_rs::OriginConvention::CenterToCorner,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum FlipDirection {
+    /// Left and right, i.e. `x' = imageWidth - x`
+    Horizontal,
+    /// Upside down, i.e. `y' = imageHeight - y`
+    Vertical,
+}
+
+impl From<FlipDirection> for labelme_rs::Flip {
+    fn from(value: FlipDirection) -> Self {
+        match value {
+            FlipDirection::Horizontal => labelme_rs::Flip::Horizontal,
+            FlipDirection::Vertical => labelme_rs::Flip::Vertical,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+#[clap(group(clap::ArgGroup::new("transform").required(true).multiple(true)))]
+pub struct MatCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Convert the pixel coordinate origin convention
+    #[clap(long, group = "transform")]
+    pub origin: Option<OriginShift>,
+    /// Rotate clockwise by degrees. Multiples of 90 rotate the whole image, i.e. points are kept non-negative and `imageWidth` and `imageHeight` are swapped for 90 and 270.
+    /// Other angles rotate points about the image center
+    #[clap(long, group = "transform", allow_negative_numbers = true, value_hint = ValueHint::Other)]
+    pub rotate: Option<f64>,
+    /// Mirror points within the image. Applied after `--rotate`
+    #[clap(long, group = "transform")]
+    pub flip: Option<FlipDirection>,
+    /// Output directory for rotated images. Only for multiples of 90 degrees. `imagePath` is rewritten to the saved image
+    #[clap(long, requires = "rotate", conflicts_with = "flip", value_hint = ValueHint::DirPath)]
+    pub image: Option<PathBuf>,
+    /// Fail if any point is outside the image after the transformation
+    #[clap(long)]
+    pub assert_in_bounds: bool,
+}
+
+/// Rotation by 90 degrees of `augment`
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuarterTurn {
+    /// 90 degrees clockwise
+    Cw,
+    /// 90 degrees counterclockwise
+    Ccw,
+    /// 180 degrees
+    #[value(name = "180")]
+    Half,
+}
+
+#[derive(Debug, Args)]
+#[clap(group(clap::ArgGroup::new("augmentation").required(true).multiple(true)))]
+pub struct AugmentCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Flip horizontally. `_fliph` is appended to the filename
+    #[clap(long, group = "augmentation")]
+    pub flip_h: bool,
+    /// Flip vertically. `_flipv` is appended to the filename
+    #[clap(long, group = "augmentation")]
+    pub flip_v: bool,
+    /// Rotate by 90 degrees. Comma separated list. `_rotcw`, `_rotccw`, or `_rot180` is appended to the filename
+    #[clap(
+        long = "rotate-90",
+        value_enum,
+        value_delimiter = ',',
+        group = "augmentation"
+    )]
+    pub rotate_90: Vec<QuarterTurn>,
+    /// Apply all the augmentations
+    #[clap(long, group = "augmentation")]
+    pub all: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReprojectCmdArgs {
+    /// Input jsonl/ndjson. Specify `-` to use stdin
+    pub input: PathBuf,
+    /// Json with point shapes in the coordinates of the input
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub source: PathBuf,
+    /// Json with point shapes of the same labels in the target coordinates. 4 or more labels are required.
+    /// `imageWidth` and `imageHeight` of the outputs are set to those of the target
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub target: PathBuf,
+    /// Fail if any point is outside the image after the transformation
+    #[clap(long)]
+    pub assert_in_bounds: bool,
 }
 
 #[derive(Debug, Args)]
@@ -387,6 +1185,59 @@ pub struct SortCmdArgs {
     /// Invert label matching. i.e. sort labels not in the list
     #[clap(long = "inv-label", requires = "labels")]
     pub invert_label_matching: bool,
+
+    /// Encoding of the json input (e.g. `shift_jis`). See `lmrs ndjson --encoding`
+    #[clap(long, value_parser = crate::parse_encoding, value_hint = ValueHint::Other)]
+    pub encoding: Option<&'static labelme_rs::encoding_rs::Encoding>,
+
+    /// Input format. `auto` infers it from the input
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+}
+
+/// Interpretation of the input of commands accepting multiple input types
+#[derive(ValueEnum, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Infer from the input: directory, `.json`, or `-`, `.jsonl`, and `.ndjson` for ndjson
+    #[default]
+    Auto,
+    /// Single labelme json
+    Json,
+    /// jsonl/ndjson. `-` for stdin
+    Ndjson,
+    /// json containing directory
+    Dir,
+}
+
+impl InputFormat {
+    /// Resolve `Auto` by the input. `None` if the format cannot be inferred
+    ///
+    /// ```
+    /// use lmrs::cli::InputFormat;
+    /// use std::path::Path;
+    /// assert_eq!(InputFormat::Auto.resolve(Path::new("a.json")), Some(InputFormat::Json));
+    /// assert_eq!(InputFormat::Auto.resolve(Path::new("-")), Some(InputFormat::Ndjson));
+    /// assert_eq!(InputFormat::Auto.resolve(Path::new("a.txt")), None);
+    /// assert_eq!(InputFormat::Ndjson.resolve(Path::new("a.txt")), Some(InputFormat::Ndjson));
+    /// ```
+    pub fn resolve(self, input: &Path) -> Option<InputFormat> {
+        if self != InputFormat::Auto {
+            return Some(self);
+        }
+        if input.is_dir() {
+            Some(InputFormat::Dir)
+        } else if input.extension().is_some_and(|ext| ext == "json") {
+            Some(InputFormat::Json)
+        } else if input.as_os_str() == "-"
+            || input
+                .extension()
+                .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
+        {
+            Some(InputFormat::Ndjson)
+        } else {
+            None
+        }
+    }
 }
 
 /// Server config
@@ -434,4 +1285,8 @@ pub struct BrowseCmdArgs {
     /// SVG config
     #[clap(flatten)]
     pub svg: SvgConfig,
+
+    /// Deep zoom config. Tiles are served from `/tiles/{id}/{level}/{col}_{row}.jpg` with the descriptor `/tiles/{id}.dzi`
+    #[clap(flatten)]
+    pub deep_zoom: DeepZoomArgs,
 }
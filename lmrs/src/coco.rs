@@ -0,0 +1,112 @@
+use anyhow::{bail, ensure, Context, Result};
+use labelme_rs::coco::{self, CocoDataset, CocoOptions, PointExport};
+use labelme_rs::indexmap::IndexSet;
+use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use lmrs::cli::CocoCmdArgs as CmdArgs;
+
+/// Labels listed one per line. Empty lines are ignored
+pub(crate) fn load_labels(filename: &Path) -> Result<Vec<String>> {
+    let content =
+        std::fs::read_to_string(filename).with_context(|| format!("Reading {:?}", filename))?;
+    let labels: Vec<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+    let distinct: IndexSet<_> = labels.iter().collect();
+    ensure!(
+        distinct.len() == labels.len(),
+        "Duplicate labels in {:?}",
+        filename
+    );
+    Ok(labels)
+}
+
+/// Records of the directory or ndjson
+pub(crate) fn load_records(input: &Path) -> Result<Vec<(LabelMeData, String)>> {
+    let mut records = vec![];
+    if input.is_dir() {
+        let entries: Vec<_> = glob::glob(
+            input
+                .join("*.json")
+                .to_str()
+                .context("Failed to get glob")?,
+        )
+        .expect("Failed to read glob pattern")
+        .collect();
+        for entry in entries {
+            let entry = entry?;
+            let data = LabelMeData::try_from(entry.as_path())
+                .with_context(|| format!("Parsing {:?}", entry))?;
+            let filename = entry
+                .file_name()
+                .context("Failed to obtain filename")?
+                .to_string_lossy()
+                .to_string();
+            records.push((data, filename));
+        }
+    } else if input.as_os_str() == "-"
+        || input
+            .extension()
+            .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
+    {
+        let reader: Box<dyn BufRead> = if input.as_os_str() == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(input)?))
+        };
+        for line in reader.lines() {
+            let line = line?;
+            let lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
+            records.push((lm_data_line.content, lm_data_line.filename));
+        }
+    } else {
+        bail!("Unknown input type: {:?}", input);
+    }
+    Ok(records)
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    if args.import {
+        let reader = BufReader::new(
+            File::open(&args.input).with_context(|| format!("Opening {:?}", args.input))?,
+        );
+        let dataset: CocoDataset =
+            serde_json::from_reader(reader).with_context(|| format!("Parsing {:?}", args.input))?;
+        for annotation in dataset.annotations.iter().filter(|a| a.is_crowd()) {
+            warn!(
+                "Skipping crowd annotation {} of image {}",
+                annotation.id, annotation.image_id
+            );
+        }
+        for line in coco::from_coco_dataset(&dataset)? {
+            labelme_rs::integral::to_writer(&mut writer, &line)?;
+            writeln!(writer)?;
+        }
+        return Ok(());
+    }
+    let points = match (args.points_as_boxes, args.points_as_keypoints) {
+        (Some(size), _) => PointExport::Boxes(size),
+        (None, true) => PointExport::Keypoints,
+        (None, false) => PointExport::Skip,
+    };
+    let options = CocoOptions {
+        labels: args.labels.as_deref().map(load_labels).transpose()?,
+        points,
+    };
+    let (records, filenames): (Vec<_>, Vec<_>) = load_records(&args.input)?.into_iter().unzip();
+    let entries: Vec<_> = records
+        .into_iter()
+        .zip(filenames.iter().map(String::as_str))
+        .collect();
+    let dataset = coco::to_coco_dataset_with_options(&entries, &options)?;
+    labelme_rs::integral::to_writer_pretty(&mut writer, &dataset)?;
+    writeln!(writer)?;
+    Ok(())
+}
@@ -2,43 +2,84 @@ use mimalloc::MiMalloc;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 #[macro_use]
 extern crate log;
 use anyhow::Result;
 
+mod agreement;
 mod archive;
+mod augment;
 mod browse;
+mod check_dims;
+mod coco;
+mod compare_stats;
+mod convert;
+mod cooccur;
 mod count;
+mod crop;
+mod dataset_split;
+mod dedup;
+mod dedupe_points;
 mod drop_dups;
 mod exist;
 mod filter;
+mod fix;
+mod flags;
 mod init;
 mod join;
 mod lm2svg;
 mod lms2html;
+mod ls;
+mod mask;
+mod mat;
+mod merge_configs;
 mod ndjson;
+mod normalize;
+mod pdf;
+mod rasterize;
 mod remove;
+mod rename;
+mod reproject;
 mod resize;
+mod sample;
 mod shapeshift;
 mod sort;
 mod split_ndjson;
 mod swap_prefix;
+#[cfg(test)]
+mod test_util;
+mod to_labelme;
 mod validate;
+mod voc;
+mod yolo;
 
 use lmrs::cli::Cli;
 use lmrs::cli::Command;
 
 fn main() -> Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let (_, sub_matches) = matches.subcommand().expect("Subcommand is required");
     #[cfg(unix)]
     unsafe {
         libc::signal(libc::SIGPIPE, libc::SIG_DFL);
     }
-    match cli.command {
-        Command::Catalog(args) => lms2html::cmd(args),
-        Command::Svg(args) => lm2svg::cmd(args),
+    if matches!(
+        cli.command,
+        Command::Catalog(_)
+            | Command::Validate(_)
+            | Command::Split(_)
+            | Command::Resize(_)
+            | Command::Crop(_)
+            | Command::Archive(_)
+    ) {
+        lmrs::cancel::install_handler()?;
+    }
+    let result = match cli.command {
+        Command::Catalog(args) => lms2html::cmd(args, sub_matches),
+        Command::Svg(args) => lm2svg::cmd(args, sub_matches),
         Command::Validate(args) => validate::cmd(args),
         Command::Swap(args) => swap_prefix::cmd(args),
         Command::Ndjson(args) => ndjson::cmd(args),
@@ -54,6 +95,40 @@ fn main() -> Result<()> {
         Command::Archive(args) => archive::cmd(args),
         Command::Count(args) => count::cmd(args),
         Command::Sort(args) => sort::cmd(args),
-        Command::Browse(args) => browse::cmd(args),
+        Command::Browse(args) => browse::cmd(args, sub_matches),
+        Command::CheckDims(args) => check_dims::cmd(args),
+        Command::Fix(args) => fix::cmd(args),
+        Command::Cooccur(args) => cooccur::cmd(args),
+        Command::Mat(args) => mat::cmd(args),
+        Command::CompareStats(args) => compare_stats::cmd(args),
+        Command::MergeConfigs(args) => merge_configs::cmd(args),
+        Command::Ls(args) => ls::cmd(args),
+        Command::Flags(args) => flags::cmd(args),
+        Command::Rename(args) => rename::cmd(args),
+        Command::Reproject(args) => reproject::cmd(args),
+        Command::Augment(args) => augment::cmd(args),
+        Command::DatasetSplit(args) => dataset_split::cmd(args),
+        Command::Sample(args) => sample::cmd(args),
+        Command::Normalize(args) => normalize::cmd(args),
+        Command::Crop(args) => crop::cmd(args),
+        Command::DedupePoints(args) => dedupe_points::cmd(args),
+        Command::Dedup(args) => dedup::cmd(args),
+        Command::Coco(args) => coco::cmd(args),
+        Command::Rasterize(args) => rasterize::cmd(args),
+        Command::Mask(args) => mask::cmd(args),
+        Command::Yolo(args) => yolo::cmd(args),
+        Command::Convert(args) => convert::cmd(args),
+        Command::Agreement(args) => agreement::cmd(args),
+        Command::Voc(args) => voc::cmd(args),
+        Command::ToLabelme(args) => to_labelme::cmd(args),
+    };
+    if let Some(cancelled) = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<lmrs::cancel::Cancelled>())
+    {
+        warn!("{}", cancelled);
+        std::process::exit(lmrs::cancel::EXIT_CODE);
     }
+    result
 }
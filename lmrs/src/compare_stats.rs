@@ -0,0 +1,172 @@
+use anyhow::Result;
+use labelme_rs::indexmap::{IndexMap, IndexSet};
+use std::io::Write;
+use std::path::Path;
+
+use crate::count::{for_each_data, LabelInventory};
+use lmrs::cli::CompareStatsCmdArgs as CmdArgs;
+
+/// Change of a label between two inputs
+#[derive(Debug, PartialEq)]
+struct LabelDelta {
+    label: String,
+    before: usize,
+    after: usize,
+}
+
+impl LabelDelta {
+    fn status(&self) -> &'static str {
+        match (self.before, self.after) {
+            (0, _) => "added",
+            (_, 0) => "removed",
+            (b, a) if b != a => "changed",
+            _ => "unchanged",
+        }
+    }
+
+    fn delta(&self) -> String {
+        format!("{:+}", self.after as isize - self.before as isize)
+    }
+}
+
+/// Deltas of all labels in `before` or `after`, sorted by label
+fn compare(before: &IndexMap<String, usize>, after: &IndexMap<String, usize>) -> Vec<LabelDelta> {
+    let mut labels: Vec<&String> = before
+        .keys()
+        .chain(after.keys())
+        .collect::<IndexSet<_>>()
+        .into_iter()
+        .collect();
+    labels.sort();
+    labels
+        .into_iter()
+        .map(|label| LabelDelta {
+            label: label.clone(),
+            before: before.get(label).copied().unwrap_or(0),
+            after: after.get(label).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+fn write_table(deltas: &[LabelDelta], writer: &mut impl Write) -> std::io::Result<()> {
+    let header = ["label", "before", "after", "delta", "status"];
+    let rows: Vec<[String; 5]> = deltas
+        .iter()
+        .map(|d| {
+            [
+                d.label.clone(),
+                d.before.to_string(),
+                d.after.to_string(),
+                d.delta(),
+                d.status().to_string(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut write_row = |cells: [&str; 5]| {
+        let line: Vec<_> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| match i {
+                // numbers are right-aligned
+                1..=3 => format!("{:>width$}", cell),
+                _ => format!("{:<width$}", cell),
+            })
+            .collect();
+        writeln!(writer, "{}", line.join("  ").trim_end())
+    };
+    write_row(header)?;
+    for row in &rows {
+        write_row(row.each_ref().map(String::as_str))?;
+    }
+    Ok(())
+}
+
+fn count_labels(input: &Path) -> Result<IndexMap<String, usize>> {
+    let mut inventory = LabelInventory::default();
+    for_each_data(input, lmrs::cli::InputFormat::Auto, |data| {
+        inventory.count(data)
+    })?;
+    Ok(inventory.labels)
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let before = count_labels(&args.before)?;
+    let after = count_labels(&args.after)?;
+    let deltas = compare(&before, &after);
+    write_table(&deltas, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use labelme_rs::LabelMeData;
+
+    fn count(datasets: Vec<LabelMeData>) -> IndexMap<String, usize> {
+        let mut inventory = LabelInventory::default();
+        for data in datasets {
+            inventory.count(data);
+        }
+        inventory.labels
+    }
+
+    #[test]
+    fn test_compare() -> Result<()> {
+        let before = count(vec![LabelMeData::new(
+            &[(1.0, 1.0), (2.0, 2.0), (3.0, 3.0)],
+            &["L1".into(), "L2".into(), "L3".into()],
+            128,
+            128,
+            "1.jpg",
+        )]);
+        let after = count(vec![
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0)],
+                &["L1".into(), "L1".into()],
+                128,
+                128,
+                "1.jpg",
+            ),
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0)],
+                &["L3".into(), "L4".into()],
+                128,
+                128,
+                "2.jpg",
+            ),
+        ]);
+        let deltas = compare(&before, &after);
+        let statuses: Vec<_> = deltas
+            .iter()
+            .map(|d| (d.label.as_str(), d.before, d.after, d.status()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("L1", 1, 2, "changed"),
+                ("L2", 1, 0, "removed"),
+                ("L3", 1, 1, "unchanged"),
+                ("L4", 0, 1, "added"),
+            ]
+        );
+        let mut table = vec![];
+        write_table(&deltas, &mut table)?;
+        assert_eq!(
+            String::from_utf8(table)?,
+            "label  before  after  delta  status
+L1          1      2     +1  changed
+L2          1      0     -1  removed
+L3          1      1     +0  unchanged
+L4          0      1     +1  added
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+//! User-level defaults shared by the svg related commands
+
+use crate::cli::{EmbedFormat, Marker, SvgConfig};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable to override the location of the user defaults file
+pub const CONFIG_ENV: &str = "LMRS_CONFIG";
+
+/// Filename of the user defaults in the lmrs config directory
+pub const DEFAULTS_FILENAME: &str = "defaults.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read {0:?}")]
+    IoError(PathBuf, #[source] std::io::Error),
+    #[error("failed to parse {0:?}")]
+    TomlError(PathBuf, #[source] toml::de::Error),
+}
+
+/// lmrs config directory. e.g. `~/.config/lmrs`
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("lmrs"))
+}
+
+/// Location of the user defaults: `$LMRS_CONFIG` or `defaults.toml` in the config directory
+pub fn user_defaults_path() -> Option<PathBuf> {
+    match std::env::var_os(CONFIG_ENV) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_dir().map(|dir| dir.join(DEFAULTS_FILENAME)),
+    }
+}
+
+/// Defaults for [`SvgConfig`] of user defaults and per-command configs. Unspecified fields keep the lower layers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SvgDefaults {
+    /// Config yaml file of Labelme. Relative path is resolved from the defaults file
+    pub config: Option<PathBuf>,
+    pub radius: Option<usize>,
+    pub line_width: Option<usize>,
+    pub resize: Option<String>,
+    pub max_image_bytes: Option<usize>,
+    pub auto_contrast: Option<bool>,
+    #[serde(alias = "draw_labels")]
+    pub show_labels: Option<bool>,
+    pub font_size: Option<usize>,
+    pub marker: Option<Marker>,
+    pub image_format: Option<EmbedFormat>,
+    pub jpeg_quality: Option<u8>,
+    pub no_embed: Option<bool>,
+    pub polygon_fill_opacity: Option<f64>,
+    pub only_shapes: Option<Vec<String>>,
+}
+
+impl SvgDefaults {
+    /// Overwrite the fields of `config` specified in `self`
+    fn apply(&self, config: &mut SvgConfig) {
+        config.config = self.config.clone().or(config.config.take());
+        config.radius = self.radius.unwrap_or(config.radius);
+        config.line_width = self.line_width.unwrap_or(config.line_width);
+        config.resize = self.resize.clone().or(config.resize.take());
+        config.max_image_bytes = self.max_image_bytes.or(config.max_image_bytes);
+        config.auto_contrast = self.auto_contrast.unwrap_or(config.auto_contrast);
+        config.show_labels = self.show_labels.unwrap_or(config.show_labels);
+        config.font_size = self.font_size.or(config.font_size);
+        config.marker = self.marker.unwrap_or(config.marker);
+        config.image_format = self.image_format.unwrap_or(config.image_format);
+        config.jpeg_quality = self.jpeg_quality.or(config.jpeg_quality);
+        config.no_embed = self.no_embed.unwrap_or(config.no_embed);
+        config.polygon_fill_opacity = self.polygon_fill_opacity.or(config.polygon_fill_opacity);
+        if let Some(only_shapes) = &self.only_shapes {
+            config.only_shapes.clone_from(only_shapes);
+        }
+    }
+}
+
+/// Contents of `defaults.toml`
+///
+/// ```toml
+/// [svg]
+/// config = "colors.yaml"
+/// radius = 3
+/// resize = "512x512"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserDefaults {
+    #[serde(default)]
+    pub svg: SvgDefaults,
+}
+
+impl UserDefaults {
+    /// Load defaults from `path`. Relative paths in the file are resolved from its directory
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::IoError(path.to_path_buf(), e))?;
+        let mut defaults: Self =
+            toml::from_str(&s).map_err(|e| ConfigError::TomlError(path.to_path_buf(), e))?;
+        if let (Some(config), Some(parent)) = (defaults.svg.config.as_mut(), path.parent()) {
+            if config.is_relative() {
+                *config = parent.join(&config);
+            }
+        }
+        Ok(defaults)
+    }
+
+    /// Load defaults from [`user_defaults_path`] if the file exists
+    pub fn load_user() -> Result<Option<Self>, ConfigError> {
+        match user_defaults_path() {
+            Some(path) if path.exists() => {
+                log::debug!("user defaults: {:?}", path);
+                Self::load(&path).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn is_given(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+impl SvgConfig {
+    /// Resolve the effective config from the precedence chain
+    /// `builtin` < user defaults < `base` (per-command config) < CLI flags.
+    ///
+    /// Arguments:
+    /// - `self`: SvgConfig parsed from the command line
+    /// - `matches`: matches of the subcommand `self` was parsed from
+    /// - `builtin`: builtin defaults of the command
+    /// - `base`: per-command config such as the browse config. Only the specified fields are applied
+    pub fn resolve(
+        &self,
+        matches: &ArgMatches,
+        builtin: SvgConfig,
+        base: Option<&SvgDefaults>,
+    ) -> Result<SvgConfig, ConfigError> {
+        let mut resolved = builtin;
+        if !self.no_user_config {
+            if let Some(defaults) = UserDefaults::load_user()? {
+                defaults.svg.apply(&mut resolved);
+            }
+        }
+        if let Some(base) = base {
+            base.apply(&mut resolved);
+        }
+        if is_given(matches, "config") {
+            resolved.config.clone_from(&self.config);
+        }
+        if is_given(matches, "radius") {
+            resolved.radius = self.radius;
+        }
+        if is_given(matches, "line_width") {
+            resolved.line_width = self.line_width;
+        }
+        if is_given(matches, "resize") {
+            resolved.resize.clone_from(&self.resize);
+        }
+        if is_given(matches, "max_image_bytes") {
+            resolved.max_image_bytes = self.max_image_bytes;
+        }
+        if is_given(matches, "auto_contrast") {
+            resolved.auto_contrast = self.auto_contrast;
+        }
+        if is_given(matches, "show_labels") {
+            resolved.show_labels = self.show_labels;
+        }
+        if is_given(matches, "font_size") {
+            resolved.font_size = self.font_size;
+        }
+        if is_given(matches, "marker") {
+            resolved.marker = self.marker;
+        }
+        if is_given(matches, "image_format") {
+            resolved.image_format = self.image_format;
+        }
+        if is_given(matches, "jpeg_quality") {
+            resolved.jpeg_quality = self.jpeg_quality;
+        }
+        if is_given(matches, "no_embed") {
+            resolved.no_embed = self.no_embed;
+        }
+        if is_given(matches, "polygon_fill_opacity") {
+            resolved.polygon_fill_opacity = self.polygon_fill_opacity;
+        }
+        if is_given(matches, "only_shapes") {
+            resolved.only_shapes.clone_from(&self.only_shapes);
+        }
+        resolved.no_user_config = self.no_user_config;
+        Ok(resolved)
+    }
+
+    /// Rendering options of [`labelme_rs::LabelMeData::to_svg_with_options`]
+    pub fn svg_options(&self) -> labelme_rs::SvgOptions {
+        labelme_rs::SvgOptions {
+            auto_contrast: self.auto_contrast,
+            show_labels: self.show_labels,
+            font_size: self.font_size,
+            point_marker: self.marker.into(),
+            image_format: self.image_format.into(),
+            jpeg_quality: self.jpeg_quality,
+            embed_image: !self.no_embed,
+            label_styles: self.label_styles.clone(),
+            polygon_fill_opacity: self.polygon_fill_opacity,
+            shape_types: self.only_shapes.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::SvgCmdArgs;
+    use clap::{Args, FromArgMatches};
+
+    fn parse(args: &[&str]) -> (SvgCmdArgs, ArgMatches) {
+        let matches = SvgCmdArgs::augment_args(clap::Command::new("svg"))
+            .no_binary_name(true)
+            .get_matches_from(args);
+        let parsed = SvgCmdArgs::from_arg_matches(&matches).unwrap();
+        (parsed, matches)
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let base = SvgDefaults {
+            radius: Some(5),
+            line_width: Some(4),
+            ..Default::default()
+        };
+        let (args, matches) = parse(&["in.json", "out.svg", "--no-user-config"]);
+        let resolved = args
+            .svg
+            .resolve(&matches, SvgConfig::default(), None)
+            .unwrap();
+        assert_eq!(resolved.radius, 2, "builtin");
+        let resolved = args
+            .svg
+            .resolve(&matches, SvgConfig::default(), Some(&base))
+            .unwrap();
+        assert_eq!(resolved.radius, 5, "base over builtin");
+
+        let (args, matches) = parse(&["in.json", "out.svg", "--no-user-config", "--radius", "9"]);
+        let resolved = args
+            .svg
+            .resolve(&matches, SvgConfig::default(), Some(&base))
+            .unwrap();
+        assert_eq!(resolved.radius, 9, "CLI over base");
+        assert_eq!(resolved.line_width, 4);
+    }
+
+    #[test]
+    fn test_resolve_user_defaults_under_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let defaults = dir.path().join(DEFAULTS_FILENAME);
+        std::fs::write(&defaults, "[svg]\nradius = 5\nshow_labels = true\n").unwrap();
+        std::env::set_var(CONFIG_ENV, &defaults);
+        // the base specifies neither the radius nor show_labels
+        let base = SvgDefaults {
+            line_width: Some(4),
+            ..Default::default()
+        };
+        let (args, matches) = parse(&["in.json", "out.svg"]);
+        let resolved = args
+            .svg
+            .resolve(&matches, SvgConfig::default(), Some(&base))
+            .unwrap();
+        assert_eq!(resolved.radius, 5, "user defaults under base");
+        assert!(resolved.show_labels);
+        assert_eq!(resolved.line_width, 4);
+
+        // base can turn off a user default
+        let base = SvgDefaults {
+            show_labels: Some(false),
+            ..Default::default()
+        };
+        let resolved = args
+            .svg
+            .resolve(&matches, SvgConfig::default(), Some(&base))
+            .unwrap();
+        assert!(!resolved.show_labels);
+        assert_eq!(resolved.radius, 5);
+        std::env::remove_var(CONFIG_ENV);
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use labelme_rs::voc::{self, VocOptions};
+use labelme_rs::yolo::{self, YoloOptions};
+use labelme_rs::LabelMeData;
+use std::path::Path;
+
+use crate::coco::{load_labels, load_records};
+use lmrs::cli::{ConvertCmdArgs as CmdArgs, ConvertFormat};
+
+/// Write the converted contents of each record (data and json filename) into `{json stem}.{extension}` in `output`
+pub(crate) fn write_each(
+    records: Vec<(LabelMeData, String)>,
+    output: &Path,
+    extension: &str,
+    mut convert: impl FnMut(&LabelMeData) -> Result<String>,
+) -> Result<()> {
+    std::fs::create_dir_all(output)?;
+    for (data, filename) in records {
+        let content = convert(&data).with_context(|| format!("Converting {}", filename))?;
+        let stem = Path::new(&filename)
+            .file_stem()
+            .context("Failed to obtain file stem")?
+            .to_string_lossy();
+        let outname = output.join(format!("{stem}.{extension}"));
+        std::fs::write(&outname, content).with_context(|| format!("Writing {:?}", outname))?;
+    }
+    Ok(())
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let records = load_records(&args.input)?;
+    match args.format {
+        ConvertFormat::Voc => {
+            let options = VocOptions {
+                include_segmentation: args.include_segmentation,
+                ..Default::default()
+            };
+            write_each(records, &args.output, "xml", |data| {
+                Ok(voc::to_voc_xml(data, &options))
+            })
+        }
+        ConvertFormat::Yolo => {
+            let options = YoloOptions {
+                all_shapes: args.all_shapes,
+                ..Default::default()
+            };
+            let names = match &args.classes {
+                Some(classes) => load_labels(classes)?,
+                None => yolo::class_names(records.iter().map(|(data, _)| data), &options),
+            };
+            std::fs::create_dir_all(&args.output)?;
+            let classes: String = names.iter().map(|name| format!("{name}\n")).collect();
+            std::fs::write(args.output.join("classes.txt"), classes)?;
+            write_each(records, &args.output, "txt", |data| {
+                Ok(yolo::to_yolo_txt(data, &names, &options)?)
+            })
+        }
+    }
+}
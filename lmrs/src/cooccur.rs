@@ -0,0 +1,112 @@
+use anyhow::Result;
+use labelme_rs::indexmap::{IndexMap, IndexSet};
+use labelme_rs::{serde_json, LabelMeData};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::count::for_each_data;
+use lmrs::cli::{CooccurCmdArgs as CmdArgs, CooccurFormat};
+
+/// Label co-occurrence matrix.
+/// `matrix[i][j]` is the number of files containing both `labels[i]` and `labels[j]`
+/// and `matrix[i][i]` is the number of files containing `labels[i]`
+#[derive(Serialize, Debug, Default)]
+struct Cooccurrence {
+    labels: Vec<String>,
+    matrix: Vec<Vec<usize>>,
+}
+
+impl Cooccurrence {
+    fn from_label_sets(label_sets: &[IndexSet<String>]) -> Self {
+        let mut labels: Vec<String> = label_sets
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<IndexSet<_>>()
+            .into_iter()
+            .collect();
+        labels.sort();
+        let indices: IndexMap<&str, usize> = labels
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.as_str(), i))
+            .collect();
+        let mut matrix = vec![vec![0; labels.len()]; labels.len()];
+        for set in label_sets {
+            let set_indices: Vec<usize> = set.iter().map(|l| indices[l.as_str()]).collect();
+            for &i in &set_indices {
+                for &j in &set_indices {
+                    matrix[i][j] += 1;
+                }
+            }
+        }
+        Self { labels, matrix }
+    }
+
+    fn write_csv(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, ",{}", self.labels.join(","))?;
+        for (label, row) in self.labels.iter().zip(&self.matrix) {
+            let row: Vec<_> = row.iter().map(|c| c.to_string()).collect();
+            writeln!(writer, "{},{}", label, row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn label_set(data: LabelMeData) -> IndexSet<String> {
+    data.shapes.into_iter().map(|s| s.label).collect()
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let mut label_sets = vec![];
+    for_each_data(&args.input, lmrs::cli::InputFormat::Auto, |data| {
+        label_sets.push(label_set(data))
+    })?;
+    let cooccurrence = Cooccurrence::from_label_sets(&label_sets);
+    let mut writer = std::io::stdout().lock();
+    match args.format {
+        CooccurFormat::Csv => cooccurrence.write_csv(&mut writer)?,
+        CooccurFormat::Json => writeln!(writer, "{}", serde_json::to_string(&cooccurrence)?)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooccurrence() -> Result<()> {
+        let datasets = [
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0)],
+                &["B".into(), "A".into()],
+                128,
+                128,
+                "1.jpg",
+            ),
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0), (3.0, 3.0)],
+                &["A".into(), "B".into(), "A".into()],
+                128,
+                128,
+                "2.jpg",
+            ),
+            LabelMeData::new(&[(1.0, 1.0)], &["C".into()], 128, 128, "3.jpg"),
+        ];
+        let label_sets: Vec<_> = datasets.into_iter().map(label_set).collect();
+        let cooccurrence = Cooccurrence::from_label_sets(&label_sets);
+        assert_eq!(cooccurrence.labels, vec!["A", "B", "C"]);
+        assert_eq!(
+            cooccurrence.matrix,
+            vec![vec![2, 2, 0], vec![2, 2, 0], vec![0, 0, 1]]
+        );
+        let mut csv = vec![];
+        cooccurrence.write_csv(&mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            ",A,B,C\nA,2,2,0\nB,2,2,0\nC,0,0,1\n"
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,12 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use labelme_rs::indexmap::IndexMap;
 use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
-use lmrs::cli::CountCmdArgs as CmdArgs;
+use lmrs::cli::{CountCmdArgs as CmdArgs, InputFormat};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Counts {
@@ -28,48 +29,233 @@ impl Counts {
     }
 }
 
-pub fn cmd(args: CmdArgs) -> Result<()> {
-    let mut counts = Counts::new();
-    if args.input.is_dir() {
-        let entries: Vec<_> = glob::glob(
-            args.input
-                .join("*.json")
-                .to_str()
-                .context("Failed to get glob")?,
-        )
-        .expect("Failed to read glob pattern")
-        .collect();
-        for entry in entries {
-            let entry = entry?;
-            let reader = BufReader::new(File::open(&entry)?);
-            let data: LabelMeData = serde_json::from_reader(reader)?;
-            counts.count(data);
+/// Distinct labels and shape types with their total counts across the input
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct LabelInventory {
+    pub labels: IndexMap<String, usize>,
+    pub shape_types: IndexMap<String, usize>,
+}
+
+impl LabelInventory {
+    pub fn count(&mut self, data: LabelMeData) {
+        for (shape_type, label_counts) in data.count_by_shape_type() {
+            for (label, count) in label_counts {
+                *self.labels.entry(label.to_string()).or_insert(0) += count;
+                *self.shape_types.entry(shape_type.to_string()).or_insert(0) += count;
+            }
         }
-    } else {
-        debug!("File or stdin input");
-        if args.input.extension().is_some_and(|ext| ext == "json") {
-            unimplemented!("json file input");
-        } else if args.input.as_os_str() == "-"
-            || args
-                .input
-                .extension()
-                .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
-        {
+    }
+
+    /// Sort labels and shape types by name
+    pub fn sort(&mut self) {
+        self.labels.sort_keys();
+        self.shape_types.sort_keys();
+    }
+}
+
+/// The numbers of files by image size
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ImageSizeCounts {
+    image_sizes: IndexMap<String, usize>,
+    #[serde(skip)]
+    sizes: IndexMap<(usize, usize), usize>,
+}
+
+impl ImageSizeCounts {
+    pub fn count(&mut self, width: usize, height: usize) {
+        *self.sizes.entry((width, height)).or_insert(0) += 1;
+    }
+
+    /// Finish counting. Sizes are formatted as `WxH` and sorted by width and then height
+    pub fn finish(&mut self) {
+        self.sizes.sort_keys();
+        self.image_sizes = self
+            .sizes
+            .iter()
+            .map(|((w, h), count)| (format!("{w}x{h}"), *count))
+            .collect();
+    }
+}
+
+/// Size of the image file of `data`. `imagePath` is relative to `image_dir`
+fn open_image_size(data: &LabelMeData, image_dir: &Path) -> Result<(usize, usize)> {
+    let image_path = image_dir.join(data.imagePath.replace('\\', "/"));
+    let (width, height) = labelme_rs::image_dimensions(&image_path)
+        .with_context(|| format!("reading {}", image_path.display()))?;
+    Ok((width as usize, height as usize))
+}
+
+/// Call `f` for each labelme data in the directory, json, ndjson file, tar archive, or stdin
+pub(crate) fn for_each_data(
+    input: &Path,
+    format: InputFormat,
+    mut f: impl FnMut(LabelMeData),
+) -> Result<()> {
+    match format.resolve(input) {
+        Some(InputFormat::Dir) => {
+            let entries: Vec<_> = glob::glob(
+                input
+                    .join("*.json")
+                    .to_str()
+                    .context("Failed to get glob")?,
+            )
+            .expect("Failed to read glob pattern")
+            .collect();
+            for entry in entries {
+                let entry = entry?;
+                let reader = BufReader::new(File::open(&entry)?);
+                let data: LabelMeData = serde_json::from_reader(reader)?;
+                f(data);
+            }
+        }
+        Some(InputFormat::Json) => {
+            let reader = BufReader::new(File::open(input)?);
+            let data: LabelMeData =
+                serde_json::from_reader(reader).with_context(|| format!("Parsing {:?}", input))?;
+            f(data);
+        }
+        Some(InputFormat::Ndjson) => {
             // jsonl or ndjson
-            let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+            let reader: Box<dyn BufRead> = if input.as_os_str() == "-" {
                 Box::new(BufReader::new(std::io::stdin()))
             } else {
-                Box::new(BufReader::new(File::open(&args.input)?))
+                Box::new(BufReader::new(File::open(input)?))
             };
             for line in reader.lines() {
                 let line = line?;
                 let lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
-                counts.count(lm_data_line.content);
+                f(lm_data_line.content);
             }
-        } else {
-            panic!("Unknown input type: {:?}", args.input);
         }
+        _ if lmrs::tarball::is_tarball(input) => {
+            // corrupt members are reported and skipped instead of aborting the whole archive
+            let mut failures = 0;
+            lmrs::tarball::for_each_json(input, |member, content| -> Result<()> {
+                match serde_json::from_slice::<LabelMeData>(content) {
+                    Ok(data) => f(data),
+                    Err(e) => {
+                        warn!("{}: {}", member, e);
+                        failures += 1;
+                    }
+                }
+                Ok(())
+            })?;
+            if failures > 0 {
+                warn!("Failed to parse {} json member(s) of {:?}", failures, input);
+            }
+        }
+        _ => bail!(
+            "Unknown input type: {:?}. Specify \"--input-format\" if needed",
+            input
+        ),
+    }
+    Ok(())
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    if args.image_sizes {
+        let image_dir = if args.input.is_dir() {
+            args.input.clone()
+        } else if args.input.as_os_str() == "-" {
+            PathBuf::from(".")
+        } else {
+            if args.open_images && lmrs::tarball::is_tarball(&args.input) {
+                bail!("\"--open-images\" is not supported for tar archives");
+            }
+            args.input
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        };
+        let mut counts = ImageSizeCounts::default();
+        let mut result = Ok(());
+        for_each_data(&args.input, args.input_format, |data| {
+            if result.is_err() {
+                return;
+            }
+            if args.open_images {
+                match open_image_size(&data, &image_dir) {
+                    Ok((width, height)) => counts.count(width, height),
+                    Err(e) => result = Err(e),
+                }
+            } else {
+                counts.count(data.imageWidth, data.imageHeight);
+            }
+        })?;
+        result?;
+        counts.finish();
+        println!("{}", serde_json::to_string_pretty(&counts)?);
+    } else if args.distinct_labels {
+        let mut inventory = LabelInventory::default();
+        for_each_data(&args.input, args.input_format, |mut data| {
+            if !args.only_shapes.is_empty() {
+                data.shapes
+                    .retain(|shape| args.only_shapes.contains(&shape.shape_type));
+            }
+            inventory.count(data)
+        })?;
+        inventory.sort();
+        println!("{}", serde_json::to_string_pretty(&inventory)?);
+    } else {
+        let mut counts = Counts::new();
+        for_each_data(&args.input, args.input_format, |data| counts.count(data))?;
+        println!("{}", serde_json::to_string_pretty(&counts)?);
     }
-    println!("{}", serde_json::to_string_pretty(&counts)?);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_size_counts() -> Result<()> {
+        let mut counts = ImageSizeCounts::default();
+        for (width, height) in [(640, 480), (256, 256), (640, 480)] {
+            counts.count(width, height);
+        }
+        counts.finish();
+        assert_eq!(
+            serde_json::to_string(&counts)?,
+            r#"{"image_sizes":{"256x256":1,"640x480":2}}"#
+        );
+
+        let json_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+        let data = LabelMeData::try_from(json_dir.join("Mandrill.json").as_path())?;
+        assert_eq!(open_image_size(&data, &json_dir)?, (256, 256));
+        Ok(())
+    }
+
+    #[test]
+    fn test_label_inventory() {
+        let datasets = [
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0)],
+                &["L2".into(), "L1".into()],
+                128,
+                128,
+                "1.jpg",
+            ),
+            LabelMeData::new(
+                &[(1.0, 1.0), (2.0, 2.0), (3.0, 3.0)],
+                &["L1".into(), "L3".into(), "L1".into()],
+                128,
+                128,
+                "2.jpg",
+            ),
+        ];
+        let mut inventory = LabelInventory::default();
+        for data in datasets {
+            inventory.count(data);
+        }
+        inventory.sort();
+        let labels: Vec<_> = inventory
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect();
+        assert_eq!(labels, vec![("L1", 3), ("L2", 1), ("L3", 1)]);
+        assert_eq!(inventory.shape_types.get("point"), Some(&5));
+        assert_eq!(inventory.shape_types.len(), 1);
+    }
+}
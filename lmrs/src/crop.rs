@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use labelme_rs::{serde_json, CropParam, LabelMeDataLine, LabelMeDataWImage};
+use lmrs::cli::CropCmdArgs as CmdArgs;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Regions of the bounding boxes of the shapes with `padding`, clipped by the image
+fn shape_regions(
+    data: &labelme_rs::LabelMeData,
+    labels: &[String],
+    padding: u32,
+) -> Vec<(usize, CropParam)> {
+    let (width, height) = (data.imageWidth as u32, data.imageHeight as u32);
+    data.shapes
+        .iter()
+        .enumerate()
+        .filter(|(_, shape)| labels.is_empty() || labels.contains(&shape.label))
+        .filter_map(|(i, shape)| {
+            let ((x0, y0), (x1, y1)) = shape.bounding_box()?;
+            let (x0, y0) = (x0.floor().max(0.0) as u32, y0.floor().max(0.0) as u32);
+            let (x1, y1) = (x1.ceil().max(0.0) as u32, y1.ceil().max(0.0) as u32);
+            let x = x0.saturating_sub(padding);
+            let y = y0.saturating_sub(padding);
+            let region = CropParam {
+                x,
+                y,
+                width: (x1 + padding).max(x0 + 1) - x,
+                height: (y1 + padding).max(y0 + 1) - y,
+            };
+            Some((i, region.clip(width, height)?))
+        })
+        .collect()
+}
+
+/// Write the crop around each shape into `output_dir`
+fn crop_shapes(lm_line: LabelMeDataLine, args: &CmdArgs, output_dir: &Path) -> Result<()> {
+    let regions = shape_regions(&lm_line.content, &args.label, args.padding);
+    if regions.is_empty() {
+        return Ok(());
+    }
+    let image_path = PathBuf::from(&lm_line.content.imagePath);
+    let data_w_image: LabelMeDataWImage = lm_line
+        .content
+        .try_into()
+        .with_context(|| format!("Opening {:?}", image_path))?;
+    let stem = Path::new(&lm_line.filename)
+        .file_stem()
+        .context("Failed to obtain file stem")?
+        .to_string_lossy();
+    let extension = image_path
+        .extension()
+        .map(|ext| ext.to_string_lossy())
+        .unwrap_or("png".into());
+    for (i, region) in regions {
+        let mut cropped = data_w_image.clone();
+        cropped.crop(region.x, region.y, region.width, region.height);
+        let image_name = format!("{stem}_{i}.{extension}");
+        let image_outname = output_dir.join(&image_name);
+        cropped
+            .image
+            .save(&image_outname)
+            .with_context(|| format!("Saving {:?}", image_outname))?;
+        cropped.data.imagePath = image_name;
+        cropped.data.imageData = None;
+        let writer = BufWriter::new(File::create(output_dir.join(format!("{stem}_{i}.json")))?);
+        serde_json::to_writer_pretty(writer, &cropped.data)?;
+    }
+    Ok(())
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    let crop_param = args
+        .region
+        .as_deref()
+        .map(CropParam::try_from)
+        .transpose()?;
+    if let Some(output_dir) = &args.output_dir {
+        std::fs::create_dir_all(output_dir)?;
+    }
+    let cancel = lmrs::cancel::flag();
+    for (i, line) in reader.lines().enumerate() {
+        if lmrs::cancel::is_cancelled(cancel) {
+            return Err(lmrs::cancel::Cancelled {
+                completed: i,
+                total: None,
+            }
+            .into());
+        }
+        let line = line?;
+        let mut lm_line: LabelMeDataLine = line.as_str().try_into()?;
+        let Some(crop_param) = crop_param else {
+            let output_dir = args.output_dir.as_deref().expect("Required by clap");
+            crop_shapes(lm_line, &args, output_dir)?;
+            continue;
+        };
+        let region = crop_param
+            .clip(
+                lm_line.content.imageWidth as u32,
+                lm_line.content.imageHeight as u32,
+            )
+            .with_context(|| {
+                format!(
+                    "{}: {} is outside the image",
+                    lm_line.filename,
+                    args.region.as_deref().unwrap_or_default()
+                )
+            })?;
+        match args.image {
+            Some(ref image_dir) => {
+                let image_path = PathBuf::from(&lm_line.content.imagePath);
+                let mut data_w_image: labelme_rs::LabelMeDataWImage = lm_line
+                    .content
+                    .try_into()
+                    .with_context(|| format!("Opening {:?}", image_path))?;
+                data_w_image.crop(region.x, region.y, region.width, region.height);
+                let outname = image_dir.join(image_path.file_name().unwrap_or_default());
+                data_w_image.image.save(outname)?;
+                lm_line.content = data_w_image.data;
+            }
+            None => lm_line
+                .content
+                .crop(region.x, region.y, region.width, region.height),
+        }
+        let writer = BufWriter::new(stdout().lock());
+        serde_json::to_writer(writer, &lm_line)?;
+        println!();
+    }
+    Ok(())
+}
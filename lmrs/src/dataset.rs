@@ -0,0 +1,282 @@
+//! Dataset-level rules evaluated once over the aggregate of all files
+//!
+//! Lines of a rules file prefixed with [`DATASET_PREFIX`] are dataset rules.
+//! Variables are the total counts of labels, `_files` (the number of files), and `_flag_<name>` (the number of files with the flag set).
+//! `count_with_flag(label, flag)` counts shapes with the shape flag across the files.
+//! `paired("_pre", "_post")` requires every file whose name contains `_pre` to have the counterpart with `_post` in the same directory.
+use crate::{
+    count_shape_flags, eval_with_shape_flags, format_parse_error, parser, CountMode, Expr,
+    ParseError, ShapeFlagCounts,
+};
+use chumsky::prelude::*;
+use labelme_rs::indexmap::{IndexMap, IndexSet};
+use labelme_rs::LabelMeData;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Prefix of dataset rules in rules files
+pub const DATASET_PREFIX: &str = "@dataset:";
+
+/// Split rules into per-file rules and dataset rules without the prefix
+///
+/// ```
+/// let (rules, dataset_rules) = lmrs::dataset::split_rules(vec!["TL > 0".into(), "@dataset: TL == _files".into()]);
+/// assert_eq!(rules, vec!["TL > 0"]);
+/// assert_eq!(dataset_rules, vec!["TL == _files"]);
+/// ```
+pub fn split_rules(rules: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let (dataset_rules, rules): (Vec<_>, Vec<_>) = rules
+        .into_iter()
+        .partition(|rule| rule.trim_start().starts_with(DATASET_PREFIX));
+    let dataset_rules = dataset_rules
+        .into_iter()
+        .map(|rule| rule.trim_start()[DATASET_PREFIX.len()..].trim().to_string())
+        .collect();
+    (rules, dataset_rules)
+}
+
+/// Parsed dataset rule
+#[derive(Clone, Debug)]
+pub enum DatasetRule {
+    Expr(Expr),
+    /// Files containing the first pattern in their names need counterparts with the second pattern
+    Paired(String, String),
+}
+
+fn paired_parser() -> impl Parser<char, (String, String), Error = Simple<char>> {
+    let string = just('"')
+        .ignore_then(filter(|c| *c != '"').repeated())
+        .then_ignore(just('"'))
+        .collect::<String>()
+        .padded();
+    text::keyword("paired")
+        .padded()
+        .ignore_then(
+            string
+                .then_ignore(just(','))
+                .then(string)
+                .delimited_by(just('('), just(')')),
+        )
+        .padded()
+        .then_ignore(end())
+}
+
+/// Parse dataset rules
+/// ```
+/// let rules = lmrs::dataset::parse_rules(&vec!["paired(\"_pre\", \"_post\")".into(), "TL == _files".into()]);
+/// assert!(rules.is_ok());
+/// ```
+pub fn parse_rules(rules: &[String]) -> Result<Vec<DatasetRule>, ParseError> {
+    rules
+        .iter()
+        .map(|rule| {
+            if let Ok((from, to)) = paired_parser().parse(rule.as_str()) {
+                return Ok(DatasetRule::Paired(from, to));
+            }
+            parser()
+                .parse(rule.as_str())
+                .map(DatasetRule::Expr)
+                .map_err(|errs| {
+                    let errs: Vec<_> = errs.iter().map(|e| format_parse_error(rule, e)).collect();
+                    ParseError::Error(errs.join("\n"))
+                })
+        })
+        .collect()
+}
+
+/// Aggregate of the files for dataset rules
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DatasetStats {
+    pub files: usize,
+    pub label_counts: IndexMap<String, isize>,
+    pub flag_counts: IndexMap<String, isize>,
+    /// Counts for `count_with_flag`
+    pub shape_flag_counts: ShapeFlagCounts,
+    /// Filenames relative to the input directory
+    pub filenames: Vec<PathBuf>,
+}
+
+impl DatasetStats {
+    pub fn add(&mut self, filename: &Path, data: &LabelMeData, count_mode: CountMode) {
+        self.files += 1;
+        for shape in &data.shapes {
+            *self.label_counts.entry(shape.label.clone()).or_default() += count_mode.count(shape);
+        }
+        for (flag, _) in data.flags.iter().filter(|(_, v)| **v) {
+            *self.flag_counts.entry(flag.clone()).or_default() += 1;
+        }
+        for (key, count) in count_shape_flags(&data.shapes, count_mode) {
+            *self.shape_flag_counts.entry(key).or_default() += count;
+        }
+        self.filenames.push(filename.to_path_buf());
+    }
+
+    /// Merge stats accumulated separately (e.g. by another thread)
+    pub fn merge(&mut self, other: DatasetStats) {
+        self.files += other.files;
+        for (label, count) in other.label_counts {
+            *self.label_counts.entry(label).or_default() += count;
+        }
+        for (flag, count) in other.flag_counts {
+            *self.flag_counts.entry(flag).or_default() += count;
+        }
+        for (key, count) in other.shape_flag_counts {
+            *self.shape_flag_counts.entry(key).or_default() += count;
+        }
+        self.filenames.extend(other.filenames);
+    }
+
+    /// Variables of the dataset rules. `_files` and flag counts take precedence over labels with the same names
+    pub fn vars(&self) -> Vec<(String, isize)> {
+        self.label_counts
+            .iter()
+            .map(|(label, count)| (label.clone(), *count))
+            .chain(std::iter::once(("_files".to_string(), self.files as isize)))
+            .chain(
+                self.flag_counts
+                    .iter()
+                    .map(|(flag, count)| (format!("_flag_{flag}"), *count)),
+            )
+            .collect()
+    }
+
+    /// Files containing `from` in their names without the counterparts in which `from` is replaced with `to`, sorted by name
+    pub fn unpaired(&self, from: &str, to: &str) -> Vec<PathBuf> {
+        let filenames: IndexSet<_> = self.filenames.iter().collect();
+        let mut unpaired: Vec<_> = self
+            .filenames
+            .iter()
+            .filter(|filename| {
+                let Some(name) = filename.file_name().and_then(|n| n.to_str()) else {
+                    return false;
+                };
+                match name.rfind(from) {
+                    Some(pos) => {
+                        let counterpart =
+                            format!("{}{}{}", &name[..pos], to, &name[pos + from.len()..]);
+                        !filenames.contains(&filename.with_file_name(counterpart))
+                    }
+                    None => false,
+                }
+            })
+            .cloned()
+            .collect();
+        unpaired.sort();
+        unpaired
+    }
+
+    /// Evaluate `dataset_rules` parsed from `rules` and return the failures
+    pub fn evaluate(&self, rules: &[String], dataset_rules: &[DatasetRule]) -> Vec<DatasetFailure> {
+        let vars = self.vars();
+        let vars: Vec<_> = vars.iter().map(|(k, v)| (k, *v)).collect();
+        rules
+            .iter()
+            .zip(dataset_rules)
+            .filter_map(|(rule, dataset_rule)| match dataset_rule {
+                DatasetRule::Expr(ast) => {
+                    eval_with_shape_flags(ast, &vars, &self.shape_flag_counts)
+                        .err()
+                        .map(|vals| DatasetFailure::Unsatisfied(rule.clone(), vals))
+                }
+                DatasetRule::Paired(from, to) => {
+                    let unpaired = self.unpaired(from, to);
+                    (!unpaired.is_empty()).then(|| DatasetFailure::Unpaired(rule.clone(), unpaired))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Failure of a dataset rule with the aggregate values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatasetFailure {
+    Unsatisfied(String, (isize, isize)),
+    Unpaired(String, Vec<PathBuf>),
+}
+
+impl fmt::Display for DatasetFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetFailure::Unsatisfied(rule, (c1, c2)) => {
+                write!(f, "Unsatisfied dataset rule; \"{rule}\": {c1} vs. {c2}")
+            }
+            DatasetFailure::Unpaired(rule, filenames) => {
+                let filenames: Vec<_> = filenames.iter().map(|f| format!("{:?}", f)).collect();
+                write!(
+                    f,
+                    "Unsatisfied dataset rule; \"{rule}\": {} unpaired; {}",
+                    filenames.len(),
+                    filenames.join(" ")
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(files: &[(&str, &[&str], &[&str])]) -> DatasetStats {
+        let mut stats = DatasetStats::default();
+        for (filename, labels, flags) in files {
+            let data = LabelMeData {
+                shapes: labels
+                    .iter()
+                    .map(|label| crate::test_util::shape(label, "point", [(0.0, 0.0)]))
+                    .collect(),
+                flags: flags.iter().map(|f| (f.to_string(), true)).collect(),
+                ..Default::default()
+            };
+            stats.add(Path::new(filename), &data, CountMode::Shapes);
+        }
+        stats
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let stats = stats(&[
+            ("a_pre.json", &["TL", "TL"], &["ok"]),
+            ("a_post.json", &["TL"], &[]),
+            ("sub/b_pre.json", &[], &["ok"]),
+        ]);
+        let rules: Vec<String> = vec![
+            "TL == _files".into(),
+            "_flag_ok == 2".into(),
+            "TL < 2".into(),
+            "paired(\"_pre\", \"_post\")".into(),
+            "paired(\"a_\", \"b_\")".into(),
+        ];
+        let dataset_rules = parse_rules(&rules).unwrap();
+        assert_eq!(
+            stats.evaluate(&rules, &dataset_rules),
+            vec![
+                DatasetFailure::Unsatisfied(rules[2].clone(), (3, 2)),
+                DatasetFailure::Unpaired(rules[3].clone(), vec!["sub/b_pre.json".into()]),
+                DatasetFailure::Unpaired(
+                    rules[4].clone(),
+                    vec!["a_post.json".into(), "a_pre.json".into()]
+                ),
+            ]
+        );
+        assert!(parse_rules(&["paired(\"_pre\")".into()]).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut merged = stats(&[("a.json", &["TL"], &["ok"])]);
+        merged.merge(stats(&[("b.json", &["TL", "TR"], &["ok"])]));
+        assert_eq!(merged.files, 2);
+        assert_eq!(merged.label_counts.get("TL"), Some(&2));
+        assert_eq!(merged.flag_counts.get("ok"), Some(&2));
+        assert_eq!(
+            merged.vars(),
+            vec![
+                ("TL".to_string(), 2),
+                ("TR".to_string(), 1),
+                ("_files".to_string(), 2),
+                ("_flag_ok".to_string(), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{bail, ensure, Context, Result};
+use labelme_rs::serde_json;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use lmrs::cli::DatasetSplitCmdArgs as CmdArgs;
+
+/// Position of `filename` in [0, 1) determined by SHA-256 of the seed and the filename, which is stable across runs and platforms
+fn hash_fraction(seed: u64, filename: &str) -> f64 {
+    use sha2::Digest;
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update(filename.as_bytes());
+    let digest = hasher.finalize();
+    let value = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Cumulative ratios normalized to end with 1
+fn cumulative(ratios: &[f64]) -> Vec<f64> {
+    let total: f64 = ratios.iter().sum();
+    ratios
+        .iter()
+        .scan(0.0, |acc, r| {
+            *acc += r / total;
+            Some(*acc)
+        })
+        .collect()
+}
+
+/// Assign each fraction to the split whose cumulative ratio range contains it
+fn assign_by_threshold(fractions: &[f64], ratios: &[f64]) -> Vec<usize> {
+    let cum = cumulative(ratios);
+    fractions
+        .iter()
+        .map(|f| cum.iter().position(|c| f < c).unwrap_or(ratios.len() - 1))
+        .collect()
+}
+
+/// Assign fractions ranked by their values so that the numbers of records match the ratios as close as possible
+fn assign_by_rank(fractions: &[f64], ratios: &[f64]) -> Vec<usize> {
+    let n = fractions.len();
+    let boundaries: Vec<usize> = cumulative(ratios)
+        .into_iter()
+        .map(|c| (c * n as f64).round() as usize)
+        .collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|a, b| fractions[*a].total_cmp(&fractions[*b]));
+    let mut assignments = vec![0; n];
+    for (rank, i) in order.into_iter().enumerate() {
+        assignments[i] = boundaries
+            .iter()
+            .position(|b| rank < *b)
+            .unwrap_or(ratios.len() - 1);
+    }
+    assignments
+}
+
+/// Assign records to splits. `strata` are the groups to be split separately
+fn assign(fractions: &[f64], strata: Option<&[bool]>, ratios: &[f64]) -> Vec<usize> {
+    let Some(strata) = strata else {
+        return assign_by_threshold(fractions, ratios);
+    };
+    let mut assignments = vec![0; fractions.len()];
+    for stratum in [true, false] {
+        let indices: Vec<usize> = (0..fractions.len())
+            .filter(|i| strata[*i] == stratum)
+            .collect();
+        let stratum_fractions: Vec<f64> = indices.iter().map(|i| fractions[*i]).collect();
+        for (i, a) in indices
+            .into_iter()
+            .zip(assign_by_rank(&stratum_fractions, ratios))
+        {
+            assignments[i] = a;
+        }
+    }
+    assignments
+}
+
+fn has_label(line: &serde_json::Value, label: &str) -> bool {
+    line["content"]["shapes"]
+        .as_array()
+        .is_some_and(|shapes| shapes.iter().any(|s| s["label"] == label))
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    ensure!(
+        args.ratios.len() == args.names.len(),
+        "The number of ratios ({}) differs from the number of names ({})",
+        args.ratios.len(),
+        args.names.len()
+    );
+    ensure!(
+        args.ratios.iter().all(|r| *r >= 0.0) && args.ratios.iter().any(|r| *r > 0.0),
+        "Ratios should be non-negative and not all zero: {:?}",
+        args.ratios
+    );
+    let reader: Box<dyn BufRead> = match &args.input {
+        None => Box::new(BufReader::new(std::io::stdin())),
+        Some(filename) => Box::new(BufReader::new(
+            File::open(filename).with_context(|| format!("Opening {:?}", filename))?,
+        )),
+    };
+    let mut lines = vec![];
+    let mut fractions = vec![];
+    let mut strata = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        let filename = value["filename"]
+            .as_str()
+            .context("Key filename not found")?;
+        fractions.push(hash_fraction(args.seed, filename));
+        if let Some(label) = &args.stratify {
+            strata.push(has_label(&value, label));
+        }
+        lines.push(line);
+    }
+    let assignments = assign(
+        &fractions,
+        args.stratify.as_ref().map(|_| strata.as_slice()),
+        &args.ratios,
+    );
+
+    let outdir = args.output.clone().unwrap_or_default();
+    let filenames: Vec<_> = args
+        .names
+        .iter()
+        .map(|name| outdir.join(format!("{name}.ndjson")))
+        .collect();
+    if !args.overwrite {
+        if let Some(existing) = filenames.iter().find(|f| f.exists()) {
+            bail!(
+                "Output file {existing:?} already exists. Add \"--overwrite\" option to force overwriting."
+            );
+        }
+    }
+    let mut writers = filenames
+        .iter()
+        .map(|filename| {
+            Ok(std::io::BufWriter::new(
+                File::create(filename).with_context(|| format!("Writing to {:?}", filename))?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut counts = vec![0; writers.len()];
+    for (line, a) in lines.iter().zip(assignments) {
+        writeln!(writers[a], "{}", line)?;
+        counts[a] += 1;
+    }
+    for mut writer in writers {
+        writer.flush()?;
+    }
+    let summary: Vec<_> = args
+        .names
+        .iter()
+        .zip(counts)
+        .map(|(name, count)| format!("{name}: {count}"))
+        .collect();
+    eprintln!("{}", summary.join(", "));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fractions(seed: u64, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| hash_fraction(seed, &format!("dir/img{i}.json")))
+            .collect()
+    }
+
+    fn counts(assignments: &[usize]) -> Vec<usize> {
+        let mut counts = vec![0; 3];
+        for a in assignments {
+            counts[*a] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_assign_deterministic() {
+        let ratios = [0.8, 0.1, 0.1];
+        let assignments = assign(&fractions(42, 1000), None, &ratios);
+        assert_eq!(assignments, assign(&fractions(42, 1000), None, &ratios));
+        assert_ne!(assignments, assign(&fractions(43, 1000), None, &ratios));
+        let counts = counts(&assignments);
+        assert!((750..850).contains(&counts[0]), "{:?}", counts);
+        assert!((70..130).contains(&counts[1]), "{:?}", counts);
+        assert!((70..130).contains(&counts[2]), "{:?}", counts);
+        // adding records keeps the assignments of the others
+        let more = assign(&fractions(42, 1100), None, &ratios);
+        assert_eq!(&more[..1000], &assignments[..]);
+    }
+
+    #[test]
+    fn test_assign_stratified() {
+        let ratios = [8.0, 1.0, 1.0];
+        let strata: Vec<bool> = (0..1000).map(|i| i % 10 == 0).collect();
+        let assignments = assign(&fractions(0, 1000), Some(&strata), &ratios);
+        assert_eq!(counts(&assignments), vec![800, 100, 100]);
+        let labeled: Vec<usize> = assignments
+            .iter()
+            .zip(&strata)
+            .filter_map(|(a, s)| s.then_some(*a))
+            .collect();
+        assert_eq!(counts(&labeled), vec![80, 10, 10]);
+        assert_eq!(
+            assignments,
+            assign(&fractions(0, 1000), Some(&strata), &ratios)
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::LabelMeDataLine;
+use lmrs::cli::DedupCmdArgs as CmdArgs;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter, Write};
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    let mut removed_counts: IndexMap<String, usize> = IndexMap::new();
+    let mut writer = BufWriter::new(stdout().lock());
+    for line in reader.lines() {
+        let line = line?;
+        let mut lm_line: LabelMeDataLine = line.as_str().try_into()?;
+        let mut removed = lm_line.content.dedup_shapes(args.tolerance);
+        if let Some(threshold) = args.iou {
+            removed.extend(lm_line.content.dedup_overlapping_shapes(threshold));
+        }
+        if !removed.is_empty() {
+            info!("{}: removed {} shapes", lm_line.filename, removed.len());
+        }
+        for shape in removed {
+            *removed_counts.entry(shape.label).or_default() += 1;
+        }
+        labelme_rs::integral::to_writer(&mut writer, &lm_line)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    if args.stats {
+        for (label, count) in removed_counts {
+            eprintln!("{label}: {count}");
+        }
+    }
+    Ok(())
+}
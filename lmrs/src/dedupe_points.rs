@@ -0,0 +1,25 @@
+use anyhow::Result;
+use labelme_rs::LabelMeDataLine;
+use lmrs::cli::DedupePointsCmdArgs as CmdArgs;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter};
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    for line in reader.lines() {
+        let line = line?;
+        let mut lm_line: LabelMeDataLine = line.as_str().try_into()?;
+        let removed = lm_line.content.dedupe_consecutive_points(args.tolerance);
+        if removed > 0 {
+            info!("{}: removed {} vertices", lm_line.filename, removed);
+        }
+        let writer = BufWriter::new(stdout().lock());
+        labelme_rs::integral::to_writer(writer, &lm_line)?;
+        println!();
+    }
+    Ok(())
+}
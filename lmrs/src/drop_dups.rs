@@ -3,6 +3,7 @@ use labelme_rs::serde_json;
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 
 use lmrs::cli::DropCmdArgs as CmdArgs;
 
@@ -26,13 +27,51 @@ fn drop(json_lines: impl BufRead, key: &str, mut out: impl Write) -> Result<()>
     Ok(())
 }
 
+/// SHA-256 of the image embedded in `imageData`, or the image file of `imagePath` resolved relative to `json_dir`
+fn image_hash(data: &labelme_rs::LabelMeData, json_dir: &Path) -> Result<[u8; 32]> {
+    use sha2::Digest;
+    let bytes = match &data.imageData {
+        Some(image_data) => labelme_rs::decode_image_bytes(image_data)?,
+        None => {
+            let image_path = json_dir.join(&data.imagePath);
+            std::fs::read(&image_path).with_context(|| format!("Reading {:?}", image_path))?
+        }
+    };
+    Ok(sha2::Sha256::digest(bytes).into())
+}
+
+/// Keep the first line for each image content
+fn drop_by_image(json_lines: impl BufRead, json_dir: &Path, mut out: impl Write) -> Result<()> {
+    let mut existing_set: HashSet<[u8; 32]> = HashSet::new();
+    for line in json_lines.lines() {
+        let line = line?;
+        let data_line = labelme_rs::LabelMeDataLine::try_from(line.as_str())?;
+        let hash = image_hash(&data_line.content, json_dir)
+            .with_context(|| format!("Hashing the image of {}", data_line.filename))?;
+        if existing_set.insert(hash) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn cmd(args: CmdArgs) -> Result<()> {
-    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
-        Box::new(BufReader::new(std::io::stdin()))
+    let (reader, json_dir): (Box<dyn BufRead>, PathBuf) = if args.input.as_os_str() == "-" {
+        (
+            Box::new(BufReader::new(std::io::stdin())),
+            PathBuf::from("."),
+        )
     } else {
-        Box::new(BufReader::new(File::open(&args.input)?))
+        (
+            Box::new(BufReader::new(File::open(&args.input)?)),
+            args.input.parent().unwrap().to_path_buf(),
+        )
     };
-    drop(reader, &args.key, std::io::stdout())?;
+    if args.by_image {
+        drop_by_image(reader, &json_dir, std::io::stdout())?;
+    } else {
+        drop(reader, &args.key, std::io::stdout())?;
+    }
     Ok(())
 }
 
@@ -50,3 +89,38 @@ fn test_drop() -> anyhow::Result<()> {
     assert_eq!(dropped, expected);
     Ok(())
 }
+
+#[test]
+fn test_drop_by_image() -> anyhow::Result<()> {
+    use std::io::Cursor;
+    let tmp_dir = tempfile::tempdir()?;
+    std::fs::write(tmp_dir.path().join("1.jpg"), b"image")?;
+    std::fs::write(tmp_dir.path().join("2.jpg"), b"image")?;
+    std::fs::write(tmp_dir.path().join("3.jpg"), b"another image")?;
+    let line = |filename: &str, image_path: &str| {
+        let data = labelme_rs::LabelMeData::new(&[], &[], 4, 4, image_path);
+        let line = labelme_rs::LabelMeDataLine {
+            filename: filename.into(),
+            content: data,
+        };
+        serde_json::to_string(&line).unwrap()
+    };
+    let ndjson = [
+        line("1.json", "1.jpg"),
+        line("2.json", "2.jpg"),
+        line("3.json", "3.jpg"),
+    ]
+    .join("\n");
+    let mut buf = Vec::new();
+    drop_by_image(
+        BufReader::new(Cursor::new(ndjson)),
+        tmp_dir.path(),
+        Cursor::new(&mut buf),
+    )?;
+    let kept: Vec<_> = String::from_utf8(buf)?
+        .lines()
+        .map(|l| labelme_rs::LabelMeDataLine::try_from(l).map(|l| l.filename))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(kept, vec!["1.json", "3.json"]);
+    Ok(())
+}
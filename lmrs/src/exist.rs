@@ -32,8 +32,12 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         let lmdata_line: labelme_rs::LabelMeDataLine = serde_json::from_str(&line)
             .with_context(|| format!("Failed to parse JSON from the input line: {}", line))?;
         let lmdata = lmdata_line.content.to_absolute_path(&json_parent_dir);
-        let image_path = Path::new(&lmdata.imagePath);
-        if args.invert ^ image_path.exists() {
+        let exists = if args.ignore_image_data {
+            Path::new(&lmdata.imagePath).is_file()
+        } else {
+            lmdata.has_image()
+        };
+        if args.invert ^ exists {
             println!("{}", line);
         } else {
             info!("skipping: {}", lmdata.imagePath);
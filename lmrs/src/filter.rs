@@ -1,5 +1,4 @@
 use anyhow::{Context, Result};
-use labelme_rs::serde_json;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
@@ -12,6 +11,13 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             .with_context(|| format!("Reading rule file {filename:?}"))?;
         rules.extend(ar);
     }
+    let (rules, dataset_rules) = lmrs::dataset::split_rules(rules);
+    if !dataset_rules.is_empty() {
+        log::warn!(
+            "Ignoring {} dataset rule(s), which are only evaluated by `validate`",
+            dataset_rules.len()
+        );
+    }
     assert!(!rules.is_empty(), "No rule is found.");
     let asts = lmrs::parse_rules(&rules)?;
     let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
@@ -21,9 +27,15 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     };
     for line in reader.lines() {
         let line = line?;
-        let json_data: labelme_rs::LabelMeDataLine =
-            serde_json::from_str(&line).with_context(|| format!("Processing line:{line}"))?;
-        let errors = lmrs::evaluate_rules(&rules, &asts, json_data.content.shapes);
+        let errors = lmrs::check_line(
+            &rules,
+            &asts,
+            &line,
+            &args.var,
+            args.strict_vars,
+            args.count,
+        )
+        .with_context(|| format!("Processing line:{line}"))?;
         if errors.is_empty() ^ args.invert {
             println!("{}", line);
         }
@@ -0,0 +1,124 @@
+use anyhow::{ensure, Context, Result};
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::repair::Repair;
+use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use lmrs::cli::FixCmdArgs as CmdArgs;
+
+/// Changes made to a file
+#[derive(Serialize, Debug)]
+struct Report<'a> {
+    filename: &'a str,
+    repairs: IndexMap<Repair, usize>,
+}
+
+/// Write the report to stderr if anything was changed
+fn report(filename: &str, repairs: IndexMap<Repair, usize>) -> Result<()> {
+    if repairs.values().any(|&count| count > 0) {
+        let report = Report { filename, repairs };
+        eprintln!("{}", serde_json::to_string(&report)?);
+    }
+    Ok(())
+}
+
+fn fix_file(
+    input: &Path,
+    output: &Path,
+    repairs: &[Repair],
+    epsilon: f64,
+    preview: bool,
+) -> Result<()> {
+    let mut lm_data = LabelMeData::try_from(input)?;
+    let before = preview.then(|| lm_data.clone());
+    let counts = labelme_rs::repair::repair(&mut lm_data, repairs, epsilon);
+    let filename = input.to_string_lossy();
+    if let Some(before) = before {
+        if let Some(line) = lmrs::patch::preview_line(&filename, &before, &lm_data)? {
+            println!("{line}");
+        }
+    } else {
+        let content = labelme_rs::integral::to_string_pretty(&lm_data)?;
+        lmrs::lock::write_atomic(output, |writer| Ok(writeln!(writer, "{}", content)?))?;
+    }
+    report(&filename, counts)
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let repairs = args.only.unwrap_or_else(|| Repair::ALL.to_vec());
+    let preview = args.preview.preview;
+    if args.input.is_dir() {
+        let output = args.output.unwrap_or_else(|| args.input.clone());
+        debug!("Directory input");
+        ensure!(
+            output.is_dir(),
+            "Output directory \"{}\" does not exist.",
+            output.to_string_lossy()
+        );
+        let _lock = (!preview)
+            .then(|| lmrs::lock::DirLock::acquire(&output, args.lock.force_lock))
+            .transpose()?;
+        let entries: Vec<_> = glob::glob(
+            args.input
+                .join("*.json")
+                .to_str()
+                .context("Failed to get glob")?,
+        )
+        .expect("Failed to read glob pattern")
+        .collect();
+        for entry in entries {
+            let input = entry?;
+            let output = output.join(input.file_name().context("Failed to obtain filename")?);
+            fix_file(&input, &output, &repairs, args.epsilon, preview)?;
+        }
+    } else if args.input.extension().is_some_and(|ext| ext == "json") {
+        let output = args.output.unwrap_or_else(|| args.input.clone());
+        fix_file(&args.input, &output, &repairs, args.epsilon, preview)?;
+    } else if args.input.as_os_str() == "-"
+        || args
+            .input
+            .extension()
+            .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
+    {
+        let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(&args.input)?))
+        };
+        let mut writer: Box<dyn Write> = match args.output {
+            Some(x) if x.as_os_str() != "-" && !preview => {
+                Box::new(BufWriter::new(File::create(&x)?))
+            }
+            _ => Box::new(BufWriter::new(std::io::stdout())),
+        };
+        for line in reader.lines() {
+            let line = line?;
+            let mut lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
+            let before = preview.then(|| lm_data_line.content.clone());
+            let counts =
+                labelme_rs::repair::repair(&mut lm_data_line.content, &repairs, args.epsilon);
+            if let Some(before) = before {
+                if let Some(patch) = lmrs::patch::preview_line(
+                    &lm_data_line.filename,
+                    &before,
+                    &lm_data_line.content,
+                )? {
+                    writeln!(writer, "{patch}")?;
+                }
+            } else {
+                writeln!(
+                    writer,
+                    "{}",
+                    labelme_rs::integral::to_string(&lm_data_line)?
+                )?;
+            }
+            report(&lm_data_line.filename, counts)?;
+        }
+    } else {
+        panic!("Unknown input type: {:?}", args.input);
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use anyhow::{bail, ensure, Context, Result};
+use labelme_rs::{Flags, LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use lmrs::cli::FlagsCmdArgs as CmdArgs;
+
+/// Set `set` flags to the values and remove `clear` flags. Other flags are kept in place
+fn update_flags(flags: &mut Flags, set: &[(String, bool)], clear: &[String]) {
+    for (name, value) in set {
+        flags.insert(name.clone(), *value);
+    }
+    for name in clear {
+        flags.shift_remove(name);
+    }
+}
+
+/// Rule of `--only-if` and its ast
+type Condition = Option<(Vec<String>, Vec<lmrs::Expr>)>;
+
+/// Update the flags of the data satisfying the condition
+fn update_data(data: &mut LabelMeData, args: &CmdArgs, condition: &Condition) {
+    if let Some((rules, asts)) = condition {
+        if !lmrs::evaluate_rules(rules, asts, data.shapes.clone()).is_empty() {
+            return;
+        }
+    }
+    update_flags(&mut data.flags, &args.set, &args.clear);
+}
+
+fn update_file(input: &Path, output: &Path, args: &CmdArgs, condition: &Condition) -> Result<()> {
+    let mut lm_data = LabelMeData::try_from(input)?;
+    update_data(&mut lm_data, args, condition);
+    let content = labelme_rs::integral::to_string_pretty(&lm_data)?;
+    lmrs::lock::write_atomic(output, |writer| Ok(writeln!(writer, "{}", content)?))
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    if let Some((name, _)) = args.set.iter().find(|(name, _)| args.clear.contains(name)) {
+        bail!("Flag {:?} is both set and cleared", name);
+    }
+    let condition: Condition = match &args.only_if {
+        Some(rule) => {
+            let rules = vec![rule.clone()];
+            let asts = lmrs::parse_rules(&rules)?;
+            Some((rules, asts))
+        }
+        None => None,
+    };
+    if args.input.is_dir() {
+        let output = args.output.clone().unwrap_or_else(|| args.input.clone());
+        debug!("Directory input");
+        ensure!(
+            output.is_dir(),
+            "Output directory \"{}\" does not exist.",
+            output.to_string_lossy()
+        );
+        let _lock = lmrs::lock::DirLock::acquire(&output, args.lock.force_lock)?;
+        let entries: Vec<_> = glob::glob(
+            args.input
+                .join("*.json")
+                .to_str()
+                .context("Failed to get glob")?,
+        )
+        .expect("Failed to read glob pattern")
+        .collect();
+        for entry in entries {
+            let input = entry?;
+            let output = output.join(input.file_name().context("Failed to obtain filename")?);
+            update_file(&input, &output, &args, &condition)?;
+        }
+    } else if args.input.extension().is_some_and(|ext| ext == "json") {
+        let output = args.output.clone().unwrap_or_else(|| args.input.clone());
+        update_file(&args.input, &output, &args, &condition)?;
+    } else if args.input.as_os_str() == "-"
+        || args
+            .input
+            .extension()
+            .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
+    {
+        let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(&args.input)?))
+        };
+        let mut writer: Box<dyn Write> = match &args.output {
+            Some(x) if x.as_os_str() != "-" => Box::new(BufWriter::new(File::create(x)?)),
+            _ => Box::new(BufWriter::new(std::io::stdout())),
+        };
+        for line in reader.lines() {
+            let line = line?;
+            let mut lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
+            update_data(&mut lm_data_line.content, &args, &condition);
+            writeln!(
+                writer,
+                "{}",
+                labelme_rs::integral::to_string(&lm_data_line)?
+            )?;
+        }
+    } else {
+        panic!("Unknown input type: {:?}", args.input);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_flags() {
+        let mut flags = Flags::from_iter([
+            ("todo".to_string(), true),
+            ("reviewed".to_string(), false),
+            ("blurry".to_string(), true),
+        ]);
+        update_flags(
+            &mut flags,
+            &[
+                ("reviewed".into(), true),
+                ("new".into(), true),
+                ("blurry".into(), false),
+            ],
+            &["todo".into()],
+        );
+        assert_eq!(
+            flags.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("reviewed".to_string(), true),
+                ("blurry".to_string(), false),
+                ("new".to_string(), true)
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{bail, Result};
 use labelme_rs::indexmap::{IndexMap, IndexSet};
+use labelme_rs::{Point, Shape};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
@@ -40,10 +41,142 @@ fn load_ndjson(input: &Path, key: &str) -> Result<IndexMap<String, JzonObject>>
     ndjson
 }
 
+/// Shapes sharing a `group_id`
+struct Group {
+    id: JzonObject,
+    /// Centroids of the shapes by label
+    centroids: IndexMap<String, Vec<Point>>,
+}
+
+fn mean(points: impl Iterator<Item = Point>) -> Option<Point> {
+    let (n, sx, sy) = points.fold((0, 0.0, 0.0), |(n, sx, sy), p| (n + 1, sx + p.0, sy + p.1));
+    (n > 0).then(|| (sx / n as f64, sy / n as f64))
+}
+
+impl Group {
+    fn centroid(&self) -> Option<Point> {
+        mean(self.centroids.values().flatten().copied())
+    }
+
+    /// Mean distance between the centroids of the common labels, or between the centroids of the groups if no label is shared
+    fn distance(&self, other: &Group) -> Option<f64> {
+        let dist = |p: Point, q: Point| (p.0 - q.0).hypot(p.1 - q.1);
+        let common: Vec<f64> = self
+            .centroids
+            .iter()
+            .filter_map(|(label, centroids)| {
+                let other = other.centroids.get(label)?;
+                Some(dist(
+                    mean(centroids.iter().copied())?,
+                    mean(other.iter().copied())?,
+                ))
+            })
+            .collect();
+        if common.is_empty() {
+            Some(dist(self.centroid()?, other.centroid()?))
+        } else {
+            Some(common.iter().sum::<f64>() / common.len() as f64)
+        }
+    }
+}
+
+/// Groups of `content.shapes` of the object in the order of appearance
+fn groups(obj: &JzonObject) -> Vec<Group> {
+    let mut groups: IndexMap<String, Group> = IndexMap::new();
+    for shape in obj["content"]["shapes"].members() {
+        let id = &shape["group_id"];
+        if id.is_null() {
+            continue;
+        }
+        let points = shape["points"]
+            .members()
+            .filter_map(|p| Some((p[0].as_f64()?, p[1].as_f64()?)))
+            .collect();
+        let shape = Shape {
+            label: shape["label"].as_str().unwrap_or_default().to_string(),
+            points,
+            shape_type: shape["shape_type"].as_str().unwrap_or_default().to_string(),
+            ..Default::default()
+        };
+        let group = groups.entry(id.dump()).or_insert_with(|| Group {
+            id: id.clone(),
+            centroids: IndexMap::new(),
+        });
+        if let Some(centroid) = shape.centroid() {
+            group
+                .centroids
+                .entry(shape.label)
+                .or_default()
+                .push(centroid);
+        }
+    }
+    groups.into_values().collect()
+}
+
+/// Reassign `group_id` of the right shapes so that the groups of the same instances share the ids with the left groups.
+///
+/// Pairs of left and right groups are matched one-to-one in ascending order of the distance ([`Group::distance`]) up to `max_distance`.
+/// Unmatched right groups get new integer ids not used by either side
+fn align_groups(left: &JzonObject, right: &mut JzonObject, max_distance: f64) {
+    let left_groups = groups(left);
+    let right_groups = groups(right);
+    let mut pairs: Vec<(usize, usize, f64)> = left_groups
+        .iter()
+        .enumerate()
+        .flat_map(|(li, l)| {
+            right_groups
+                .iter()
+                .enumerate()
+                .filter_map(move |(ri, r)| Some((li, ri, l.distance(r)?)))
+        })
+        .filter(|(_, _, d)| *d <= max_distance)
+        .collect();
+    pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let mut new_ids: IndexMap<String, JzonObject> = IndexMap::new();
+    let mut matched_left = IndexSet::new();
+    for (li, ri, _) in pairs {
+        let key = right_groups[ri].id.dump();
+        if !matched_left.contains(&li) && !new_ids.contains_key(&key) {
+            matched_left.insert(li);
+            new_ids.insert(key, left_groups[li].id.clone());
+        }
+    }
+    let mut next_id = left_groups
+        .iter()
+        .chain(&right_groups)
+        .filter_map(|g| g.id.as_i64())
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for group in &right_groups {
+        new_ids.entry(group.id.dump()).or_insert_with(|| {
+            next_id += 1;
+            (next_id - 1).into()
+        });
+    }
+    if !right["content"]["shapes"].is_array() {
+        return;
+    }
+    for shape in right["content"]["shapes"].members_mut() {
+        if let Some(id) = new_ids.get(&shape["group_id"].dump()) {
+            shape["group_id"] = id.clone();
+        }
+    }
+}
+
+/// Merge objects, aligning the groups of shapes with `Some(max_distance)`
+fn merge(left: &mut JzonObject, mut right: JzonObject, by_group: Option<f64>) {
+    if let Some(max_distance) = by_group {
+        align_groups(left, &mut right, max_distance);
+    }
+    lmrs::merge(left, right);
+}
+
 fn join_inner(
     left: IndexMap<String, JzonObject>,
     right: IndexMap<String, JzonObject>,
     missing_handling: MissingHandling,
+    by_group: Option<f64>,
 ) -> Result<IndexMap<String, JzonObject>> {
     let mut right = right;
     let mut joined = IndexMap::new();
@@ -51,16 +184,14 @@ fn join_inner(
         match right.swap_remove(&key) {
             Some(right_obj) => {
                 let mut obj = left_obj;
-                lmrs::merge(&mut obj, right_obj);
+                merge(&mut obj, right_obj, by_group);
                 joined.insert(key, obj);
             }
-            None => {
-                if missing_handling == MissingHandling::Exit {
-                    bail!("Key {} not found in right object", key);
-                } else {
-                    debug!("Key {} not found in left object", key);
-                }
-            }
+            None => match missing_handling {
+                MissingHandling::Exit => bail!("Key {} not found in right object", key),
+                MissingHandling::Warn => warn!("Key {} not found in right object", key),
+                MissingHandling::Continue => debug!("Key {} not found in right object", key),
+            },
         }
     }
     Ok(joined)
@@ -70,20 +201,19 @@ fn join_left(
     left: IndexMap<String, JzonObject>,
     right: IndexMap<String, JzonObject>,
     missing_handling: MissingHandling,
+    by_group: Option<f64>,
 ) -> Result<IndexMap<String, JzonObject>> {
     let mut left = left;
     for (key, right_obj) in right {
         match left.entry(key) {
             labelme_rs::indexmap::map::Entry::Occupied(mut left_obj) => {
-                lmrs::merge(left_obj.get_mut(), right_obj);
-            }
-            labelme_rs::indexmap::map::Entry::Vacant(entry) => {
-                if missing_handling == MissingHandling::Exit {
-                    bail!("Key {} not found in left object", entry.key());
-                } else {
-                    debug!("Key {} not found in left object", entry.key());
-                }
+                merge(left_obj.get_mut(), right_obj, by_group);
             }
+            labelme_rs::indexmap::map::Entry::Vacant(entry) => match missing_handling {
+                MissingHandling::Exit => bail!("Key {} not found in left object", entry.key()),
+                MissingHandling::Warn => warn!("Key {} not found in left object", entry.key()),
+                MissingHandling::Continue => debug!("Key {} not found in left object", entry.key()),
+            },
         }
     }
     Ok(left)
@@ -92,13 +222,14 @@ fn join_left(
 fn join_outer(
     left: IndexMap<String, JzonObject>,
     right: IndexMap<String, JzonObject>,
+    by_group: Option<f64>,
 ) -> Result<IndexMap<String, JzonObject>> {
     let mut left = left;
     for (key, right_obj) in right.into_iter() {
         let entry = left.entry(key);
         match entry {
             labelme_rs::indexmap::map::Entry::Occupied(mut left_obj) => {
-                lmrs::merge(left_obj.get_mut(), right_obj);
+                merge(left_obj.get_mut(), right_obj, by_group);
             }
             labelme_rs::indexmap::map::Entry::Vacant(entry) => {
                 entry.insert(right_obj);
@@ -111,6 +242,7 @@ fn join_outer(
 pub fn cmd(args: CmdArgs) -> Result<()> {
     let input_set: IndexSet<PathBuf> = IndexSet::from_iter(args.input);
     anyhow::ensure!(input_set.len() > 1, "Need more than one input");
+    let by_group = args.by_group.then_some(args.max_distance);
     debug!("Read and join ndjsons");
     let joined: Result<IndexMap<String, JzonObject>, _> = input_set
         .iter()
@@ -118,9 +250,9 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         .reduce(|l, r| {
             l.and_then(|l| {
                 r.map(|r| match args.mode {
-                    JoinMode::Inner => join_inner(l, r, args.missing),
-                    JoinMode::Left => join_left(l, r, args.missing),
-                    JoinMode::Outer => join_outer(l, r),
+                    JoinMode::Inner => join_inner(l, r, args.missing, by_group),
+                    JoinMode::Left => join_left(l, r, args.missing, by_group),
+                    JoinMode::Outer => join_outer(l, r, by_group),
                 })
             })?
         })
@@ -141,24 +273,84 @@ fn test_join() -> anyhow::Result<()> {
     let r: IndexMap<String, JzonObject> = IndexMap::from([("k2".into(), jzon::parse("{}")?)]);
 
     // inner
-    let joined = join_inner(l.clone(), r.clone(), MissingHandling::Exit);
+    let joined = join_inner(l.clone(), r.clone(), MissingHandling::Exit, None);
     assert!(joined.is_err());
 
-    let joined = join_inner(l.clone(), r.clone(), MissingHandling::Continue)?;
+    let joined = join_inner(l.clone(), r.clone(), MissingHandling::Continue, None)?;
     assert!(!joined.contains_key("k1"));
     assert!(!joined.contains_key("k2"));
 
     // left
-    let joined = join_left(l.clone(), r.clone(), MissingHandling::Exit);
+    let joined = join_left(l.clone(), r.clone(), MissingHandling::Exit, None);
     assert!(joined.is_err());
 
-    let joined = join_left(l.clone(), r.clone(), MissingHandling::Continue)?;
+    let joined = join_left(l.clone(), r.clone(), MissingHandling::Continue, None)?;
     assert!(joined.contains_key("k1"));
     assert!(!joined.contains_key("k2"));
 
     // outer
-    let joined = join_outer(l, r)?;
+    let joined = join_outer(l, r, None)?;
     assert!(joined.contains_key("k1"));
     assert!(joined.contains_key("k2"));
     Ok(())
 }
+
+/// ndjson record of the shapes with the integral group ids
+#[cfg(test)]
+fn record(shapes: &[(Shape, i64)]) -> anyhow::Result<JzonObject> {
+    use labelme_rs::serde_json;
+    let shapes = shapes
+        .iter()
+        .map(|(shape, group_id)| {
+            let mut shape = serde_json::to_value(shape)?;
+            shape["group_id"] = (*group_id).into();
+            Ok(shape)
+        })
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    Ok(jzon::parse(
+        &serde_json::json!({"content": {"shapes": shapes}}).to_string(),
+    )?)
+}
+
+#[test]
+fn test_join_by_group() -> anyhow::Result<()> {
+    use crate::test_util::shape;
+    // first pass
+    let l: IndexMap<String, JzonObject> = IndexMap::from([(
+        "k1".into(),
+        record(&[
+            (shape("nose", "point", [(10.0, 10.0)]), 1),
+            (shape("left_eye", "point", [(8.0, 8.0)]), 1),
+            (shape("nose", "point", [(100.0, 100.0)]), 2),
+            (shape("left_eye", "point", [(98.0, 98.0)]), 2),
+        ])?,
+    )]);
+    // second pass with different group ids
+    let r: IndexMap<String, JzonObject> = IndexMap::from([(
+        "k1".into(),
+        record(&[
+            (shape("right_eye", "point", [(102.0, 98.0)]), 1),
+            (shape("nose", "point", [(101.0, 100.0)]), 1),
+            (shape("right_eye", "point", [(12.0, 8.0)]), 2),
+            (shape("right_eye", "point", [(500.0, 500.0)]), 3),
+        ])?,
+    )]);
+    let joined = join_outer(l.clone(), r.clone(), Some(50.0))?;
+    let group_ids: Vec<_> = joined["k1"]["content"]["shapes"]
+        .members()
+        .map(|s| (s["label"].to_string(), s["group_id"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(
+        group_ids[4..],
+        [
+            ("right_eye".to_string(), 2),
+            ("nose".to_string(), 2),
+            ("right_eye".to_string(), 1),
+            ("right_eye".to_string(), 4),
+        ]
+    );
+    // without matching, group ids are kept as is
+    let joined = join_outer(l, r, None)?;
+    assert_eq!(joined["k1"]["content"]["shapes"][6]["group_id"], 2);
+    Ok(())
+}
@@ -12,18 +12,41 @@ use std::{
 };
 use thiserror::Error;
 
+pub mod baseline;
+pub mod cancel;
 pub mod cli;
+pub mod config;
+pub mod dataset;
+pub mod lock;
+pub mod patch;
+pub mod retry;
+pub mod tarball;
+#[cfg(test)]
+mod test_util;
 
 #[derive(Clone, Debug)]
 pub enum Expr {
     Num(isize),
     Var(String),
+    /// `count_with_flag(label, flag)`: count of shapes of the label whose shape flag is true
+    CountWithFlag(String, String),
+    /// Call of a builtin function: `abs(a)`, `min(a, b, ...)`, or `max(a, b, ...)`
+    Call(String, Vec<Expr>),
 
     Neg(Box<Expr>),
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Box<Expr>, Box<Expr>),
+    /// `a % b`: remainder with the sign of `a`
+    Mod(Box<Expr>, Box<Expr>),
     Cmp(Box<Expr>, CmpOp, Box<Expr>),
+
+    /// `!a`: true if `a` is false or zero
+    Not(Box<Expr>),
+    /// `a && b`
+    And(Box<Expr>, Box<Expr>),
+    /// `a || b`
+    Or(Box<Expr>, Box<Expr>),
 }
 
 #[derive(Clone, Debug)]
@@ -36,6 +59,20 @@ pub enum CmpOp {
     NotEq,
 }
 
+/// Check the name and the number of arguments of a builtin function
+fn check_call(name: &str, n_args: usize) -> Result<(), String> {
+    let valid = match name {
+        "abs" => n_args == 1,
+        "min" | "max" => n_args >= 2,
+        _ => return Err(format!("Unknown function: {name}")),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Wrong number of arguments for {name}: {n_args}"))
+    }
+}
+
 pub fn parser() -> impl Parser<char, Expr, Error = Simple<char>> {
     let ident = text::ident().padded();
 
@@ -44,22 +81,67 @@ pub fn parser() -> impl Parser<char, Expr, Error = Simple<char>> {
             .map(|s: String| Expr::Num(s.parse().unwrap()))
             .padded();
 
+        let count_with_flag = text::keyword("count_with_flag")
+            .padded()
+            .ignore_then(
+                ident
+                    .then_ignore(just(','))
+                    .then(ident)
+                    .delimited_by(just('('), just(')')),
+            )
+            .padded()
+            .map(|(label, flag)| Expr::CountWithFlag(label, flag));
+
+        // `shape_type:label` counts only the shapes of the type, e.g. `polygon:road`
+        let qualified = text::ident()
+            .then(just(':').ignore_then(text::ident()).or_not())
+            .map(|(name, label): (String, Option<String>)| match label {
+                Some(label) => format!("{name}:{label}"),
+                None => name,
+            })
+            .padded();
+
+        // identifiers followed by arguments are function calls, and the others are variables
+        let var_or_call = qualified
+            .then(
+                expr.clone()
+                    .separated_by(just(','))
+                    .delimited_by(just('('), just(')'))
+                    .or_not(),
+            )
+            .padded()
+            .try_map(
+                |(name, args): (String, Option<Vec<Expr>>), span| match args {
+                    None => Ok(Expr::Var(name)),
+                    Some(_) if name.contains(':') => {
+                        Err(Simple::custom(span, format!("Unknown function: {name}")))
+                    }
+                    Some(args) => check_call(&name, args.len())
+                        .map(|_| Expr::Call(name, args))
+                        .map_err(|msg| Simple::custom(span, msg)),
+                },
+            );
+
         let atom = int
             .or(expr.delimited_by(just('('), just(')')))
-            .or(ident.map(Expr::Var));
+            .or(count_with_flag)
+            .or(var_or_call);
 
         let op = |c| just(c).padded();
 
         let unary = op('-')
+            .to(Expr::Neg as fn(_) -> _)
+            .or(op('!').to(Expr::Not as fn(_) -> _))
             .repeated()
             .then(atom)
-            .foldr(|_op, rhs| Expr::Neg(Box::new(rhs)));
+            .foldr(|op, rhs| op(Box::new(rhs)));
 
         let product = unary
             .clone()
             .then(
                 op('*')
                     .to(Expr::Mul as fn(_, _) -> _)
+                    .or(op('%').to(Expr::Mod as fn(_, _) -> _))
                     .then(unary)
                     .repeated(),
             )
@@ -84,24 +166,86 @@ pub fn parser() -> impl Parser<char, Expr, Error = Simple<char>> {
             .or(just(">=".to_string()).to(CmpOp::GE))
             .or(just(">".to_string()).to(CmpOp::GT));
 
-        sum.clone()
+        let cmp = sum
+            .clone()
             .then(cmp_op.then(sum).repeated())
-            .foldl(|a, (op, b)| Expr::Cmp(Box::new(a), op, Box::new(b)))
+            .foldl(|a, (op, b)| Expr::Cmp(Box::new(a), op, Box::new(b)));
+
+        let and = cmp
+            .clone()
+            .then(just("&&").padded().ignore_then(cmp).repeated())
+            .foldl(|a, b| Expr::And(Box::new(a), Box::new(b)));
+
+        and.clone()
+            .then(just("||").padded().ignore_then(and).repeated())
+            .foldl(|a, b| Expr::Or(Box::new(a), Box::new(b)))
     });
 
     expr.then_ignore(end())
 }
 
+/// Counts of shapes with true shape flags by (label, flag)
+pub type ShapeFlagCounts = IndexMap<(String, String), isize>;
+
+/// Count shapes with true shape flags by (label, flag)
+pub fn count_shape_flags(shapes: &[labelme_rs::Shape], count_mode: CountMode) -> ShapeFlagCounts {
+    let mut counts = ShapeFlagCounts::new();
+    for shape in shapes {
+        for (flag, _) in shape.flags.iter().filter(|(_, v)| **v) {
+            *counts
+                .entry((shape.label.clone(), flag.clone()))
+                .or_default() += count_mode.count(shape);
+        }
+    }
+    counts
+}
+
 pub fn eval<'a>(expr: &'a Expr, vars: &Vec<(&'a String, isize)>) -> Result<isize, (isize, isize)> {
+    eval_with_shape_flags(expr, vars, &ShapeFlagCounts::new())
+}
+
+/// [`eval`] with counts for `count_with_flag`
+///
+/// Operands of `!`, `&&`, and `||` are false if they evaluate to zero or to unsatisfied comparisons.
+/// Unsatisfied `&&` reports `(0, 0)`. Other unsatisfied boolean expressions report the values of the last unsatisfied comparison (or the zero operand).
+/// `a % 0` is unsatisfied with the values `(a, 0)`
+pub fn eval_with_shape_flags<'a>(
+    expr: &'a Expr,
+    vars: &Vec<(&'a String, isize)>,
+    shape_flags: &ShapeFlagCounts,
+) -> Result<isize, (isize, isize)> {
+    let eval = |expr| eval_with_shape_flags(expr, vars, shape_flags);
+    let truth = |expr| match eval(expr) {
+        Ok(0) => Err((0, 0)),
+        Ok(_) => Ok(1),
+        Err(vals) => Err(vals),
+    };
     match expr {
         Expr::Num(x) => Ok(*x),
-        Expr::Neg(a) => Ok(-eval(a, vars)?),
-        Expr::Add(a, b) => Ok(eval(a, vars)? + eval(b, vars)?),
-        Expr::Sub(a, b) => Ok(eval(a, vars)? - eval(b, vars)?),
-        Expr::Mul(a, b) => Ok(eval(a, vars)? * eval(b, vars)?),
+        Expr::Neg(a) => Ok(-eval(a)?),
+        Expr::Add(a, b) => Ok(eval(a)? + eval(b)?),
+        Expr::Sub(a, b) => Ok(eval(a)? - eval(b)?),
+        Expr::Mul(a, b) => Ok(eval(a)? * eval(b)?),
+        Expr::Mod(a, b) => {
+            let (a, b) = (eval(a)?, eval(b)?);
+            a.checked_rem(b).ok_or((a, b))
+        }
+        Expr::Call(name, args) => {
+            let args = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            match name.as_str() {
+                "abs" => Ok(args[0].abs()),
+                "min" => Ok(args.into_iter().min().unwrap_or(0)),
+                "max" => Ok(args.into_iter().max().unwrap_or(0)),
+                _ => unreachable!("Unknown functions are rejected by the parser"),
+            }
+        }
+        Expr::CountWithFlag(label, flag) => Ok(shape_flags
+            .get(&(label.clone(), flag.clone()))
+            .copied()
+            .unwrap_or(0)),
         Expr::Cmp(a, op, b) => {
-            let a = eval(a, vars)?;
-            let b = eval(b, vars)?;
+            let a = eval(a)?;
+            let b = eval(b)?;
             let ret = match op {
                 CmpOp::Eq => a == b,
                 CmpOp::NotEq => a != b,
@@ -116,6 +260,12 @@ pub fn eval<'a>(expr: &'a Expr, vars: &Vec<(&'a String, isize)>) -> Result<isize
                 Err((a, b))
             }
         }
+        Expr::Not(a) => match eval(a) {
+            Ok(0) | Err(_) => Ok(1),
+            Ok(val) => Err((val, 0)),
+        },
+        Expr::And(a, b) => truth(a).and_then(|_| truth(b)).map_err(|_| (0, 0)),
+        Expr::Or(a, b) => truth(a).or_else(|_| truth(b)),
         Expr::Var(name) => {
             if let Some((_, val)) = vars.iter().rev().find(|(var, _)| *var == name) {
                 Ok(*val)
@@ -126,10 +276,71 @@ pub fn eval<'a>(expr: &'a Expr, vars: &Vec<(&'a String, isize)>) -> Result<isize
     }
 }
 
+/// Parse an encoding label of the WHATWG Encoding Standard (e.g. `shift_jis`, `euc-jp`, `utf-8`)
+///
+/// ```
+/// assert_eq!(lmrs::parse_encoding("sjis").unwrap(), labelme_rs::encoding_rs::SHIFT_JIS);
+/// assert!(lmrs::parse_encoding("unknown").is_err());
+/// ```
+pub fn parse_encoding(label: &str) -> Result<&'static labelme_rs::encoding_rs::Encoding, String> {
+    labelme_rs::encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("Unknown encoding: {label}"))
+}
+
+/// Parse a fraction between 0 and 1
+///
+/// ```
+/// assert_eq!(lmrs::parse_fraction("0.25").unwrap(), 0.25);
+/// assert!(lmrs::parse_fraction("1.5").is_err());
+/// ```
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s.parse().map_err(|err| format!("{err}: {s}"))?;
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(format!("Fraction should be between 0 and 1: {s}"))
+    }
+}
+
+/// Parse `NAME[=true|false]` flag assignment. The value is true if omitted
+///
+/// ```
+/// assert_eq!(lmrs::parse_flag_assignment("reviewed").unwrap(), ("reviewed".into(), true));
+/// assert_eq!(lmrs::parse_flag_assignment("reviewed=false").unwrap(), ("reviewed".into(), false));
+/// assert!(lmrs::parse_flag_assignment("reviewed=no").is_err());
+/// ```
+pub fn parse_flag_assignment(s: &str) -> Result<(String, bool), String> {
+    match s.split_once('=') {
+        None => Ok((s.to_string(), true)),
+        Some((name, value)) => value
+            .parse()
+            .map(|value| (name.to_string(), value))
+            .map_err(|_| format!("Expected `NAME[=true|false]`: {s}")),
+    }
+}
+
+/// Parse `old=new` label mapping
+///
+/// ```
+/// assert_eq!(lmrs::parse_label_mapping("TL=top_left").unwrap(), ("TL".into(), "top_left".into()));
+/// assert!(lmrs::parse_label_mapping("TL").is_err());
+/// ```
+pub fn parse_label_mapping(mapping: &str) -> Result<(String, String), String> {
+    mapping
+        .split_once('=')
+        .map(|(old, new)| (old.to_string(), new.to_string()))
+        .ok_or_else(|| format!("Expected `old=new`: {mapping}"))
+}
+
+/// Load rules from a text file. Empty lines and comment lines starting with `#` are skipped
 pub fn load_rules(filename: &Path) -> std::io::Result<Vec<String>> {
     let rules: Vec<String> = BufReader::new(File::open(filename)?)
         .lines()
         .map_while(Result::ok)
+        .filter(|line| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
         .collect();
     Ok(rules)
 }
@@ -140,7 +351,40 @@ pub enum ParseError {
     Error(String),
 }
 
-/// Parse rules
+/// Message of the parse error in `rule` with the column (1-based) and the caret-underlined rule.
+/// Custom errors (e.g. unknown functions) are reported as they are
+///
+/// ```
+/// let rule = "TL = 0";
+/// let errs = lmrs::parser().parse(rule).unwrap_err();
+/// # use chumsky::Parser;
+/// assert_eq!(
+///     lmrs::format_parse_error(rule, &errs[0]),
+///     "rule \"TL = 0\": unexpected ' ' at column 5\n  TL = 0\n      ^"
+/// );
+/// ```
+pub fn format_parse_error(rule: &str, e: &Simple<char>) -> String {
+    let span = e.span();
+    let reason = match (e.reason(), e.found()) {
+        (chumsky::error::SimpleReason::Custom(msg), _) => msg.clone(),
+        (chumsky::error::SimpleReason::Unclosed { delimiter, .. }, _) => {
+            format!("unclosed {delimiter:?}")
+        }
+        (_, Some(c)) => format!("unexpected {c:?}"),
+        (_, None) => "unexpected end of input".to_string(),
+    };
+    let caret = format!(
+        "{}{}",
+        " ".repeat(span.start),
+        "^".repeat(span.len().max(1))
+    );
+    format!(
+        "rule {rule:?}: {reason} at column {}\n  {rule}\n  {caret}",
+        span.start + 1
+    )
+}
+
+/// Parse rules. Errors of all the rules are joined into one message
 /// ```
 /// let ast = lmrs::parse_rules(&vec!["a == b".into()]);
 /// assert!(ast.is_ok());
@@ -148,20 +392,29 @@ pub enum ParseError {
 /// assert!(ast.is_err());
 /// ```
 pub fn parse_rules(rules: &[String]) -> Result<Vec<Expr>, ParseError> {
-    let asts: Result<Vec<_>, _> = rules.iter().map(|r| parser().parse(r.clone())).collect();
-    asts.map_err(|parse_errs| {
-        let errs: Vec<_> = parse_errs
-            .into_iter()
-            .map(|e| format!("Parse error: {e}"))
-            .collect();
-        ParseError::Error(errs.join("\n"))
-    })
+    let mut asts = Vec::with_capacity(rules.len());
+    let mut errs = vec![];
+    for rule in rules {
+        match parser().parse(rule.as_str()) {
+            Ok(ast) => asts.push(ast),
+            Err(parse_errs) => {
+                errs.extend(parse_errs.iter().map(|e| format_parse_error(rule, e)));
+            }
+        }
+    }
+    if errs.is_empty() {
+        Ok(asts)
+    } else {
+        Err(ParseError::Error(errs.join("\n")))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CheckError {
     FileNotFound,
+    IoError(String),
     InvalidJson(String),
+    MissingVariable(String),
     EvaluatedFalse(String, (isize, isize)),
     EvaluatedMultipleFalses(Vec<(String, (isize, isize))>),
 }
@@ -181,6 +434,8 @@ impl fmt::Display for CheckError {
                     .join(", ");
                 f.write_str(&msg)
             }
+            CheckError::IoError(err) => write!(f, "IO error; {err}"),
+            CheckError::MissingVariable(var) => write!(f, "Missing variable; {var}"),
             _ => write!(f, "{self:?}"),
         }
     }
@@ -208,6 +463,34 @@ pub fn check_json_file(
     check_json(rules, asts, json_data, flags, ignores)
 }
 
+/// Read a labelme json file with retries on transient IO errors
+pub fn read_json_file_with_retry(
+    json_filename: &Path,
+    retry: &retry::RetryPolicy,
+) -> Result<LabelMeData, CheckError> {
+    let json_str = retry
+        .read_to_string(json_filename)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => CheckError::FileNotFound,
+            _ => CheckError::IoError(format!("{err}")),
+        })?;
+    serde_json::from_str(&json_str).map_err(|err| CheckError::InvalidJson(format!("{err}")))
+}
+
+/// [`check_json_file`] with retries on transient IO errors and the count mode
+pub fn check_json_file_with_retry(
+    rules: &[String],
+    asts: &[Expr],
+    json_filename: &Path,
+    flags: &FlagSet,
+    ignores: &FlagSet,
+    retry: &retry::RetryPolicy,
+    count_mode: CountMode,
+) -> Result<CheckResult, CheckError> {
+    let json_data = read_json_file_with_retry(json_filename, retry)?;
+    check_json_with_count_mode(rules, asts, json_data, flags, ignores, count_mode)
+}
+
 pub fn check_jsons(
     rules: &[String],
     asts: &[Expr],
@@ -237,21 +520,64 @@ pub fn evaluate_rules(
     asts: &[Expr],
     shapes: Vec<labelme_rs::Shape>,
 ) -> Vec<(String, (isize, isize))> {
-    let mut point_map: IndexMap<String, Vec<Point>> = IndexMap::new();
+    evaluate_rules_with_vars(rules, asts, shapes, &[], CountMode::Shapes)
+}
+
+/// How a shape contributes to the count of its label in rules
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CountMode {
+    /// Each shape counts as 1 regardless of the number of its points
+    #[default]
+    Shapes,
+    /// Each shape counts as the number of its points (e.g. vertices of a polygon)
+    Vertices,
+}
+
+impl CountMode {
+    fn count(&self, shape: &labelme_rs::Shape) -> isize {
+        match self {
+            CountMode::Shapes => 1,
+            CountMode::Vertices => shape.points.len() as isize,
+        }
+    }
+}
+
+/// [`evaluate_rules`] with additional variables and the count mode.
+/// Additional variables take precedence over labels with the same name.
+/// `shape_type:label` variables are counted per shape type (see [`check_json`])
+pub fn evaluate_rules_with_vars(
+    rules: &[String],
+    asts: &[Expr],
+    shapes: Vec<labelme_rs::Shape>,
+    extra_vars: &[(String, isize)],
+    count_mode: CountMode,
+) -> Vec<(String, (isize, isize))> {
+    let shape_flags = count_shape_flags(&shapes, count_mode);
+    let mut count_map: IndexMap<String, isize> = IndexMap::new();
+    let mut typed_count_map: IndexMap<(String, String), isize> = IndexMap::new();
     for shape in shapes.into_iter() {
-        let vec: &mut Vec<Point> = point_map.entry(shape.label).or_default();
-        vec.push(shape.points[0]);
+        let count = count_mode.count(&shape);
+        *typed_count_map
+            .entry((shape.shape_type, shape.label.clone()))
+            .or_default() += count;
+        *count_map.entry(shape.label).or_default() += count;
     }
-    let vars: Vec<_> = point_map
+    let typed_names: Vec<_> = typed_count_map
+        .into_iter()
+        .map(|((shape_type, label), v)| (format!("{shape_type}:{label}"), v))
+        .collect();
+    let vars: Vec<_> = count_map
         .iter()
-        .map(|(k, v)| (k, v.len() as isize))
+        .map(|(k, v)| (k, *v))
+        .chain(typed_names.iter().map(|(k, v)| (k, *v)))
+        .chain(extra_vars.iter().map(|(k, v)| (k, *v)))
         .collect();
 
     let errors: Vec<_> = asts
         .iter()
         .zip(rules.iter())
         .filter_map(|(ast, rule)| {
-            let result = eval(ast, &vars);
+            let result = eval_with_shape_flags(ast, &vars, &shape_flags);
             match result {
                 Ok(_) => None,
                 Err(vals) => Some((rule.clone(), vals)),
@@ -261,6 +587,211 @@ pub fn evaluate_rules(
     errors
 }
 
+/// Unsatisfied rules paired with the evaluated values of both sides
+pub type UnsatisfiedRules = Vec<(String, (isize, isize))>;
+
+/// Variable extracted from a json line, specified as `NAME=PATH[*SCALE]`
+///
+/// `PATH` is a dot separated path into the line (e.g. `metrics.blur`). Array elements are specified by indices.
+/// Since rules are evaluated in integers, the value multiplied by `SCALE` is truncated toward zero.
+///
+/// ```
+/// let var: lmrs::LineVar = "blur_pct=metrics.blur*100".parse().unwrap();
+/// let line = labelme_rs::serde_json::json!({"metrics": {"blur": 0.834}});
+/// assert_eq!(var.extract(&line), Some(83));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineVar {
+    pub name: String,
+    pub path: Vec<String>,
+    pub scale: f64,
+}
+
+#[derive(Error, Debug)]
+#[error("invalid variable \"{0}\". NAME=PATH[*SCALE] is expected")]
+pub struct LineVarError(String);
+
+impl std::str::FromStr for LineVar {
+    type Err = LineVarError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || LineVarError(s.to_string());
+        let (name, rhs) = s.split_once('=').ok_or_else(err)?;
+        let (path, scale) = match rhs.split_once('*') {
+            Some((path, scale)) => (path, scale.trim().parse().map_err(|_| err())?),
+            None => (rhs, 1.0),
+        };
+        let name = name.trim();
+        let path: Vec<String> = path.trim().split('.').map(String::from).collect();
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name || path.iter().any(|p| p.is_empty()) {
+            return Err(err());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            scale,
+        })
+    }
+}
+
+impl LineVar {
+    /// Extract the scaled value. `None` if the path is missing or not a number
+    pub fn extract(&self, line: &serde_json::Value) -> Option<isize> {
+        let mut value = line;
+        for key in &self.path {
+            value = match value {
+                serde_json::Value::Array(array) => array.get(key.parse::<usize>().ok()?)?,
+                _ => value.get(key)?,
+            };
+        }
+        value.as_f64().map(|v| (v * self.scale) as isize)
+    }
+}
+
+/// Output filename pattern with placeholders.
+///
+/// - `{stem}`: file stem of the filename
+/// - `{parent}`: parent directory of the filename (empty if none)
+/// - `{index}`: 0-based index of the line
+///
+/// ```
+/// let pattern: lmrs::FilenamePattern = "{index}_{stem}.json".parse().unwrap();
+/// assert_eq!(pattern.render("dir/img.json", 3), "3_img.json");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilenamePattern(Vec<PatternSegment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum PatternSegment {
+    Literal(String),
+    Stem,
+    Parent,
+    Index,
+}
+
+#[derive(Error, Debug)]
+pub enum FilenamePatternError {
+    #[error("unknown placeholder {{{0}}}. Available: {{stem}}, {{parent}}, {{index}}")]
+    UnknownPlaceholder(String),
+    #[error("unclosed brace in \"{0}\"")]
+    UnclosedBrace(String),
+}
+
+impl std::str::FromStr for FilenamePattern {
+    type Err = FilenamePatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find(['{', '}']) {
+            let (literal, tail) = rest.split_at(start);
+            if !literal.is_empty() {
+                segments.push(PatternSegment::Literal(literal.to_string()));
+            }
+            let end = match tail.strip_prefix('{') {
+                Some(tail) => tail
+                    .find(['{', '}'])
+                    .filter(|i| tail[*i..].starts_with('}')),
+                None => None,
+            }
+            .ok_or_else(|| FilenamePatternError::UnclosedBrace(s.to_string()))?;
+            let name = &tail[1..end + 1];
+            segments.push(match name {
+                "stem" => PatternSegment::Stem,
+                "parent" => PatternSegment::Parent,
+                "index" => PatternSegment::Index,
+                _ => return Err(FilenamePatternError::UnknownPlaceholder(name.to_string())),
+            });
+            rest = &tail[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(PatternSegment::Literal(rest.to_string()));
+        }
+        Ok(Self(segments))
+    }
+}
+
+impl FilenamePattern {
+    /// Render the pattern for the `index`-th `filename`
+    pub fn render(&self, filename: &str, index: usize) -> String {
+        let path = Path::new(filename);
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                PatternSegment::Literal(s) => s.clone(),
+                PatternSegment::Stem => path
+                    .file_stem()
+                    .map_or_else(String::new, |s| s.to_string_lossy().into_owned()),
+                PatternSegment::Parent => path
+                    .parent()
+                    .map_or_else(String::new, |p| p.to_string_lossy().into_owned()),
+                PatternSegment::Index => index.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Evaluate rules for an ndjson line with variables extracted from the line.
+///
+/// Missing variables evaluate as 0 unless `strict_vars` is set.
+pub fn check_line(
+    rules: &[String],
+    asts: &[Expr],
+    line: &str,
+    vars: &[LineVar],
+    strict_vars: bool,
+    count_mode: CountMode,
+) -> Result<UnsatisfiedRules, CheckError> {
+    let value: serde_json::Value =
+        serde_json::from_str(line).map_err(|err| CheckError::InvalidJson(format!("{err}")))?;
+    let extra_vars = vars
+        .iter()
+        .map(|var| match var.extract(&value) {
+            Some(v) => Ok((var.name.clone(), v)),
+            None if strict_vars => Err(CheckError::MissingVariable(format!(
+                "{}: {}",
+                var.name,
+                var.path.join(".")
+            ))),
+            None => Ok((var.name.clone(), 0)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let json_data: LabelMeDataLine =
+        serde_json::from_value(value).map_err(|err| CheckError::InvalidJson(format!("{err}")))?;
+    Ok(evaluate_rules_with_vars(
+        rules,
+        asts,
+        json_data.content.shapes,
+        &extra_vars,
+        count_mode,
+    ))
+}
+
+/// Check the data against the rules
+///
+/// Variables in the rules are resolved in the following order:
+///
+/// 1. `shape_type:label` (e.g. `polygon:road`): the number of shapes of the shape type with the label
+/// 2. `label` (e.g. `road`): the number of shapes with the label regardless of the shape type
+///
+/// Unknown variables are 0.
+///
+/// ```
+/// let data = labelme_rs::LabelMeData {
+///     shapes: vec![
+///         labelme_rs::Shape::polygon(vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], "road"),
+///         labelme_rs::Shape::rectangle((0.0, 0.0), (1.0, 1.0), "road"),
+///     ],
+///     ..Default::default()
+/// };
+/// let rules = vec!["polygon:road == 1 && rectangle:road == 1 && road == 2".to_string()];
+/// let asts = lmrs::parse_rules(&rules).unwrap();
+/// let empty = lmrs::FlagSet::new();
+/// let result = lmrs::check_json(&rules, &asts, data, &empty, &empty);
+/// assert_eq!(result, Ok(lmrs::CheckResult::Passed));
+/// ```
 pub fn check_json(
     rules: &[String],
     asts: &[Expr],
@@ -268,17 +799,75 @@ pub fn check_json(
     flags: &FlagSet,
     ignores: &FlagSet,
 ) -> Result<CheckResult, CheckError> {
+    check_json_with_count_mode(rules, asts, json_data, flags, ignores, CountMode::Shapes)
+}
+
+/// Check if the data is out of the scope of the check.
+/// i.e. the data has none of `flags` (if any) or any of `ignores` set to true
+/// Fail if any point of the line is outside the image, e.g. after transformation
+pub fn ensure_in_bounds(line: &LabelMeDataLine) -> anyhow::Result<()> {
+    let out_of_bounds = line.content.points_out_of_bounds();
+    if let Some((label, (x, y))) = out_of_bounds.first() {
+        anyhow::bail!(
+            "{}: {} point(s) out of bounds of {}x{} (e.g. \"{label}\" at ({x}, {y}))",
+            line.filename,
+            out_of_bounds.len(),
+            line.content.imageWidth,
+            line.content.imageHeight
+        );
+    }
+    Ok(())
+}
+
+/// Parse a boolean expression over flags (e.g. `(reviewed && !rejected) || urgent`)
+pub fn parse_flag_expr(expr: &str) -> Result<Expr, ParseError> {
+    parse_rules(&[expr.to_string()]).map(|mut asts| asts.remove(0))
+}
+
+/// Evaluate a flag expression with each flag of the data as 1 if set and 0 otherwise
+///
+/// ```
+/// let expr = lmrs::parse_flag_expr("(reviewed && !rejected) || urgent").unwrap();
+/// let mut data = lmrs::LabelMeData::default();
+/// data.flags.insert("reviewed".into(), true);
+/// assert!(lmrs::satisfies_flag_expr(&expr, &data));
+/// data.flags.insert("rejected".into(), true);
+/// assert!(!lmrs::satisfies_flag_expr(&expr, &data));
+/// data.flags.insert("urgent".into(), true);
+/// assert!(lmrs::satisfies_flag_expr(&expr, &data));
+/// ```
+pub fn satisfies_flag_expr(expr: &Expr, json_data: &LabelMeData) -> bool {
+    let vars: Vec<_> = json_data
+        .flags
+        .iter()
+        .map(|(flag, value)| (flag, *value as isize))
+        .collect();
+    matches!(eval(expr, &vars), Ok(val) if val != 0)
+}
+
+pub fn is_skipped(json_data: &LabelMeData, flags: &FlagSet, ignores: &FlagSet) -> bool {
     let json_flags: FlagSet = json_data
         .flags
-        .into_iter()
-        .filter_map(|(k, v)| if v { Some(k) } else { None })
+        .iter()
+        .filter_map(|(k, v)| if *v { Some(k.clone()) } else { None })
         .collect();
-    if (!flags.is_empty() && json_flags.intersection(flags).count() == 0)
+    (!flags.is_empty() && json_flags.intersection(flags).count() == 0)
         || json_flags.intersection(ignores).count() > 0
-    {
+}
+
+/// [`check_json`] with the count mode
+pub fn check_json_with_count_mode(
+    rules: &[String],
+    asts: &[Expr],
+    json_data: LabelMeData,
+    flags: &FlagSet,
+    ignores: &FlagSet,
+    count_mode: CountMode,
+) -> Result<CheckResult, CheckError> {
+    if is_skipped(&json_data, flags, ignores) {
         return Ok(CheckResult::Skipped);
     }
-    let mut errors = evaluate_rules(rules, asts, json_data.shapes);
+    let mut errors = evaluate_rules_with_vars(rules, asts, json_data.shapes, &[], count_mode);
     if errors.is_empty() {
         Ok(CheckResult::Passed)
     } else if errors.len() == 1 {
@@ -487,3 +1076,304 @@ fn test_check_json() {
         "False rule"
     );
 }
+
+#[test]
+fn test_mod() {
+    use std::path::PathBuf;
+    let filename = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/test.json");
+    let check = |rule: &str| {
+        let rules = vec![rule.to_string()];
+        let asts = parse_rules(&rules).unwrap();
+        check_json_file(&rules, &asts, &filename, &FlagSet::new(), &FlagSet::new())
+    };
+    assert_eq!(check("TL % 2 == 1"), Ok(CheckResult::Passed));
+    assert_eq!(
+        check("TL % 2 == 0"),
+        Err(CheckError::EvaluatedFalse("TL % 2 == 0".into(), (1, 0)))
+    );
+    // same precedence as `*`, i.e. TL + (TR % 2) and (TL * 3) % 2
+    assert_eq!(check("TL + TR % 2 == 2"), Ok(CheckResult::Passed));
+    assert_eq!(check("TL * 3 % 2 == 1"), Ok(CheckResult::Passed));
+    assert_eq!(check("-3 % 2 == -1"), Ok(CheckResult::Passed));
+    // division by zero fails the rule instead of panicking
+    assert_eq!(
+        check("TL % X == 0"),
+        Err(CheckError::EvaluatedFalse("TL % X == 0".into(), (1, 0)))
+    );
+}
+
+#[test]
+fn test_builtin_functions() {
+    use std::path::PathBuf;
+    let filename = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/test.json");
+    let check = |rule: &str| {
+        let rules = vec![rule.to_string()];
+        let asts = parse_rules(&rules).unwrap();
+        check_json_file(&rules, &asts, &filename, &FlagSet::new(), &FlagSet::new())
+    };
+    assert_eq!(check("abs(TL - TR) <= 1"), Ok(CheckResult::Passed));
+    assert_eq!(check("abs(TL - 3) == 2"), Ok(CheckResult::Passed));
+    assert_eq!(check("min(TL, X, 5) == 0"), Ok(CheckResult::Passed));
+    assert_eq!(check("max(TL, TR + BL, 1) == 2"), Ok(CheckResult::Passed));
+    assert_eq!(
+        check("max(abs(-3), min(TL, TR)) == 3"),
+        Ok(CheckResult::Passed)
+    );
+    assert_eq!(
+        check("abs(TL - 4) < 3"),
+        Err(CheckError::EvaluatedFalse("abs(TL - 4) < 3".into(), (3, 3)))
+    );
+    // bare identifiers are still the counts of labels
+    assert_eq!(check("abs == 0 && TL == 1"), Ok(CheckResult::Passed));
+
+    for rule in [
+        "sqrt(TL) == 1",
+        "abs(TL, TR) == 1",
+        "min(TL) == 1",
+        "abs() == 0",
+    ] {
+        assert!(
+            parse_rules(&[rule.to_string()]).is_err(),
+            "{rule} should be rejected"
+        );
+    }
+    let err = parse_rules(&["sqrt(TL) == 1".to_string()]).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("Unknown function: sqrt"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_parse_error_columns() {
+    let rules = vec![
+        "TL == 0".to_string(),
+        "TL == (TR".to_string(),
+        "sqrt(TL) == 1".to_string(),
+    ];
+    let ParseError::Error(msg) = parse_rules(&rules).unwrap_err();
+    let lines: Vec<_> = msg.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"rule "TL == (TR": unexpected '(' at column 7"#,
+            "  TL == (TR",
+            "        ^",
+            r#"rule "sqrt(TL) == 1": Unknown function: sqrt at column 1"#,
+            "  sqrt(TL) == 1",
+            "  ^^^^^^^^^",
+        ]
+    );
+}
+
+#[test]
+fn test_shape_type_qualified_vars() {
+    use labelme_rs::Shape;
+    let shapes = vec![
+        Shape::polygon(vec![(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)], "road"),
+        Shape::polygon(vec![(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)], "road"),
+        Shape::rectangle((0.0, 0.0), (1.0, 1.0), "road"),
+    ];
+    let evaluate = |rule: &str, count_mode| {
+        let rules = vec![rule.to_string()];
+        let asts = parse_rules(&rules).unwrap();
+        evaluate_rules_with_vars(&rules, &asts, shapes.clone(), &[], count_mode)
+    };
+    assert!(evaluate("polygon:road == 2", CountMode::Shapes).is_empty());
+    assert!(evaluate("rectangle:road == 1", CountMode::Shapes).is_empty());
+    assert!(evaluate("road == 3 && point:road == 0", CountMode::Shapes).is_empty());
+    assert!(evaluate("polygon:road == 6", CountMode::Vertices).is_empty());
+    assert_eq!(
+        evaluate("polygon:road < 2", CountMode::Shapes),
+        vec![("polygon:road < 2".to_string(), (2, 2))]
+    );
+    assert!(parse_rules(&["polygon:road(1) == 1".to_string()]).is_err());
+}
+
+#[test]
+fn test_logical_ops() {
+    use std::path::PathBuf;
+    let filename = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/test.json");
+    let check = |rule: &str| {
+        let rules = vec![rule.to_string()];
+        let asts = parse_rules(&rules).unwrap();
+        check_json_file(&rules, &asts, &filename, &FlagSet::new(), &FlagSet::new())
+    };
+    assert_eq!(check("TL > 0 && TR > 0"), Ok(CheckResult::Passed));
+    assert_eq!(check("TL == 0 || TR > 0"), Ok(CheckResult::Passed));
+    // lower precedence than the comparisons, and `&&` binds tighter than `||`
+    assert_eq!(
+        check("TL == 0 && TR == 0 || BL == 1"),
+        Ok(CheckResult::Passed)
+    );
+    assert_eq!(
+        check("BL == 1 || TL == 0 && TR == 0"),
+        Ok(CheckResult::Passed)
+    );
+    assert_eq!(check("TL + TR && BL"), Ok(CheckResult::Passed));
+    // `&&` reports (0, 0), and `||` the values of the failed comparison
+    assert_eq!(
+        check("TL > 0 && TR == 0"),
+        Err(CheckError::EvaluatedFalse(
+            "TL > 0 && TR == 0".into(),
+            (0, 0)
+        ))
+    );
+    assert_eq!(
+        check("TL == 0 || TR == 2"),
+        Err(CheckError::EvaluatedFalse(
+            "TL == 0 || TR == 2".into(),
+            (1, 2)
+        ))
+    );
+}
+
+#[test]
+fn test_filename_pattern() {
+    let pattern: FilenamePattern = "{parent}/{stem}_{index}.json".parse().unwrap();
+    assert_eq!(pattern.render("a/b/c.json", 0), "a/b/c_0.json");
+    assert_eq!(pattern.render("c.json", 12), "/c_12.json");
+    let pattern: FilenamePattern = "out.json".parse().unwrap();
+    assert_eq!(pattern.render("c.json", 1), "out.json");
+    assert!(matches!(
+        "{name}.json".parse::<FilenamePattern>(),
+        Err(FilenamePatternError::UnknownPlaceholder(name)) if name == "name"
+    ));
+    for invalid in ["{stem.json", "stem}.json", "{{stem}}.json", "{}"] {
+        assert!(invalid.parse::<FilenamePattern>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_line_var() {
+    let var: LineVar = "blur_pct=metrics.blur*100".parse().unwrap();
+    assert_eq!(var.name, "blur_pct");
+    assert_eq!(var.path, vec!["metrics", "blur"]);
+    assert_eq!(var.scale, 100.0);
+    let var: LineVar = "first=scores.0".parse().unwrap();
+    assert_eq!(
+        var.extract(&serde_json::json!({"scores": [-3.9, 1.0]})),
+        Some(-3),
+        "Truncation toward zero"
+    );
+    assert_eq!(var.extract(&serde_json::json!({"scores": []})), None);
+    assert_eq!(var.extract(&serde_json::json!({"scores": "a"})), None);
+    assert!("blur_pct".parse::<LineVar>().is_err());
+    assert!("1x=a".parse::<LineVar>().is_err());
+    assert!("x=a..b".parse::<LineVar>().is_err());
+    assert!("x=a*b".parse::<LineVar>().is_err());
+}
+
+#[test]
+fn test_check_line() {
+    let rules = vec!["blur_pct < 50".to_string(), "TL > 0".to_string()];
+    let asts = parse_rules(&rules).unwrap();
+    let vars = vec!["blur_pct=metrics.blur*100".parse().unwrap()];
+    let line = |metrics: &str| {
+        format!(
+            r#"{{"filename":"1.json","content":{},{metrics}}}"#,
+            serde_json::to_string(&LabelMeData::new(
+                &[(1.0, 1.0)],
+                &["TL".into()],
+                8,
+                8,
+                "1.jpg"
+            ))
+            .unwrap()
+        )
+    };
+    let errors = check_line(
+        &rules,
+        &asts,
+        &line(r#""metrics":{"blur":0.3}"#),
+        &vars,
+        false,
+        CountMode::Shapes,
+    );
+    assert!(errors.unwrap().is_empty());
+    let errors = check_line(
+        &rules,
+        &asts,
+        &line(r#""metrics":{"blur":0.83}"#),
+        &vars,
+        false,
+        CountMode::Shapes,
+    );
+    assert_eq!(errors.unwrap(), vec![(rules[0].clone(), (83, 50))]);
+    let errors = check_line(
+        &rules,
+        &asts,
+        &line(r#""metrics":{}"#),
+        &vars,
+        false,
+        CountMode::Shapes,
+    );
+    assert!(errors.unwrap().is_empty(), "Missing variable is 0");
+    let errors = check_line(
+        &rules,
+        &asts,
+        &line(r#""metrics":{}"#),
+        &vars,
+        true,
+        CountMode::Shapes,
+    );
+    assert_eq!(
+        errors.unwrap_err(),
+        CheckError::MissingVariable("blur_pct: metrics.blur".into())
+    );
+}
+
+#[test]
+fn test_count_mode() {
+    let rules = vec!["poly == 1".to_string()];
+    let asts = parse_rules(&rules).unwrap();
+    let shapes = vec![labelme_rs::Shape {
+        label: "poly".into(),
+        points: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+        shape_type: "polygon".into(),
+        ..Default::default()
+    }];
+    let errors = evaluate_rules_with_vars(&rules, &asts, shapes.clone(), &[], CountMode::Shapes);
+    assert!(errors.is_empty(), "A polygon counts as 1 by default");
+    let errors = evaluate_rules_with_vars(&rules, &asts, shapes, &[], CountMode::Vertices);
+    assert_eq!(errors, vec![(rules[0].clone(), (4, 1))]);
+}
+
+#[test]
+fn test_count_with_flag() {
+    let rules = vec![
+        "count_with_flag(person, visible) == 2".to_string(),
+        "count_with_flag(person, occluded) + count_with_flag(car, visible) == 0".to_string(),
+        "person == 3".to_string(),
+    ];
+    let asts = parse_rules(&rules).unwrap();
+    let points = [(0.0, 0.0), (1.0, 1.0)];
+    let mut shapes = vec![test_util::shape("person", "line", points); 3];
+    shapes[0].flags = labelme_rs::Flags::from([("visible".into(), true)]);
+    shapes[1].flags =
+        labelme_rs::Flags::from([("visible".into(), true), ("occluded".into(), false)]);
+    shapes[2].flags = labelme_rs::Flags::from([("visible".into(), false)]);
+    shapes.push(test_util::shape("car", "line", points));
+    let errors = evaluate_rules_with_vars(&rules, &asts, shapes.clone(), &[], CountMode::Shapes);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let errors = evaluate_rules_with_vars(&rules, &asts, shapes, &[], CountMode::Vertices);
+    assert_eq!(
+        errors,
+        vec![(rules[0].clone(), (4, 2)), (rules[2].clone(), (6, 3))]
+    );
+    assert!(parse_rules(&["count_with_flag(person) > 0".into()]).is_err());
+    // a label named after the function is still a variable
+    assert!(parse_rules(&["count_with_flag > 0".into()]).is_ok());
+}
+
+#[test]
+fn test_load_rules() {
+    let dir = tempfile::tempdir().unwrap();
+    let filename = dir.path().join("rules.txt");
+    std::fs::write(
+        &filename,
+        "# comment\nTL > 0\n\n  # indented comment\nTL == TR\n",
+    )
+    .unwrap();
+    assert_eq!(load_rules(&filename).unwrap(), vec!["TL > 0", "TL == TR"]);
+}
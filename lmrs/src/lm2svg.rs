@@ -1,10 +1,129 @@
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
+use labelme_rs::image::GenericImageView;
+use rayon::prelude::*;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
-use labelme_rs::{load_label_colors, LabelColorsHex};
-use lmrs::cli::SvgCmdArgs as CmdArgs;
+use clap::ArgMatches;
+use labelme_rs::{load_label_colors, load_label_styles, LabelColorsHex};
+use lmrs::cli::{SvgCmdArgs as CmdArgs, SvgConfig};
 
-pub fn cmd(args: CmdArgs) -> Result<()> {
+/// Render svg of `json_data` whose `imagePath` is absolute. `image` is loaded from `imagePath` if omitted
+fn render(
+    mut json_data: labelme_rs::LabelMeData,
+    image: Option<labelme_rs::image::DynamicImage>,
+    label_colors: &LabelColorsHex,
+    svg_config: &SvgConfig,
+    args: &CmdArgs,
+) -> Result<labelme_rs::svg::Document> {
+    if args.no_image {
+        if let Some(resize) = &svg_config.resize {
+            let resize_param = labelme_rs::ResizeParam::try_from(resize.as_str())?;
+            json_data
+                .scale(resize_param.scale(json_data.imageWidth as _, json_data.imageHeight as _));
+        }
+        return Ok(json_data.to_svg_without_image(
+            label_colors,
+            svg_config.radius,
+            svg_config.line_width,
+            args.margin,
+        ));
+    }
+    let mut data_w_image = match image {
+        Some(image) => labelme_rs::LabelMeDataWImage::new(json_data, image),
+        None => json_data.try_into()?,
+    };
+    if let Some(resize) = &svg_config.resize {
+        let resize_param = labelme_rs::ResizeParam::try_from(resize.as_str())?;
+        data_w_image.resize(&resize_param);
+    }
+    if let Some(max_bytes) = svg_config.max_image_bytes {
+        data_w_image.fit_encoded_size(max_bytes, svg_config.image_format.into())?;
+    }
+    let image_data = svg_config
+        .max_image_bytes
+        .is_none()
+        .then_some(data_w_image.data.imageData.as_deref())
+        .flatten();
+    Ok(data_w_image.data.to_svg_with_image_data(
+        label_colors,
+        svg_config.radius,
+        svg_config.line_width,
+        &data_w_image.image,
+        image_data,
+        &svg_config.svg_options(),
+    ))
+}
+
+/// Render each json of the directory or ndjson input into the file named by `template` in parallel.
+/// Colors of labels missing in `label_colors` are assigned across the input so that they agree between the files
+fn render_batch(
+    template: &lmrs::FilenamePattern,
+    mut label_colors: LabelColorsHex,
+    svg_config: &SvgConfig,
+    args: &CmdArgs,
+) -> Result<()> {
+    let retry = lmrs::retry::RetryPolicy::default();
+    let entries = crate::lms2html::load_entries(&args.input, &retry)?;
+    ensure!(!entries.is_empty(), "No json file found.");
+    let json_dir = crate::lms2html::json_dir(&args.input)?;
+    let mut cycler = labelme_rs::ColorCycler::default();
+    for (_, json_data) in &entries {
+        for shape in &json_data.shapes {
+            label_colors
+                .entry(shape.label.clone())
+                .or_insert_with(|| cycler.cycle().to_string());
+        }
+    }
+    let bar = indicatif::ProgressBar::new(entries.len() as _);
+    bar.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("[{elapsed}<{eta}] | {wide_bar} | {pos}/{len}")?,
+    );
+    entries.into_par_iter().enumerate().try_for_each(
+        |(index, (input, json_data))| -> Result<()> {
+            let filename = if args.input.is_dir() {
+                input.file_name().unwrap_or_default().to_string_lossy()
+            } else {
+                input.to_string_lossy()
+            };
+            let output = PathBuf::from(template.render(&filename, index));
+            let mut json_data = *json_data;
+            json_data.imagePath = json_data.imagePath.replace('\\', "/");
+            let json_data = json_data.to_absolute_path(&json_dir);
+            let image = if args.no_image {
+                None
+            } else {
+                Some(crate::lms2html::load_image(&json_data, &input, &retry)?.0)
+            };
+            let document = render(json_data, image, &label_colors, svg_config, args)
+                .with_context(|| format!("Rendering {:?}", input))?;
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            labelme_rs::svg::save(&output, &document)
+                .with_context(|| format!("Writing to {:?}", output))?;
+            bar.inc(1);
+            Ok(())
+        },
+    )?;
+    bar.finish();
+    Ok(())
+}
+
+pub fn cmd(args: CmdArgs, matches: &ArgMatches) -> Result<()> {
+    let mut svg_config = args.svg.resolve(matches, SvgConfig::default(), None)?;
+    let label_colors = match &svg_config.config {
+        Some(config) => {
+            svg_config.label_styles = load_label_styles(config)?;
+            load_label_colors(config)?
+        }
+        None => LabelColorsHex::new(),
+    };
+    if let Some(template) = &args.output_template {
+        return render_batch(template, label_colors, &svg_config, &args);
+    }
+    let output = args.output.clone().context("Output is required")?;
     let mut json_data = labelme_rs::LabelMeData::try_from(if args.input.as_os_str() == "-" {
         let mut buf = String::new();
         std::io::stdin().read_to_string(&mut buf)?;
@@ -12,10 +131,6 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     } else {
         std::fs::read_to_string(&args.input)?
     })?;
-    let label_colors = match args.svg.config {
-        Some(config) => load_label_colors(&config)?,
-        None => LabelColorsHex::new(),
-    };
 
     if args.input.as_os_str() != "-" {
         let canonical_input = args.input.canonicalize()?;
@@ -24,17 +139,63 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             .with_context(|| format!("Failed to get parent directory of:{:?}", args.input))?;
         json_data = json_data.to_absolute_path(json_dir);
     };
-    let mut data_w_image: labelme_rs::LabelMeDataWImage = json_data.try_into()?;
-    if let Some(resize) = args.svg.resize {
-        let resize_param = labelme_rs::ResizeParam::try_from(resize.as_str())?;
-        data_w_image.resize(&resize_param);
+    if args.deep_zoom.deep_zoom {
+        let data_w_image: labelme_rs::LabelMeDataWImage = json_data.try_into()?;
+        let document = write_deep_zoom(
+            &data_w_image,
+            &label_colors,
+            &svg_config,
+            &output,
+            args.deep_zoom.tile_size,
+        )?;
+        labelme_rs::svg::save(output, &document)?;
+        return Ok(());
     }
-    let document = data_w_image.data.to_svg(
-        &label_colors,
-        args.svg.radius,
-        args.svg.line_width,
-        &data_w_image.image,
-    );
-    labelme_rs::svg::save(args.output, &document)?;
+    let document = render(json_data, None, &label_colors, &svg_config, &args)?;
+    labelme_rs::svg::save(output, &document)?;
     Ok(())
 }
+
+/// Write DZI tiles and descriptor next to `output` and create svg referring to them
+fn write_deep_zoom(
+    data_w_image: &labelme_rs::LabelMeDataWImage,
+    label_colors: &LabelColorsHex,
+    svg_config: &SvgConfig,
+    output: &Path,
+    tile_size: u32,
+) -> Result<labelme_rs::svg::Document> {
+    let image = &data_w_image.image;
+    let (width, height) = image.dimensions();
+    let stem = output
+        .file_stem()
+        .with_context(|| format!("Failed to get file_stem of:{:?}", output))?
+        .to_string_lossy();
+    let dzi_path = output.with_file_name(format!("{stem}.dzi"));
+    let tiles_dir = output.with_file_name(format!("{stem}_files"));
+    labelme_rs::tiles::make_tiles(image, tile_size)
+        .par_bridge()
+        .try_for_each(|tile| -> Result<()> {
+            let level_dir = tiles_dir.join(tile.level.to_string());
+            std::fs::create_dir_all(&level_dir)?;
+            let bytes =
+                labelme_rs::encode_image(&tile.image, labelme_rs::image::ImageFormat::Jpeg)?;
+            std::fs::write(
+                level_dir.join(format!("{}_{}.jpg", tile.col, tile.row)),
+                bytes,
+            )?;
+            Ok(())
+        })?;
+    std::fs::write(
+        &dzi_path,
+        labelme_rs::tiles::dzi_descriptor(width, height, tile_size, None),
+    )?;
+    let base = image.thumbnail(tile_size, tile_size);
+    Ok(data_w_image.data.to_deep_zoom_svg(
+        label_colors,
+        svg_config.radius,
+        svg_config.line_width,
+        (width, height),
+        &base,
+        &format!("{stem}.dzi"),
+    ))
+}
@@ -1,39 +1,23 @@
 use anyhow::{bail, ensure, Context, Result};
 use labelme_rs::indexmap::{IndexMap, IndexSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
-use labelme_rs::{load_label_colors, LabelColorsHex, LabelMeDataWImage};
-use lmrs::cli::HtmlCmdArgs as CmdArgs;
+use clap::ArgMatches;
+use labelme_rs::image::GenericImageView;
+use labelme_rs::{load_label_colors, load_label_styles, LabelColorsHex, LabelMeDataWImage};
+use lmrs::cli::{HtmlCmdArgs as CmdArgs, SvgConfig};
 
-pub fn cmd(args: CmdArgs) -> Result<()> {
-    let mut templates = tera::Tera::default();
-    templates.autoescape_on(vec![]);
-    templates.add_raw_templates(vec![
-        ("catalog.html", include_str!("templates/catalog.html")),
-        ("img.html", include_str!("templates/img.html")),
-        ("legend.html", include_str!("templates/legend.html")),
-        (
-            "tag_checkbox.html",
-            include_str!("templates/tag_checkbox.html"),
-        ),
-        (
-            "shape_toggle.html",
-            include_str!("templates/shape_toggle.html"),
-        ),
-    ])?;
-    let n_jobs = if let Some(n) = args.jobs {
-        n
-    } else {
-        std::thread::available_parallelism()?.get()
-    };
-    debug!("Use {n_jobs} cores");
-    debug!("Load jsons");
-    let mut entries: Vec<(PathBuf, Box<labelme_rs::LabelMeData>)> = if args.input.is_dir() {
-        let entries: Result<Vec<_>> = glob::glob(
-            args.input
+/// Load jsons in the directory, or lines of the ndjson file or stdin (`-`) as (filename, data)
+pub(crate) fn load_entries(
+    input: &Path,
+    retry: &lmrs::retry::RetryPolicy,
+) -> Result<Vec<(PathBuf, Box<labelme_rs::LabelMeData>)>> {
+    if input.is_dir() {
+        glob::glob(
+            input
                 .join("*.json")
                 .to_str()
                 .context("Failed to obtain glob string")?,
@@ -41,19 +25,18 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         .expect("Failed to read glob pattern")
         .map(|entry| {
             let entry = entry?;
-            let s = std::fs::read_to_string(&entry)?;
+            let s = retry.read_to_string(&entry)?;
             let obj = labelme_rs::LabelMeData::try_from(s.as_str())?;
             Ok((entry, obj.into()))
         })
-        .collect();
-        entries?
+        .collect()
     } else {
-        let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        let reader: Box<dyn BufRead> = if input.as_os_str() == "-" {
             Box::new(BufReader::new(std::io::stdin()))
         } else {
-            Box::new(BufReader::new(File::open(&args.input)?))
+            Box::new(BufReader::new(File::open(input)?))
         };
-        let entries: Result<Vec<_>> = reader
+        reader
             .lines()
             .map(|line| {
                 let line = line?;
@@ -63,21 +46,82 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
                     Box::new(json_data.content),
                 ))
             })
-            .collect();
-        entries?
-    };
+            .collect()
+    }
+}
 
-    ensure!(!entries.is_empty(), "No json file found.");
-    let json_dir: PathBuf = if args.input.is_dir() {
-        args.input.canonicalize()?
-    } else if args.input.as_os_str() == "-" {
+/// Directory `imagePath`s of the entries of [`load_entries`] are relative to
+pub(crate) fn json_dir(input: &Path) -> Result<PathBuf> {
+    Ok(if input.is_dir() {
+        input.canonicalize()?
+    } else if input.as_os_str() == "-" {
         PathBuf::from(".").canonicalize()?
     } else {
-        args.input
+        input
             .parent()
             .context("Input has no parent directory")?
             .canonicalize()?
+    })
+}
+
+/// Load the image of `json_data` with absolute `imagePath`, falling back to `imageData`.
+/// `imageData` is used without trying `imagePath` if `imagePath` is empty or not a file.
+/// Returns the image and its file size, which is unknown for the image embedded in `imageData`
+pub(crate) fn load_image(
+    json_data: &labelme_rs::LabelMeData,
+    input: &Path,
+    retry: &lmrs::retry::RetryPolicy,
+) -> Result<(labelme_rs::image::DynamicImage, Option<u64>)> {
+    let image_path = &json_data.imagePath;
+    if let (false, Some(image_data)) = (Path::new(image_path).is_file(), &json_data.imageData) {
+        let image = labelme_rs::decode_image_data(image_data)
+            .with_context(|| format!("decode imageData of {:?}", input))?;
+        return Ok((image, None));
+    }
+    let loaded = retry.run(image_path, || labelme_rs::load_image(Path::new(image_path)));
+    match (loaded, &json_data.imageData) {
+        (Ok(image), _) => {
+            let file_size = std::fs::metadata(image_path)?.len();
+            Ok((image, Some(file_size)))
+        }
+        (Err(e), Some(image_data)) => {
+            debug!("{}: {}. Use imageData instead", image_path, e);
+            let image = labelme_rs::decode_image_data(image_data)
+                .with_context(|| format!("decode imageData of {:?}", input))?;
+            Ok((image, None))
+        }
+        (Err(e), None) => Err(e).with_context(|| format!("load {}", image_path)),
+    }
+}
+
+pub fn cmd(args: CmdArgs, matches: &ArgMatches) -> Result<()> {
+    let mut svg_config = args.svg.resolve(matches, SvgConfig::default(), None)?;
+    let mut templates = tera::Tera::default();
+    templates.autoescape_on(vec![]);
+    templates.add_raw_templates(vec![
+        ("catalog.html", include_str!("templates/catalog.html")),
+        ("img.html", include_str!("templates/img.html")),
+        ("legend.html", include_str!("templates/legend.html")),
+        (
+            "tag_checkbox.html",
+            include_str!("templates/tag_checkbox.html"),
+        ),
+        (
+            "shape_toggle.html",
+            include_str!("templates/shape_toggle.html"),
+        ),
+    ])?;
+    let n_jobs = if let Some(n) = args.jobs {
+        n
+    } else {
+        std::thread::available_parallelism()?.get()
     };
+    debug!("Use {n_jobs} cores");
+    let retry = lmrs::retry::RetryPolicy::from(&args.retry);
+    debug!("Load jsons");
+    let mut entries = load_entries(&args.input, &retry)?;
+    ensure!(!entries.is_empty(), "No json file found.");
+    let json_dir = json_dir(&args.input)?;
     let json_dir = if let Some(image_dir) = &args.image_dir {
         image_dir.canonicalize()?
     } else {
@@ -89,10 +133,14 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             .template("[{elapsed}<{eta}] | {wide_bar} | {pos}/{len}")?,
     );
     let shared_bar = Arc::new(Mutex::new(bar));
-    let mut label_colors = match args.svg.config {
-        Some(config) => load_label_colors(&config)?,
+    let mut label_colors = match &svg_config.config {
+        Some(config) => {
+            svg_config.label_styles = load_label_styles(config)?;
+            load_label_colors(config)?
+        }
         None => LabelColorsHex::new(),
     };
+    let svg_options = svg_config.svg_options();
     let mut all_tags: IndexMap<String, bool> = match args.flags {
         Some(filename) => {
             let buff_reader = std::io::BufReader::new(std::fs::File::open(filename)?);
@@ -146,90 +194,145 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         }
     });
 
-    let mut svgs: Vec<String> = Vec::with_capacity(entries.len());
-    let resize_param = match args.svg.resize {
+    if let Some(filename) = &args.save_colors {
+        labelme_rs::save_label_colors(filename, &label_colors)
+            .with_context(|| format!("Failed to save label colors to {:?}", filename))?;
+    }
+
+    let resize_param = match svg_config.resize {
         Some(s) => Some(labelme_rs::ResizeParam::try_from(s.as_str())?),
         None => None,
     };
 
+    let raster_options = args.pdf.then(crate::pdf::raster_options);
+
+    let cancel = lmrs::cancel::flag();
     debug!("Generate svgs");
+    // Figures are written to a spill file per chunk as soon as they are rendered,
+    // and the spill files are concatenated in order into the output
+    let mut spills: Vec<File> = Vec::with_capacity(n_jobs);
+    let mut pages: Vec<crate::pdf::Page> = vec![];
     std::thread::scope(|scope| {
         let mut handles: Vec<_> = Vec::with_capacity(n_jobs);
         let chunk_size = (entries.len() as f64 / n_jobs as f64).ceil() as usize;
         for chunk in entries.chunks_mut(chunk_size) {
-            handles.push(scope.spawn(|| {
-                let svgs: Result<Vec<String>> = chunk
-                    .iter_mut()
-                    .map(|entry| {
-                        let input = &mut entry.0;
-                        let mut json_data = entry.1.clone();
+            handles.push(scope.spawn(|| -> Result<(File, Vec<crate::pdf::Page>)> {
+                let mut spill = BufWriter::new(tempfile::tempfile()?);
+                let mut pages = vec![];
+                for (i, entry) in chunk.iter_mut().enumerate() {
+                    if lmrs::cancel::is_cancelled(cancel) {
+                        break;
+                    }
+                    let input = &mut entry.0;
+                    let mut json_data = entry.1.clone();
 
-                        json_data.imagePath = json_data.imagePath.replace('\\', "/");
-                        let image_path = json_data.imagePath.clone();
-                        let json_data = json_data.to_absolute_path(&json_dir);
-                        let mut data_w_img: LabelMeDataWImage =
-                            LabelMeDataWImage::try_from(json_data)
-                                .with_context(|| format!("load {}", image_path))?;
+                    json_data.imagePath = json_data.imagePath.replace('\\', "/");
+                    let json_data = json_data.to_absolute_path(&json_dir);
+                    let (image, file_size) = load_image(&json_data, input, &retry)?;
+                    let (width, height) = image.dimensions();
+                    let mut data_w_img = LabelMeDataWImage::new(json_data, image);
 
-                        if let Some(param) = resize_param.as_ref() {
-                            data_w_img.resize(param);
-                        }
+                    if let Some(param) = resize_param.as_ref() {
+                        data_w_img.resize(param);
+                    }
+                    if let Some(max_bytes) = svg_config.max_image_bytes {
+                        data_w_img.fit_encoded_size(max_bytes, svg_config.image_format.into())?;
+                    }
 
-                        let flags: Vec<_> = data_w_img
-                            .data
-                            .flags
-                            .iter()
-                            .filter(|(_k, v)| **v)
-                            .map(|(k, _v)| k.clone())
-                            .collect();
-                        let flags = flags.join(" ");
-                        let label_counts = data_w_img.data.count_labels();
-                        let title = label_counts
-                            .iter()
-                            .map(|(k, v)| format!("{k}:{v}"))
-                            .collect::<Vec<_>>()
-                            .join("\n");
-                        let document = data_w_img.data.to_svg(
-                            &label_colors,
-                            args.svg.radius,
-                            args.svg.line_width,
-                            &data_w_img.image,
-                        );
-                        let mut context = tera::Context::new();
-                        context.insert("tags", &flags);
-                        context.insert("flags", &flags);
-                        context.insert("title", &title);
-                        context.insert(
-                            "name",
-                            &input
-                                .file_stem()
-                                .context("Failed to get file_stem")?
-                                .to_string_lossy(),
-                        );
-                        context.insert("svg", &document.to_string());
-                        let fig = templates
-                            .render("img.html", &context)
-                            .expect("Failed to render img.html");
-                        let bar = shared_bar.lock().unwrap();
-                        bar.inc(1);
-                        Ok(fig)
-                    })
-                    .collect();
-                svgs
+                    let flags: Vec<_> = data_w_img
+                        .data
+                        .flags
+                        .iter()
+                        .filter(|(_k, v)| **v)
+                        .map(|(k, _v)| k.clone())
+                        .collect();
+                    let flags = flags.join(" ");
+                    let label_counts = data_w_img.data.count_labels();
+                    let title = label_counts
+                        .iter()
+                        .map(|(k, v)| format!("{k}:{v}"))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let image_data = svg_config
+                        .max_image_bytes
+                        .is_none()
+                        .then_some(data_w_img.data.imageData.as_deref())
+                        .flatten();
+                    let document = data_w_img.data.to_svg_with_image_data(
+                        &label_colors,
+                        svg_config.radius,
+                        svg_config.line_width,
+                        &data_w_img.image,
+                        image_data,
+                        &svg_options,
+                    );
+                    drop(data_w_img);
+                    let name = input
+                        .file_stem()
+                        .context("Failed to get file_stem")?
+                        .to_string_lossy();
+                    if let Some(options) = &raster_options {
+                        let caption = format!("{}  {}", name, title.replace('\n', " "));
+                        pages.push(crate::pdf::rasterize(
+                            &document.to_string(),
+                            caption,
+                            options,
+                            svg_config.jpeg_quality,
+                        )?);
+                        shared_bar.lock().unwrap().inc(1);
+                        continue;
+                    }
+                    let mut context = tera::Context::new();
+                    context.insert("tags", &flags);
+                    context.insert("flags", &flags);
+                    context.insert("title", &title);
+                    context.insert("width", &width);
+                    context.insert("height", &height);
+                    context.insert("bytes", &file_size);
+                    context.insert("size", &size_caption(width, height, file_size));
+                    context.insert("name", &name);
+                    if i > 0 {
+                        spill.write_all(b"\n")?;
+                    }
+                    write_figure(&mut spill, &templates, &mut context, document)?;
+                    let bar = shared_bar.lock().unwrap();
+                    bar.inc(1);
+                }
+                let mut spill = spill.into_inner()?;
+                spill.rewind()?;
+                Ok((spill, pages))
             }));
         }
         for handle in handles {
-            let results: Result<_> = handle.join().unwrap();
-            let mut results = results
+            let result: Result<_> = handle.join().unwrap();
+            let (spill, chunk_pages) = result
                 .or_else(|e| bail!("Failed to generate html: {}", e))
                 .unwrap();
-            svgs.append(&mut results);
+            spills.push(spill);
+            pages.extend(chunk_pages);
         }
     });
     {
-        shared_bar.lock().unwrap().finish();
+        let bar = shared_bar.lock().unwrap();
+        if lmrs::cancel::is_cancelled(cancel) {
+            bar.abandon();
+            // The output is not created until all the figures are rendered
+            return Err(lmrs::cancel::Cancelled {
+                completed: bar.position() as usize,
+                total: Some(entries.len()),
+            }
+            .into());
+        }
+        bar.finish();
     };
+    if args.pdf {
+        debug!("Write pdf");
+        return crate::pdf::write_pdf(&args.output, &pages);
+    }
     debug!("Generate html");
+    if svg_options.show_labels {
+        all_shapes.insert(labelme_rs::LABEL_TEXT_CLASS.to_string());
+    }
     let shape_toggles: std::result::Result<Vec<_>, _> = all_shapes
         .iter()
         .map(|shape| {
@@ -266,16 +369,212 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     } else {
         include_str!("templates/default.css").into()
     };
+    let assets_dir = match &args.external_assets {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(dir.join("catalog.css"), &style)?;
+            std::fs::write(dir.join("catalog.js"), SCRIPT)?;
+            Some(relative_url(&args.output, dir)?)
+        }
+        None => None,
+    };
+    context.insert("assets_dir", &assets_dir);
+    context.insert("script", SCRIPT);
     context.insert("title", &args.title);
     context.insert("legend", &legends?.join("\n"));
     context.insert("shape_toggles", &shape_toggles?.join("\n"));
     context.insert("tag_checkboxes", &tag_cbs?.join("\n"));
-    context.insert("main", &svgs.join("\n"));
+    context.insert("main", PLACEHOLDER);
     context.insert("style", &style);
     let html = templates.render("catalog.html", &context)?;
+    let (head, tail) = html
+        .split_once(PLACEHOLDER)
+        .context("Placeholder for main not found")?;
     debug!("Write html");
-    let mut writer = std::io::BufWriter::new(std::fs::File::create(args.output)?);
-    writer.write_all(html.as_bytes())?;
+    let mut writer = BufWriter::new(File::create(args.output)?);
+    writer.write_all(head.as_bytes())?;
+    for (i, mut spill) in spills.into_iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n")?;
+        }
+        std::io::copy(&mut spill, &mut writer)?;
+    }
+    writer.write_all(tail.as_bytes())?;
+    writer.flush()?;
     debug!("Done");
     Ok(())
 }
+
+/// Format the number of bytes in a human-readable form with SI prefixes. e.g. 2.1 MB
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    // Carry before rounding so that e.g. 999.95 KB is shown as 1.0 MB instead of 1000.0 KB
+    while value >= 999.95 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Caption of the image size. e.g. "4032×3024, 2.1 MB" or "256×256, embedded"
+fn size_caption(width: u32, height: u32, file_size: Option<u64>) -> String {
+    let size = file_size.map_or_else(|| "embedded".into(), format_bytes);
+    format!("{}×{}, {}", width, height, size)
+}
+
+/// Script of the catalog
+const SCRIPT: &str = include_str!("templates/catalog.js");
+
+/// Relative url of `dir` from the directory of `html`
+fn relative_url(html: &Path, dir: &Path) -> Result<String> {
+    let html_dir = html
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+    let dir = dir.canonicalize()?;
+    let common = html_dir
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let parts: Vec<String> =
+        std::iter::repeat_n("..".to_string(), html_dir.components().count() - common)
+            .chain(
+                dir.components()
+                    .skip(common)
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect();
+    Ok(if parts.is_empty() {
+        ".".into()
+    } else {
+        parts.join("/")
+    })
+}
+
+/// Placeholder to split a rendered template around the streamed content
+const PLACEHOLDER: &str = "\u{0}lmrs\u{0}";
+
+/// Write a figure rendered by `img.html` with `document` as its svg.
+///
+/// `document` is streamed into `writer` instead of being rendered to a string
+/// (`svg::write` renders the whole document to a string first) and dropped as soon as it is written.
+fn write_figure<W: Write>(
+    writer: &mut W,
+    templates: &tera::Tera,
+    context: &mut tera::Context,
+    document: labelme_rs::svg::Document,
+) -> Result<()> {
+    context.insert("svg", PLACEHOLDER);
+    let fig = templates.render("img.html", context)?;
+    let (prefix, suffix) = fig
+        .split_once(PLACEHOLDER)
+        .context("Placeholder for svg not found")?;
+    writer.write_all(prefix.as_bytes())?;
+    write!(writer, "{}", document)?;
+    drop(document);
+    writer.write_all(suffix.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use labelme_rs::image::{DynamicImage, RgbImage};
+    use labelme_rs::LabelMeData;
+
+    /// Writer recording the size of the largest single write
+    #[derive(Default)]
+    struct MaxWriteSize {
+        buf: Vec<u8>,
+        max: usize,
+    }
+
+    impl Write for MaxWriteSize {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.max = self.max.max(buf.len());
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_figure() -> Result<()> {
+        let mut templates = tera::Tera::default();
+        templates.autoescape_on(vec![]);
+        templates.add_raw_template("img.html", include_str!("templates/img.html"))?;
+        let data: LabelMeData = labelme_rs::serde_json::from_str(
+            r#"{"version":"5.0.1","flags":{},"shapes":[{"label":"L1","points":[[10,20]],"group_id":null,"shape_type":"point","flags":{}}],"imagePath":"img.jpg","imageData":null,"imageHeight":512,"imageWidth":512}"#,
+        )?;
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(512, 512, |x, y| {
+            labelme_rs::image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        }));
+        let document = data.to_svg(&LabelColorsHex::new(), 2, 2, &image);
+
+        let mut context = tera::Context::new();
+        context.insert("name", "img");
+        context.insert("tags", "");
+        context.insert("flags", "");
+        context.insert("title", "L1:1");
+        context.insert("width", &512);
+        context.insert("height", &512);
+        context.insert("bytes", &Some(1024));
+        context.insert("size", &size_caption(512, 512, Some(1024)));
+        context.insert("svg", &document.to_string());
+        let expected = templates.render("img.html", &context)?;
+
+        let mut writer = MaxWriteSize::default();
+        write_figure(&mut writer, &templates, &mut context, document)?;
+        assert_eq!(String::from_utf8(writer.buf)?, expected);
+        assert!(writer.max < expected.len());
+        assert!(expected.contains(r#"data-width="512" data-height="512" data-bytes="1024""#));
+        assert!(expected.contains("512×512, 1.0 KB"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_url() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let html = tmp_dir.path().join("out/catalog.html");
+        for dir in ["out", "out/assets/v1", "assets"] {
+            std::fs::create_dir_all(tmp_dir.path().join(dir))?;
+        }
+        assert_eq!(relative_url(&html, &tmp_dir.path().join("out"))?, ".");
+        assert_eq!(
+            relative_url(&html, &tmp_dir.path().join("out/assets/v1"))?,
+            "assets/v1"
+        );
+        assert_eq!(
+            relative_url(&html, &tmp_dir.path().join("assets"))?,
+            "../assets"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1000), "1.0 KB");
+        assert_eq!(format_bytes(999_949), "999.9 KB");
+        assert_eq!(format_bytes(999_950), "1.0 MB");
+        assert_eq!(format_bytes(2_100_000), "2.1 MB");
+        assert_eq!(format_bytes(1_000_000_000), "1.0 GB");
+        assert_eq!(format_bytes(5_000_000_000_000_000), "5000.0 TB");
+        assert_eq!(
+            size_caption(4032, 3024, Some(2_100_000)),
+            "4032×3024, 2.1 MB"
+        );
+        assert_eq!(size_caption(256, 256, None), "256×256, embedded");
+    }
+}
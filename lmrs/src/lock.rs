@@ -0,0 +1,243 @@
+//! Advisory directory locks and atomic writes for commands modifying files in place
+
+use labelme_rs::serde_json;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Name of the lock file created at the directory root
+pub const LOCK_FILENAME: &str = ".lmrs.lock";
+
+/// Locks older than this are considered stale
+pub const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Content of the lock file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub host: String,
+    /// Start time in seconds since the unix epoch
+    pub started: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("{path:?} is locked by pid {} on {} since {} (unix time). Add \"--force-lock\" if the lock is left behind", .info.pid, .info.host, .info.started)]
+    Held { path: PathBuf, info: LockInfo },
+    #[error("Failed to lock {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(unix)]
+fn is_process_alive(pid: u32) -> bool {
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(not(unix))]
+fn is_process_alive(_pid: u32) -> bool {
+    true
+}
+
+impl LockInfo {
+    /// Info of the current process
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+            started: now(),
+        }
+    }
+
+    /// Stale if older than [`MAX_AGE`] or the process on this host is gone
+    pub fn is_stale(&self) -> bool {
+        now().saturating_sub(self.started) > MAX_AGE.as_secs()
+            || (self.host == hostname() && !is_process_alive(self.pid))
+    }
+}
+
+/// Lock of a directory removed on drop, including unwinding by panic
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+}
+
+impl DirLock {
+    /// Create the lock file in `dir`.
+    ///
+    /// Fails if a fresh lock exists. Stale locks, unreadable locks, and any lock with `force` are replaced
+    pub fn acquire(dir: &Path, force: bool) -> Result<Self, LockError> {
+        let path = dir.join(LOCK_FILENAME);
+        let io_err = |source| LockError::Io {
+            path: path.clone(),
+            source,
+        };
+        loop {
+            match std::fs::File::options()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    let info = serde_json::to_string(&LockInfo::current()).unwrap();
+                    let lock = Self { path: path.clone() };
+                    file.write_all(info.as_bytes()).map_err(io_err)?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let info = std::fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|s| serde_json::from_str::<LockInfo>(&s).ok());
+                    match info {
+                        Some(info) if !force && !info.is_stale() => {
+                            return Err(LockError::Held { path, info })
+                        }
+                        Some(info) => {
+                            log::warn!("Removing the lock of pid {} on {}", info.pid, info.host)
+                        }
+                        None => log::warn!("Removing the unreadable lock {:?}", path),
+                    }
+                    match std::fs::remove_file(&path) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                            return Err(io_err(e))
+                        }
+                        _ => {}
+                    }
+                }
+                Err(e) => return Err(io_err(e)),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Write a file by renaming a temporary file in the same directory so that readers never see a partially written file
+pub fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut std::io::BufWriter<&std::fs::File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    // temporary files are only readable by the owner
+    match std::fs::metadata(path) {
+        Ok(metadata) => tmp.as_file().set_permissions(metadata.permissions())?,
+        #[cfg(unix)]
+        Err(_) => {
+            use std::os::unix::fs::PermissionsExt;
+            tmp.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o644))?
+        }
+        #[cfg(not(unix))]
+        Err(_) => {}
+    }
+    {
+        let mut writer = std::io::BufWriter::new(tmp.as_file());
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+    tmp.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_held() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let lock = DirLock::acquire(tmp_dir.path(), false)?;
+        assert!(lock.path().exists());
+        let err = DirLock::acquire(tmp_dir.path(), false).unwrap_err();
+        assert!(matches!(&err, LockError::Held { info, .. } if info.pid == std::process::id()));
+        drop(lock);
+        assert!(!tmp_dir.path().join(LOCK_FILENAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_stale_and_force() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join(LOCK_FILENAME);
+        // held by another live process
+        let held = LockInfo {
+            pid: std::process::id(),
+            host: "another-host".into(),
+            started: now(),
+        };
+        std::fs::write(&path, serde_json::to_string(&held)?)?;
+        assert!(DirLock::acquire(tmp_dir.path(), false).is_err());
+        let lock = DirLock::acquire(tmp_dir.path(), true)?;
+        let info: LockInfo = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        assert_eq!(info.host, hostname());
+        drop(lock);
+
+        let old = LockInfo {
+            started: now() - MAX_AGE.as_secs() - 1,
+            ..held
+        };
+        assert!(old.is_stale());
+        std::fs::write(&path, serde_json::to_string(&old)?)?;
+        drop(DirLock::acquire(tmp_dir.path(), false)?);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_removed_on_panic() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let result = std::panic::catch_unwind(|| {
+            let _lock = DirLock::acquire(tmp_dir.path(), false).unwrap();
+            panic!("induced panic while locked");
+        });
+        assert!(result.is_err());
+        assert!(!tmp_dir.path().join(LOCK_FILENAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_atomic() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("out.json");
+        write_atomic(&path, |w| Ok(writeln!(w, "{{}}")?))?;
+        assert_eq!(std::fs::read_to_string(&path)?, "{}\n");
+        assert!(write_atomic(&path, |_| anyhow::bail!("failed")).is_err());
+        assert_eq!(std::fs::read_to_string(&path)?, "{}\n");
+        assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::{bail, Context, Result};
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::serde_json;
+use serde::{Deserialize, Deserializer};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use lmrs::cli::{InputFormat, LsCmdArgs as CmdArgs};
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Deserialize, Debug)]
+struct ShapeLabel {
+    label: String,
+}
+
+/// Part of labelme data needed for listing. Other fields including `imageData` are skipped without allocation
+#[derive(Deserialize, Debug, Default)]
+struct Summary {
+    #[serde(default, deserialize_with = "null_as_default")]
+    flags: IndexMap<String, bool>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    shapes: Vec<ShapeLabel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SummaryLine {
+    filename: String,
+    content: Summary,
+}
+
+impl Summary {
+    /// Distinct labels sorted by name with their numbers of shapes
+    fn label_counts(&self) -> Vec<(&str, usize)> {
+        let mut counts: IndexMap<&str, usize> = IndexMap::new();
+        for shape in &self.shapes {
+            *counts.entry(shape.label.as_str()).or_insert(0) += 1;
+        }
+        counts.sort_keys();
+        counts.into_iter().collect()
+    }
+}
+
+/// Escape a field so that it is safe to be a cell.
+///
+/// `%`, the delimiter, tabs, line breaks, and commas (the separator of labels and flags) are percent-encoded
+/// (e.g. `a,b` -> `a%2Cb`). Other characters including spaces are kept as they are.
+fn escape(field: &str, delimiter: char) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        if matches!(c, '%' | '\t' | '\n' | '\r' | ',') || c == delimiter {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+struct RowWriter<W: Write> {
+    writer: W,
+    delimiter: char,
+    labels_only: bool,
+}
+
+impl<W: Write> RowWriter<W> {
+    fn write_cells(&mut self, cells: &[&str]) -> std::io::Result<()> {
+        let line: Vec<_> = cells.iter().map(|c| escape(c, self.delimiter)).collect();
+        writeln!(self.writer, "{}", line.join(&self.delimiter.to_string()))
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let delimiter = self.delimiter.to_string();
+        let header = if self.labels_only {
+            ["filename", "label", "count"].join(&delimiter)
+        } else {
+            ["filename", "shapes", "labels", "flags"].join(&delimiter)
+        };
+        writeln!(self.writer, "{}", header)
+    }
+
+    fn write(&mut self, filename: &str, summary: &Summary) -> std::io::Result<()> {
+        let label_counts = summary.label_counts();
+        if self.labels_only {
+            for (label, count) in label_counts {
+                let count = count.to_string();
+                self.write_cells(&[filename, label, &count])?;
+            }
+            return Ok(());
+        }
+        let join = |names: Vec<&str>, delimiter: char| -> String {
+            names
+                .into_iter()
+                .map(|name| escape(name, delimiter))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let labels = join(
+            label_counts.into_iter().map(|(l, _)| l).collect(),
+            self.delimiter,
+        );
+        let flags = join(
+            summary
+                .flags
+                .iter()
+                .filter_map(|(name, &state)| state.then_some(name.as_str()))
+                .collect(),
+            self.delimiter,
+        );
+        writeln!(
+            self.writer,
+            "{}",
+            [
+                escape(filename, self.delimiter),
+                summary.shapes.len().to_string(),
+                labels,
+                flags
+            ]
+            .join(&self.delimiter.to_string())
+        )
+    }
+}
+
+/// Call `f` for each (filename, summary) in the directory, json, ndjson file, tar archive, or stdin.
+/// Lines are not buffered and member paths are the filenames for tar archives
+fn for_each_summary(
+    input: &Path,
+    format: InputFormat,
+    mut f: impl FnMut(&str, &Summary) -> Result<()>,
+) -> Result<()> {
+    let resolved = format.resolve(input);
+    if resolved == Some(InputFormat::Dir) {
+        let pattern = input.join("*.json");
+        for entry in glob::glob(pattern.to_str().context("Failed to get glob")?)? {
+            let entry = entry?;
+            let summary: Summary = serde_json::from_str(&std::fs::read_to_string(&entry)?)
+                .with_context(|| format!("Parsing {:?}", entry))?;
+            f(&entry.file_name().unwrap().to_string_lossy(), &summary)?;
+        }
+    } else if resolved.is_none() && lmrs::tarball::is_tarball(input) {
+        // corrupt members are reported and skipped instead of aborting the whole archive
+        let mut failures = 0;
+        lmrs::tarball::for_each_json(input, |member, content| -> Result<()> {
+            match serde_json::from_slice::<Summary>(content) {
+                Ok(summary) => f(member, &summary)?,
+                Err(e) => {
+                    warn!("{}: {}", member, e);
+                    failures += 1;
+                }
+            }
+            Ok(())
+        })?;
+        if failures > 0 {
+            warn!("Failed to parse {} json member(s) of {:?}", failures, input);
+        }
+    } else if resolved == Some(InputFormat::Json) {
+        let summary: Summary = serde_json::from_str(&std::fs::read_to_string(input)?)
+            .with_context(|| format!("Parsing {:?}", input))?;
+        f(&input.to_string_lossy(), &summary)?;
+    } else if resolved == Some(InputFormat::Ndjson) {
+        let reader: Box<dyn BufRead> = if input.as_os_str() == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(input)?))
+        };
+        for (i, line) in reader.lines().enumerate() {
+            let line: SummaryLine =
+                serde_json::from_str(&line?).with_context(|| format!("Parsing line {}", i + 1))?;
+            f(&line.filename, &line.content)?;
+        }
+    } else {
+        bail!("Unknown input type: {:?}", input);
+    }
+    Ok(())
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let mut writer = RowWriter {
+        writer: std::io::BufWriter::new(std::io::stdout().lock()),
+        delimiter: args.delimiter,
+        labels_only: args.labels_only,
+    };
+    if args.header {
+        writer.write_header()?;
+    }
+    for_each_summary(&args.input, args.input_format, |filename, summary| {
+        Ok(writer.write(filename, summary)?)
+    })?;
+    writer.writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("big cat", '\t'), "big cat");
+        assert_eq!(escape("a\tb,c%", '\t'), "a%09b%2Cc%25");
+        assert_eq!(escape("a;b\n", ';'), "a%3Bb%0A");
+    }
+
+    #[test]
+    fn test_summary() -> Result<()> {
+        let summary: Summary = serde_json::from_str(
+            r#"{"flags":{"f1":true,"f2":false},"shapes":[{"label":"b","points":[]},{"label":"a"},{"label":"b"}],"imageData":"..."}"#,
+        )?;
+        assert_eq!(summary.label_counts(), vec![("a", 1), ("b", 2)]);
+        let summary: Summary = serde_json::from_str(r#"{"flags":null,"shapes":null}"#)?;
+        assert!(summary.shapes.is_empty());
+        Ok(())
+    }
+}
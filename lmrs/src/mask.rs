@@ -0,0 +1,22 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::coco::load_records;
+use lmrs::cli::MaskCmdArgs as CmdArgs;
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let label_values = labelme_rs::load_label_values(&args.labels)
+        .with_context(|| format!("Loading {:?}", args.labels))?;
+    std::fs::create_dir_all(&args.output)?;
+    for (data, filename) in load_records(&args.input)? {
+        let stem = Path::new(&filename)
+            .file_stem()
+            .context("Failed to obtain file stem")?
+            .to_string_lossy();
+        let outname = args.output.join(format!("{stem}.png"));
+        data.to_mask(&label_values)
+            .save(&outname)
+            .with_context(|| format!("Saving {:?}", outname))?;
+    }
+    Ok(())
+}
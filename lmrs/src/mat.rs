@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use labelme_rs::{serde_json, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use lmrs::cli::MatCmdArgs as CmdArgs;
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let quarter_turns = args
+        .rotate
+        .map(|degrees| degrees.rem_euclid(360.0))
+        .filter(|degrees| degrees % 90.0 == 0.0)
+        .map(|degrees| (degrees / 90.0) as u32);
+    if let Some(degrees) = args.rotate {
+        if quarter_turns.is_none() {
+            if args.image.is_some() {
+                bail!("\"--image\" requires a multiple of 90 degrees: {degrees}");
+            }
+            eprintln!("Warning: rotation by {degrees} degrees keeps imageWidth and imageHeight, and rectangles and circles may be distorted");
+        }
+    }
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    for line in reader.lines() {
+        let line = line?;
+        let mut lm_line: LabelMeDataLine =
+            serde_json::from_str(&line).with_context(|| format!("Processing line:{line}"))?;
+        match (&args.image, quarter_turns) {
+            (Some(image_dir), Some(quarter_turns)) => {
+                // imagePath is relative to the input file (or the current directory for stdin)
+                let data = lm_line.content.reset_image_path(&args.input)?;
+                let image_path = PathBuf::from(&data.imagePath);
+                let mut data_w_image: labelme_rs::LabelMeDataWImage = data
+                    .try_into()
+                    .with_context(|| format!("Opening {:?}", image_path))?;
+                data_w_image.rotate90(quarter_turns);
+                let outname = image_dir.join(image_path.file_name().unwrap_or_default());
+                data_w_image
+                    .image
+                    .save(&outname)
+                    .with_context(|| format!("Saving {:?}", outname))?;
+                lm_line.content = data_w_image.data;
+                lm_line.content.imagePath = outname
+                    .canonicalize()?
+                    .to_str()
+                    .context("Non UTF-8 image path")?
+                    .to_string();
+            }
+            _ => {
+                if let Some(degrees) = args.rotate {
+                    lm_line.content.rotate(degrees);
+                }
+            }
+        }
+        if let Some(flip) = args.flip {
+            lm_line.content.flip(flip.into());
+        }
+        if let Some(origin) = args.origin {
+            lm_line.content.shift_origin(origin.into());
+        }
+        if args.assert_in_bounds {
+            lmrs::ensure_in_bounds(&lm_line)?;
+        }
+        serde_json::to_writer(&mut writer, &lm_line)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
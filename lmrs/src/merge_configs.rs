@@ -0,0 +1,89 @@
+use anyhow::{bail, Context, Result};
+use labelme_rs::{indexmap::IndexMap, LabelColorsHex};
+use std::path::{Path, PathBuf};
+
+use lmrs::cli::MergeConfigsCmdArgs as CmdArgs;
+
+/// Merge `label_colors` of the configs in the given order.
+/// A label assigned different colors is an error unless `last_wins`, in which case the later color is used
+fn merge(configs: &[(PathBuf, LabelColorsHex)], last_wins: bool) -> Result<LabelColorsHex> {
+    // label -> (color, source filename)
+    let mut merged: IndexMap<&str, (&str, &Path)> = IndexMap::new();
+    for (filename, colors) in configs {
+        let mut labels: Vec<_> = colors.keys().collect();
+        labels.sort();
+        for label in labels {
+            let color = colors[label].as_str();
+            match merged.get(label.as_str()) {
+                Some((existing, source)) if *existing != color && !last_wins => bail!(
+                    "Conflicting colors for label {label:?}: {existing} in {source:?} and {color} in {filename:?}. Add \"--last-wins\" to use the latter."
+                ),
+                _ => {
+                    merged.insert(label, (color, filename));
+                }
+            }
+        }
+    }
+    Ok(merged
+        .into_iter()
+        .map(|(label, (color, _))| (label.to_string(), color.to_string()))
+        .collect())
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let configs = args
+        .input
+        .iter()
+        .map(|filename| {
+            labelme_rs::load_label_colors(filename)
+                .with_context(|| format!("Loading {:?}", filename))
+                .map(|colors| (filename.clone(), colors))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let merged = merge(&configs, args.last_wins)?;
+    let yaml = labelme_rs::label_colors_to_yaml(&merged)?;
+    match &args.output {
+        Some(output) => {
+            std::fs::write(output, yaml).with_context(|| format!("Writing to {:?}", output))?
+        }
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(entries: &[(&str, &str)]) -> LabelColorsHex {
+        entries
+            .iter()
+            .map(|(label, color)| (label.to_string(), color.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let configs = vec![
+            (
+                PathBuf::from("a.yaml"),
+                colors(&[("L1", "#FF0000"), ("L2", "#00FF00")]),
+            ),
+            (
+                PathBuf::from("b.yaml"),
+                colors(&[("L2", "#00FF00"), ("L3", "#0000FF")]),
+            ),
+        ];
+        let merged = merge(&configs, false)?;
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged["L2"], "#00FF00");
+
+        let mut configs = configs;
+        configs[1].1.insert("L1".into(), "#FFFFFF".into());
+        let err = merge(&configs, false).unwrap_err().to_string();
+        assert!(err.contains(r#""L1""#), "{}", err);
+        assert!(err.contains("b.yaml"), "{}", err);
+        assert_eq!(merge(&configs, true)?["L1"], "#FFFFFF");
+        Ok(())
+    }
+}
@@ -2,15 +2,24 @@ use anyhow::{bail, ensure, Context, Result};
 use labelme_rs::serde_json;
 use lmrs::cli::{NdjsonCmdArgs as CmdArgs, ParentHandling};
 use serde_json::{Map, Value};
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 #[cfg(not(target_os = "windows"))]
 extern crate libc;
 
-fn print_ndjson(input: PathBuf, key: &str, parent_handling: ParentHandling) -> Result<()> {
-    let json_str =
-        std::fs::read_to_string(&input).with_context(|| format!("Reading {:?}", input))?;
+fn print_ndjson(
+    writer: &mut impl Write,
+    input: PathBuf,
+    key: &str,
+    parent_handling: ParentHandling,
+    encoding: Option<&'static labelme_rs::encoding_rs::Encoding>,
+) -> Result<()> {
+    let json_str = std::fs::read(&input)
+        .map_err(labelme_rs::LabelMeDataError::from)
+        .and_then(|bytes| labelme_rs::decode_text(bytes, encoding))
+        .with_context(|| format!("Reading {:?}", input))?;
     let content: Map<String, Value> = serde_json::from_str(&json_str)?;
     let mut json_data: Map<String, Value> = Map::default();
     json_data.insert("content".into(), content.into());
@@ -25,11 +34,47 @@ fn print_ndjson(input: PathBuf, key: &str, parent_handling: ParentHandling) -> R
     };
     json_data.insert(key.to_string(), filename.into());
     let line = serde_json::to_string(&json_data)?;
-    println!("{line}");
+    writeln!(writer, "{line}")?;
     Ok(())
 }
 
+/// Open `output` to append lines.
+/// Fails if `output` is not an ndjson/jsonl file or does not contain json lines
+fn open_append(output: &Path) -> Result<File> {
+    ensure!(
+        output
+            .extension()
+            .is_some_and(|ext| ext == "ndjson" || ext == "jsonl"),
+        "Appending to non-ndjson file {:?}",
+        output
+    );
+    if output.exists() {
+        let reader = BufReader::new(File::open(output)?);
+        if let Some(line) = reader.lines().next() {
+            let line = line?;
+            ensure!(
+                serde_json::from_str::<Map<String, Value>>(&line).is_ok(),
+                "Appending to non-ndjson file {:?}",
+                output
+            );
+        }
+        let mut file = File::open(output)?;
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            ensure!(last[0] == b'\n', "{:?} does not end with a newline", output);
+        }
+    }
+    Ok(OpenOptions::new().create(true).append(true).open(output)?)
+}
+
 pub fn cmd(args: CmdArgs) -> Result<()> {
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(output) if args.append => Box::new(BufWriter::new(open_append(output)?)),
+        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
     for input in args.input {
         ensure!(input.exists(), "Input {:?} does not exist", input);
         let mut options = glob::MatchOptions::new();
@@ -45,22 +90,35 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             .expect("Failed to read glob pattern");
             for entry in entries {
                 let input = entry?;
-                print_ndjson(input, &args.filename, args.parent)?;
+                print_ndjson(
+                    &mut writer,
+                    input,
+                    &args.filename,
+                    args.parent,
+                    args.encoding,
+                )?;
             }
         } else if input
             .extension()
             .map(|ext| ext == "ndjson" || ext == "jsonl")
             .unwrap_or(false)
         {
-            let file = BufReader::new(std::fs::File::open(&input)?);
+            let file = BufReader::new(File::open(&input)?);
             for line in file.lines() {
-                println!("{}", line?);
+                writeln!(writer, "{}", line?)?;
             }
         } else if input.extension().map(|ext| ext == "json").unwrap_or(false) {
-            print_ndjson(input, &args.filename, args.parent)?;
+            print_ndjson(
+                &mut writer,
+                input,
+                &args.filename,
+                args.parent,
+                args.encoding,
+            )?;
         } else {
             bail!("{:?} is not a directory, json, or ndjson/jsonl", input);
         }
     }
+    writer.flush()?;
     Ok(())
 }
@@ -0,0 +1,31 @@
+use anyhow::{ensure, Result};
+use labelme_rs::{serde_json, LabelMeDataLine};
+use lmrs::cli::NormalizeCmdArgs as CmdArgs;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufReader, BufWriter};
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    for line in reader.lines() {
+        let line = line?;
+        let mut lm_line: LabelMeDataLine = line.as_str().try_into()?;
+        ensure!(
+            lm_line.content.imageWidth > 0 && lm_line.content.imageHeight > 0,
+            "{}: imageWidth and imageHeight should be positive",
+            lm_line.filename
+        );
+        if args.denormalize {
+            lm_line.content.denormalize();
+        } else {
+            lm_line.content.normalize();
+        }
+        let writer = BufWriter::new(stdout().lock());
+        serde_json::to_writer(writer, &lm_line)?;
+        println!();
+    }
+    Ok(())
+}
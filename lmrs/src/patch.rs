@@ -0,0 +1,169 @@
+//! JSON Patch (RFC 6902) style diff for previewing changes
+use labelme_rs::serde_json::{self, Value};
+use serde::Serialize;
+
+/// JSON Patch operation
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+/// Escape a key as a JSON pointer token
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Compute operations transforming `before` into `after`.
+///
+/// Arrays are compared element-wise by index: common indices are diffed recursively,
+/// trailing elements of `after` are added, and trailing elements of `before` are removed from the end.
+/// Thus, inserting an element at the front of an array results in replacing all the following elements.
+///
+/// ```
+/// use labelme_rs::serde_json::json;
+/// use lmrs::patch::{diff, PatchOp};
+/// let ops = diff(&json!({"a": 1, "b": [1]}), &json!({"a": 2, "b": [1, 2]}));
+/// assert_eq!(
+///     ops,
+///     vec![
+///         PatchOp::Replace { path: "/a".into(), value: json!(2) },
+///         PatchOp::Add { path: "/b/1".into(), value: json!(2) },
+///     ]
+/// );
+/// ```
+pub fn diff(before: &Value, after: &Value) -> Vec<PatchOp> {
+    let mut ops = vec![];
+    diff_at("", before, after, &mut ops);
+    ops
+}
+
+fn diff_at(path: &str, before: &Value, after: &Value, ops: &mut Vec<PatchOp>) {
+    match (before, after) {
+        (Value::Object(b), Value::Object(a)) => {
+            for (key, b_value) in b {
+                let child = format!("{}/{}", path, escape(key));
+                match a.get(key) {
+                    Some(a_value) => diff_at(&child, b_value, a_value, ops),
+                    None => ops.push(PatchOp::Remove { path: child }),
+                }
+            }
+            for (key, a_value) in a {
+                if !b.contains_key(key) {
+                    ops.push(PatchOp::Add {
+                        path: format!("{}/{}", path, escape(key)),
+                        value: a_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Array(b), Value::Array(a)) => {
+            for (i, (b_value, a_value)) in b.iter().zip(a).enumerate() {
+                diff_at(&format!("{}/{}", path, i), b_value, a_value, ops);
+            }
+            for (i, a_value) in a.iter().enumerate().skip(b.len()) {
+                ops.push(PatchOp::Add {
+                    path: format!("{}/{}", path, i),
+                    value: a_value.clone(),
+                });
+            }
+            for i in (a.len()..b.len()).rev() {
+                ops.push(PatchOp::Remove {
+                    path: format!("{}/{}", path, i),
+                });
+            }
+        }
+        (b, a) if b != a => ops.push(PatchOp::Replace {
+            path: path.to_string(),
+            value: a.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Patch of a file in the preview output
+#[derive(Serialize, Debug)]
+pub struct FilePatch<'a> {
+    pub filename: &'a str,
+    pub patch: Vec<PatchOp>,
+}
+
+/// Diff serializable `before` and `after` and return an ndjson line of [`FilePatch`] if anything changed
+pub fn preview_line<T: Serialize>(
+    filename: &str,
+    before: &T,
+    after: &T,
+) -> serde_json::Result<Option<String>> {
+    let patch = diff(
+        &serde_json::to_value(before)?,
+        &serde_json::to_value(after)?,
+    );
+    if patch.is_empty() {
+        Ok(None)
+    } else {
+        serde_json::to_string(&FilePatch { filename, patch }).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use labelme_rs::serde_json::json;
+
+    #[test]
+    fn test_diff_nested() {
+        let before = json!({
+            "flags": {"a": true, "b/c": false},
+            "shapes": [{"label": "L1", "points": [[0, 0], [1, 1]]}, {"label": "L2"}, {"label": "L3"}],
+            "removed": null,
+        });
+        let after = json!({
+            "flags": {"a": false, "b/c": false, "d~": true},
+            "shapes": [{"label": "L1", "points": [[0, 0], [2, 1]]}],
+        });
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                PatchOp::Replace {
+                    path: "/flags/a".into(),
+                    value: json!(false)
+                },
+                PatchOp::Add {
+                    path: "/flags/d~0".into(),
+                    value: json!(true)
+                },
+                PatchOp::Replace {
+                    path: "/shapes/0/points/1/0".into(),
+                    value: json!(2)
+                },
+                PatchOp::Remove {
+                    path: "/shapes/2".into()
+                },
+                PatchOp::Remove {
+                    path: "/shapes/1".into()
+                },
+                PatchOp::Remove {
+                    path: "/removed".into()
+                },
+            ]
+        );
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_diff_type_change() {
+        assert_eq!(
+            diff(&json!({"a": [1]}), &json!({"a": {"b": 1}})),
+            vec![PatchOp::Replace {
+                path: "/a".into(),
+                value: json!({"b": 1})
+            }]
+        );
+        assert_eq!(
+            serde_json::to_string(&diff(&json!(1), &json!(2))).unwrap(),
+            r#"[{"op":"replace","path":"","value":2}]"#
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Multi-page PDF of rasterized figures for `catalog --pdf`
+use anyhow::{Context, Result};
+use labelme_rs::image::{DynamicImage, RgbImage};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use resvg::{tiny_skia, usvg};
+use std::path::Path;
+
+/// Font size of the captions in points
+const CAPTION_FONT_SIZE: f32 = 12.0;
+/// Height of the caption area below the figure in points
+const CAPTION_HEIGHT: f32 = CAPTION_FONT_SIZE * 2.0;
+
+/// Figure of a page, which is a JPEG image and its caption
+pub(crate) struct Page {
+    width: u32,
+    height: u32,
+    jpeg: Vec<u8>,
+    caption: String,
+}
+
+/// Options to rasterize svgs. System fonts are loaded once to draw labels
+pub(crate) fn raster_options() -> usvg::Options<'static> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    options
+}
+
+/// Rasterize the svg on white background into a page
+pub(crate) fn rasterize(
+    svg: &str,
+    caption: String,
+    options: &usvg::Options,
+    jpeg_quality: Option<u8>,
+) -> Result<Page> {
+    let tree = usvg::Tree::from_str(svg, options).context("Parsing svg")?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .context("Failed to allocate an image for the svg")?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    // opaque after filling with white, so premultiplied colors are the colors as is
+    let rgb: Vec<u8> = pixmap
+        .data()
+        .chunks_exact(4)
+        .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+        .collect();
+    let image = RgbImage::from_raw(size.width(), size.height(), rgb)
+        .context("Failed to convert the rasterized svg")?;
+    let jpeg = labelme_rs::encode_image_with_quality(
+        &DynamicImage::ImageRgb8(image),
+        labelme_rs::image::ImageFormat::Jpeg,
+        jpeg_quality,
+    )?;
+    Ok(Page {
+        width: size.width(),
+        height: size.height(),
+        jpeg,
+        caption,
+    })
+}
+
+/// Write the pages into a PDF, one figure per page with the caption below it.
+/// One point of the page is one pixel of the figure.
+/// Captions are drawn in Helvetica, so characters out of ASCII are replaced with `?`
+pub(crate) fn write_pdf(filename: &Path, pages: &[Page]) -> Result<()> {
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    // page, image, and content of each page
+    let page_refs = |i: usize| {
+        let base = 4 + 3 * i as i32;
+        (Ref::new(base), Ref::new(base + 1), Ref::new(base + 2))
+    };
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids((0..pages.len()).map(|i| page_refs(i).0))
+        .count(pages.len() as i32);
+    pdf.type1_font(font_id).base_font(Name(b"Helvetica"));
+    for (i, page) in pages.iter().enumerate() {
+        let (page_id, image_id, content_id) = page_refs(i);
+        let (width, height) = (page.width as f32, page.height as f32);
+        let mut pdf_page = pdf.page(page_id);
+        pdf_page
+            .media_box(Rect::new(0.0, 0.0, width, height + CAPTION_HEIGHT))
+            .parent(tree_id)
+            .contents(content_id);
+        let mut resources = pdf_page.resources();
+        resources.x_objects().pair(Name(b"Im"), image_id);
+        resources.fonts().pair(Name(b"F"), font_id);
+        resources.finish();
+        pdf_page.finish();
+
+        let mut image = pdf.image_xobject(image_id, &page.jpeg);
+        image.filter(Filter::DctDecode);
+        image.width(page.width as i32);
+        image.height(page.height as i32);
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.finish();
+
+        let caption: String = page
+            .caption
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '?' })
+            .collect();
+        let mut content = Content::new();
+        content
+            .save_state()
+            .transform([width, 0.0, 0.0, height, 0.0, CAPTION_HEIGHT])
+            .x_object(Name(b"Im"))
+            .restore_state();
+        content
+            .begin_text()
+            .set_font(Name(b"F"), CAPTION_FONT_SIZE)
+            .next_line(CAPTION_FONT_SIZE / 2.0, CAPTION_FONT_SIZE / 2.0)
+            .show(Str(caption.as_bytes()))
+            .end_text();
+        pdf.stream(content_id, &content.finish());
+    }
+    std::fs::write(filename, pdf.finish()).with_context(|| format!("Writing {:?}", filename))?;
+    Ok(())
+}
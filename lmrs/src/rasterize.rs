@@ -0,0 +1,89 @@
+use anyhow::{ensure, Context, Result};
+use labelme_rs::image::GrayImage;
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::{LabelMeData, LabelMeDataLine};
+use lmrs::cli::RasterizeCmdArgs as CmdArgs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Fill the shapes of the labels as [`LabelMeData::to_mask`] does. Pixel values are the 1-based indices of the labels if `multi_class`, and 255 otherwise.
+/// All labels are filled with 255 if `labels` is empty
+pub(crate) fn rasterize(data: &LabelMeData, labels: &[String], multi_class: bool) -> GrayImage {
+    let mut label_values: IndexMap<String, u8> = IndexMap::new();
+    if labels.is_empty() {
+        for shape in &data.shapes {
+            label_values.insert(shape.label.clone(), 255);
+        }
+    } else {
+        for (i, label) in labels.iter().enumerate() {
+            let value = if multi_class { (i + 1) as u8 } else { 255 };
+            label_values.entry(label.clone()).or_insert(value);
+        }
+    }
+    data.to_mask(&label_values)
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    ensure!(
+        !args.multi_class || args.label.len() < 256,
+        "Too many labels for 8-bit masks: {}",
+        args.label.len()
+    );
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    std::fs::create_dir_all(&args.output_dir)?;
+    for line in reader.lines() {
+        let line = line?;
+        let lm_line: LabelMeDataLine = line.as_str().try_into()?;
+        let mask = rasterize(&lm_line.content, &args.label, args.multi_class);
+        let stem = Path::new(&lm_line.content.imagePath)
+            .file_stem()
+            .with_context(|| format!("{}: No imagePath", lm_line.filename))?
+            .to_string_lossy();
+        let outname = args.output_dir.join(format!("{stem}_mask.png"));
+        mask.save(&outname)
+            .with_context(|| format!("Saving {:?}", outname))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rasterize() {
+        let mut data = LabelMeData::new(&[(1.0, 1.0)], &["P".into()], 10, 8, "img.jpg");
+        data.shapes
+            .push(labelme_rs::Shape::rectangle((1.0, 1.0), (4.0, 3.0), "R"));
+        // closed explicitly and partially outside the image
+        data.shapes.push(labelme_rs::Shape::polygon(
+            vec![
+                (6.0, 4.0),
+                (12.0, 4.0),
+                (12.0, 10.0),
+                (6.0, 10.0),
+                (6.0, 4.0),
+            ],
+            "Q",
+        ));
+        let count = |mask: &GrayImage, value| mask.pixels().filter(|p| p.0[0] == value).count();
+
+        let mask = rasterize(&data, &[], false);
+        assert_eq!(mask.dimensions(), (10, 8));
+        assert_eq!(count(&mask, 255), 3 * 2 + 4 * 4);
+
+        let labels = vec!["Q".to_string(), "R".to_string()];
+        let mask = rasterize(&data, &labels, true);
+        assert_eq!(count(&mask, 1), 4 * 4);
+        assert_eq!(count(&mask, 2), 3 * 2);
+        assert_eq!(mask.get_pixel(1, 1).0[0], 2);
+
+        let mask = rasterize(&data, &labels[1..], false);
+        assert_eq!(count(&mask, 255), 3 * 2);
+    }
+}
@@ -32,7 +32,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     for line in reader.lines() {
         let line = line?;
         let json_data_line = remove_labels(&line, &args.label, args.invert)?;
-        serde_json::to_writer(writer.lock(), &json_data_line)?;
+        labelme_rs::integral::to_writer(writer.lock(), &json_data_line)?;
         println!();
     }
     Ok(())
@@ -51,7 +51,7 @@ mod tests {
             filename: name.to_string(),
             content: labelme_data?,
         };
-        let line = serde_json::to_string(&labelme_data_line)?;
+        let line = labelme_rs::integral::to_string(&labelme_data_line)?;
         Ok(line)
     }
 
@@ -0,0 +1,241 @@
+use anyhow::{bail, ensure, Context, Result};
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::{LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use lmrs::cli::{InputFormat, MissingHandling, RenameCmdArgs as CmdArgs};
+use regex::Regex;
+
+/// Mapping in csv (`old,new` without header) or yaml (`{old: new}`) decided by the extension
+fn load_map_file(filename: &Path) -> Result<IndexMap<String, String>> {
+    if filename.extension().is_some_and(|ext| ext == "csv") {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(filename)
+            .with_context(|| format!("Opening {:?}", filename))?;
+        let mut mapping = IndexMap::new();
+        for record in reader.deserialize() {
+            let (old, new): (String, String) =
+                record.with_context(|| format!("Parsing {:?}", filename))?;
+            mapping.insert(old, new);
+        }
+        Ok(mapping)
+    } else {
+        labelme_rs::load_label_mapping(filename).with_context(|| format!("Loading {:?}", filename))
+    }
+}
+
+/// Mapping of labels. Old labels are exact labels, or regular expressions matching whole labels
+struct Relabeler {
+    mapping: IndexMap<String, String>,
+    patterns: Option<Vec<Regex>>,
+}
+
+impl Relabeler {
+    fn new(mapping: IndexMap<String, String>, regex: bool) -> Result<Self> {
+        let patterns = regex
+            .then(|| {
+                mapping
+                    .keys()
+                    .map(|old| {
+                        Regex::new(&format!("^(?:{old})$"))
+                            .with_context(|| format!("Parsing regex {:?}", old))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+        Ok(Self { mapping, patterns })
+    }
+
+    /// Index of the first mapping matching `label` and the new label
+    fn relabel(&self, label: &str) -> Option<(usize, String)> {
+        match &self.patterns {
+            None => self
+                .mapping
+                .get_full(label)
+                .map(|(i, _, new)| (i, new.clone())),
+            Some(patterns) => patterns
+                .iter()
+                .position(|pattern| pattern.is_match(label))
+                .map(|i| {
+                    let new = patterns[i].replace(label, self.mapping[i].as_str());
+                    (i, new.into_owned())
+                }),
+        }
+    }
+}
+
+/// Rename labels of the shapes. Numbers of the renamed shapes are counted per mapping in `counts`
+fn rename_labels(data: &mut LabelMeData, relabeler: &Relabeler, counts: &mut [usize]) {
+    for shape in &mut data.shapes {
+        if let Some((i, new)) = relabeler.relabel(&shape.label) {
+            counts[i] += 1;
+            shape.label = new;
+        }
+    }
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let mut mapping = args
+        .map_file
+        .as_deref()
+        .map(load_map_file)
+        .transpose()?
+        .unwrap_or_default();
+    mapping.extend(args.map.iter().cloned());
+    let relabeler = Relabeler::new(mapping, args.regex)?;
+    let mut counts = vec![0; relabeler.mapping.len()];
+    // all inputs are renamed before writing so that `--missing exit` leaves the outputs untouched
+    let mut files: Vec<(PathBuf, LabelMeData)> = vec![];
+    let mut lines: Vec<LabelMeDataLine> = vec![];
+    let mut output_dir = None;
+    match InputFormat::Auto.resolve(&args.input) {
+        Some(InputFormat::Dir) => {
+            let output = args.output.clone().unwrap_or_else(|| args.input.clone());
+            debug!("Directory input");
+            ensure!(
+                output.is_dir(),
+                "Output directory \"{}\" does not exist.",
+                output.to_string_lossy()
+            );
+            let entries: Vec<_> = glob::glob(
+                args.input
+                    .join("*.json")
+                    .to_str()
+                    .context("Failed to get glob")?,
+            )
+            .expect("Failed to read glob pattern")
+            .collect();
+            for entry in entries {
+                let input = entry?;
+                let mut lm_data = LabelMeData::try_from(input.as_path())?;
+                rename_labels(&mut lm_data, &relabeler, &mut counts);
+                let output = output.join(input.file_name().context("Failed to obtain filename")?);
+                files.push((output, lm_data));
+            }
+            output_dir = Some(output);
+        }
+        Some(InputFormat::Json) => {
+            let mut lm_data = LabelMeData::try_from(args.input.as_path())?;
+            rename_labels(&mut lm_data, &relabeler, &mut counts);
+            let output = args.output.clone().unwrap_or_else(|| args.input.clone());
+            files.push((output, lm_data));
+        }
+        Some(InputFormat::Ndjson) => {
+            let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+                Box::new(BufReader::new(std::io::stdin()))
+            } else {
+                Box::new(BufReader::new(File::open(&args.input)?))
+            };
+            for line in reader.lines() {
+                let line = line?;
+                let mut lm_data_line = LabelMeDataLine::try_from(line.as_str())?;
+                rename_labels(&mut lm_data_line.content, &relabeler, &mut counts);
+                lines.push(lm_data_line);
+            }
+        }
+        Some(InputFormat::Auto) | None => bail!("Unknown input type: {:?}", args.input),
+    }
+
+    for ((old, new), count) in relabeler.mapping.iter().zip(&counts) {
+        eprintln!("{old} -> {new}: {count}");
+    }
+    let missing: Vec<_> = relabeler
+        .mapping
+        .keys()
+        .zip(&counts)
+        .filter_map(|(old, count)| (*count == 0).then_some(old))
+        .collect();
+    let missing_handling = if args.strict {
+        MissingHandling::Exit
+    } else {
+        args.missing
+    };
+    if !missing.is_empty() {
+        match missing_handling {
+            MissingHandling::Exit => bail!("Labels not found in the inputs: {:?}", missing),
+            MissingHandling::Warn => warn!("Labels not found in the inputs: {:?}", missing),
+            MissingHandling::Continue => {}
+        }
+    }
+
+    let _lock = output_dir
+        .map(|output| lmrs::lock::DirLock::acquire(&output, args.lock.force_lock))
+        .transpose()?;
+    for (output, lm_data) in files {
+        let content = labelme_rs::integral::to_string_pretty(&lm_data)?;
+        lmrs::lock::write_atomic(&output, |writer| Ok(writeln!(writer, "{}", content)?))?;
+    }
+    if !lines.is_empty() {
+        let mut writer: Box<dyn Write> = match &args.output {
+            Some(x) if x.as_os_str() != "-" => Box::new(BufWriter::new(File::create(x)?)),
+            _ => Box::new(BufWriter::new(std::io::stdout())),
+        };
+        for lm_data_line in lines {
+            writeln!(
+                writer,
+                "{}",
+                labelme_rs::integral::to_string(&lm_data_line)?
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_labels() {
+        let mut data = LabelMeData::new(
+            &[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)],
+            &["TL".into(), "TR".into(), "TL".into()],
+            4,
+            4,
+            "img.jpg",
+        );
+        let mapping = IndexMap::from([
+            ("TL".to_string(), "top_left".to_string()),
+            ("BL".to_string(), "bottom_left".to_string()),
+        ]);
+        let relabeler = Relabeler::new(mapping, false).unwrap();
+        let mut counts = vec![0; 2];
+        rename_labels(&mut data, &relabeler, &mut counts);
+        let labels: Vec<_> = data.shapes.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, vec!["top_left", "TR", "top_left"]);
+        assert_eq!(counts, vec![2, 0]);
+    }
+
+    #[test]
+    fn test_rename_labels_regex() {
+        let mut data = LabelMeData::new(
+            &[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)],
+            &[
+                "car_1".into(),
+                "car_12".into(),
+                "cart".into(),
+                "bus_3".into(),
+            ],
+            4,
+            4,
+            "img.jpg",
+        );
+        let mapping = IndexMap::from([
+            ("car_(\\d+)".to_string(), "vehicle_${1}".to_string()),
+            ("car.*|bus_.*".to_string(), "other".to_string()),
+            ("truck".to_string(), "vehicle".to_string()),
+        ]);
+        let relabeler = Relabeler::new(mapping, true).unwrap();
+        let mut counts = vec![0; 3];
+        rename_labels(&mut data, &relabeler, &mut counts);
+        let labels: Vec<_> = data.shapes.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, vec!["vehicle_1", "vehicle_12", "other", "other"]);
+        assert_eq!(counts, vec![2, 2, 0]);
+
+        let mapping = IndexMap::from([("car(".to_string(), "x".to_string())]);
+        assert!(Relabeler::new(mapping, true).is_err());
+    }
+}
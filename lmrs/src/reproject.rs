@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use labelme_rs::homography::{self, Homography};
+use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use lmrs::cli::ReprojectCmdArgs as CmdArgs;
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let source = LabelMeData::try_from(args.source.as_path())
+        .with_context(|| format!("Loading {:?}", args.source))?;
+    let target = LabelMeData::try_from(args.target.as_path())
+        .with_context(|| format!("Loading {:?}", args.target))?;
+    let h = Homography::from_point_labels(&source, &target).with_context(|| {
+        format!(
+            "Estimating homography from {:?} to {:?}",
+            args.source, args.target
+        )
+    })?;
+    debug!("Homography: {:?}", h);
+    let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.input)?))
+    };
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    for line in reader.lines() {
+        let line = line?;
+        let mut lm_line: LabelMeDataLine =
+            serde_json::from_str(&line).with_context(|| format!("Processing line:{line}"))?;
+        homography::reproject(&mut lm_line.content, &h);
+        lm_line.content.imageWidth = target.imageWidth;
+        lm_line.content.imageHeight = target.imageHeight;
+        if args.assert_in_bounds {
+            lmrs::ensure_in_bounds(&lm_line)?;
+        }
+        serde_json::to_writer(&mut writer, &lm_line)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
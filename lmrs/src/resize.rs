@@ -12,7 +12,15 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         Box::new(BufReader::new(File::open(&args.input)?))
     };
     let resize_param = ResizeParam::try_from(args.param.as_str())?;
-    for line in reader.lines() {
+    let cancel = lmrs::cancel::flag();
+    for (i, line) in reader.lines().enumerate() {
+        if lmrs::cancel::is_cancelled(cancel) {
+            return Err(lmrs::cancel::Cancelled {
+                completed: i,
+                total: None,
+            }
+            .into());
+        }
         let line = line?;
         let mut lm_line: LabelMeDataLine = line.as_str().try_into()?;
         let scale = resize_param.scale(
@@ -20,6 +28,9 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             lm_line.content.imageHeight as u32,
         );
         lm_line.content.scale(scale);
+        if args.assert_in_bounds {
+            lmrs::ensure_in_bounds(&lm_line)?;
+        }
         let writer = BufWriter::new(stdout().lock());
         serde_json::to_writer(writer, &lm_line)?;
         println!();
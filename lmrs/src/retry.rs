@@ -0,0 +1,172 @@
+//! Retry for transient IO errors on network-mounted inputs
+
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+/// Errors that may succeed when retried
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for std::io::Error {
+    /// Errors other than the ones caused by the path or the content itself
+    fn is_transient(&self) -> bool {
+        use std::io::ErrorKind::*;
+        !matches!(
+            self.kind(),
+            NotFound | PermissionDenied | AlreadyExists | InvalidInput | InvalidData | Unsupported
+        )
+    }
+}
+
+impl Transient for labelme_rs::ImageError {
+    fn is_transient(&self) -> bool {
+        match self {
+            labelme_rs::ImageError::ImageError(labelme_rs::image::ImageError::IoError(e)) => {
+                e.is_transient()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Upper bound of the wait between retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Retry policy with exponential backoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt
+    pub retries: usize,
+    /// Wait before the first retry. Doubled for each retry up to [`MAX_BACKOFF`]
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// No retry
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(retries: usize, backoff: Duration) -> Self {
+        Self { retries, backoff }
+    }
+
+    /// Call `f` until it succeeds, fails with a non-transient error, or the retries are exhausted
+    ///
+    /// Arguments:
+    /// - `target`: Name of the target (e.g. filename) for logging
+    /// - `f`: Operation to retry
+    pub fn run<T, E, F>(&self, target: impl Display, mut f: F) -> Result<T, E>
+    where
+        E: Transient + Display,
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt < self.retries && e.is_transient() => {
+                    attempt += 1;
+                    log::warn!(
+                        "{target}: {e}. Retrying in {backoff:?} ({attempt}/{})",
+                        self.retries
+                    );
+                    std::thread::sleep(backoff);
+                    backoff = next_backoff(backoff);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// [`std::fs::read_to_string`] with retries
+    pub fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        self.run(path.display(), || std::fs::read_to_string(path))
+    }
+}
+
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ErrorKind, Read};
+
+    /// Reader failing for the first `failures` reads
+    struct FlakyReader<'a> {
+        failures: usize,
+        data: &'a [u8],
+    }
+
+    impl Read for FlakyReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "stale file handle",
+                ));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_retry() {
+        let policy = RetryPolicy::new(2, Duration::from_millis(1));
+        let mut reader = FlakyReader {
+            failures: 2,
+            data: b"{}",
+        };
+        let mut attempts = 0;
+        let content = policy.run("flaky", || {
+            attempts += 1;
+            let mut s = String::new();
+            reader.read_to_string(&mut s).map(|_| s)
+        });
+        assert_eq!(content.unwrap(), "{}");
+        assert_eq!(attempts, 3);
+
+        let mut reader = FlakyReader {
+            failures: 3,
+            data: b"{}",
+        };
+        let content = policy.run("flaky", || {
+            let mut s = String::new();
+            reader.read_to_string(&mut s).map(|_| s)
+        });
+        assert_eq!(content.unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        assert_eq!(
+            next_backoff(Duration::from_millis(500)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(next_backoff(Duration::from_secs(40)), MAX_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_no_retry_for_missing_file() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let mut attempts = 0;
+        let result = policy.run("missing", || {
+            attempts += 1;
+            std::fs::read_to_string("does/not/exist.json")
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
+}
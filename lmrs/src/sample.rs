@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use labelme_rs::serde_json;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use lmrs::cli::SampleCmdArgs as CmdArgs;
+
+/// Split `k` into the quotas of strata of `sizes` in proportion to the sizes.
+/// Remainders are given to the strata with the largest fractional parts
+fn quotas(sizes: &[usize], k: usize) -> Vec<usize> {
+    let total: usize = sizes.iter().sum();
+    if total == 0 {
+        return vec![0; sizes.len()];
+    }
+    let exact: Vec<f64> = sizes
+        .iter()
+        .map(|size| (k * size) as f64 / total as f64)
+        .collect();
+    let mut quotas: Vec<usize> = exact.iter().map(|e| e.floor() as usize).collect();
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| {
+        (exact[*b] - exact[*b].floor()).total_cmp(&(exact[*a] - exact[*a].floor()))
+    });
+    let remainder = k - quotas.iter().sum::<usize>();
+    for i in order.into_iter().take(remainder) {
+        quotas[i] += 1;
+    }
+    quotas
+}
+
+/// Indices of `k` records sampled without replacement in the input order.
+/// `strata` are the groups sampled separately in proportion to their sizes
+fn sample_indices(n: usize, k: usize, strata: Option<&[bool]>, rng: &mut StdRng) -> Vec<usize> {
+    let groups: Vec<Vec<usize>> = match strata {
+        None => vec![(0..n).collect()],
+        Some(strata) => [true, false]
+            .iter()
+            .map(|stratum| (0..n).filter(|i| strata[*i] == *stratum).collect())
+            .collect(),
+    };
+    let sizes: Vec<usize> = groups.iter().map(Vec::len).collect();
+    let mut indices = vec![];
+    for (mut group, quota) in groups.into_iter().zip(quotas(&sizes, k.min(n))) {
+        group.shuffle(rng);
+        indices.extend_from_slice(&group[..quota]);
+    }
+    indices.sort_unstable();
+    indices
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn BufRead> = match &args.input {
+        None => Box::new(BufReader::new(std::io::stdin())),
+        Some(filename) => Box::new(BufReader::new(
+            File::open(filename).with_context(|| format!("Opening {:?}", filename))?,
+        )),
+    };
+    let mut lines = vec![];
+    let mut strata = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(flag) = &args.stratify {
+            let value: serde_json::Value = serde_json::from_str(&line)?;
+            strata.push(value["content"]["flags"][flag].as_bool().unwrap_or(false));
+        }
+        lines.push(line);
+    }
+    let k = match (args.count, args.fraction) {
+        (Some(count), _) => count,
+        (None, Some(fraction)) => (fraction * lines.len() as f64).round() as usize,
+        (None, None) => unreachable!("Either --count or --fraction is required"),
+    };
+    if k > lines.len() {
+        warn!(
+            "Requested {} records but the input has only {}",
+            k,
+            lines.len()
+        );
+    }
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let indices = sample_indices(
+        lines.len(),
+        k,
+        args.stratify.as_ref().map(|_| strata.as_slice()),
+        &mut rng,
+    );
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    for i in indices {
+        writeln!(writer, "{}", lines[i])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotas() {
+        assert_eq!(quotas(&[10, 90], 10), vec![1, 9]);
+        assert_eq!(quotas(&[1, 2], 2), vec![1, 1]);
+        assert_eq!(quotas(&[3, 3, 3], 4).iter().sum::<usize>(), 4);
+        assert_eq!(quotas(&[0, 0], 3), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_sample_indices() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let indices = sample_indices(100, 10, None, &mut rng);
+        assert_eq!(indices.len(), 10);
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            indices,
+            sample_indices(100, 10, None, &mut StdRng::seed_from_u64(42))
+        );
+        assert_eq!(sample_indices(5, 10, None, &mut rng), vec![0, 1, 2, 3, 4]);
+
+        let strata: Vec<bool> = (0..100).map(|i| i % 5 == 0).collect();
+        let indices = sample_indices(100, 20, Some(&strata), &mut rng);
+        assert_eq!(indices.len(), 20);
+        assert_eq!(indices.iter().filter(|i| strata[**i]).count(), 4);
+    }
+}
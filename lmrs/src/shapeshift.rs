@@ -33,7 +33,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         let mut json_data_line: labelme_rs::LabelMeDataLine =
             serde_json::from_str(&line).with_context(|| format!("Processing line:{line}"))?;
         change_shape(&mut json_data_line, &args.reshape);
-        serde_json::to_writer(writer.lock(), &json_data_line)?;
+        labelme_rs::integral::to_writer(writer.lock(), &json_data_line)?;
         println!();
     }
     Ok(())
@@ -55,7 +55,7 @@ mod tests {
             filename: name.to_string(),
             content: labelme_data?,
         };
-        let line = serde_json::to_string(&labelme_data_line)?;
+        let line = labelme_rs::integral::to_string(&labelme_data_line)?;
         Ok(line)
     }
 
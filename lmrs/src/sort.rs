@@ -1,10 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use labelme_rs::indexmap::IndexMap;
-use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine, Shape};
+use labelme_rs::{LabelMeData, LabelMeDataLine, Shape};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-use lmrs::cli::SortCmdArgs as CmdArgs;
+use lmrs::cli::{InputFormat, SortCmdArgs as CmdArgs};
 
 /// Collection of shape_type -> shape_label -> shapes
 #[derive(Debug)]
@@ -110,9 +110,10 @@ fn process_data(
 }
 
 pub fn cmd(args: CmdArgs) -> Result<()> {
-    if args.input.extension().is_some_and(|ext| ext == "json") {
-        let reader = BufReader::new(File::open(&args.input)?);
-        let data: LabelMeData = serde_json::from_reader(reader)?;
+    let format = args.input_format.resolve(&args.input);
+    if format == Some(InputFormat::Json) {
+        let data = LabelMeData::from_path_with_encoding(&args.input, args.encoding)
+            .with_context(|| format!("Reading {:?}", args.input))?;
         let sorted_data = process_data(
             data,
             args.by_x,
@@ -122,13 +123,8 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             &args.labels,
             args.invert_label_matching,
         );
-        println!("{}", serde_json::to_string_pretty(&sorted_data)?);
-    } else if args.input.as_os_str() == "-"
-        || args
-            .input
-            .extension()
-            .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
-    {
+        println!("{}", labelme_rs::integral::to_string_pretty(&sorted_data)?);
+    } else if format == Some(InputFormat::Ndjson) {
         // jsonl or ndjson
         let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
             Box::new(BufReader::new(std::io::stdin()))
@@ -151,7 +147,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
                 content: sorted_data,
                 ..lm_data_line
             };
-            println!("{}", serde_json::to_string(&sorted_data_line)?);
+            println!("{}", labelme_rs::integral::to_string(&sorted_data_line)?);
         }
     } else {
         panic!("Unknown input type: {:?}", args.input);
@@ -1,41 +1,259 @@
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, Context, Result};
 use labelme_rs::serde_json;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
 
-use lmrs::cli::SplitCmdArgs as CmdArgs;
+use lmrs::cli::{SplitCmdArgs as CmdArgs, SplitIfExists};
 
 pub fn cmd(args: CmdArgs) -> Result<()> {
-    let reader: Box<dyn BufRead> = match args.input {
+    let reader: Box<dyn BufRead> = match &args.input {
         None => Box::new(BufReader::new(std::io::stdin())),
         Some(filename) => Box::new(BufReader::new(
-            File::open(&filename).with_context(|| format!("Opening {:?}", filename))?,
+            File::open(filename).with_context(|| format!("Opening {:?}", filename))?,
         )),
     };
-    let outdir = args.output.unwrap_or_default();
-    for line in reader.lines() {
-        let json_data: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line?)?;
+    if let Some(route) = Route::from_args(&args) {
+        return route_records(reader, &args, &route, lmrs::cancel::flag());
+    }
+    let counts = split(reader, &args, lmrs::cancel::flag())?;
+    if matches!(args.if_exists, SplitIfExists::Skip | SplitIfExists::Update) {
+        eprintln!(
+            "written: {}, skipped: {}, updated: {}",
+            counts.written, counts.skipped, counts.updated
+        );
+    }
+    Ok(())
+}
+
+/// Numbers of output files by outcome
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SplitCounts {
+    written: usize,
+    skipped: usize,
+    updated: usize,
+}
+
+/// Check if the existing file holds `content`. Unreadable or invalid json is considered different
+fn is_same_content(filename: &Path, content: &serde_json::Value) -> bool {
+    std::fs::read_to_string(filename)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+        .is_some_and(|existing| &existing == content)
+}
+
+/// Split lines into files. Each file is written entirely, so the files written before cancellation are complete
+fn split(reader: impl BufRead, args: &CmdArgs, cancel: &AtomicBool) -> Result<SplitCounts> {
+    let outdir = args.output.clone().unwrap_or_default();
+    let if_exists = if args.overwrite {
+        SplitIfExists::Overwrite
+    } else {
+        args.if_exists
+    };
+    let mut counts = SplitCounts::default();
+    for (index, line) in reader.lines().enumerate() {
+        if lmrs::cancel::is_cancelled(cancel) {
+            return Err(lmrs::cancel::Cancelled {
+                completed: index,
+                total: None,
+            }
+            .into());
+        }
+        let mut json_data: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&line?)?;
         let v_filename = json_data
             .get(&args.filename)
             .with_context(|| format!("Key {} not found", &args.filename))?;
         let serde_json::Value::String(filename) = v_filename else {
-            panic!("expected String")
+            bail!(
+                "Value for {} should be string. {} found",
+                &args.filename,
+                v_filename
+            )
         };
-        let output_filename = match args.parent {
-            lmrs::cli::SplitParentHandling::Keep => outdir.join(filename),
-            lmrs::cli::SplitParentHandling::Ignore => {
-                outdir.join(std::path::Path::new(&filename).file_name().unwrap())
-            }
+        let output_filename = match (&args.pattern, args.parent) {
+            (Some(pattern), _) => outdir.join(pattern.render(filename, index)),
+            (None, lmrs::cli::SplitParentHandling::Keep) => outdir.join(filename),
+            (None, lmrs::cli::SplitParentHandling::Ignore) => outdir.join(
+                Path::new(&filename)
+                    .file_name()
+                    .with_context(|| format!("Failed to get file_name: {}", filename))?,
+            ),
         };
-        if !args.overwrite {
-            ensure!(!output_filename.exists(),
-            "Output file {output_filename:?} already exists. Add \"--overwrite\" option to force overwriting.");
+        let content = json_data
+            .remove(&args.content)
+            .unwrap_or(serde_json::Value::Null);
+        if output_filename.exists() {
+            match if_exists {
+                SplitIfExists::Error => bail!(
+                    "Output file {output_filename:?} already exists. Add \"--overwrite\" option to force overwriting."
+                ),
+                SplitIfExists::Skip => {
+                    counts.skipped += 1;
+                    continue;
+                }
+                SplitIfExists::Update if is_same_content(&output_filename, &content) => {
+                    counts.skipped += 1;
+                    continue;
+                }
+                SplitIfExists::Overwrite | SplitIfExists::Update => counts.updated += 1,
+            }
+        } else {
+            counts.written += 1;
         }
         let writer = std::io::BufWriter::new(
             std::fs::File::create(&output_filename)
                 .with_context(|| format!("Writing to {:?}", output_filename))?,
         );
-        serde_json::to_writer_pretty(writer, &json_data.get(&args.content))?;
+        serde_json::to_writer_pretty(writer, &content)?;
+    }
+    Ok(counts)
+}
+
+/// Key to route records by
+#[derive(Debug)]
+enum Route<'a> {
+    Flag(&'a str),
+    Label(&'a str),
+}
+
+impl<'a> Route<'a> {
+    fn from_args(args: &'a CmdArgs) -> Option<Self> {
+        match (&args.by_flag, &args.by_label) {
+            (Some(flag), _) => Some(Route::Flag(flag)),
+            (None, Some(label)) => Some(Route::Label(label)),
+            (None, None) => None,
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Route::Flag(key) | Route::Label(key) => key,
+        }
+    }
+
+    /// Check if the labelme content has the flag set to true or a shape with the label
+    fn matches(&self, content: &serde_json::Value) -> bool {
+        match self {
+            Route::Flag(flag) => content["flags"][flag].as_bool().unwrap_or(false),
+            Route::Label(label) => content["shapes"]
+                .as_array()
+                .is_some_and(|shapes| shapes.iter().any(|s| s["label"] == *label)),
+        }
+    }
+}
+
+/// Write each line into `<key>.ndjson` or `not_<key>.ndjson` depending on whether the content matches `route`.
+/// Both files are created even if no line is routed to them
+fn route_records(
+    reader: impl BufRead,
+    args: &CmdArgs,
+    route: &Route,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let outdir = args.output.clone().unwrap_or_default();
+    let overwrite = match (args.overwrite, args.if_exists) {
+        (true, _) | (_, SplitIfExists::Overwrite) => true,
+        (_, SplitIfExists::Error) => false,
+        (_, SplitIfExists::Skip | SplitIfExists::Update) => {
+            bail!("Only \"--if-exists error\" and \"--if-exists overwrite\" are supported with \"--by-flag\" and \"--by-label\"")
+        }
+    };
+    let filenames = [
+        outdir.join(format!("{}.ndjson", route.key())),
+        outdir.join(format!("not_{}.ndjson", route.key())),
+    ];
+    if !overwrite {
+        if let Some(existing) = filenames.iter().find(|f| f.exists()) {
+            bail!(
+                "Output file {existing:?} already exists. Add \"--overwrite\" option to force overwriting."
+            );
+        }
+    }
+    let mut writers = filenames
+        .iter()
+        .map(|filename| {
+            Ok(std::io::BufWriter::new(
+                File::create(filename).with_context(|| format!("Writing to {:?}", filename))?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (index, line) in reader.lines().enumerate() {
+        if lmrs::cancel::is_cancelled(cancel) {
+            return Err(lmrs::cancel::Cancelled {
+                completed: index,
+                total: None,
+            }
+            .into());
+        }
+        let line = line?;
+        let json_data: serde_json::Value = serde_json::from_str(&line)?;
+        let writer = if route.matches(&json_data[&args.content]) {
+            &mut writers[0]
+        } else {
+            &mut writers[1]
+        };
+        writeln!(writer, "{}", line)?;
+    }
+    for mut writer in writers {
+        writer.flush()?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::atomic::Ordering;
+
+    /// Reader setting the flag when it is read
+    struct CancelOnRead<'a>(&'a AtomicBool);
+
+    impl Read for CancelOnRead<'_> {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_split_cancel() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let cancel = AtomicBool::new(false);
+        let lines =
+            "{\"filename\":\"1.json\",\"content\":{}}\n{\"filename\":\"2.json\",\"content\":{}}\n";
+        // The second line is read after the flag is set
+        let (first, second) = lines.split_at(lines.find('\n').unwrap() + 1);
+        let reader = BufReader::new(
+            first
+                .as_bytes()
+                .chain(CancelOnRead(&cancel))
+                .chain(second.as_bytes()),
+        );
+        let args = CmdArgs {
+            input: None,
+            output: Some(tmp_dir.path().into()),
+            filename: "filename".into(),
+            content: "content".into(),
+            overwrite: false,
+            if_exists: SplitIfExists::Error,
+            parent: lmrs::cli::SplitParentHandling::Keep,
+            pattern: None,
+            by_flag: None,
+            by_label: None,
+        };
+        let err = split(reader, &args, &cancel).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<lmrs::cancel::Cancelled>(),
+            Some(&lmrs::cancel::Cancelled {
+                completed: 1,
+                total: None
+            })
+        );
+        assert!(tmp_dir.path().join("1.json").exists());
+        assert!(!tmp_dir.path().join("2.json").exists());
+        Ok(())
+    }
+}
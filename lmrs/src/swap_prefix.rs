@@ -1,22 +1,20 @@
 use anyhow::{ensure, Context, Result};
-use labelme_rs::{serde_json, LabelMeData, LabelMeDataLine};
+use labelme_rs::{LabelMeData, LabelMeDataLine};
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 
-use lmrs::cli::SwapCmdArgs as CmdArgs;
+use lmrs::cli::{InputFormat, SwapCmdArgs as CmdArgs};
 
 fn swap_prefix_file(input: &Path, prefix: &str, output: &Path, pretty: bool) -> Result<()> {
     let mut lm_data = LabelMeData::try_from(input)?;
     lm_data.swap_prefix(prefix)?;
     let line = if pretty {
-        serde_json::to_string_pretty(&lm_data)?
+        labelme_rs::integral::to_string_pretty(&lm_data)?
     } else {
-        serde_json::to_string(&lm_data)?
+        labelme_rs::integral::to_string(&lm_data)?
     };
-    let mut writer = std::io::BufWriter::new(std::fs::File::create(output)?);
-    writeln!(writer, "{}", line)?;
-    Ok(())
+    lmrs::lock::write_atomic(output, |writer| Ok(writeln!(writer, "{}", line)?))
 }
 
 trait Swap {
@@ -37,8 +35,7 @@ impl Swap for LabelMeData {
         let file_name = Path::new(&self.imagePath)
             .file_name()
             .with_context(|| format!("Failed to get file_name: {}", self.imagePath))?
-            .to_str()
-            .unwrap();
+            .to_string_lossy();
         if prefix.is_empty() {
             self.imagePath = file_name.into();
         } else {
@@ -54,8 +51,7 @@ impl Swap for LabelMeData {
         self.imagePath = self.imagePath.replace('\\', "/");
         self.imagePath = Path::new(&self.imagePath)
             .with_extension(suffix)
-            .to_str()
-            .unwrap()
+            .to_string_lossy()
             .into();
         Ok(())
     }
@@ -65,13 +61,11 @@ fn swap_suffix_file(input: &Path, suffix: &str, output: &Path, pretty: bool) ->
     let mut lm_data = LabelMeData::try_from(input)?;
     lm_data.swap_suffix(suffix)?;
     let line = if pretty {
-        serde_json::to_string_pretty(&lm_data)?
+        labelme_rs::integral::to_string_pretty(&lm_data)?
     } else {
-        serde_json::to_string(&lm_data)?
+        labelme_rs::integral::to_string(&lm_data)?
     };
-    let mut writer = std::io::BufWriter::new(std::fs::File::create(output)?);
-    writeln!(writer, "{}", line)?;
-    Ok(())
+    lmrs::lock::write_atomic(output, |writer| Ok(writeln!(writer, "{}", line)?))
 }
 
 #[test]
@@ -136,7 +130,8 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         args.prefix.trim_end_matches('/')
     };
 
-    if args.input.is_dir() {
+    let format = args.input_format.resolve(&args.input);
+    if format == Some(InputFormat::Dir) {
         let output = args.output.unwrap_or_else(|| args.input.clone());
         debug!("Directory input");
         ensure!(
@@ -149,6 +144,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             "Existing file \"{}\" found: directory output is required for directory input.",
             output.to_string_lossy()
         );
+        let _lock = lmrs::lock::DirLock::acquire(&output, args.lock.force_lock)?;
         let entries: Vec<_> = glob::glob(
             args.input
                 .join("*.json")
@@ -177,7 +173,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         bar.finish();
     } else {
         debug!("File or stdin input");
-        if args.input.extension().is_some_and(|ext| ext == "json") {
+        if format == Some(InputFormat::Json) {
             // single json
             let output = args.output.unwrap_or_else(|| args.input.clone());
             if args.suffix {
@@ -185,12 +181,7 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             } else {
                 swap_prefix_file(&args.input, sanitized_prefix_suffix, &output, true)?;
             }
-        } else if args.input.as_os_str() == "-"
-            || args
-                .input
-                .extension()
-                .is_some_and(|ext| ext == "jsonl" || ext == "ndjson")
-        {
+        } else if format == Some(InputFormat::Ndjson) {
             // jsonl or ndjson
             let reader: Box<dyn BufRead> = if args.input.as_os_str() == "-" {
                 Box::new(BufReader::new(std::io::stdin()))
@@ -215,7 +206,11 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
                 } else {
                     lm_data_line.content.swap_prefix(sanitized_prefix_suffix)?;
                 }
-                writeln!(writer, "{}", serde_json::to_string(&lm_data_line)?)?;
+                writeln!(
+                    writer,
+                    "{}",
+                    labelme_rs::integral::to_string(&lm_data_line)?
+                )?;
             }
         } else {
             panic!("Unknown input type: {:?}", args.input);
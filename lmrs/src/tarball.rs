@@ -0,0 +1,94 @@
+//! Reading json members of tar archives (e.g. made by `lmrs archive`) without extraction
+
+use std::io::Read;
+use std::path::Path;
+
+/// Check if the path is a tar archive by its extension: `.tar`, `.tar.gz`, or `.tgz`
+pub fn is_tarball(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+fn open(path: &Path) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "tar") {
+        Box::new(file)
+    } else {
+        Box::new(flate2::read::GzDecoder::new(file))
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Call `f` with the member path and the content of each `.json` member in the order of the archive.
+/// Other members (e.g. images) are skipped without being read
+pub fn for_each_json<E>(
+    path: &Path,
+    mut f: impl FnMut(&str, &[u8]) -> Result<(), E>,
+) -> Result<(), E>
+where
+    E: From<std::io::Error>,
+{
+    let mut archive = open(path)?;
+    let mut content = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let member = entry.path()?.to_string_lossy().into_owned();
+        if !entry.header().entry_type().is_file() || !member.ends_with(".json") {
+            continue;
+        }
+        content.clear();
+        entry.read_to_end(&mut content)?;
+        f(&member, &content)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_json() -> anyhow::Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        for name in ["a.tar", "a.tar.gz"] {
+            let path = tmp_dir.path().join(name);
+            let file = std::fs::File::create(&path)?;
+            let writer: Box<dyn std::io::Write> = if name.ends_with(".gz") {
+                Box::new(flate2::write::GzEncoder::new(
+                    file,
+                    flate2::Compression::default(),
+                ))
+            } else {
+                Box::new(file)
+            };
+            let mut builder = tar::Builder::new(writer);
+            for (member, content) in [("dir/1.json", "{}"), ("1.jpg", "jpg"), ("2.json", "[]")] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, member, content.as_bytes())?;
+            }
+            builder.into_inner()?.flush()?;
+
+            assert!(is_tarball(&path));
+            let mut members = vec![];
+            for_each_json(&path, |member, content| -> std::io::Result<()> {
+                members.push((
+                    member.to_string(),
+                    String::from_utf8_lossy(content).into_owned(),
+                ));
+                Ok(())
+            })?;
+            assert_eq!(
+                members,
+                vec![
+                    ("dir/1.json".to_string(), "{}".to_string()),
+                    ("2.json".to_string(), "[]".to_string())
+                ]
+            );
+        }
+        assert!(!is_tarball(Path::new("a.ndjson")));
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+//! Helpers shared by the unit tests of the library and the commands
+
+use labelme_rs::{Point, Shape};
+
+/// Shape of the label, the shape type, and the points
+pub(crate) fn shape(label: &str, shape_type: &str, points: impl Into<Vec<Point>>) -> Shape {
+    Shape {
+        label: label.into(),
+        points: points.into(),
+        shape_type: shape_type.into(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{bail, Context, Result};
+use labelme_rs::indexmap::IndexMap;
+use labelme_rs::{LabelMeData, LabelMeDataLine, Shape};
+use serde::Deserialize;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use lmrs::cli::ToLabelmeCmdArgs as CmdArgs;
+
+#[derive(Deserialize, Debug)]
+struct Row {
+    filename: String,
+    label: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    shape_type: Option<String>,
+    #[serde(default)]
+    group_id: Option<String>,
+    #[serde(default)]
+    width: Option<usize>,
+    #[serde(default)]
+    height: Option<usize>,
+}
+
+/// Build labelme data per filename in the order of appearance.
+///
+/// Each row of `point` (default) is a shape. Consecutive rows of the other shape types (e.g. vertices of a polygon) are combined
+/// into a shape while the label, the shape type, and the group id are the same
+fn from_csv(reader: impl Read, image_dir: Option<&Path>) -> Result<Vec<LabelMeDataLine>> {
+    let mut records: IndexMap<String, LabelMeData> = IndexMap::new();
+    for (i, row) in csv::Reader::from_reader(reader).deserialize().enumerate() {
+        let row: Row = row.with_context(|| format!("Parsing row {}", i + 1))?;
+        if !records.contains_key(&row.filename) {
+            let (width, height) = match (image_dir, row.width, row.height) {
+                (Some(image_dir), _, _) => {
+                    let path = image_dir.join(&row.filename);
+                    let (width, height) = labelme_rs::image_dimensions(&path)
+                        .with_context(|| format!("Reading {:?}", path))?;
+                    (width as usize, height as usize)
+                }
+                (None, Some(width), Some(height)) => (width, height),
+                _ => bail!(
+                    "{}: image size is unknown. Add width and height columns or --image-dir",
+                    row.filename
+                ),
+            };
+            let data = LabelMeData::new(&[], &[], width, height, &row.filename);
+            records.insert(row.filename.clone(), data);
+        }
+        let data = &mut records[&row.filename];
+        let shape_type = row.shape_type.unwrap_or_else(|| "point".into());
+        match data.shapes.last_mut() {
+            Some(last)
+                if shape_type != "point"
+                    && last.label == row.label
+                    && last.shape_type == shape_type
+                    && last.group_id == row.group_id =>
+            {
+                last.points.push((row.x, row.y));
+            }
+            _ => data.shapes.push(Shape {
+                label: row.label,
+                points: vec![(row.x, row.y)],
+                group_id: row.group_id,
+                shape_type,
+                ..Default::default()
+            }),
+        }
+    }
+    records
+        .into_iter()
+        .map(|(filename, content)| {
+            let stem = Path::new(&filename)
+                .file_stem()
+                .with_context(|| format!("Invalid filename {:?}", filename))?;
+            Ok(LabelMeDataLine {
+                filename: format!("{}.json", stem.to_string_lossy()),
+                content,
+            })
+        })
+        .collect()
+}
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let reader: Box<dyn Read> = if args.input.as_os_str() == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(
+            std::fs::File::open(&args.input)
+                .with_context(|| format!("Opening {:?}", args.input))?,
+        )
+    };
+    let lines = from_csv(reader, args.image_dir.as_deref())?;
+    let mut writer = BufWriter::new(std::io::stdout().lock());
+    for line in lines {
+        labelme_rs::integral::to_writer(&mut writer, &line)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_csv() -> Result<()> {
+        let csv = "filename,label,x,y,shape_type,group_id,width,height
+a.jpg,road,0,0,polygon,,8,6
+a.jpg,road,4,0,polygon,,8,6
+a.jpg,road,4,4,polygon,,8,6
+a.jpg,road,1,1,polygon,2,8,6
+a.jpg,road,2,1,polygon,2,8,6
+b.jpg,car,1.5,2,,,4,4
+";
+        let lines = from_csv(csv.as_bytes(), None)?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].filename, "a.json");
+        assert_eq!(lines[0].content.imagePath, "a.jpg");
+        let shapes = &lines[0].content.shapes;
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0].points, vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)]);
+        assert_eq!(shapes[1].group_id.as_deref(), Some("2"));
+        assert_eq!(lines[1].content.shapes[0].shape_type, "point");
+        assert_eq!(lines[1].content.imageWidth, 4);
+
+        assert!(from_csv("filename,label,x,y\na.jpg,L,1,1\n".as_bytes(), None).is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use glob::glob;
 use labelme_rs::indexmap::IndexSet;
 use std::sync::{
@@ -6,7 +6,9 @@ use std::sync::{
     Arc,
 };
 
+use lmrs::baseline::Baseline;
 use lmrs::cli::ValidateCmdArgs as CmdArgs;
+use lmrs::dataset::DatasetFailure;
 
 pub fn cmd(args: CmdArgs) -> Result<()> {
     let verbosity = args.verbose;
@@ -15,7 +17,9 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
         let ar = lmrs::load_rules(&filename)?;
         rules.extend(ar);
     }
+    let (rules, dataset_rules) = lmrs::dataset::split_rules(rules);
     let asts = lmrs::parse_rules(&rules)?;
+    let dataset_asts = lmrs::dataset::parse_rules(&dataset_rules)?;
     let indir = &args.input;
     if !indir.exists() {
         return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
@@ -24,6 +28,8 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     if n_threads == 0 {
         n_threads = num_cpus::get_physical();
     }
+    let cancel = lmrs::cancel::flag();
+    let processed_count = Arc::new(AtomicUsize::new(0));
     let checked_count = Arc::new(AtomicUsize::new(0));
     let valid_count = Arc::new(AtomicUsize::new(0));
     let file_list: Vec<_> = glob(
@@ -37,25 +43,71 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
     let file_list = Arc::new(file_list);
     let flag_set: IndexSet<String> = args.flag.into_iter().collect();
     let ignore_set: IndexSet<String> = args.ignore.into_iter().collect();
-    std::thread::scope(|scope| {
+    let flag_expr = args
+        .flag_expr
+        .as_deref()
+        .map(lmrs::parse_flag_expr)
+        .transpose()?;
+    let retry = lmrs::retry::RetryPolicy::from(&args.retry);
+    let count_mode = args.count;
+    let baseline = match &args.baseline {
+        Some(path) if !args.update_baseline => Some(Baseline::load(path)?),
+        _ => None,
+    };
+    let tolerated_count = AtomicUsize::new(0);
+    let new_count = AtomicUsize::new(0);
+    let report = |key: &str, reasons: Vec<String>, message: &dyn std::fmt::Display| {
+        if baseline
+            .as_ref()
+            .is_some_and(|b| b.tolerates(key, &reasons))
+        {
+            tolerated_count.fetch_add(1, Ordering::SeqCst);
+        } else {
+            new_count.fetch_add(1, Ordering::SeqCst);
+            println!("{}", message);
+        }
+        (key.to_string(), reasons)
+    };
+    let (dataset_stats, mut failures) = std::thread::scope(|scope| {
         let mut handles = vec![];
         for thread_i in 0..n_threads {
             let checked_count = Arc::clone(&checked_count);
             let valid_count = Arc::clone(&valid_count);
+            let processed_count = Arc::clone(&processed_count);
             let file_list = &file_list;
             let indir = &args.input;
             let flag_set = &flag_set;
             let ignore_set = &ignore_set;
+            let flag_expr = &flag_expr;
             let rules = &rules;
             let asts = &asts;
+            let retry = &retry;
+            let report = &report;
             let handle = scope.spawn(move || {
+                let mut stats = lmrs::dataset::DatasetStats::default();
+                let mut failures = vec![];
                 for i in (thread_i..file_list.len()).step_by(n_threads) {
+                    if lmrs::cancel::is_cancelled(cancel) {
+                        break;
+                    }
                     let entry = &file_list[i];
                     match entry {
                         Ok(path) => {
-                            let check_result =
-                                lmrs::check_json_file(rules, asts, path, flag_set, ignore_set);
                             let disp_path = path.strip_prefix(indir).unwrap_or(path.as_path());
+                            let check_result = lmrs::read_json_file_with_retry(path, retry)
+                                .and_then(|json_data| {
+                                    if flag_expr.as_ref().is_some_and(|expr| {
+                                        !lmrs::satisfies_flag_expr(expr, &json_data)
+                                    }) {
+                                        return Ok(lmrs::CheckResult::Skipped);
+                                    }
+                                    if !lmrs::is_skipped(&json_data, flag_set, ignore_set) {
+                                        stats.add(disp_path, &json_data, count_mode);
+                                    }
+                                    lmrs::check_json_with_count_mode(
+                                        rules, asts, json_data, flag_set, ignore_set, count_mode,
+                                    )
+                                });
                             match check_result {
                                 Ok(ret) => {
                                     if ret == lmrs::CheckResult::Passed {
@@ -68,23 +120,51 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
                                 }
                                 Err(err) => {
                                     checked_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                                    println!("{:?},{}", disp_path, err);
+                                    failures.push(report(
+                                        &lmrs::baseline::key(disp_path),
+                                        lmrs::baseline::reasons(&err),
+                                        &format_args!("{:?},{}", disp_path, err),
+                                    ));
                                 }
                             };
                         }
                         Err(e) => println!("{e:?}"),
                     }
+                    processed_count.fetch_add(1, Ordering::SeqCst);
                 }
+                (stats, failures)
             });
             handles.push(handle);
         }
+        let mut dataset_stats = lmrs::dataset::DatasetStats::default();
+        let mut failures = vec![];
         for handle in handles {
-            handle
+            let (stats, thread_failures) = handle
                 .join()
                 .or_else(|e| bail!("Failed to execute validation: {:?}", e))
                 .unwrap();
+            dataset_stats.merge(stats);
+            failures.extend(thread_failures);
         }
+        (dataset_stats, failures)
     });
+    if lmrs::cancel::is_cancelled(cancel) {
+        return Err(lmrs::cancel::Cancelled {
+            completed: processed_count.load(Ordering::SeqCst),
+            total: Some(file_list.len()),
+        }
+        .into());
+    }
+    for failure in dataset_stats.evaluate(&dataset_rules, &dataset_asts) {
+        let rule = match &failure {
+            DatasetFailure::Unsatisfied(rule, _) | DatasetFailure::Unpaired(rule, _) => rule,
+        };
+        failures.push(report(
+            lmrs::baseline::DATASET_KEY,
+            vec![rule.clone()],
+            &format_args!("{},{}", lmrs::baseline::DATASET_KEY, failure),
+        ));
+    }
     if args.stats {
         println!(
             "{} / {} annotations are valid.",
@@ -92,5 +172,24 @@ pub fn cmd(args: CmdArgs) -> Result<()> {
             checked_count.load(Ordering::SeqCst)
         );
     }
+    if let (Some(path), true) = (&args.baseline, args.update_baseline) {
+        let mut baseline = Baseline::default();
+        for (key, reasons) in failures {
+            baseline.insert(&key, reasons);
+        }
+        baseline.save(path)?;
+        info!(
+            "Recorded failures of {} file(s) to {:?}",
+            baseline.len(),
+            path
+        );
+    } else if baseline.is_some() {
+        let tolerated = tolerated_count.load(Ordering::SeqCst);
+        if tolerated > 0 {
+            info!("{} failure(s) in the baseline are tolerated", tolerated);
+        }
+        let new = new_count.load(Ordering::SeqCst);
+        ensure!(new == 0, "{} failure(s) not in the baseline", new);
+    }
     Ok(())
 }
@@ -0,0 +1,16 @@
+use anyhow::Result;
+use labelme_rs::voc::{self, VocOptions};
+
+use crate::coco::load_records;
+use crate::convert::write_each;
+use lmrs::cli::VocCmdArgs as CmdArgs;
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let options = VocOptions {
+        polygon_bboxes: true,
+        ..Default::default()
+    };
+    write_each(load_records(&args.input)?, &args.output, "xml", |data| {
+        Ok(voc::to_voc_xml(data, &options))
+    })
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+use labelme_rs::yolo::{self, YoloOptions};
+
+use crate::coco::{load_labels, load_records};
+use crate::convert::write_each;
+use lmrs::cli::YoloCmdArgs as CmdArgs;
+
+pub fn cmd(args: CmdArgs) -> Result<()> {
+    let names = load_labels(&args.names)?;
+    let options = YoloOptions {
+        skip_unknown: args.skip_unknown,
+        poly_as_bbox: args.poly_as_bbox,
+        ..Default::default()
+    };
+    write_each(load_records(&args.input)?, &args.output, "txt", |data| {
+        Ok(yolo::to_yolo_txt(data, &names, &options)?)
+    })
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use labelme_rs::serde_json;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
@@ -156,6 +157,710 @@ fn test_exist() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_exist_image_data() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let img = labelme_rs::image::DynamicImage::new_rgb8(4, 4);
+    let mut data = labelme_rs::LabelMeData::new(&[], &[], 4, 4, "");
+    data.imageData = Some(labelme_rs::img2base64(
+        &img,
+        labelme_rs::image::ImageFormat::Png,
+    )?);
+    let embedded = serde_json::to_string(&labelme_rs::LabelMeDataLine {
+        content: data.clone(),
+        filename: "embedded.json".into(),
+    })?;
+    data.imageData = None;
+    let missing = serde_json::to_string(&labelme_rs::LabelMeDataLine {
+        content: data,
+        filename: "missing.json".into(),
+    })?;
+    let input = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&input, format!("{embedded}\n{missing}\n"))?;
+
+    let output = Command::new(bin).arg("exist").arg(&input).output()?;
+    assert!(output.status.success());
+    assert_eq!(str::from_utf8(&output.stdout)?, format!("{embedded}\n"));
+    let output = Command::new(bin)
+        .arg("exist")
+        .arg(&input)
+        .arg("--ignore-image-data")
+        .arg("--invert")
+        .output()?;
+    assert_eq!(
+        str::from_utf8(&output.stdout)?,
+        format!("{embedded}\n{missing}\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_mat_rotate() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/");
+    let tmp_dir = tempfile::tempdir()?;
+    let ndjson_output = Command::new(bin)
+        .current_dir(&json_dir)
+        .arg("ndjson")
+        .arg("Mandrill.json")
+        .output()?;
+    assert!(ndjson_output.status.success());
+    let original =
+        labelme_rs::LabelMeDataLine::try_from(str::from_utf8(&ndjson_output.stdout)?.trim())?;
+
+    let rotate = |input: &[u8]| -> Result<Vec<u8>> {
+        let mut proc = Command::new(bin)
+            .args(["mat", "-", "--rotate", "90"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        proc.stdin.as_mut().unwrap().write_all(input)?;
+        let output = proc.wait_with_output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+        Ok(output.stdout)
+    };
+    // imagePath is resolved relative to the input file, not the current directory
+    let input = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&input, &ndjson_output.stdout)?;
+    std::fs::copy(
+        json_dir.join("Mandrill.jpg"),
+        tmp_dir.path().join("Mandrill.jpg"),
+    )?;
+    let image_dir = tmp_dir.path().join("rotated");
+    std::fs::create_dir(&image_dir)?;
+    let mat_output = Command::new(bin)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["mat", "--rotate", "90", "--image"])
+        .arg(&image_dir)
+        .arg(&input)
+        .output()?;
+    assert!(
+        mat_output.status.success(),
+        "{}",
+        str::from_utf8(&mat_output.stderr)?
+    );
+    let mut output = mat_output.stdout;
+    let rotated = labelme_rs::LabelMeDataLine::try_from(str::from_utf8(&output)?.trim())?;
+    let (x, y) = original.content.shapes[0].points[0];
+    assert_eq!(
+        rotated.content.shapes[0].points[0],
+        (original.content.imageHeight as f64 - y, x)
+    );
+    let rotated_image = image_dir.join("Mandrill.jpg").canonicalize()?;
+    assert_eq!(rotated.content.imagePath, rotated_image.to_str().unwrap());
+    for _ in 0..3 {
+        output = rotate(&output)?;
+    }
+    let round_trip = labelme_rs::LabelMeDataLine::try_from(str::from_utf8(&output)?.trim())?;
+    for (s, t) in round_trip
+        .content
+        .shapes
+        .iter()
+        .zip(&original.content.shapes)
+    {
+        for (p, q) in s.points.iter().zip(&t.points) {
+            assert!((p.0 - q.0).abs() < 1e-9 && (p.1 - q.1).abs() < 1e-9);
+        }
+    }
+
+    let mut proc = Command::new(bin)
+        .args(["mat", "-", "--flip", "horizontal"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    proc.stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&ndjson_output.stdout)?;
+    let output = proc.wait_with_output()?;
+    assert!(output.status.success());
+    let flipped = labelme_rs::LabelMeDataLine::try_from(str::from_utf8(&output.stdout)?.trim())?;
+    assert_eq!(
+        flipped.content.shapes[0].points[0],
+        (original.content.imageWidth as f64 - x, y)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_assert_in_bounds() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let data = labelme_rs::LabelMeData::new(
+        &[(0.2, 10.0), (32.0, 32.0)],
+        &["L1".into(), "L2".into()],
+        64,
+        64,
+        "image.jpg",
+    );
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "image.json".into(),
+        content: data,
+    };
+    let input = labelme_rs::serde_json::to_string(&line)?;
+    let mat = |assert_in_bounds: bool| -> Result<std::process::Output> {
+        let mut command = Command::new(bin);
+        command.args(["mat", "-", "--origin", "corner-to-center"]);
+        if assert_in_bounds {
+            command.arg("--assert-in-bounds");
+        }
+        let mut proc = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        proc.stdin.as_mut().unwrap().write_all(input.as_bytes())?;
+        Ok(proc.wait_with_output()?)
+    };
+    assert!(mat(false)?.status.success());
+    let output = mat(true)?;
+    assert!(!output.status.success());
+    let stderr = str::from_utf8(&output.stderr)?;
+    assert!(
+        stderr.contains("image.json: 1 point(s) out of bounds of 64x64"),
+        "{stderr}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_coco() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/");
+    let output = Command::new(bin)
+        .arg("coco")
+        .arg(&json_dir)
+        .args(["--points-as-boxes", "4"])
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let dataset: labelme_rs::serde_json::Value =
+        labelme_rs::serde_json::from_slice(&output.stdout)?;
+    assert_eq!(dataset["images"][0]["file_name"], "Mandrill.jpg");
+    assert_eq!(dataset["images"][0]["width"], 256);
+    let categories: Vec<_> = dataset["categories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        categories,
+        vec!["Eye", "Glabella", "Iris", "Nose", "Nostril"]
+    );
+    // 2 eyes, 2 irises, a nose, 2 nostrils, and a glabella. Lines are skipped
+    assert_eq!(dataset["annotations"].as_array().unwrap().len(), 8);
+    Ok(())
+}
+
+#[test]
+fn test_yolo() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let names = tmp_dir.path().join("names.txt");
+    std::fs::write(&names, "person\ncar\n")?;
+    let data = labelme_rs::LabelMeData {
+        shapes: vec![
+            labelme_rs::Shape::rectangle((10.0, 20.0), (30.0, 60.0), "car"),
+            labelme_rs::Shape::rectangle((0.0, 0.0), (1.0, 1.0), "tree"),
+        ],
+        imageWidth: 100,
+        imageHeight: 200,
+        imagePath: "img.v2.jpg".into(),
+        ..Default::default()
+    };
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "img.v2.json".into(),
+        content: data,
+    };
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, serde_json::to_string(&line)? + "\n")?;
+    let output_dir = tmp_dir.path().join("labels");
+    let yolo = |skip_unknown: bool| {
+        let mut command = Command::new(bin);
+        command
+            .arg("yolo")
+            .arg(&ndjson)
+            .arg("--names")
+            .arg(&names)
+            .arg("--output")
+            .arg(&output_dir);
+        if skip_unknown {
+            command.arg("--skip-unknown");
+        }
+        command.output()
+    };
+    let output = yolo(false)?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("\"tree\""));
+    let output = yolo(true)?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("img.v2.txt"))?,
+        "1 0.200000 0.200000 0.200000 0.200000\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_convert_voc() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let mut data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["P".into()], 64, 48, "img.jpg");
+    data.shapes.push(labelme_rs::Shape::rectangle(
+        (4.0, 8.0),
+        (20.0, 16.0),
+        "car",
+    ));
+    data.shapes.push(labelme_rs::Shape::polygon(
+        vec![(0.0, 0.0), (8.0, 0.0), (8.0, 8.0)],
+        "road",
+    ));
+    std::fs::write(json_dir.join("img.json"), serde_json::to_string(&data)?)?;
+    let output_dir = tmp_dir.path().join("voc");
+    let output = Command::new(bin)
+        .arg("convert")
+        .arg(&json_dir)
+        .arg("--format")
+        .arg("voc")
+        .arg("--output")
+        .arg(&output_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let xml = std::fs::read_to_string(output_dir.join("img.xml"))?;
+    assert_eq!(xml.matches("<object>").count(), 1, "{xml}");
+    assert!(xml.contains("<name>car</name>"));
+    assert!(xml.contains(
+        "<xmin>5</xmin>\n      <ymin>9</ymin>\n      <xmax>20</xmax>\n      <ymax>16</ymax>"
+    ));
+    assert!(xml.contains("<width>64</width>\n    <height>48</height>"));
+    Ok(())
+}
+
+#[test]
+fn test_convert_yolo() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let mut data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["P".into()], 40, 20, "img.jpg");
+    data.shapes.push(labelme_rs::Shape::rectangle(
+        (30.0, -5.0),
+        (50.0, 10.0),
+        "car",
+    ));
+    data.shapes.push(labelme_rs::Shape::polygon(
+        vec![(0.0, 0.0), (8.0, 0.0), (8.0, 10.0)],
+        "bike",
+    ));
+    std::fs::write(json_dir.join("img.json"), serde_json::to_string(&data)?)?;
+    let output_dir = tmp_dir.path().join("yolo");
+    let convert = |all_shapes: bool| {
+        let mut command = Command::new(bin);
+        command
+            .arg("convert")
+            .arg(&json_dir)
+            .arg("--format")
+            .arg("yolo")
+            .arg("--output")
+            .arg(&output_dir);
+        if all_shapes {
+            command.arg("--all-shapes");
+        }
+        command.output()
+    };
+    let output = convert(false)?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("classes.txt"))?,
+        "car\n"
+    );
+    // clamped to the image
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("img.txt"))?,
+        "0 0.875000 0.250000 0.250000 0.500000\n"
+    );
+    let output = convert(true)?;
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("classes.txt"))?,
+        "P\nbike\ncar\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(output_dir.join("img.txt"))?,
+        "2 0.875000 0.250000 0.250000 0.500000\n1 0.100000 0.250000 0.200000 0.500000\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_to_labelme() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let image_dir = tmp_dir.path().join("images");
+    std::fs::create_dir(&image_dir)?;
+    labelme_rs::image::GrayImage::new(12, 8).save(image_dir.join("img.png"))?;
+    let csv = tmp_dir.path().join("points.csv");
+    std::fs::write(
+        &csv,
+        "filename,label,x,y\nimg.png,TL,1,2\nimg.png,BR,10.5,7\n",
+    )?;
+    let output = Command::new(bin)
+        .arg("to-labelme")
+        .arg(&csv)
+        .arg("--image-dir")
+        .arg(&image_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let stdout = str::from_utf8(&output.stdout)?;
+    assert_eq!(stdout.lines().count(), 1);
+    let line = labelme_rs::LabelMeDataLine::try_from(stdout.trim_end())?;
+    assert_eq!(line.filename, "img.json");
+    assert_eq!(line.content.imagePath, "img.png");
+    assert_eq!((line.content.imageWidth, line.content.imageHeight), (12, 8));
+    let shapes: Vec<_> = line
+        .content
+        .shapes
+        .iter()
+        .map(|s| (s.label.as_str(), s.shape_type.as_str(), s.points.clone()))
+        .collect();
+    assert_eq!(
+        shapes,
+        vec![
+            ("TL", "point", vec![(1.0, 2.0)]),
+            ("BR", "point", vec![(10.5, 7.0)])
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_mask() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let data = labelme_rs::LabelMeData {
+        shapes: vec![
+            labelme_rs::Shape::rectangle((0.0, 0.0), (8.0, 4.0), "road"),
+            labelme_rs::Shape::rectangle((2.0, 2.0), (4.0, 4.0), "_background_"),
+        ],
+        imageWidth: 16,
+        imageHeight: 8,
+        ..Default::default()
+    };
+    std::fs::write(json_dir.join("img.json"), serde_json::to_string(&data)?)?;
+    let labels = tmp_dir.path().join("labels.yaml");
+    std::fs::write(&labels, "road: 3\n")?;
+    let output_dir = tmp_dir.path().join("masks");
+    let output = Command::new(bin)
+        .arg("mask")
+        .arg(&json_dir)
+        .arg("--labels")
+        .arg(&labels)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let mask = labelme_rs::image::open(output_dir.join("img.png"))?.into_luma8();
+    assert_eq!(mask.dimensions(), (16, 8));
+    assert_eq!(mask.pixels().filter(|p| p.0[0] == 3).count(), 32 - 4);
+    Ok(())
+}
+
+#[test]
+fn test_rasterize_matches_mask() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let fixture =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/all_shapes.json");
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "all_shapes.json".into(),
+        content: labelme_rs::LabelMeData::try_from(fixture.as_path())?,
+    };
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, serde_json::to_string(&line)?)?;
+    // every label is filled with 255 as rasterize does without --label
+    let labels: String = line
+        .content
+        .shapes
+        .iter()
+        .map(|s| s.label.as_str())
+        .collect::<labelme_rs::indexmap::IndexSet<_>>()
+        .into_iter()
+        .map(|label| format!("{label:?}: 255\n"))
+        .collect();
+    let labels_yaml = tmp_dir.path().join("labels.yaml");
+    std::fs::write(&labels_yaml, labels)?;
+    let output_dir = tmp_dir.path().join("masks");
+    for args in [
+        vec![
+            "mask",
+            "--labels",
+            labels_yaml.to_str().unwrap(),
+            "--output",
+        ],
+        vec!["rasterize", "--output-dir"],
+    ] {
+        let output = Command::new(bin)
+            .args(args)
+            .arg(&output_dir)
+            .arg(&ndjson)
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+    }
+    let stem = std::path::Path::new(&line.content.imagePath)
+        .file_stem()
+        .unwrap()
+        .to_string_lossy();
+    let mask = labelme_rs::image::open(output_dir.join("all_shapes.png"))?.into_luma8();
+    let rasterized =
+        labelme_rs::image::open(output_dir.join(format!("{stem}_mask.png")))?.into_luma8();
+    assert!(mask.pixels().any(|p| p.0[0] == 255));
+    assert_eq!(mask, rasterized);
+    Ok(())
+}
+
+#[test]
+fn test_voc() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let mut data =
+        labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["P".into()], 64, 48, "images/img.jpg");
+    let mut rect = labelme_rs::Shape::rectangle((4.0, 8.0), (20.0, 16.0), "salt & pepper");
+    rect.flags.insert("difficult".into(), true);
+    data.shapes.push(rect);
+    data.shapes.push(labelme_rs::Shape::polygon(
+        vec![(0.5, 0.0), (8.0, 0.0), (8.0, 8.5)],
+        "<road>",
+    ));
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "img.json".into(),
+        content: data,
+    };
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, serde_json::to_string(&line)? + "\n")?;
+    let output_dir = tmp_dir.path().join("voc/annotations");
+    let output = Command::new(bin)
+        .arg("voc")
+        .arg(&ndjson)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    insta::assert_snapshot!("voc", std::fs::read_to_string(output_dir.join("img.xml"))?);
+    Ok(())
+}
+
+#[test]
+fn test_crop() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/");
+    let tmp_dir = tempfile::tempdir()?;
+    let ndjson_output = Command::new(bin)
+        .current_dir(&json_dir)
+        .arg("ndjson")
+        .arg("Mandrill.json")
+        .output()?;
+    assert!(ndjson_output.status.success());
+
+    let mut proc = Command::new(bin)
+        .current_dir(&json_dir)
+        .arg("crop")
+        .arg("-")
+        .arg("128x128+64+0")
+        .arg("--image")
+        .arg(tmp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    proc.stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&ndjson_output.stdout)?;
+    let output = proc.wait_with_output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+
+    let line = labelme_rs::LabelMeDataLine::try_from(str::from_utf8(&output.stdout)?.trim())?;
+    let data = line.content;
+    assert_eq!((data.imageWidth, data.imageHeight), (128, 128));
+    let labels: Vec<_> = data.shapes.iter().map(|s| s.label.as_str()).collect();
+    assert!(labels.contains(&"Glabella"));
+    assert!(!labels.contains(&"Nose"));
+    assert!(!labels.contains(&"Nostril"));
+    let glabella = data.shapes.iter().find(|s| s.label == "Glabella").unwrap();
+    assert!((glabella.points[0].0 - 60.81389578163771).abs() < 1e-9);
+    assert!(data
+        .shapes
+        .iter()
+        .filter(|s| s.shape_type != "circle")
+        .flat_map(|s| &s.points)
+        .all(|p| (0.0..=128.0).contains(&p.0) && (0.0..=128.0).contains(&p.1)));
+    let image = labelme_rs::image::open(tmp_dir.path().join("Mandrill.jpg"))?;
+    assert_eq!(
+        labelme_rs::image::GenericImageView::dimensions(&image),
+        (128, 128)
+    );
+
+    // region outside the image
+    let mut proc = Command::new(bin)
+        .arg("crop")
+        .arg("-")
+        .arg("10x10+300+0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    proc.stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&ndjson_output.stdout)?;
+    let output = proc.wait_with_output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("outside the image"));
+
+    // around each shape
+    let output_dir = tmp_dir.path().join("shapes");
+    let mut proc = Command::new(bin)
+        .current_dir(&json_dir)
+        .arg("crop")
+        .arg("-")
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("--label")
+        .arg("Glabella")
+        .arg("--padding")
+        .arg("8")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    proc.stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&ndjson_output.stdout)?;
+    let output = proc.wait_with_output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let original = labelme_rs::LabelMeData::try_from(json_dir.join("Mandrill.json").as_path())?;
+    let index = original
+        .shapes
+        .iter()
+        .position(|s| s.label == "Glabella")
+        .unwrap();
+    let (x, y) = original.shapes[index].points[0];
+    let data = labelme_rs::LabelMeData::try_from(
+        output_dir.join(format!("Mandrill_{index}.json")).as_path(),
+    )?;
+    assert_eq!(data.imagePath, format!("Mandrill_{index}.jpg"));
+    assert_eq!((data.imageWidth, data.imageHeight), (17, 17));
+    let glabella = data.shapes.iter().find(|s| s.label == "Glabella").unwrap();
+    let expected = (x - x.floor() + 8.0, y - y.floor() + 8.0);
+    assert!((glabella.points[0].0 - expected.0).abs() < 1e-9);
+    assert!((glabella.points[0].1 - expected.1).abs() < 1e-9);
+    let image = labelme_rs::image::open(output_dir.join(format!("Mandrill_{index}.jpg")))?;
+    assert_eq!(
+        labelme_rs::image::GenericImageView::dimensions(&image),
+        (17, 17)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_input_format() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let mut data = labelme_rs::LabelMeData::new(
+        &[(2.0, 2.0), (1.0, 1.0)],
+        &["L1".into(), "L1".into()],
+        4,
+        4,
+        "img.jpg",
+    );
+    data.flags = labelme_rs::Flags::from_iter([("ok".to_string(), true)]);
+    let line = serde_json::to_string(&labelme_rs::LabelMeDataLine {
+        content: data,
+        filename: "img.json".into(),
+    })?;
+    // extensionless ndjson
+    let input = tmp_dir.path().join("annotations");
+    std::fs::write(&input, format!("{line}\n{line}\n"))?;
+
+    let output = Command::new(bin).arg("count").arg(&input).output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("--input-format"));
+    let output = Command::new(bin)
+        .arg("count")
+        .arg(&input)
+        .args(["--input-format", "ndjson"])
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let counts: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(counts["flags"]["ok"], 2);
+
+    let output = Command::new(bin)
+        .arg("sort")
+        .arg(&input)
+        .args(["--input-format", "ndjson"])
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(str::from_utf8(&output.stdout)?.lines().count(), 2);
+    Ok(())
+}
+
 #[test]
 fn test_sort() -> Result<()> {
     let bin = env!("CARGO_BIN_EXE_lmrs");
@@ -226,3 +931,1552 @@ fn test_sort() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_sort_non_ascii_label() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data = labelme_rs::LabelMeData::new(
+        &[(1.0, 2.0), (3.0, 1.0)],
+        &["右目".into(), "左目".into()],
+        8,
+        8,
+        "画像.jpg",
+    );
+    let json = labelme_rs::integral::to_string_pretty(&data)?;
+    let input = tmp_dir.path().join("utf8.json");
+    std::fs::write(&input, &json)?;
+    let output = Command::new(bin).arg("sort").arg(&input).output()?;
+    assert!(output.status.success());
+    assert_eq!(str::from_utf8(&output.stdout)?.trim_end(), json);
+
+    // shift_jis is an error without --encoding
+    let (sjis, _, _) = labelme_rs::encoding_rs::SHIFT_JIS.encode(&json);
+    let input = tmp_dir.path().join("sjis.json");
+    std::fs::write(&input, &sjis)?;
+    let output = Command::new(bin).arg("sort").arg(&input).output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("Invalid UTF-8"));
+    let output = Command::new(bin)
+        .arg("sort")
+        .arg(&input)
+        .arg("--encoding")
+        .arg("shift_jis")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(str::from_utf8(&output.stdout)?.trim_end(), json);
+    Ok(())
+}
+
+#[test]
+fn test_sort_keeps_integer_coordinates() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/sort.json");
+    let output = Command::new(bin).arg("sort").arg(&input).output()?;
+    assert!(output.status.success());
+    // sorting an already sorted file with integer coordinates changes nothing
+    let sorted = tmp_dir.path().join("sorted.json");
+    std::fs::write(&sorted, &output.stdout)?;
+    let output = Command::new(bin).arg("sort").arg(&sorted).output()?;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, std::fs::read(&sorted)?);
+    assert!(!str::from_utf8(&output.stdout)?.contains(".0,\n"));
+    Ok(())
+}
+#[test]
+fn test_svg_user_defaults() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/Mandrill.json");
+    let defaults = tmp_dir.path().join("defaults.toml");
+    std::fs::write(&defaults, "[svg]\nradius = 7\n")?;
+    let svg = tmp_dir.path().join("Mandrill.svg");
+
+    let output = Command::new(bin)
+        .env("LMRS_CONFIG", &defaults)
+        .arg("svg")
+        .arg(&json)
+        .arg(&svg)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let svg_str = std::fs::read_to_string(&svg)?;
+    assert!(svg_str.contains(r#"r="7""#), "Radius from user defaults");
+
+    let output = Command::new(bin)
+        .env("LMRS_CONFIG", &defaults)
+        .arg("svg")
+        .arg(&json)
+        .arg(&svg)
+        .arg("--radius")
+        .arg("5")
+        .output()?;
+    assert!(output.status.success());
+    let svg_str = std::fs::read_to_string(&svg)?;
+    assert!(svg_str.contains(r#"r="5""#), "Radius from command line");
+    assert!(!svg_str.contains(r#"r="7""#));
+
+    let output = Command::new(bin)
+        .env("LMRS_CONFIG", &defaults)
+        .arg("svg")
+        .arg(&json)
+        .arg(&svg)
+        .arg("--no-user-config")
+        .output()?;
+    assert!(output.status.success());
+    let svg_str = std::fs::read_to_string(&svg)?;
+    assert!(svg_str.contains(r#"r="2""#), "Builtin radius");
+    assert!(!svg_str.contains(r#"r="7""#));
+    Ok(())
+}
+
+#[test]
+fn test_check_dims() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    std::fs::copy(
+        data_dir.join("Mandrill.jpg"),
+        tmp_dir.path().join("Mandrill.jpg"),
+    )?;
+    let json_str = std::fs::read_to_string(data_dir.join("Mandrill.json"))?;
+    std::fs::write(tmp_dir.path().join("Mandrill.json"), &json_str)?;
+    std::fs::write(
+        tmp_dir.path().join("mismatch.json"),
+        json_str.replace(r#""imageHeight":256"#, r#""imageHeight":100"#),
+    )?;
+
+    let output = Command::new(bin)
+        .arg("check-dims")
+        .arg(tmp_dir.path())
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let stdout = str::from_utf8(&output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "Only the mismatched file is reported");
+    let report: serde_json::Value = serde_json::from_str(lines[0])?;
+    assert!(report["filename"]
+        .as_str()
+        .unwrap()
+        .ends_with("mismatch.json"));
+    assert_eq!(report["annotation"], serde_json::json!([256, 100]));
+    assert_eq!(report["image"], serde_json::json!([256, 256]));
+    Ok(())
+}
+
+#[test]
+fn test_fix() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fix/issues.json");
+    let output = tmp_dir.path().join("issues.json");
+
+    let proc_output = Command::new(bin)
+        .arg("fix")
+        .arg(&input)
+        .arg(&output)
+        .output()?;
+    assert!(proc_output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&proc_output.stderr)?;
+    assert_eq!(
+        report["repairs"],
+        serde_json::json!({"corner-order": 1, "dedupe-vertices": 2, "close-polygons": 1, "drop-zero-area": 1})
+    );
+    let fixed = labelme_rs::LabelMeData::try_from(output.as_path())?;
+    let labels: Vec<_> = fixed.shapes.iter().map(|s| s.label.as_str()).collect();
+    assert_eq!(labels, vec!["swapped", "duplicated", "unclosed", "ok"]);
+    assert_eq!(fixed.shapes[0].points, vec![(20.0, 10.0), (100.0, 80.0)]);
+    assert_eq!(fixed.shapes[2].shape_type, "polygon");
+
+    let proc_output = Command::new(bin)
+        .arg("fix")
+        .arg(&input)
+        .arg(&output)
+        .arg("--only")
+        .arg("corner-order,dedupe-vertices")
+        .output()?;
+    assert!(proc_output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&proc_output.stderr)?;
+    assert_eq!(
+        report["repairs"],
+        serde_json::json!({"corner-order": 1, "dedupe-vertices": 2})
+    );
+    let fixed = labelme_rs::LabelMeData::try_from(output.as_path())?;
+    assert_eq!(fixed.shapes.len(), 5);
+    Ok(())
+}
+
+#[test]
+fn test_ndjson_append() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let first = json_dir.join("img1.json");
+    let second = json_dir.join("sort.json");
+    let output = tmp_dir.path().join("appended.ndjson");
+
+    let expected: Vec<u8> = [&first, &second]
+        .iter()
+        .map(|input| Command::new(bin).arg("ndjson").arg(input).output())
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .flat_map(|output| output.stdout)
+        .collect();
+    for input in [&first, &second] {
+        let proc_output = Command::new(bin)
+            .arg("ndjson")
+            .arg(input)
+            .arg("--output")
+            .arg(&output)
+            .arg("--append")
+            .output()?;
+        assert!(
+            proc_output.status.success(),
+            "{}",
+            str::from_utf8(&proc_output.stderr)?
+        );
+    }
+    assert_eq!(std::fs::read(&output)?, expected);
+
+    // Guard against non-ndjson output
+    let proc_output = Command::new(bin)
+        .arg("ndjson")
+        .arg(&first)
+        .arg("--output")
+        .arg(tmp_dir.path().join("appended.json"))
+        .arg("--append")
+        .output()?;
+    assert!(!proc_output.status.success());
+    let not_ndjson = tmp_dir.path().join("not.ndjson");
+    std::fs::write(&not_ndjson, "not json\n")?;
+    let proc_output = Command::new(bin)
+        .arg("ndjson")
+        .arg(&first)
+        .arg("--output")
+        .arg(&not_ndjson)
+        .arg("--append")
+        .output()?;
+    assert!(!proc_output.status.success());
+    assert_eq!(std::fs::read_to_string(&not_ndjson)?, "not json\n");
+    Ok(())
+}
+
+#[test]
+fn test_filter_var() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let rule_file = tmp_dir.path().join("rules.txt");
+    std::fs::write(&rule_file, "blur_pct < 50\n")?;
+
+    let ndjson_output = Command::new(bin)
+        .arg("ndjson")
+        .arg(json_dir.join("img1.json"))
+        .arg(json_dir.join("sort.json"))
+        .output()?;
+    assert!(ndjson_output.status.success());
+    let lines: Vec<serde_json::Value> = str::from_utf8(&ndjson_output.stdout)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2);
+    // Lines joined with metrics
+    let joined = |blurs: &[Option<f64>]| -> String {
+        lines
+            .iter()
+            .zip(blurs)
+            .map(|(line, blur)| {
+                let mut line = line.clone();
+                if let Some(blur) = blur {
+                    line["metrics"] = serde_json::json!({ "blur": blur });
+                }
+                serde_json::to_string(&line).unwrap() + "\n"
+            })
+            .collect()
+    };
+    let filter = |input: &str, extra_args: &[&str]| -> Result<std::process::Output> {
+        let mut proc = Command::new(bin)
+            .arg("filter")
+            .arg("-")
+            .arg("-r")
+            .arg(&rule_file)
+            .arg("--var")
+            .arg("blur_pct=metrics.blur*100")
+            .args(extra_args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        proc.stdin.as_mut().unwrap().write_all(input.as_bytes())?;
+        Ok(proc.wait_with_output()?)
+    };
+
+    let input = joined(&[Some(0.83), Some(0.2)]);
+    let output = filter(&input, &[])?;
+    assert!(output.status.success());
+    assert_eq!(
+        str::from_utf8(&output.stdout)?,
+        input.lines().nth(1).unwrap().to_string() + "\n"
+    );
+
+    // Missing metrics evaluate as 0
+    let input = joined(&[Some(0.83), None]);
+    let output = filter(&input, &[])?;
+    assert!(output.status.success());
+    assert_eq!(str::from_utf8(&output.stdout)?.lines().count(), 1);
+
+    let output = filter(&input, &["--strict-vars"])?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("blur_pct"));
+    Ok(())
+}
+
+#[test]
+fn test_svg_deep_zoom() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data/Mandrill.json");
+    let svg = tmp_dir.path().join("Mandrill.svg");
+
+    let output = Command::new(bin)
+        .arg("svg")
+        .arg(&json)
+        .arg(&svg)
+        .arg("--deep-zoom")
+        .arg("--tile-size")
+        .arg("128")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let svg_str = std::fs::read_to_string(&svg)?;
+    assert!(svg_str.contains(r#"data-dzi="Mandrill.dzi""#));
+    let dzi = std::fs::read_to_string(tmp_dir.path().join("Mandrill.dzi"))?;
+    assert!(dzi.contains(r#"TileSize="128""#));
+    let tiles_dir = tmp_dir.path().join("Mandrill_files");
+    // 2x2 tiles at the full resolution level 8 and 1 tile for each of levels 0-7
+    assert_eq!(std::fs::read_dir(tiles_dir.join("8"))?.count(), 4);
+    assert_eq!(std::fs::read_dir(&tiles_dir)?.count(), 9);
+    assert!(tiles_dir.join("0/0_0.jpg").exists());
+    Ok(())
+}
+
+#[test]
+fn test_fix_preview() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fix/issues.json");
+    let output = tmp_dir.path().join("issues.json");
+
+    let proc_output = Command::new(bin)
+        .arg("fix")
+        .arg(&input)
+        .arg(&output)
+        .arg("--only")
+        .arg("corner-order")
+        .arg("--preview")
+        .output()?;
+    assert!(proc_output.status.success());
+    assert!(!output.exists(), "Nothing is written in preview");
+    let preview: serde_json::Value = serde_json::from_slice(&proc_output.stdout)?;
+    assert_eq!(preview["filename"], input.to_string_lossy().as_ref());
+    assert_eq!(
+        preview["patch"],
+        serde_json::json!([
+            {"op": "replace", "path": "/shapes/0/points/0/0", "value": 20.0},
+            {"op": "replace", "path": "/shapes/0/points/0/1", "value": 10.0},
+            {"op": "replace", "path": "/shapes/0/points/1/0", "value": 100.0},
+            {"op": "replace", "path": "/shapes/0/points/1/1", "value": 80.0},
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_svg_no_image() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json = tmp_dir.path().join("shapes.json");
+    let data = labelme_rs::LabelMeData::new(
+        &[(10.0, 20.0), (110.0, 70.0)],
+        &["L1".into(), "L2".into()],
+        512,
+        512,
+        "missing.jpg",
+    );
+    std::fs::write(&json, serde_json::to_string(&data)?)?;
+    let svg = tmp_dir.path().join("shapes.svg");
+
+    let output = Command::new(bin)
+        .arg("svg")
+        .arg(&json)
+        .arg(&svg)
+        .arg("--no-image")
+        .arg("--margin")
+        .arg("5")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let svg_str = std::fs::read_to_string(&svg)?;
+    assert!(svg_str.contains(r#"viewBox="5 15 110 60""#), "{svg_str}");
+    assert!(svg_str.contains(r#"width="110""#));
+    assert!(!svg_str.contains("<image"));
+    Ok(())
+}
+
+#[test]
+fn test_validate_dataset_rules() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let rules = tmp_dir.path().join("rules.txt");
+    std::fs::write(
+        &rules,
+        "@dataset: TL >= 1\n@dataset: paired(\"backslash\", \"sort\")\n",
+    )?;
+    let output = Command::new(bin)
+        .arg("validate")
+        .arg(&rules)
+        .arg(&json_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(str::from_utf8(&output.stdout)?, "");
+
+    std::fs::write(
+        &rules,
+        "TL >= 0\n@dataset: paired(\"test\", \"img2\")\n@dataset: _files < 0\n",
+    )?;
+    let output = Command::new(bin)
+        .arg("validate")
+        .arg(&rules)
+        .arg(&json_dir)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = str::from_utf8(&output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert_eq!(
+        lines[0],
+        r#"@dataset,Unsatisfied dataset rule; "paired("test", "img2")": 1 unpaired; "test.json""#
+    );
+    assert!(lines[1].starts_with(r#"@dataset,Unsatisfied dataset rule; "_files < 0": "#));
+    Ok(())
+}
+
+#[test]
+fn test_validate_baseline() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let rules = tmp_dir.path().join("rules.txt");
+    std::fs::write(&rules, "TL > 0\n")?;
+    let baseline = tmp_dir.path().join("baseline.json");
+    let write_json = |name: &str, label: &str| {
+        let data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &[label.into()], 8, 8, "");
+        std::fs::write(json_dir.join(name), serde_json::to_string(&data).unwrap())
+    };
+    write_json("legacy.json", "TR")?;
+    write_json("valid.json", "TL")?;
+    let validate = |update: bool| {
+        let mut command = Command::new(bin);
+        command
+            .arg("validate")
+            .arg(&rules)
+            .arg(&json_dir)
+            .arg("--baseline")
+            .arg(&baseline);
+        if update {
+            command.arg("--update-baseline");
+        }
+        command.output()
+    };
+
+    let output = validate(true)?;
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&baseline)?,
+        "{\n  \"legacy.json\": [\n    \"TL > 0\"\n  ]\n}\n"
+    );
+    // the legacy failure is tolerated
+    let output = validate(false)?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(output.stdout.len(), 0);
+
+    // a new failure is reported
+    write_json("valid.json", "BL")?;
+    let output = validate(false)?;
+    assert!(!output.status.success());
+    assert_eq!(
+        str::from_utf8(&output.stdout)?,
+        "\"valid.json\",Unsatisfied rule; \"TL > 0\": 0 vs. 0\n"
+    );
+    assert!(str::from_utf8(&output.stderr)?.contains("1 failure(s) not in the baseline"));
+    Ok(())
+}
+
+#[test]
+fn test_validate_flag_expr() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let rules = tmp_dir.path().join("rules.txt");
+    std::fs::write(&rules, "TL > 0\n")?;
+    for (name, flags) in [
+        ("reviewed.json", &["reviewed"][..]),
+        ("rejected.json", &["reviewed", "rejected"]),
+        ("urgent.json", &["rejected", "urgent"]),
+        ("none.json", &[]),
+    ] {
+        let mut data = labelme_rs::LabelMeData::new(&[(1.0, 1.0)], &["TR".into()], 8, 8, "");
+        data.flags = flags.iter().map(|f| (f.to_string(), true)).collect();
+        std::fs::write(json_dir.join(name), serde_json::to_string(&data)?)?;
+    }
+    let output = Command::new(bin)
+        .arg("validate")
+        .arg(&rules)
+        .arg(&json_dir)
+        .arg("--flag-expr")
+        .arg("(reviewed && !rejected) || urgent")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let stdout = str::from_utf8(&output.stdout)?;
+    let mut files: Vec<_> = stdout.lines().map(|l| l.split(',').next()).collect();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![Some("\"reviewed.json\""), Some("\"urgent.json\"")],
+        "{}",
+        stdout
+    );
+
+    let output = Command::new(bin)
+        .arg("validate")
+        .arg(&rules)
+        .arg(&json_dir)
+        .arg("--flag-expr")
+        .arg("reviewed &&")
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_empty_annotations() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/empty");
+    let rules = tmp_dir.path().join("rules.txt");
+    std::fs::write(&rules, "TL == 0\n")?;
+
+    let output = Command::new(bin)
+        .arg("validate")
+        .arg(&rules)
+        .arg(&json_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+
+    let output = Command::new(bin).arg("ndjson").arg(&json_dir).output()?;
+    assert!(output.status.success());
+    let lines: Vec<_> = str::from_utf8(&output.stdout)?.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        let line = labelme_rs::LabelMeDataLine::try_from(line)?;
+        assert!(line.content.is_empty(), "{}", line.filename);
+    }
+
+    let html = tmp_dir.path().join("catalog.html");
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&json_dir)
+        .arg(&html)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(std::fs::read_to_string(&html)?.matches("<svg").count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_split_pattern() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = tmp_dir.path().join("input.ndjson");
+    std::fs::write(
+        &input,
+        [
+            r#"{"filename":"a/img1.json","content":{}}"#,
+            r#"{"filename":"b/img2.json","content":{}}"#,
+        ]
+        .join("\n"),
+    )?;
+
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--output")
+        .arg(tmp_dir.path())
+        .arg("--pattern")
+        .arg("{index}_{stem}.json")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert!(tmp_dir.path().join("0_img1.json").exists());
+    assert!(tmp_dir.path().join("1_img2.json").exists());
+
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--pattern")
+        .arg("{name}.json")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("unknown placeholder {name}"));
+    Ok(())
+}
+
+#[test]
+fn test_split_by_flag() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = tmp_dir.path().join("input.ndjson");
+    let lines = [
+        r#"{"filename":"1.json","content":{"flags":{"reviewed":true},"shapes":[]}}"#,
+        r#"{"filename":"2.json","content":{"flags":{"reviewed":false},"shapes":[{"label":"cat"}]}}"#,
+        r#"{"filename":"3.json","content":{"flags":{},"shapes":[]}}"#,
+    ];
+    std::fs::write(&input, lines.join("\n"))?;
+
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--output")
+        .arg(tmp_dir.path())
+        .arg("--by-flag")
+        .arg("reviewed")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(
+        std::fs::read_to_string(tmp_dir.path().join("reviewed.ndjson"))?,
+        format!("{}\n", lines[0])
+    );
+    assert_eq!(
+        std::fs::read_to_string(tmp_dir.path().join("not_reviewed.ndjson"))?,
+        format!("{}\n{}\n", lines[1], lines[2])
+    );
+
+    // existing shards are kept without --overwrite
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--output")
+        .arg(tmp_dir.path())
+        .arg("--by-flag")
+        .arg("reviewed")
+        .output()?;
+    assert!(!output.status.success());
+
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--output")
+        .arg(tmp_dir.path())
+        .arg("--by-label")
+        .arg("cat")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(tmp_dir.path().join("cat.ndjson"))?,
+        format!("{}\n", lines[1])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_if_exists_update() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let input = tmp_dir.path().join("input.ndjson");
+    let outdir = tmp_dir.path().join("out");
+    std::fs::create_dir(&outdir)?;
+    let mut lines = vec![
+        r#"{"filename":"1.json","content":{"shapes":[{"label":"L1"}]}}"#,
+        r#"{"filename":"2.json","content":{"shapes":[]}}"#,
+        r#"{"filename":"3.json","content":{"flags":{"a":true}}}"#,
+    ];
+    let split = |lines: &[&str]| -> Result<String> {
+        std::fs::write(&input, lines.join("\n"))?;
+        let output = Command::new(bin)
+            .arg("split")
+            .arg(&input)
+            .arg("--output")
+            .arg(&outdir)
+            .arg("--if-exists")
+            .arg("update")
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+        Ok(String::from_utf8(output.stderr)?)
+    };
+    let old_time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let mtimes = || -> Result<Vec<std::time::SystemTime>> {
+        ["1.json", "2.json", "3.json"]
+            .iter()
+            .map(|name| Ok(std::fs::metadata(outdir.join(name))?.modified()?))
+            .collect()
+    };
+
+    assert_eq!(split(&lines)?.trim(), "written: 3, skipped: 0, updated: 0");
+    // compact formatting is the same content
+    std::fs::write(outdir.join("2.json"), r#"{"shapes":[]}"#)?;
+    for name in ["1.json", "2.json", "3.json"] {
+        std::fs::File::options()
+            .write(true)
+            .open(outdir.join(name))?
+            .set_modified(old_time)?;
+    }
+    assert_eq!(split(&lines)?.trim(), "written: 0, skipped: 3, updated: 0");
+    assert_eq!(mtimes()?, vec![old_time; 3]);
+
+    lines[2] = r#"{"filename":"3.json","content":{"flags":{"a":false}}}"#;
+    assert_eq!(split(&lines)?.trim(), "written: 0, skipped: 2, updated: 1");
+    let mtimes = mtimes()?;
+    assert_eq!(mtimes[..2], [old_time; 2]);
+    assert_ne!(mtimes[2], old_time);
+    let content: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(outdir.join("3.json"))?)?;
+    assert_eq!(content["flags"]["a"], false);
+
+    let output = Command::new(bin)
+        .arg("split")
+        .arg(&input)
+        .arg("--output")
+        .arg(&outdir)
+        .output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("already exists"));
+    Ok(())
+}
+
+#[test]
+fn test_merge_configs() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let config_a = tmp_dir.path().join("a.yaml");
+    let config_b = tmp_dir.path().join("b.yaml");
+    let merged = tmp_dir.path().join("merged.yaml");
+    std::fs::write(
+        &config_a,
+        "label_colors:\n  L1: [255, 0, 0]\n  L2: [0, 255, 0]\n",
+    )?;
+    std::fs::write(
+        &config_b,
+        "label_colors:\n  L2: [0, 0, 255]\n  L3: [255, 255, 0]\n",
+    )?;
+
+    let output = Command::new(bin)
+        .arg("merge-configs")
+        .arg(&config_a)
+        .arg(&config_b)
+        .arg("-o")
+        .arg(&merged)
+        .output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("Conflicting colors for label \"L2\""));
+    assert!(!merged.exists());
+
+    let output = Command::new(bin)
+        .arg("merge-configs")
+        .arg(&config_a)
+        .arg(&config_b)
+        .arg("-o")
+        .arg(&merged)
+        .arg("--last-wins")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let colors = labelme_rs::load_label_colors(&merged)?;
+    assert_eq!(colors.len(), 3);
+    assert_eq!(colors["L1"], "#FF0000");
+    assert_eq!(colors["L2"], "#0000FF");
+    assert_eq!(colors["L3"], "#FFFF00");
+    Ok(())
+}
+
+#[test]
+fn test_ls() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let output = Command::new(bin)
+        .arg("ls")
+        .arg(&data_dir)
+        .arg("--header")
+        .output()?;
+    insta::assert_snapshot!("ls-default", str::from_utf8(&output.stdout)?);
+    let output = Command::new(bin)
+        .arg("ls")
+        .arg(&data_dir)
+        .arg("--labels-only")
+        .output()?;
+    insta::assert_snapshot!("ls-labels-only", str::from_utf8(&output.stdout)?);
+
+    // labels with spaces are kept and tabs and commas are percent-encoded
+    let mut proc = Command::new(bin)
+        .arg("ls")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    proc.stdin.as_mut().unwrap().write_all(
+        br#"{"filename":"a b.json","content":{"flags":{"f 1":true,"f2":false},"shapes":[{"label":"big cat"},{"label":"x\ty,z"}]}}"#,
+    )?;
+    let output = proc.wait_with_output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        str::from_utf8(&output.stdout)?,
+        "a b.json\t2\tbig cat,x%09y%2Cz\tf 1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_swap_lock() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let json_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    std::fs::copy(json_dir.join("img1.json"), tmp_dir.path().join("img1.json"))?;
+    let lock = tmp_dir.path().join(".lmrs.lock");
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    std::fs::write(
+        &lock,
+        format!(r#"{{"pid":1,"host":"another-host","started":{started}}}"#),
+    )?;
+
+    let output = Command::new(bin)
+        .arg("swap")
+        .arg(tmp_dir.path())
+        .arg("images")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("is locked by pid 1 on another-host"));
+    let data = labelme_rs::LabelMeData::try_from(tmp_dir.path().join("img1.json").as_path())?;
+    assert!(!data.imagePath.starts_with("images/"));
+
+    let output = Command::new(bin)
+        .arg("swap")
+        .arg(tmp_dir.path())
+        .arg("images")
+        .arg("--force-lock")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert!(!lock.exists());
+    let data = labelme_rs::LabelMeData::try_from(tmp_dir.path().join("img1.json").as_path())?;
+    assert!(data.imagePath.starts_with("images/"));
+    assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_svg_output_template() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    std::fs::copy(data_dir.join("Mandrill.jpg"), json_dir.join("Mandrill.jpg"))?;
+    for name in ["a.json", "b.json"] {
+        std::fs::copy(data_dir.join("Mandrill.json"), json_dir.join(name))?;
+    }
+    let template = format!("{}/svgs/{{index}}_{{stem}}.svg", tmp_dir.path().display());
+    let output = Command::new(bin)
+        .arg("svg")
+        .arg(&json_dir)
+        .arg("--output-template")
+        .arg(&template)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let mut svgs: Vec<_> = std::fs::read_dir(tmp_dir.path().join("svgs"))?
+        .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<std::io::Result<_>>()?;
+    svgs.sort();
+    assert_eq!(svgs, vec!["0_a.svg", "1_b.svg"]);
+    let svg = std::fs::read_to_string(tmp_dir.path().join("svgs/1_b.svg"))?;
+    assert!(svg.contains("<image"));
+    assert!(svg.contains(r#"class="polygon Eye""#));
+
+    let output = Command::new(bin)
+        .arg("svg")
+        .arg(&json_dir)
+        .arg("out.svg")
+        .arg("--output-template")
+        .arg(&template)
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_count_tarball() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let tarball = tmp_dir.path().join("data.tar");
+    let output = Command::new(bin)
+        .arg("archive")
+        .arg(&data_dir)
+        .arg(&tarball)
+        .output()?;
+    assert!(output.status.success());
+
+    let run = |args: &[&std::ffi::OsStr]| -> Result<String> {
+        let output = Command::new(bin).args(args).output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    for args in [
+        vec!["count"],
+        vec!["count", "--distinct-labels"],
+        vec!["ls"],
+        vec!["ls", "--labels-only"],
+    ] {
+        let args: Vec<_> = args.iter().map(std::ffi::OsStr::new).collect();
+        let from_dir = run(&[args.as_slice(), &[data_dir.as_os_str()]].concat())?;
+        let from_tar = run(&[args.as_slice(), &[tarball.as_os_str()]].concat())?;
+        assert_eq!(from_dir, from_tar, "{:?}", args);
+    }
+
+    // a corrupt member is reported and skipped
+    let corrupt = tmp_dir.path().join("corrupt.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&corrupt)?);
+    for (member, content) in [
+        ("sub/broken.json", "{".to_string()),
+        (
+            "sub/Mandrill.json",
+            std::fs::read_to_string(data_dir.join("Mandrill.json"))?,
+        ),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, member, content.as_bytes())?;
+    }
+    builder.finish()?;
+    let output = Command::new(bin).arg("ls").arg(&corrupt).output()?;
+    assert!(output.status.success());
+    assert!(str::from_utf8(&output.stdout)?.starts_with("sub/Mandrill.json\t15\t"));
+    let stderr = str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("sub/broken.json"), "{}", stderr);
+    assert!(
+        stderr.contains("Failed to parse 1 json member(s)"),
+        "{}",
+        stderr
+    );
+    Ok(())
+}
+
+#[test]
+fn test_flags() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let mut data = labelme_rs::LabelMeData::new(&[], &[], 4, 4, "img.jpg");
+    data.flags =
+        labelme_rs::Flags::from_iter([("todo".to_string(), true), ("blurry".to_string(), false)]);
+    std::fs::write(
+        tmp_dir.path().join("img.json"),
+        serde_json::to_string(&data)?,
+    )?;
+    let output = Command::new(bin)
+        .arg("flags")
+        .arg(tmp_dir.path())
+        .arg("--set")
+        .arg("reviewed")
+        .arg("--clear")
+        .arg("todo")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let data = labelme_rs::LabelMeData::try_from(tmp_dir.path().join("img.json").as_path())?;
+    assert_eq!(data.flags.get("reviewed"), Some(&true));
+    assert_eq!(data.flags.get("blurry"), Some(&false));
+    assert!(!data.flags.contains_key("todo"));
+    assert!(!tmp_dir.path().join(".lmrs.lock").exists());
+
+    // flip an existing flag
+    let output = Command::new(bin)
+        .arg("flags")
+        .arg(tmp_dir.path().join("img.json"))
+        .arg("--set")
+        .arg("reviewed=false,blurry=true")
+        .output()?;
+    assert!(output.status.success());
+    let data = labelme_rs::LabelMeData::try_from(tmp_dir.path().join("img.json").as_path())?;
+    assert_eq!(
+        data.flags.into_iter().collect::<Vec<_>>(),
+        vec![
+            ("blurry".to_string(), true),
+            ("reviewed".to_string(), false)
+        ]
+    );
+
+    // ndjson is written to stdout, and only the lines satisfying `--only-if` are updated
+    let lines: Vec<_> = [vec![(1.0, 1.0)], vec![(1.0, 1.0), (2.0, 2.0)]]
+        .iter()
+        .enumerate()
+        .map(|(i, points)| {
+            let labels = vec!["TL".to_string(); points.len()];
+            serde_json::to_string(&labelme_rs::LabelMeDataLine {
+                filename: format!("img{i}.json"),
+                content: labelme_rs::LabelMeData::new(points, &labels, 4, 4, "img.jpg"),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, lines.join("\n"))?;
+    let output = Command::new(bin)
+        .arg("flags")
+        .arg(&ndjson)
+        .arg("--set")
+        .arg("needs_review")
+        .arg("--only-if")
+        .arg("TL > 1")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let lines: Vec<labelme_rs::LabelMeDataLine> = str::from_utf8(&output.stdout)?
+        .lines()
+        .map(labelme_rs::LabelMeDataLine::try_from)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].content.flags.is_empty());
+    assert_eq!(lines[1].content.flags.get("needs_review"), Some(&true));
+    Ok(())
+}
+
+#[test]
+fn test_rename() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data = labelme_rs::LabelMeData::new(
+        &[(1.0, 1.0), (2.0, 2.0)],
+        &["TL".into(), "TR".into()],
+        4,
+        4,
+        "TL.jpg",
+    );
+    let json = tmp_dir.path().join("img.json");
+    std::fs::write(&json, serde_json::to_string(&data)?)?;
+    let map_file = tmp_dir.path().join("map.csv");
+    std::fs::write(&map_file, "TL,top_left\nBL,bottom_left\n")?;
+
+    // nothing is written with `--missing exit`
+    let output = Command::new(bin)
+        .arg("rename")
+        .arg(tmp_dir.path())
+        .arg("--map-file")
+        .arg(&map_file)
+        .arg("--missing")
+        .arg("exit")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(str::from_utf8(&output.stderr)?.contains("BL"));
+    let renamed = labelme_rs::LabelMeData::try_from(json.as_path())?;
+    assert_eq!(renamed.shapes[0].label, "TL");
+
+    let output = Command::new(bin)
+        .arg("rename")
+        .arg(tmp_dir.path())
+        .arg("--map-file")
+        .arg(&map_file)
+        .arg("--map")
+        .arg("TR=top_right")
+        .arg("--missing")
+        .arg("continue")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let renamed = labelme_rs::LabelMeData::try_from(json.as_path())?;
+    let labels: Vec<_> = renamed.shapes.iter().map(|s| s.label.as_str()).collect();
+    assert_eq!(labels, vec!["top_left", "top_right"]);
+    assert_eq!(renamed.imagePath, "TL.jpg");
+    assert!(str::from_utf8(&output.stderr)?.contains("TR -> top_right: 1"));
+
+    // regex with capture groups, and `--strict` fails on the mapping without matches
+    let output = Command::new(bin)
+        .arg("relabel")
+        .arg(&json)
+        .arg("--regex")
+        .arg("--map")
+        .arg("top_(.+)=${1}")
+        .arg("--map")
+        .arg("bottom_.*=bottom")
+        .arg("--strict")
+        .output()?;
+    assert!(!output.status.success());
+    let renamed = labelme_rs::LabelMeData::try_from(json.as_path())?;
+    assert_eq!(renamed.shapes[0].label, "top_left");
+    let output = Command::new(bin)
+        .arg("relabel")
+        .arg(&json)
+        .arg("--regex")
+        .arg("--map")
+        .arg("top_(.+)=${1}")
+        .arg("--strict")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    assert_eq!(str::from_utf8(&output.stderr)?, "top_(.+) -> ${1}: 2\n");
+    let renamed = labelme_rs::LabelMeData::try_from(json.as_path())?;
+    let labels: Vec<_> = renamed.shapes.iter().map(|s| s.label.as_str()).collect();
+    assert_eq!(labels, vec!["left", "right"]);
+
+    // unknown input types are errors, not panics
+    let output = Command::new(bin)
+        .arg("rename")
+        .arg(tmp_dir.path().join("img.txt"))
+        .arg("--map")
+        .arg("TL=top_left")
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = str::from_utf8(&output.stderr)?;
+    assert!(stderr.contains("Unknown input type"), "{stderr}");
+    Ok(())
+}
+
+#[test]
+fn test_catalog_only_shapes() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let html = tmp_dir.path().join("catalog.html");
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&data_dir)
+        .arg(&html)
+        .arg("--only-shapes")
+        .arg("point")
+        .arg("--no-user-config")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let html_str = std::fs::read_to_string(&html)?;
+    assert!(html_str.contains(r#"class="point Nostril""#));
+    assert!(!html_str.contains("<rect"));
+    assert!(!html_str.contains("<polygon"));
+
+    let output = Command::new(bin)
+        .arg("count")
+        .arg(&data_dir)
+        .arg("--distinct-labels")
+        .arg("--only-shapes")
+        .arg("point,rectangle")
+        .output()?;
+    assert!(output.status.success());
+    let counts: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        counts["shape_types"],
+        serde_json::json!({"point": 3, "rectangle": 1})
+    );
+    Ok(())
+}
+
+#[test]
+fn test_catalog_size_caption() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let html = tmp_dir.path().join("catalog.html");
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&data_dir)
+        .arg(&html)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let html_str = std::fs::read_to_string(&html)?;
+    assert!(html_str.contains("256×256, 16.2 KB"));
+    assert!(html_str.contains(r#"data-width="256" data-height="256" data-bytes="16238""#));
+
+    // image embedded in imageData without the image file
+    let mut data = labelme_rs::LabelMeData::try_from(std::fs::read_to_string(
+        data_dir.join("Mandrill.json"),
+    )?)?;
+    let image = labelme_rs::load_image(&data_dir.join("Mandrill.jpg"))?;
+    data.imagePath = "missing.jpg".into();
+    data.imageData = Some(labelme_rs::img2base64(
+        &image,
+        labelme_rs::image::ImageFormat::Png,
+    )?);
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "Mandrill.json".into(),
+        content: data,
+    };
+    let input = tmp_dir.path().join("embedded.ndjson");
+    std::fs::write(&input, serde_json::to_string(&line)?)?;
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&input)
+        .arg(&html)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let html_str = std::fs::read_to_string(&html)?;
+    assert!(html_str.contains("256×256, embedded"));
+    assert!(!html_str.contains("data-bytes"));
+    Ok(())
+}
+
+#[test]
+fn test_catalog_external_assets() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let html = tmp_dir.path().join("catalog.html");
+    let assets_dir = tmp_dir.path().join("assets");
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&data_dir)
+        .arg(&html)
+        .arg("--external-assets")
+        .arg(&assets_dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let html_str = std::fs::read_to_string(&html)?;
+    assert!(html_str.contains(r#"href="assets/catalog.css""#));
+    assert!(html_str.contains(r#"src="assets/catalog.js""#));
+    assert!(!html_str.contains("function toggle_visibility"));
+    assert!(std::fs::read_to_string(assets_dir.join("catalog.js"))?
+        .contains("function toggle_visibility"));
+    assert!(std::fs::read_to_string(assets_dir.join("catalog.css"))?.contains("figcaption"));
+    Ok(())
+}
+
+#[test]
+fn test_catalog_save_colors() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let json_dir = tmp_dir.path().join("jsons");
+    std::fs::create_dir(&json_dir)?;
+    let mut data = labelme_rs::LabelMeData::try_from(data_dir.join("Mandrill.json").as_path())?;
+    data.imagePath = data_dir.join("Mandrill.jpg").to_string_lossy().into_owned();
+    std::fs::write(json_dir.join("1.json"), serde_json::to_string(&data)?)?;
+    let colors = tmp_dir.path().join("colors.yaml");
+    let html = tmp_dir.path().join("catalog.html");
+    let catalog = || -> Result<std::process::Output> {
+        let mut command = Command::new(bin);
+        command.arg("catalog").arg(&json_dir).arg(&html);
+        if colors.exists() {
+            command.arg("--config").arg(&colors);
+        }
+        Ok(command.arg("--save-colors").arg(&colors).output()?)
+    };
+
+    let output = catalog()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let first = labelme_rs::load_label_colors(&colors)?;
+    assert_eq!(first.len(), 7);
+
+    // A new label found first would shift the automatically assigned colors
+    data.shapes[0].label = "New".into();
+    std::fs::write(json_dir.join("0.json"), serde_json::to_string(&data)?)?;
+    let output = catalog()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let second = labelme_rs::load_label_colors(&colors)?;
+    assert_eq!(second.len(), 8);
+    for (label, color) in &first {
+        assert_eq!(&second[label], color, "{}", label);
+    }
+    assert!(second.contains_key("New"));
+    Ok(())
+}
+
+#[test]
+fn test_sample() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    let lines: Vec<_> = (0..20)
+        .map(|i| {
+            let mut data = labelme_rs::LabelMeData::new(&[], &[], 4, 4, "img.jpg");
+            data.flags.insert("rare".into(), i % 4 == 0);
+            serde_json::to_string(&labelme_rs::LabelMeDataLine {
+                filename: format!("img{i}.json"),
+                content: data,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    std::fs::write(&ndjson, lines.join("\n") + "\n")?;
+    let sample = |args: &[&str]| -> Result<Vec<String>> {
+        let output = Command::new(bin)
+            .arg("sample")
+            .arg(&ndjson)
+            .args(args)
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+        Ok(str::from_utf8(&output.stdout)?
+            .lines()
+            .map(String::from)
+            .collect())
+    };
+
+    let sampled = sample(&["--count", "5", "--seed", "1"])?;
+    assert_eq!(sampled.len(), 5);
+    assert!(sampled.iter().all(|line| lines.contains(line)));
+    assert_eq!(sampled, sample(&["--count", "5", "--seed", "1"])?);
+    assert_eq!(sample(&["--fraction", "0.5"])?.len(), 10);
+
+    let sampled = sample(&["--count", "8", "--stratify", "rare"])?;
+    let rare = sampled
+        .iter()
+        .filter(|line| line.contains(r#""rare":true"#))
+        .count();
+    assert_eq!(rare, 2);
+
+    let output = Command::new(bin)
+        .arg("sample")
+        .arg(&ndjson)
+        .args(["--count", "5", "--fraction", "0.5"])
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_catalog_pdf() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/data");
+    let data = labelme_rs::LabelMeData::try_from(data_dir.join("Mandrill.json").as_path())?
+        .to_absolute_path(&data_dir);
+    let lines: Vec<_> = (0..3)
+        .map(|i| {
+            serde_json::to_string(&labelme_rs::LabelMeDataLine {
+                filename: format!("img{i}.json"),
+                content: data.clone(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, lines.join("\n"))?;
+    let pdf = tmp_dir.path().join("catalog.pdf");
+    let output = Command::new(bin)
+        .arg("catalog")
+        .arg(&ndjson)
+        .arg(&pdf)
+        .arg("--pdf")
+        .arg("--resize")
+        .arg("25%")
+        .arg("--no-user-config")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let content = std::fs::read(&pdf)?;
+    let content = String::from_utf8_lossy(&content);
+    assert!(content.starts_with("%PDF-"));
+    assert_eq!(content.matches("/Type /Page\n").count(), 3);
+    assert!(content.contains("(img1  "));
+    Ok(())
+}
+
+#[test]
+fn test_augment() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "dir/img.json".into(),
+        content: labelme_rs::LabelMeData::new(&[(10.0, 20.0)], &["L1".into()], 100, 50, "img.jpg"),
+    };
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, serde_json::to_string(&line)?)?;
+    let output = Command::new(bin)
+        .arg("augment")
+        .arg(&ndjson)
+        .arg("--all")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        str::from_utf8(&output.stderr)?
+    );
+    let lines: Vec<labelme_rs::LabelMeDataLine> = str::from_utf8(&output.stdout)?
+        .lines()
+        .map(labelme_rs::LabelMeDataLine::try_from)
+        .collect::<Result<_, _>>()?;
+    let filenames: Vec<_> = lines.iter().map(|l| l.filename.as_str()).collect();
+    assert_eq!(
+        filenames,
+        vec![
+            "dir/img_fliph.json",
+            "dir/img_flipv.json",
+            "dir/img_rotcw.json",
+            "dir/img_rotccw.json",
+            "dir/img_rot180.json"
+        ]
+    );
+    assert_eq!(lines[0].content.shapes[0].points[0], (90.0, 20.0));
+    assert_eq!(
+        (lines[2].content.imageWidth, lines[2].content.imageHeight),
+        (50, 100)
+    );
+
+    let output = Command::new(bin).arg("augment").arg(&ndjson).output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_dedup() -> Result<()> {
+    let bin = env!("CARGO_BIN_EXE_lmrs");
+    let tmp_dir = tempfile::tempdir()?;
+    let line = labelme_rs::LabelMeDataLine {
+        filename: "img.json".into(),
+        content: labelme_rs::LabelMeData {
+            shapes: vec![
+                labelme_rs::Shape::rectangle((0.0, 0.0), (10.0, 10.0), "car"),
+                labelme_rs::Shape::rectangle((0.2, 0.0), (10.0, 10.3), "car"),
+                labelme_rs::Shape::rectangle((1.0, 0.0), (11.0, 10.0), "car"),
+                labelme_rs::Shape::rectangle((1.0, 0.0), (11.0, 10.0), "bus"),
+            ],
+            ..labelme_rs::LabelMeData::new(&[], &[], 32, 32, "img.jpg")
+        },
+    };
+    let ndjson = tmp_dir.path().join("input.ndjson");
+    std::fs::write(&ndjson, serde_json::to_string(&line)?)?;
+    let dedup = |extra_args: &[&str]| -> Result<(Vec<String>, String)> {
+        let output = Command::new(bin)
+            .arg("dedup")
+            .arg(&ndjson)
+            .args(extra_args)
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            str::from_utf8(&output.stderr)?
+        );
+        let stdout = str::from_utf8(&output.stdout)?;
+        // integral coordinates are kept as integers
+        assert!(stdout.contains("[[0,0],[10,10]]"), "{stdout}");
+        let deduped = labelme_rs::LabelMeDataLine::try_from(stdout)?;
+        let labels = deduped
+            .content
+            .shapes
+            .iter()
+            .map(|s| format!("{}{:?}", s.label, s.points[0]))
+            .collect();
+        Ok((labels, str::from_utf8(&output.stderr)?.to_string()))
+    };
+    let (labels, _) = dedup(&[])?;
+    assert_eq!(
+        labels,
+        vec!["car(0.0, 0.0)", "car(1.0, 0.0)", "bus(1.0, 0.0)"]
+    );
+    let (labels, stats) = dedup(&["--tolerance", "0", "--iou", "0.8", "--stats"])?;
+    assert_eq!(labels, vec!["car(0.0, 0.0)", "bus(1.0, 0.0)"]);
+    assert!(stats.ends_with("\ncar: 2\n"), "{stats}");
+    Ok(())
+}
@@ -7,6 +7,10 @@ class Validator(_Validator):
     def __new__(cls, rules: List[str], flags: List[str], ignores: List[str]):
         return super().__new__(cls, rules, flags, ignores)
 
+    @classmethod
+    def from_file(cls, path: Union[str, Path], flags: List[str], ignores: List[str]):
+        return super().from_file(path, flags, ignores)
+
     def validate_jsons(self, json_str: str):
         return super().validate_jsons(json_str)
 
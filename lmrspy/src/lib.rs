@@ -1,6 +1,10 @@
+// pyo3 generated wrappers trigger `useless_conversion` on PyResult returns
+#![allow(clippy::useless_conversion)]
 use pyo3::prelude::*;
+use pyo3::types::PyType;
+use std::path::PathBuf;
 
-#[pyclass]
+#[pyclass(subclass)]
 struct Validator {
     rules: Vec<String>,
     asts: Vec<lmrs::Expr>,
@@ -34,6 +38,19 @@ impl Validator {
             Err(err) => Err(pyo3::exceptions::PyValueError::new_err(format!("{}", err))),
         }
     }
+
+    /// Create a validator with rules loaded from a text file by `lmrs::load_rules`
+    #[classmethod]
+    fn from_file<'py>(
+        cls: &Bound<'py, PyType>,
+        path: PathBuf,
+        flag_set: Vec<String>,
+        ignore_set: Vec<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let rules = lmrs::load_rules(&path)?;
+        cls.call1((rules, flag_set, ignore_set))
+    }
+
     fn __repr__(&self) -> PyResult<String> {
         let rules = concat(self.rules.iter(), ", ");
         let flags = concat(self.flags.iter(), ", ");
@@ -7,7 +7,7 @@ import lmrspy
 class TestStringMethods(unittest.TestCase):
 
     def test_validation(self):
-        json_path = str(Path(__file__).parent / '../../app/tests/test.json')
+        json_path = str(Path(__file__).parent / '../../lmrs/tests/test.json')
         rules = ['TL==1', 'TL>0']
         flags = []
         ignores = []
@@ -23,5 +23,18 @@ class TestStringMethods(unittest.TestCase):
         with self.assertRaises(ValueError):
             validator.validate_json(json_path)
 
+    def test_from_file(self):
+        json_path = str(Path(__file__).parent / '../../lmrs/tests/test.json')
+        rules_path = Path(__file__).parent / 'rules.txt'
+        validator = lmrspy.Validator.from_file(rules_path, [], [])
+        self.assertIsInstance(validator, lmrspy.Validator)
+        self.assertTrue(validator.validate_json(json_path))
+
+        validator = lmrspy.Validator.from_file(str(rules_path), [], ['f1'])
+        self.assertFalse(validator.validate_json(json_path))
+
+        with self.assertRaises(FileNotFoundError):
+            lmrspy.Validator.from_file(Path(__file__).parent / 'missing.txt', [], [])
+
 if __name__ == '__main__':
     unittest.main()
\ No newline at end of file